@@ -7,7 +7,7 @@
 
 use rustysd::control::jsonrpc2::Call;
 use serde_json::Value;
-use std::io::Write;
+use std::io::{Read, Write};
 
 fn main() {
     let mut args: Vec<_> = std::env::args().collect();
@@ -23,6 +23,21 @@ fn main() {
         
         Example:
             rsdctl 0.0.0.0:8080 restart test.service
+            rsdctl 0.0.0.0:8080 reload test.service
+            rsdctl 0.0.0.0:8080 reload-or-restart test.service
+            rsdctl /path/to/control.socket start rescue.target --mode isolate
+            rsdctl /path/to/control.socket list-jobs
+            rsdctl /path/to/control.socket cancel 42
+            rsdctl /path/to/control.socket logs test.service --since -1h -n 100 --follow
+
+        Options for logs (all optional):
+            <unit>              only show entries of this unit
+            --since <time>      seconds since the epoch, \"YYYY-MM-DD HH:MM:SS\", \"YYYY-MM-DD\" or relative like -10m
+            -n, --lines <n>     only show the last n entries
+            -f, --follow        keep printing new entries
+
+        Options for start, stop, restart, reload and reload-or-restart:
+            --mode <mode>       replace (default), fail, isolate or ignore-dependencies
         ");
         return;
     }
@@ -34,7 +49,11 @@ fn main() {
     };
     let args = args;
 
-    let params = if args.len() == 2 {
+    let params = if args[0] == "logs" {
+        Some(logs_params(&args[1..]))
+    } else if JOB_COMMANDS.contains(&args[0].as_str()) && args.len() > 2 {
+        Some(job_params(&args[1..]))
+    } else if args.len() == 2 {
         Some(Value::String(args[1].clone()))
     } else if args.len() > 1 {
         Some({
@@ -61,17 +80,90 @@ fn main() {
         stream.write_all(str_call.as_bytes()).unwrap();
         stream.shutdown(std::net::Shutdown::Write).unwrap();
         println!("Wait for response");
-        let resp: Value = serde_json::from_reader(&mut stream).unwrap();
-        println!("Got response");
-        println!("{}", serde_json::to_string_pretty(&resp).unwrap());
+        print_responses(&args[0], &mut stream);
     } else {
         let mut stream = std::net::TcpStream::connect(addr).unwrap();
         println!("Write cmd: {}", str_call);
         stream.write_all(str_call.as_bytes()).unwrap();
         stream.shutdown(std::net::Shutdown::Write).unwrap();
         println!("Wait for response");
-        let resp: Value = serde_json::from_reader(&mut stream).unwrap();
-        println!("Got response");
-        println!("{}", serde_json::to_string_pretty(&resp).unwrap());
+        print_responses(&args[0], &mut stream);
+    };
+}
+
+/// Commands that create jobs and accept a job mode
+const JOB_COMMANDS: &[&str] = &["start", "stop", "restart", "reload", "reload-or-restart"];
+
+/// Pack the unit and the job mode into an object
+fn job_params(args: &[String]) -> Value {
+    let mut params = serde_json::Map::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--mode" => {
+                let mode = args.next().expect("--mode needs a value");
+                params.insert("mode".into(), Value::String(mode.clone()));
+            }
+            unit => {
+                params.insert("unit".into(), Value::String(unit.to_owned()));
+            }
+        }
+    }
+    Value::Object(params)
+}
+
+/// Pack the args of the logs command into an object
+fn logs_params(args: &[String]) -> Value {
+    let mut params = serde_json::Map::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--since" => {
+                let since = args.next().expect("--since needs a value");
+                params.insert("since".into(), Value::String(since.clone()));
+            }
+            "-n" | "--lines" => {
+                let lines = args.next().expect("--lines needs a value");
+                let lines: u64 = lines.parse().expect("--lines needs a number");
+                params.insert("lines".into(), Value::Number(lines.into()));
+            }
+            "-f" | "--follow" => {
+                params.insert("follow".into(), Value::Bool(true));
+            }
+            unit => {
+                params.insert("unit".into(), Value::String(unit.to_owned()));
+            }
+        }
+    }
+    Value::Object(params)
+}
+
+fn print_log_entry(entry: &Value) {
+    let field = |name: &str| entry.get(name).and_then(Value::as_str).unwrap_or("");
+    let pid = match entry.get("PID").and_then(Value::as_str) {
+        Some(pid) => format!("[{}]", pid),
+        None => String::new(),
     };
+    println!(
+        "{} {}{} {}: {}",
+        field("Time"),
+        field("Unit"),
+        pid,
+        field("Stream"),
+        field("Message")
+    );
+}
+
+/// Print the response. The logs command in follow mode keeps sending new entries after the response.
+fn print_responses<R: Read>(method: &str, stream: &mut R) {
+    let mut responses = serde_json::Deserializer::from_reader(stream).into_iter::<Value>();
+    let resp = responses.next().unwrap().unwrap();
+    println!("Got response");
+    match resp.get("result").and_then(Value::as_array) {
+        Some(entries) if method == "logs" => entries.iter().for_each(print_log_entry),
+        _ => println!("{}", serde_json::to_string_pretty(&resp).unwrap()),
+    }
+    for entry in responses {
+        print_log_entry(&entry.unwrap());
+    }
 }
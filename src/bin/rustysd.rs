@@ -6,10 +6,13 @@ use rustysd::config;
 use rustysd::control;
 use rustysd::logging;
 use rustysd::notification_handler;
+use rustysd::path_activation;
 use rustysd::platform;
 use rustysd::signal_handler;
 use rustysd::socket_activation;
+use rustysd::timers;
 use rustysd::units;
+use rustysd::watchdog;
 use signal_hook::iterator::Signals;
 use std::sync::{Arc, Mutex, RwLock};
 
@@ -108,7 +111,12 @@ fn pid1_specific_setup() {
 #[cfg(not(target_os = "linux"))]
 fn pid1_specific_setup() {}
 
-fn prepare_runtimeinfo(conf: &config::Config, dry_run: bool) -> Arc<units::RuntimeInfo> {
+fn prepare_runtimeinfo(
+    conf: &config::Config,
+    dry_run: bool,
+    journal: rustysd::journal::ArcMutJournal,
+    log_sink: rustysd::log_sink::ArcLogSink,
+) -> Arc<units::RuntimeInfo> {
     // initial loading of the units and matching of the various before/after settings
     // also opening all fildescriptors in the socket files
     let mut first_id = 0;
@@ -166,7 +174,10 @@ fn prepare_runtimeinfo(conf: &config::Config, dry_run: bool) -> Arc<units::Runti
         status_table: status_table.clone(),
 
         last_id: Arc::new(Mutex::new(first_id)),
+        job_table: Arc::new(units::JobTable::default()),
         config: conf.clone(),
+        journal,
+        log_sink,
     });
 
     run_info
@@ -174,17 +185,17 @@ fn prepare_runtimeinfo(conf: &config::Config, dry_run: bool) -> Arc<units::Runti
 
 fn start_notification_handler_thread(run_info: units::ArcRuntimeInfo, eventfd: platform::EventFd) {
     std::thread::spawn(move || {
-        notification_handler::handle_all_streams(eventfd, run_info.unit_table.clone());
+        notification_handler::handle_all_streams(eventfd, run_info);
     });
 }
 fn start_stdout_handler_thread(run_info: units::ArcRuntimeInfo, eventfd: platform::EventFd) {
     std::thread::spawn(move || {
-        notification_handler::handle_all_std_out(eventfd, run_info.unit_table.clone());
+        notification_handler::handle_all_std_out(eventfd, run_info);
     });
 }
 fn start_stderr_handler_thread(run_info: units::ArcRuntimeInfo, eventfd: platform::EventFd) {
     std::thread::spawn(move || {
-        notification_handler::handle_all_std_err(eventfd, run_info.unit_table.clone());
+        notification_handler::handle_all_std_err(eventfd, run_info);
     });
 }
 fn start_signal_handler_thread(
@@ -251,7 +262,7 @@ fn main() {
 
     let (log_conf, conf) = config::load_config(&cli_args.conf_path);
 
-    logging::setup_logging(&log_conf).unwrap();
+    let (journal, log_sink) = logging::setup_logging(&log_conf).unwrap();
     let conf = match conf {
         Ok(conf) => conf,
         Err(e) => {
@@ -275,7 +286,7 @@ fn main() {
 
     rustysd::platform::become_subreaper(true);
 
-    let run_info = prepare_runtimeinfo(&conf, cli_args.dry_run);
+    let run_info = prepare_runtimeinfo(&conf, cli_args.dry_run, journal, log_sink);
 
     let notification_eventfd = platform::make_event_fd().unwrap();
     let stdout_eventfd = platform::make_event_fd().unwrap();
@@ -310,6 +321,7 @@ fn main() {
     start_notification_handler_thread(run_info.clone(), notification_eventfd);
     start_stdout_handler_thread(run_info.clone(), stdout_eventfd);
     start_stderr_handler_thread(run_info.clone(), stderr_eventfd);
+    watchdog::start_watchdog_thread(run_info.clone());
 
     socket_activation::start_socketactivation_thread(
         run_info.clone(),
@@ -318,6 +330,18 @@ fn main() {
         Arc::new(eventfds.clone()),
     );
 
+    path_activation::start_pathactivation_thread(
+        run_info.clone(),
+        conf.notification_sockets_dir.clone(),
+        Arc::new(eventfds.clone()),
+    );
+
+    timers::start_timer_thread(
+        run_info.clone(),
+        conf.notification_sockets_dir.clone(),
+        Arc::new(eventfds.clone()),
+    );
+
     // parallel startup of all services
     units::activate_units(
         run_info.clone(),
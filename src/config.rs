@@ -4,11 +4,13 @@
 //! ### Logging
 //! 1. Wether or not to log to disk (and the dir to put the logs in)
 //! 1. Wether or not to log to stdout
+//! 1. Where to forward the output of services to (syslog or journald)
 //!
 //! ### General config
 //! 1. Where to find the units (one or more directories)
 //! 1. notification-socket directory (where the unix-domain sockets are placed on which services can notify rustysd)
 //! 1. Which unit is the target that should be started
+//! 1. state directory (where state that should survive a restart is kept, like the last trigger of persistent timers)
 
 use std::{collections::HashMap, fs::File, io::Read, path::PathBuf};
 use toml;
@@ -18,13 +20,17 @@ pub struct LoggingConfig {
     pub log_to_stdout: bool,
     pub log_to_disk: bool,
     pub log_dir: PathBuf,
+    /// Where the output of services is forwarded to, see log_sink
+    pub sink: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct Config {
+    /// Searched in this order, a unit file in an earlier dir shadows files with the same name in later dirs
     pub unit_dirs: Vec<PathBuf>,
     pub target_unit: String,
     pub notification_sockets_dir: PathBuf,
+    pub state_dir: PathBuf,
 }
 
 #[derive(Debug)]
@@ -69,10 +75,13 @@ fn load_toml(
             settings.insert("logging.dir".to_owned(), SettingValue::Str(val.clone()));
         }
         if let Some(toml::Value::Boolean(val)) = map.get("log_to_disk") {
-            settings.insert("logging.to.disk".to_owned(), SettingValue::Boolean(*val));
+            settings.insert("logging.to_disk".to_owned(), SettingValue::Boolean(*val));
         }
         if let Some(toml::Value::Boolean(val)) = map.get("log_to_stdout") {
-            settings.insert("logging.to.stdout".to_owned(), SettingValue::Boolean(*val));
+            settings.insert("logging.to_stdout".to_owned(), SettingValue::Boolean(*val));
+        }
+        if let Some(toml::Value::String(val)) = map.get("log_sink") {
+            settings.insert("logging.sink".to_owned(), SettingValue::Str(val.clone()));
         }
         if let Some(toml::Value::String(val)) = map.get("target_unit") {
             settings.insert("target.unit".to_owned(), SettingValue::Str(val.clone()));
@@ -83,6 +92,9 @@ fn load_toml(
                 SettingValue::Str(val.clone()),
             );
         }
+        if let Some(toml::Value::String(val)) = map.get("state_dir") {
+            settings.insert("state.dir".to_owned(), SettingValue::Str(val.clone()));
+        }
     }
     Ok(())
 }
@@ -124,6 +136,9 @@ fn load_json(
         if let Some(serde_json::Value::Bool(val)) = map.get("log_to_stdout") {
             settings.insert("logging.to_stdout".to_owned(), SettingValue::Boolean(*val));
         }
+        if let Some(serde_json::Value::String(val)) = map.get("log_sink") {
+            settings.insert("logging.sink".to_owned(), SettingValue::Str(val.clone()));
+        }
         if let Some(serde_json::Value::String(val)) = map.get("target_unit") {
             settings.insert("target.unit".to_owned(), SettingValue::Str(val.clone()));
         }
@@ -133,6 +148,9 @@ fn load_json(
                 SettingValue::Str(val.clone()),
             );
         }
+        if let Some(serde_json::Value::String(val)) = map.get("state_dir") {
+            settings.insert("state.dir".to_owned(), SettingValue::Str(val.clone()));
+        }
     }
     Ok(())
 }
@@ -186,6 +204,10 @@ pub fn load_config(config_path: &Option<PathBuf>) -> (LoggingConfig, Result<Conf
         SettingValue::Boolean(b) => *b,
         _ => false,
     });
+    let log_sink = settings.get("logging.sink").and_then(|val| match val {
+        SettingValue::Str(s) => Some(s.clone()),
+        _ => None,
+    });
     let log_to_disk = settings.get("logging.to_disk").map(|val| match val {
         SettingValue::Boolean(b) => *b,
         _ => false,
@@ -195,6 +217,10 @@ pub fn load_config(config_path: &Option<PathBuf>) -> (LoggingConfig, Result<Conf
         SettingValue::Str(s) => Some(PathBuf::from(s)),
         _ => None,
     });
+    let state_dir = settings.get("state.dir").and_then(|dir| match dir {
+        SettingValue::Str(s) => Some(PathBuf::from(s)),
+        _ => None,
+    });
     let target_unit = settings.get("target.unit").map(|name| match name {
         SettingValue::Str(s) => Some(s.clone()),
         _ => None,
@@ -228,6 +254,7 @@ pub fn load_config(config_path: &Option<PathBuf>) -> (LoggingConfig, Result<Conf
         notification_sockets_dir: notification_sockets_dir
             .unwrap_or_else(|| Some(PathBuf::from("./notifications")))
             .unwrap(),
+        state_dir: state_dir.unwrap_or_else(|| PathBuf::from("./state")),
     };
 
     let conf = if let Some(json_conf) = json_conf {
@@ -260,6 +287,7 @@ pub fn load_config(config_path: &Option<PathBuf>) -> (LoggingConfig, Result<Conf
                 .unwrap_or_else(|| PathBuf::from("./logs")),
             log_to_disk: log_to_disk.unwrap_or(false),
             log_to_stdout: log_to_stdout.unwrap_or(true),
+            sink: log_sink,
         },
         conf,
     )
@@ -27,9 +27,15 @@ pub fn open_all_sockets(run_info: ArcRuntimeInfo, conf: &crate::config::Config)
 pub enum Command {
     ListUnits(Option<UnitIdKind>),
     Status(Option<String>),
-    Restart(String),
+    Start(String, JobMode),
+    Restart(String, JobMode),
+    Reload(String, JobMode),
+    ReloadOrRestart(String, JobMode),
     LoadNew(String),
-    Stop(String),
+    Stop(String, JobMode),
+    ListJobs,
+    Cancel(JobId),
+    Logs(crate::journal::LogFilter),
     Shutdown,
 }
 
@@ -38,6 +44,75 @@ enum ParseError {
     ParamsInvalid(String),
 }
 
+/// Either the unit name or an object with the fields unit and (optionally) mode
+fn parse_job_params(call: &super::jsonrpc2::Call) -> Result<(String, JobMode), ParseError> {
+    match &call.params {
+        Some(Value::String(unit)) => Ok((unit.clone(), JobMode::Replace)),
+        Some(Value::Object(map)) => {
+            let unit = match map.get("unit") {
+                Some(Value::String(unit)) => unit.clone(),
+                _ => {
+                    return Err(ParseError::ParamsInvalid(
+                        "The unit must be a string".to_owned(),
+                    ))
+                }
+            };
+            let mode = match map.get("mode") {
+                None => JobMode::Replace,
+                Some(Value::String(mode)) => {
+                    JobMode::from_name(mode).map_err(ParseError::ParamsInvalid)?
+                }
+                Some(_) => {
+                    return Err(ParseError::ParamsInvalid(
+                        "The mode must be a string".to_owned(),
+                    ))
+                }
+            };
+            Ok((unit, mode))
+        }
+        _ => Err(ParseError::ParamsInvalid(
+            "Params must be either a single string or an object".to_owned(),
+        )),
+    }
+}
+
+fn parse_logs_params(params: &Option<Value>) -> Result<crate::journal::LogFilter, ParseError> {
+    let mut filter = crate::journal::LogFilter::default();
+    match params {
+        None => {}
+        Some(Value::String(unit)) => filter.unit = Some(unit.clone()),
+        Some(Value::Object(map)) => {
+            for (key, value) in map {
+                match (key.as_str(), value) {
+                    ("unit", Value::String(unit)) => filter.unit = Some(unit.clone()),
+                    ("since", Value::String(since)) => {
+                        filter.since = Some(
+                            crate::journal::parse_since(since)
+                                .map_err(ParseError::ParamsInvalid)?,
+                        )
+                    }
+                    ("lines", Value::Number(lines)) if lines.is_u64() => {
+                        filter.lines = Some(lines.as_u64().unwrap() as usize)
+                    }
+                    ("follow", Value::Bool(follow)) => filter.follow = *follow,
+                    _ => {
+                        return Err(ParseError::ParamsInvalid(format!(
+                            "Invalid param: {}: {}",
+                            key, value
+                        )))
+                    }
+                }
+            }
+        }
+        _ => {
+            return Err(ParseError::ParamsInvalid(
+                "Params must be either none, a single string or an object".to_owned(),
+            ))
+        }
+    }
+    Ok(filter)
+}
+
 fn parse_command(call: &super::jsonrpc2::Call) -> Result<Command, ParseError> {
     let command = match call.method.as_str() {
         "status" => {
@@ -54,41 +129,41 @@ fn parse_command(call: &super::jsonrpc2::Call) -> Result<Command, ParseError> {
             };
             Command::Status(name)
         }
+        "start" => {
+            let (name, mode) = parse_job_params(call)?;
+            Command::Start(name, mode)
+        }
         "restart" => {
-            let name = match &call.params {
-                Some(params) => match params {
-                    Value::String(s) => s.clone(),
-                    _ => {
-                        return Err(ParseError::ParamsInvalid(format!(
-                            "Params must be a single string"
-                        )))
-                    }
-                },
-                None => {
-                    return Err(ParseError::ParamsInvalid(format!(
-                        "Params must be a single string"
-                    )))
-                }
-            };
-            Command::Restart(name)
+            let (name, mode) = parse_job_params(call)?;
+            Command::Restart(name, mode)
+        }
+        "reload" => {
+            let (name, mode) = parse_job_params(call)?;
+            Command::Reload(name, mode)
+        }
+        "reload-or-restart" => {
+            let (name, mode) = parse_job_params(call)?;
+            Command::ReloadOrRestart(name, mode)
         }
         "stop" => {
-            let name = match &call.params {
-                Some(params) => match params {
-                    Value::String(s) => s.clone(),
-                    _ => {
-                        return Err(ParseError::ParamsInvalid(format!(
-                            "Params must be a single string"
-                        )))
-                    }
-                },
+            let (name, mode) = parse_job_params(call)?;
+            Command::Stop(name, mode)
+        }
+        "list-jobs" => Command::ListJobs,
+        "cancel" => {
+            let id = match &call.params {
+                Some(Value::Number(id)) => id.as_u64(),
+                Some(Value::String(id)) => id.parse::<u64>().ok(),
+                _ => None,
+            };
+            match id {
+                Some(id) => Command::Cancel(id),
                 None => {
-                    return Err(ParseError::ParamsInvalid(format!(
-                        "Params must be a single string"
-                    )))
+                    return Err(ParseError::ParamsInvalid(
+                        "Params must be a single job id".to_owned(),
+                    ))
                 }
-            };
-            Command::Stop(name)
+            }
         }
 
         "list-units" => {
@@ -99,6 +174,8 @@ fn parse_command(call: &super::jsonrpc2::Call) -> Result<Command, ParseError> {
                             "target" => UnitIdKind::Target,
                             "socket" => UnitIdKind::Socket,
                             "service" => UnitIdKind::Service,
+                            "timer" => UnitIdKind::Timer,
+                            "path" => UnitIdKind::Path,
                             _ => {
                                 return Err(ParseError::ParamsInvalid(format!(
                                     "Kind not recognized: {}",
@@ -118,6 +195,7 @@ fn parse_command(call: &super::jsonrpc2::Call) -> Result<Command, ParseError> {
             };
             Command::ListUnits(kind)
         }
+        "logs" => Command::Logs(parse_logs_params(&call.params)?),
         "shutdown" => Command::Shutdown,
         "enable" => {
             let name = match &call.params {
@@ -148,9 +226,68 @@ fn parse_command(call: &super::jsonrpc2::Call) -> Result<Command, ParseError> {
     Ok(command)
 }
 
+/// The unit file (the template for instances) and the drop-ins that were applied to it, the files it shadows in
+/// unit dirs with a lower precedence and the aliases of the unit
+fn insert_files(map: &mut serde_json::Map<String, Value>, unit: &Unit) {
+    let unit_file = match template_name(&unit.conf.name()) {
+        Some(template) => unit.conf.filepath.with_file_name(template),
+        None => unit.conf.filepath.clone(),
+    };
+    map.insert(
+        "Files".into(),
+        Value::Array(
+            std::iter::once(&unit_file)
+                .chain(unit.conf.dropins.iter())
+                .map(|path| Value::String(path.to_string_lossy().into_owned()))
+                .collect(),
+        ),
+    );
+    if !unit.conf.shadowed.is_empty() {
+        map.insert(
+            "Shadowed".into(),
+            Value::Array(
+                unit.conf
+                    .shadowed
+                    .iter()
+                    .map(|path| Value::String(path.to_string_lossy().into_owned()))
+                    .collect(),
+            ),
+        );
+    }
+    if !unit.conf.aliases.is_empty() {
+        map.insert(
+            "Aliases".into(),
+            Value::Array(
+                unit.conf
+                    .aliases
+                    .iter()
+                    .map(|alias| Value::String(alias.clone()))
+                    .collect(),
+            ),
+        );
+    }
+}
+
+/// The OnFailure= or OnSuccess= units that were started after the last run of this unit
+fn insert_handlers(map: &mut serde_json::Map<String, Value>, unit: &Unit) {
+    if !unit.triggered_handlers.is_empty() {
+        map.insert(
+            "Handlers".into(),
+            Value::Array(
+                unit.triggered_handlers
+                    .iter()
+                    .map(|handler| Value::String(handler.clone()))
+                    .collect(),
+            ),
+        );
+    }
+}
+
 pub fn format_socket(socket_unit: &Unit, status: UnitStatus) -> Value {
     let mut map = serde_json::Map::new();
     map.insert("Name".into(), Value::String(socket_unit.conf.name()));
+    insert_files(&mut map, socket_unit);
+    insert_handlers(&mut map, socket_unit);
     map.insert("Status".into(), Value::String(format!("{:?}", status)));
 
     if let UnitSpecialized::Socket(sock) = &socket_unit.specialized {
@@ -167,6 +304,22 @@ pub fn format_socket(socket_unit: &Unit, status: UnitStatus) -> Value {
                     .collect(),
             ),
         );
+        if let Some(accept) = &sock.accept {
+            map.insert(
+                "MaxConnections".into(),
+                Value::String(accept.max_connections.to_string()),
+            );
+            map.insert("Accepted".into(), Value::String(sock.accepted.to_string()));
+            map.insert(
+                "Connections".into(),
+                Value::Array(
+                    sock.connections
+                        .iter()
+                        .map(|conn| Value::String(conn.service_name.clone()))
+                        .collect(),
+                ),
+            );
+        }
     }
 
     Value::Object(map)
@@ -175,13 +328,65 @@ pub fn format_socket(socket_unit: &Unit, status: UnitStatus) -> Value {
 pub fn format_target(socket_unit: &Unit, status: UnitStatus) -> Value {
     let mut map = serde_json::Map::new();
     map.insert("Name".into(), Value::String(socket_unit.conf.name()));
+    insert_files(&mut map, socket_unit);
+    insert_handlers(&mut map, socket_unit);
+    map.insert("Status".into(), Value::String(format!("{:?}", status)));
+    Value::Object(map)
+}
+
+fn format_system_time(time: std::time::SystemTime) -> String {
+    let time: chrono::DateTime<chrono::Local> = time.into();
+    time.format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+pub fn format_timer(timer_unit: &Unit, status: UnitStatus) -> Value {
+    let mut map = serde_json::Map::new();
+    map.insert("Name".into(), Value::String(timer_unit.conf.name()));
+    insert_files(&mut map, timer_unit);
+    insert_handlers(&mut map, timer_unit);
+    map.insert("Status".into(), Value::String(format!("{:?}", status)));
+    if let UnitSpecialized::Timer(timer) = &timer_unit.specialized {
+        map.insert("Unit".into(), Value::String(timer.config.unit.clone()));
+        if let Some(next) = timer.next_elapse_realtime() {
+            map.insert("NextElapse".into(), Value::String(format_system_time(next)));
+        }
+        if let Some(last) = timer.last_trigger_realtime {
+            map.insert(
+                "LastTrigger".into(),
+                Value::String(format_system_time(last)),
+            );
+        }
+    }
+    Value::Object(map)
+}
+
+pub fn format_path(path_unit: &Unit, status: UnitStatus) -> Value {
+    let mut map = serde_json::Map::new();
+    map.insert("Name".into(), Value::String(path_unit.conf.name()));
+    insert_files(&mut map, path_unit);
+    insert_handlers(&mut map, path_unit);
     map.insert("Status".into(), Value::String(format!("{:?}", status)));
+    if let UnitSpecialized::Path(path) = &path_unit.specialized {
+        map.insert("Unit".into(), Value::String(path.config.unit.clone()));
+        map.insert(
+            "Conditions".into(),
+            Value::Array(
+                path.config
+                    .conditions
+                    .iter()
+                    .map(|condition| Value::String(format!("{:?}", condition)))
+                    .collect(),
+            ),
+        );
+    }
     Value::Object(map)
 }
 
 pub fn format_service(srvc_unit: &Unit, status: UnitStatus) -> Value {
     let mut map = serde_json::Map::new();
     map.insert("Name".into(), Value::String(srvc_unit.conf.name()));
+    insert_files(&mut map, srvc_unit);
+    insert_handlers(&mut map, srvc_unit);
     map.insert("Status".into(), Value::String(format!("{:?}", status)));
     if let UnitSpecialized::Service(srvc) = &srvc_unit.specialized {
         map.insert(
@@ -203,28 +408,56 @@ pub fn format_service(srvc_unit: &Unit, status: UnitStatus) -> Value {
             "Restarted".into(),
             Value::String(format!("{:?}", srvc.runtime_info.restarted)),
         );
+        if let Some(pid) = srvc.pid {
+            map.insert("MainPID".into(), Value::String(format!("{}", pid)));
+        }
+        if let Some(errno) = srvc.notified_errno_description() {
+            map.insert("Errno".into(), Value::String(errno));
+        }
+        map.insert(
+            "Environment".into(),
+            Value::Array(
+                srvc.environment
+                    .iter()
+                    .map(|(key, value)| Value::String(format!("{}={}", key, value)))
+                    .collect(),
+            ),
+        );
     }
     Value::Object(map)
 }
 
-use std::sync::{Arc, Mutex};
-fn find_unit_with_name(unit_name: &str, unit_table_locked: &UnitTable) -> Option<Arc<Mutex<Unit>>> {
-    trace!("Find unit for name: {}", unit_name);
-    let mut srvc: Vec<_> = unit_table_locked
-        .values()
-        .filter(|unit| {
-            let name = unit.lock().unwrap().conf.name();
-            unit_name.starts_with(&name) && unit.lock().unwrap().is_service()
-        })
-        .cloned()
-        .collect();
-    if srvc.len() != 1 {
-        None
-    } else {
-        Some(srvc.remove(0))
+pub fn format_job(job: &Job) -> Value {
+    let mut map = serde_json::Map::new();
+    map.insert("Id".into(), Value::Number(job.id.into()));
+    map.insert("Unit".into(), Value::String(job.unit_name.clone()));
+    map.insert("Type".into(), Value::String(job.kind.name().into()));
+    map.insert("State".into(), Value::String(format!("{:?}", job.state)));
+    Value::Object(map)
+}
+
+pub fn format_finished_job(job: &FinishedJob) -> Value {
+    let mut map = serde_json::Map::new();
+    map.insert("Id".into(), Value::Number(job.id.into()));
+    map.insert("Unit".into(), Value::String(job.unit_name.clone()));
+    map.insert("Type".into(), Value::String(job.kind.name().into()));
+    map.insert("Result".into(), Value::String(format!("{}", job.result)));
+    Value::Object(map)
+}
+
+pub fn format_log_entry(entry: &crate::journal::LogEntry) -> Value {
+    let mut map = serde_json::Map::new();
+    map.insert("Unit".into(), Value::String(entry.unit.clone()));
+    map.insert("Time".into(), Value::String(entry.format_time()));
+    map.insert("Stream".into(), Value::String(entry.stream.as_str().into()));
+    if let Some(pid) = entry.pid {
+        map.insert("PID".into(), Value::String(format!("{}", pid)));
     }
+    map.insert("Message".into(), Value::String(entry.message.clone()));
+    Value::Object(map)
 }
 
+use std::sync::{Arc, Mutex};
 // TODO make this some kind of regex pattern matching
 fn find_units_with_pattern(
     name_pattern: &str,
@@ -234,14 +467,85 @@ fn find_units_with_pattern(
     let units: Vec<_> = unit_table_locked
         .values()
         .filter(|unit| {
-            let name = unit.lock().unwrap().conf.name();
-            name_pattern.starts_with(&name) && unit.lock().unwrap().is_service()
+            let unit = unit.lock().unwrap();
+            let name = unit.conf.name();
+            (name_pattern.starts_with(&name) || unit.conf.aliases.iter().any(|a| a == name_pattern))
+                && unit.is_service()
         })
         .cloned()
         .collect();
     units
 }
 
+/// Units that are being started stay locked for a while. They are checked last so requests for other units don't wait
+/// for them
+fn find_unit_id(unit_name: &str, run_info: &ArcRuntimeInfo) -> Result<UnitId, String> {
+    trace!("Find unit for name: {}", unit_name);
+    let matches = |unit: &Unit| {
+        unit_name.starts_with(&unit.conf.name()) || unit.conf.aliases.iter().any(|a| a == unit_name)
+    };
+    let unit_table_locked = run_info.unit_table.read().unwrap();
+    let mut found = Vec::new();
+    let mut busy = Vec::new();
+    for (id, unit) in unit_table_locked.iter() {
+        match unit.try_lock() {
+            Ok(unit_locked) => {
+                if matches(&unit_locked) {
+                    found.push(*id);
+                }
+            }
+            Err(_) => busy.push(unit),
+        }
+    }
+    if found.is_empty() {
+        for unit in busy {
+            let unit_locked = unit.lock().unwrap();
+            if matches(&unit_locked) {
+                found.push(unit_locked.id);
+            }
+        }
+    }
+    if found.len() == 1 {
+        Ok(found[0])
+    } else {
+        Err(format!("No unit found with name: {}", unit_name))
+    }
+}
+
+/// Run the transaction for this request. Fails if the job for the requested unit failed, otherwise the results
+/// of all jobs are returned
+fn execute_job(
+    unit_name: &str,
+    kind: JobKind,
+    mode: JobMode,
+    run_info: ArcRuntimeInfo,
+    notification_socket_path: std::path::PathBuf,
+) -> Result<Value, String> {
+    let id = find_unit_id(unit_name, &run_info)?;
+    let finished = crate::units::run_transaction(
+        id,
+        kind,
+        mode,
+        run_info,
+        notification_socket_path,
+        std::sync::Arc::new(Vec::new()),
+    )?;
+    for job in &finished {
+        if job.unit_id == id && job.result != JobResult::Done {
+            return Err(format!(
+                "Job {} ({} {}) {}",
+                job.id,
+                job.kind.name(),
+                job.unit_name,
+                job.result
+            ));
+        }
+    }
+    Ok(Value::Array(
+        finished.iter().map(format_finished_job).collect(),
+    ))
+}
+
 pub fn execute_command(
     cmd: Command,
     run_info: ArcRuntimeInfo,
@@ -252,34 +556,61 @@ pub fn execute_command(
         Command::Shutdown => {
             crate::signal_handler::shutdown_sequence(run_info);
         }
-        Command::Restart(unit_name) => {
-            let id = if let Some(unit) =
-                find_unit_with_name(&unit_name, &*run_info.unit_table.read().unwrap())
-            {
-                unit.lock().unwrap().id
-            } else {
-                return Err(format!("No unit found with name: {}", unit_name));
-            };
-
-            crate::units::reactivate_unit(
-                id,
+        Command::Start(unit_name, mode) => {
+            result_vec = execute_job(
+                &unit_name,
+                JobKind::Start,
+                mode,
+                run_info,
+                notification_socket_path,
+            )?;
+        }
+        Command::Restart(unit_name, mode) => {
+            result_vec = execute_job(
+                &unit_name,
+                JobKind::Restart,
+                mode,
                 run_info,
                 notification_socket_path,
-                std::sync::Arc::new(Vec::new()),
-            )
-            .map_err(|e| format!("{}", e))?;
-        }
-        Command::Stop(unit_name) => {
-            let id = if let Some(unit) =
-                find_unit_with_name(&unit_name, &*run_info.unit_table.read().unwrap())
-            {
-                unit.lock().unwrap().id
+            )?;
+        }
+        Command::Reload(unit_name, mode) => {
+            result_vec = execute_job(
+                &unit_name,
+                JobKind::Reload,
+                mode,
+                run_info,
+                notification_socket_path,
+            )?;
+        }
+        Command::ReloadOrRestart(unit_name, mode) => {
+            let id = find_unit_id(&unit_name, &run_info)?;
+            let kind = if crate::units::can_reload(id, &run_info) {
+                JobKind::Reload
             } else {
-                return Err(format!("No unit found with name: {}", unit_name));
+                JobKind::Restart
             };
-
-            crate::units::deactivate_unit_recursive(id, true, run_info)
-                .map_err(|e| format!("{}", e))?;
+            result_vec = execute_job(&unit_name, kind, mode, run_info, notification_socket_path)?;
+        }
+        Command::Stop(unit_name, mode) => {
+            result_vec = execute_job(
+                &unit_name,
+                JobKind::Stop,
+                mode,
+                run_info,
+                notification_socket_path,
+            )?;
+        }
+        Command::ListJobs => {
+            let mut jobs = run_info.job_table.list();
+            jobs.sort_by_key(|job| job.id);
+            for job in &jobs {
+                result_vec.as_array_mut().unwrap().push(format_job(job));
+            }
+        }
+        Command::Cancel(id) => {
+            let job = run_info.job_table.cancel(id)?;
+            result_vec.as_array_mut().unwrap().push(format_job(&job));
         }
         Command::Status(unit_name) => {
             match unit_name {
@@ -315,6 +646,16 @@ pub fn execute_command(
                                 .as_array_mut()
                                 .unwrap()
                                 .push(format_target(&unit_locked, status));
+                        } else if name.ends_with(".timer") {
+                            result_vec
+                                .as_array_mut()
+                                .unwrap()
+                                .push(format_timer(&unit_locked, status));
+                        } else if name.ends_with(".path") {
+                            result_vec
+                                .as_array_mut()
+                                .unwrap()
+                                .push(format_path(&unit_locked, status));
                         } else {
                             return Err("Name suffix not recognized".into());
                         }
@@ -342,6 +683,8 @@ pub fn execute_command(
                                 UnitSpecialized::Socket(_) => format_socket(&unit_locked, status),
                                 UnitSpecialized::Service(_) => format_service(&unit_locked, status),
                                 UnitSpecialized::Target => format_target(&unit_locked, status),
+                                UnitSpecialized::Timer(_) => format_timer(unit_locked, status),
+                                UnitSpecialized::Path(_) => format_path(unit_locked, status),
                             }
                         })
                         .collect();
@@ -368,6 +711,15 @@ pub fn execute_command(
                 }
             }
         }
+        Command::Logs(filter) => {
+            let entries = crate::journal::query(&run_info.journal, &filter)?;
+            for entry in &entries {
+                result_vec
+                    .as_array_mut()
+                    .unwrap()
+                    .push(format_log_entry(entry));
+            }
+        }
         Command::LoadNew(name) => {
             let this_id = {
                 let last_id = &mut *run_info.last_id.lock().unwrap();
@@ -435,6 +787,12 @@ pub fn listen_on_commands<T: 'static + Read + Write + Send>(
                                 let response_string = serde_json::to_string_pretty(&msg).unwrap();
                                 source.write_all(response_string.as_bytes()).unwrap();
                             }
+                            Ok(Command::Logs(ref filter)) if filter.follow => {
+                                trace!("Follow logs: {:?}", filter);
+                                follow_logs(source.as_mut(), call.id, &run_info.journal, filter);
+                                // the connection is used for the log entries until the other side closes it
+                                return;
+                            }
                             Ok(cmd) => {
                                 trace!("Execute command: {:?}", cmd);
                                 let msg = match execute_command(
@@ -465,14 +823,47 @@ pub fn listen_on_commands<T: 'static + Read + Write + Send>(
     });
 }
 
+/// Answer with the existing entries like a normal logs call. After that write every new entry as a separate json object
+/// until writing fails.
+fn follow_logs<T: Write>(
+    source: &mut T,
+    id: Option<Value>,
+    journal: &crate::journal::ArcMutJournal,
+    filter: &crate::journal::LogFilter,
+) {
+    let (entries, receiver) = match crate::journal::follow(journal, filter) {
+        Ok(followed) => followed,
+        Err(e) => {
+            let err = super::jsonrpc2::make_error(super::jsonrpc2::SERVER_ERROR, e, None);
+            let msg = super::jsonrpc2::make_error_response(id, err);
+            let response_string = serde_json::to_string_pretty(&msg).unwrap();
+            let _ = source.write_all(response_string.as_bytes());
+            return;
+        }
+    };
+    let result = Value::Array(entries.iter().map(format_log_entry).collect());
+    let msg = super::jsonrpc2::make_result_response(id, result);
+    let response_string = serde_json::to_string_pretty(&msg).unwrap();
+    if source.write_all(response_string.as_bytes()).is_err() {
+        return;
+    }
+    for entry in receiver {
+        let entry_string = serde_json::to_string_pretty(&format_log_entry(&entry)).unwrap();
+        if source.write_all(entry_string.as_bytes()).is_err() {
+            trace!("Stop following logs, the connection was closed");
+            return;
+        }
+    }
+}
+
 pub fn accept_control_connections_unix_socket(
     run_info: ArcRuntimeInfo,
     notification_socket_path: std::path::PathBuf,
     source: std::os::unix::net::UnixListener,
 ) {
-    std::thread::spawn(move || {
+    std::thread::spawn(move || loop {
         let stream = Box::new(source.accept().unwrap().0);
-        listen_on_commands(stream, run_info, notification_socket_path.clone())
+        listen_on_commands(stream, run_info.clone(), notification_socket_path.clone())
     });
 }
 
@@ -1,7 +1,7 @@
 //! This module provides the control access similar to systemctl from systemd. It uses the jsonrpc 2.0 spec and has the interface defined below.
 //!
 //! ### list-units Option<kind>
-//! Kind either "target", "socket", "service"
+//! Kind either "target", "socket", "service", "timer", "path"
 //! Give no kind to list all units of all types
 //! Lists all units. In the future there should be a filtering mechanism for type / name-matching / etc...
 //!
@@ -9,15 +9,31 @@
 //! * If the param is a string show status of the unit with that name (might get the same filtering as list-units in the future).
 //! * If no param is given, show status of all units
 //!
-//! ### restart name
-//! Restart unit with that name. If it was running first kill it. If it is already stopped start it.
-
-//! ### stop name
-//! Stop unit with that name. Will recursivly stop all units that require that unit
+//! ### start, stop, restart, reload, reload-or-restart name or params
+//! Create a job for the unit with that name and run it. Params is either the unit name or an object with these fields:
+//! * unit: the name of the unit
+//! * mode: replace (default), fail, isolate (only for start) or ignore-dependencies
+//!
+//! Start and restart pull in the units in Requires=, BindsTo= and Wants= and stop the conflicting units. Stop also stops
+//! all units that require that unit. The jobs run after the jobs of earlier requests are done. The answer contains the
+//! results of all jobs.
+//!
+//! ### list-jobs
+//! List the jobs that are waiting or running
+//!
+//! ### cancel id
+//! Cancel the waiting job with that id
 
 //! ### enable name
 //! Load new file with that name. Useful if you moved/copied a file in the unit-dirs and want to start it without restarting rustysd as a whole
 //!
+//! ### logs Option<unit or params>
+//! Show entries from the journal (only available if logging to disk is enabled). Params is either a unit name or an object with these optional fields:
+//! * unit: only entries of this unit. Rustysd's own log is stored under the name "rustysd"
+//! * since: seconds since the epoch, "YYYY-MM-DD HH:MM:SS", "YYYY-MM-DD" or relative like "-10m"
+//! * lines: only the last n entries
+//! * follow: after the response every new entry is sent as a separate json object until the connection is closed
+//!
 //! ### shutdown
 //! Shutdown rustysd by killing all services, closing all sockets and exiting
 //!
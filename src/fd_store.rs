@@ -2,6 +2,7 @@
 //! These can come from two sources:
 //! 1. Socket units. These are found with the name of their unit (eg "myservice.socket")
 //! 1. The sd_notifiy API which can ask rustysd to store some file descriptors so they stay open over restarts
+//! 1. Connections accepted by sockets with Accept=yes. These are found with the name of the service instance
 use std::{
     collections::HashMap,
     os::unix::io::{AsRawFd, RawFd},
@@ -15,6 +16,8 @@ pub struct FDStore {
     // Indexed by unit name
     global_sockets: HashMap<String, GlobalEntry>,
     service_stored_sockets: HashMap<String, HashMap<String, Vec<Box<RawFd>>>>,
+    // Indexed by the name of the service instance, holds the fd name and the connection
+    connections: HashMap<String, (String, RawFd)>,
 }
 
 impl FDStore {
@@ -77,6 +80,33 @@ impl FDStore {
             None
         }
     }
+    /// Remove all fds a service stored. Used when the service is stopped for good and not only restarted
+    pub fn remove_all_service_stored(&mut self, srvc_name: &str) -> Vec<Box<RawFd>> {
+        match self.service_stored_sockets.remove(srvc_name) {
+            Some(fds) => fds.into_values().flatten().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// How many fds a service currently has stored over all names
+    pub fn service_stored_count(&self, srvc_name: &str) -> usize {
+        match self.service_stored_sockets.get(srvc_name) {
+            Some(fds) => fds.values().map(|fds| fds.len()).sum(),
+            None => 0,
+        }
+    }
+
+    /// All fds a service stored together with their names, ready to be passed with LISTEN_FDS/LISTEN_FDNAMES
+    pub fn get_all_service_stored(&self, srvc_name: &str) -> Vec<(String, RawFd)> {
+        match self.service_stored_sockets.get(srvc_name) {
+            Some(fds) => fds
+                .iter()
+                .flat_map(|(fd_name, fds)| fds.iter().map(move |fd| (fd_name.clone(), **fd)))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
     /// normal get semantics on a hashmap
     pub fn get_service_stored(
         &self,
@@ -89,4 +119,19 @@ impl FDStore {
             None
         }
     }
+
+    /// Store the connection that is passed to the service instance created for it
+    pub fn insert_connection(&mut self, srvc_name: String, fd_name: String, fd: RawFd) {
+        self.connections.insert(srvc_name, (fd_name, fd));
+    }
+
+    /// normal get semantics on a hashmap
+    pub fn get_connection(&self, srvc_name: &str) -> Option<&(String, RawFd)> {
+        self.connections.get(srvc_name)
+    }
+
+    /// normal remove semantics on a hashmap
+    pub fn remove_connection(&mut self, srvc_name: &str) -> Option<(String, RawFd)> {
+        self.connections.remove(srvc_name)
+    }
 }
@@ -0,0 +1,467 @@
+//! A small journal that stores the output of units (and the log of rustysd itself) on disk so it can be looked at
+//! after the fact.
+//!
+//! Each unit gets its own file `<log_dir>/<unit-name>.log` with one entry per line:
+//! `<microseconds since epoch>\t<stream>\t<pid or ->\t<message>`. Backslashes, tabs and newlines in the message are escaped.
+//! If a file would grow over the size limit it is rotated to `<unit-name>.log.1`, `<unit-name>.log.2`, ... and the oldest file
+//! is dropped, so every unit only ever uses a bounded amount of disk space.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+/// Size after which a log file gets rotated
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// How many rotated files are kept per unit additionally to the current one
+pub const DEFAULT_MAX_ROTATED_FILES: usize = 4;
+/// The name under which the log of rustysd itself is stored
+pub const RUSTYSD_LOG_NAME: &str = "rustysd";
+
+/// The journal shared by all threads. None if logging to disk is disabled
+pub type ArcMutJournal = Arc<Mutex<Option<Journal>>>;
+
+/// The opened files of a unit with the size they had when they were opened
+type UnitFiles = (String, Vec<(File, u64)>);
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+    /// Messages rustysd wrote about the unit (or its own log)
+    Rustysd,
+}
+
+impl LogStream {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogStream::Stdout => "stdout",
+            LogStream::Stderr => "stderr",
+            LogStream::Rustysd => "rustysd",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "stdout" => Some(LogStream::Stdout),
+            "stderr" => Some(LogStream::Stderr),
+            "rustysd" => Some(LogStream::Rustysd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LogEntry {
+    pub unit: String,
+    /// Microseconds since the unix epoch
+    pub timestamp: u64,
+    pub stream: LogStream,
+    pub pid: Option<i32>,
+    pub message: String,
+}
+
+impl LogEntry {
+    pub fn new(
+        unit: &str,
+        stream: LogStream,
+        pid: Option<nix::unistd::Pid>,
+        message: &str,
+    ) -> Self {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0);
+        LogEntry {
+            unit: unit.to_owned(),
+            timestamp,
+            stream,
+            pid: pid.map(|pid| pid.as_raw()),
+            message: message.to_owned(),
+        }
+    }
+
+    pub fn to_line(&self) -> String {
+        let pid = match self.pid {
+            Some(pid) => pid.to_string(),
+            None => "-".to_owned(),
+        };
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            self.timestamp,
+            self.stream.as_str(),
+            pid,
+            escape(&self.message)
+        )
+    }
+
+    pub fn from_line(unit: &str, line: &str) -> Option<Self> {
+        let mut parts = line.splitn(4, '\t');
+        let timestamp = parts.next()?.parse::<u64>().ok()?;
+        let stream = LogStream::from_str(parts.next()?)?;
+        let pid = match parts.next()? {
+            "-" => None,
+            pid => Some(pid.parse::<i32>().ok()?),
+        };
+        let message = unescape(parts.next()?);
+        Some(LogEntry {
+            unit: unit.to_owned(),
+            timestamp,
+            stream,
+            pid,
+            message,
+        })
+    }
+
+    /// Format the timestamp in the local timezone like the rest of the logging does
+    pub fn format_time(&self) -> String {
+        use chrono::TimeZone;
+        let secs = (self.timestamp / 1_000_000) as i64;
+        let nanos = ((self.timestamp % 1_000_000) * 1000) as u32;
+        chrono::Local
+            .timestamp(secs, nanos)
+            .format("%Y-%m-%d %H:%M:%S%.6f")
+            .to_string()
+    }
+}
+
+fn escape(msg: &str) -> String {
+    msg.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(msg: &str) -> String {
+    let mut unescaped = String::with_capacity(msg.len());
+    let mut chars = msg.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('t') => unescaped.push('\t'),
+                Some('n') => unescaped.push('\n'),
+                Some(other) => unescaped.push(other),
+                None => unescaped.push('\\'),
+            }
+        } else {
+            unescaped.push(c);
+        }
+    }
+    unescaped
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    /// Only entries of this unit
+    pub unit: Option<String>,
+    /// Only entries with a timestamp at or after this (microseconds since the unix epoch)
+    pub since: Option<u64>,
+    /// Only the last n entries
+    pub lines: Option<usize>,
+    /// Keep sending new entries after the existing ones
+    pub follow: bool,
+}
+
+impl LogFilter {
+    fn matches(&self, entry: &LogEntry) -> bool {
+        if let Some(unit) = &self.unit {
+            if *unit != entry.unit {
+                return false;
+            }
+        }
+        if let Some(since) = self.since {
+            if entry.timestamp < since {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Parse the argument of --since. Accepted are seconds since the unix epoch, "YYYY-MM-DD HH:MM:SS", "YYYY-MM-DD"
+/// (both in local time) and relative times like "-10m" (units: s, m, h, d)
+pub fn parse_since(since: &str) -> Result<u64, String> {
+    use chrono::TimeZone;
+    let since = since.trim();
+    if let Ok(secs) = since.parse::<u64>() {
+        return Ok(secs * 1_000_000);
+    }
+    if since.starts_with('-') && since.len() > 2 {
+        let unit = since.chars().last().unwrap();
+        let number = &since[1..since.len() - unit.len_utf8()];
+        let multiplier = match unit {
+            's' => 1,
+            'm' => 60,
+            'h' => 60 * 60,
+            'd' => 24 * 60 * 60,
+            _ => return Err(format!("Unknown time unit in: {}", since)),
+        };
+        let number = number
+            .parse::<u64>()
+            .map_err(|_| format!("Invalid relative time: {}", since))?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_micros() as u64)
+            .unwrap_or(0);
+        return Ok(now.saturating_sub(number * multiplier * 1_000_000));
+    }
+    let naive = chrono::NaiveDateTime::parse_from_str(since, "%Y-%m-%d %H:%M:%S").or_else(|_| {
+        chrono::NaiveDate::parse_from_str(since, "%Y-%m-%d").map(|date| date.and_hms(0, 0, 0))
+    });
+    match naive {
+        Ok(naive) => match chrono::Local.from_local_datetime(&naive).single() {
+            Some(time) if time.timestamp() >= 0 => Ok(time.timestamp() as u64 * 1_000_000),
+            _ => Err(format!("Invalid time: {}", since)),
+        },
+        Err(_) => Err(format!("Invalid time: {}", since)),
+    }
+}
+
+struct Follower {
+    unit: Option<String>,
+    sender: Sender<LogEntry>,
+}
+
+pub struct Journal {
+    dir: PathBuf,
+    max_file_size: u64,
+    max_rotated_files: usize,
+    /// Open files with their current size
+    files: HashMap<String, (File, u64)>,
+    followers: Vec<Follower>,
+}
+
+impl Journal {
+    pub fn new(dir: &Path, max_file_size: u64, max_rotated_files: usize) -> Result<Self, String> {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Could not create log dir {:?}: {}", dir, e))?;
+        Ok(Journal {
+            dir: dir.to_owned(),
+            max_file_size,
+            max_rotated_files,
+            files: HashMap::new(),
+            followers: Vec::new(),
+        })
+    }
+
+    fn file_path(&self, unit: &str, rotation: usize) -> PathBuf {
+        // unit names never contain a '/' but better be safe than writing somewhere else
+        let unit = unit.replace('/', "_");
+        if rotation == 0 {
+            self.dir.join(format!("{}.log", unit))
+        } else {
+            self.dir.join(format!("{}.log.{}", unit, rotation))
+        }
+    }
+
+    fn rotate(&mut self, unit: &str) -> Result<(), String> {
+        self.files.remove(unit);
+        let oldest = self.file_path(unit, self.max_rotated_files);
+        if oldest.exists() {
+            std::fs::remove_file(&oldest)
+                .map_err(|e| format!("Could not remove old log file {:?}: {}", oldest, e))?;
+        }
+        for rotation in (0..self.max_rotated_files).rev() {
+            let from = self.file_path(unit, rotation);
+            if from.exists() {
+                let to = self.file_path(unit, rotation + 1);
+                std::fs::rename(&from, &to)
+                    .map_err(|e| format!("Could not rotate log file {:?}: {}", from, e))?;
+            }
+        }
+        if self.max_rotated_files == 0 {
+            let current = self.file_path(unit, 0);
+            if current.exists() {
+                std::fs::remove_file(&current)
+                    .map_err(|e| format!("Could not remove log file {:?}: {}", current, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn append(&mut self, entry: &LogEntry) -> Result<(), String> {
+        let line = entry.to_line();
+        let needs_rotation = match self.files.get(&entry.unit) {
+            Some((_, size)) => *size > 0 && *size + line.len() as u64 > self.max_file_size,
+            None => {
+                let size = std::fs::metadata(self.file_path(&entry.unit, 0))
+                    .map(|meta| meta.len())
+                    .unwrap_or(0);
+                size > 0 && size + line.len() as u64 > self.max_file_size
+            }
+        };
+        if needs_rotation {
+            self.rotate(&entry.unit)?;
+        }
+        if !self.files.contains_key(&entry.unit) {
+            let path = self.file_path(&entry.unit, 0);
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("Could not open log file {:?}: {}", path, e))?;
+            let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+            self.files.insert(entry.unit.clone(), (file, size));
+        }
+        let (file, size) = self.files.get_mut(&entry.unit).unwrap();
+        file.write_all(line.as_bytes())
+            .map_err(|e| format!("Could not write log entry for {}: {}", entry.unit, e))?;
+        *size += line.len() as u64;
+
+        // drop followers that went away
+        self.followers.retain(|follower| match &follower.unit {
+            Some(unit) if *unit != entry.unit => true,
+            _ => follower.sender.send(entry.clone()).is_ok(),
+        });
+        Ok(())
+    }
+
+    /// All units that have entries in the journal
+    pub fn units(&self) -> Result<Vec<String>, String> {
+        let dir = std::fs::read_dir(&self.dir)
+            .map_err(|e| format!("Could not read log dir {:?}: {}", self.dir, e))?;
+        let mut units = Vec::new();
+        for entry in dir {
+            let entry =
+                entry.map_err(|e| format!("Could not read log dir {:?}: {}", self.dir, e))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let unit = if name.ends_with(".log") {
+                &name[..name.len() - ".log".len()]
+            } else {
+                match name.rfind(".log.") {
+                    Some(pos) if name[pos + ".log.".len()..].parse::<usize>().is_ok() => {
+                        &name[..pos]
+                    }
+                    _ => continue,
+                }
+            };
+            if !units.iter().any(|known| known == unit) {
+                units.push(unit.to_owned());
+            }
+        }
+        Ok(units)
+    }
+
+    /// Open the files of all units matching the filter, oldest first, together with their current sizes. Reading them
+    /// does not need the journal anymore: rotating only renames or removes the files and appended entries are cut off.
+    fn snapshot(&self, filter: &LogFilter) -> Result<Vec<UnitFiles>, String> {
+        let units = match &filter.unit {
+            Some(unit) => vec![unit.clone()],
+            None => self.units()?,
+        };
+        let mut snapshot = Vec::new();
+        for unit in units {
+            let mut files = Vec::new();
+            for rotation in (0..=self.max_rotated_files).rev() {
+                let path = self.file_path(&unit, rotation);
+                let file = match File::open(&path) {
+                    Ok(file) => file,
+                    Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(format!("Could not open log file {:?}: {}", path, e)),
+                };
+                let size = file
+                    .metadata()
+                    .map_err(|e| format!("Could not read log file {:?}: {}", path, e))?
+                    .len();
+                files.push((file, size));
+            }
+            snapshot.push((unit, files));
+        }
+        Ok(snapshot)
+    }
+
+    /// Read all entries matching the filter, ordered by their timestamps
+    pub fn query(&self, filter: &LogFilter) -> Result<Vec<LogEntry>, String> {
+        read_snapshot(self.snapshot(filter)?, filter)
+    }
+
+    /// Get a receiver for all entries that are appended from now on and match the unit filter
+    pub fn subscribe(&mut self, unit: Option<String>) -> Receiver<LogEntry> {
+        let (sender, receiver) = channel();
+        self.followers.push(Follower { unit, sender });
+        receiver
+    }
+}
+
+fn read_snapshot(snapshot: Vec<UnitFiles>, filter: &LogFilter) -> Result<Vec<LogEntry>, String> {
+    let mut entries = Vec::new();
+    for (unit, files) in snapshot {
+        for (file, size) in files {
+            let mut content = Vec::new();
+            file.take(size)
+                .read_to_end(&mut content)
+                .map_err(|e| format!("Could not read log file of {}: {}", unit, e))?;
+            let content = String::from_utf8_lossy(&content);
+            entries.extend(
+                content
+                    .lines()
+                    .filter_map(|line| LogEntry::from_line(&unit, line))
+                    .filter(|entry| filter.matches(entry)),
+            );
+        }
+    }
+    // stable sort, so entries with the same timestamp keep their order
+    entries.sort_by_key(|entry| entry.timestamp);
+    if let Some(lines) = filter.lines {
+        if entries.len() > lines {
+            entries.drain(..entries.len() - lines);
+        }
+    }
+    Ok(entries)
+}
+
+/// Create the journal for the runtime info. Nothing is logged to disk if there is no log dir.
+pub fn init(dir: Option<&Path>) -> Result<ArcMutJournal, String> {
+    let journal = match dir {
+        Some(dir) => Some(Journal::new(
+            dir,
+            DEFAULT_MAX_FILE_SIZE,
+            DEFAULT_MAX_ROTATED_FILES,
+        )?),
+        None => None,
+    };
+    Ok(Arc::new(Mutex::new(journal)))
+}
+
+/// Write an entry for this unit into the journal (if it is enabled).
+///
+/// This must not use the log macros because the rustysd log itself is written into the journal.
+pub fn log(
+    journal: &ArcMutJournal,
+    unit: &str,
+    stream: LogStream,
+    pid: Option<nix::unistd::Pid>,
+    message: &str,
+) {
+    if let Some(journal) = &mut *journal.lock().unwrap() {
+        if let Err(e) = journal.append(&LogEntry::new(unit, stream, pid, message)) {
+            eprintln!("Could not write to the journal: {}", e);
+        }
+    }
+}
+
+/// Like Journal::query but the files are only opened while the journal is locked, reading them does not block logging
+pub fn query(journal: &ArcMutJournal, filter: &LogFilter) -> Result<Vec<LogEntry>, String> {
+    let snapshot = match &*journal.lock().unwrap() {
+        Some(journal) => journal.snapshot(filter)?,
+        None => return Err("Logging to disk is not enabled".into()),
+    };
+    read_snapshot(snapshot, filter)
+}
+
+/// Like query but also returns a receiver for all new matching entries. The files are opened and the receiver is
+/// registered while the journal is locked so no entry is missed or seen twice.
+pub fn follow(
+    journal: &ArcMutJournal,
+    filter: &LogFilter,
+) -> Result<(Vec<LogEntry>, Receiver<LogEntry>), String> {
+    let (snapshot, receiver) = match &mut *journal.lock().unwrap() {
+        Some(journal) => (
+            journal.snapshot(filter)?,
+            journal.subscribe(filter.unit.clone()),
+        ),
+        None => return Err("Logging to disk is not enabled".into()),
+    };
+    Ok((read_snapshot(snapshot, filter)?, receiver))
+}
@@ -13,26 +13,31 @@
 //! 1. Startup sorted by dependencies (parallel if possible for unrelated services)
 //! 1. Socket activation of services
 //! 1. Kill services that have dependencies on failed services
+//! 1. Timer activation of units
+//! 1. Path activation of units (with inotify, so only on linux)
 //!
 //! What is explicitly out of scope (for now, this project is still very young):
-//! 1. Timers
 //! 1. Mounts
 //! 1. Device
-//! 1. Path activation
 //! 1. Scopes
 //! 1. Slices (this might be added as it is fairly important if you are not running inside of a container)
 pub mod config;
 pub mod control;
 pub mod dbus_wait;
 pub mod fd_store;
+pub mod journal;
+pub mod log_sink;
 pub mod logging;
 pub mod notification_handler;
+pub mod path_activation;
 pub mod platform;
 pub mod services;
 pub mod signal_handler;
 pub mod socket_activation;
 pub mod sockets;
+pub mod timers;
 pub mod units;
+pub mod watchdog;
 
 #[macro_use]
 extern crate log;
@@ -0,0 +1,253 @@
+//! Forward the output of services to an external log collector. Supported are RFC 5424 syslog messages sent to a unix
+//! datagram socket or over UDP and the native journald protocol.
+//!
+//! The sink is configured with the `log_sink` setting:
+//! * `syslog:unix:/dev/log`
+//! * `syslog:udp:127.0.0.1:514`
+//! * `journald` or `journald:/path/to/socket` (defaults to /run/systemd/journal/socket)
+
+use crate::services::Service;
+use std::net::{SocketAddr, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+
+const SEVERITY_NOTICE: u8 = 5;
+const SEVERITY_INFO: u8 = 6;
+
+/// The sink shared by all threads. None if nothing is forwarded
+pub type ArcLogSink = Arc<Option<Sink>>;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SinkTarget {
+    SyslogUnix(PathBuf),
+    SyslogUdp(SocketAddr),
+    Journald(PathBuf),
+}
+
+pub fn parse_sink_target(target: &str) -> Result<SinkTarget, String> {
+    if target == "journald" {
+        return Ok(SinkTarget::Journald(PathBuf::from(JOURNALD_SOCKET)));
+    }
+    if let Some(path) = target.strip_prefix("journald:") {
+        return Ok(SinkTarget::Journald(PathBuf::from(path)));
+    }
+    if let Some(path) = target.strip_prefix("syslog:unix:") {
+        return Ok(SinkTarget::SyslogUnix(PathBuf::from(path)));
+    }
+    if let Some(addr) = target.strip_prefix("syslog:udp:") {
+        let addr = addr
+            .parse()
+            .map_err(|e| format!("Invalid address for the log sink: {}: {}", addr, e))?;
+        return Ok(SinkTarget::SyslogUdp(addr));
+    }
+    Err(format!("Unknown log sink: {}", target))
+}
+
+/// Where a forwarded message came from
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RecordSource {
+    Stdout,
+    Stderr,
+    /// STATUS= sent over the notification socket
+    Status,
+}
+
+impl RecordSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RecordSource::Stdout => "stdout",
+            RecordSource::Stderr => "stderr",
+            RecordSource::Status => "status",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Record<'a> {
+    pub unit: &'a str,
+    pub identifier: &'a str,
+    pub facility: u8,
+    pub severity: u8,
+    pub pid: Option<i32>,
+    pub source: RecordSource,
+    pub message: &'a str,
+}
+
+/// Format as RFC 5424 message. The unit and the source are put into the structured data.
+pub fn format_syslog(record: &Record, hostname: &str, timestamp: &str) -> String {
+    let pri = u32::from(record.facility) * 8 + u32::from(record.severity);
+    let procid = match record.pid {
+        Some(pid) => pid.to_string(),
+        None => "-".to_owned(),
+    };
+    format!(
+        "<{}>1 {} {} {} {} - [rustysd@32473 unit=\"{}\" stream=\"{}\"] {}",
+        pri,
+        timestamp,
+        syslog_header_field(hostname, 255),
+        syslog_header_field(record.identifier, 48),
+        procid,
+        escape_sd_param(record.unit),
+        record.source.as_str(),
+        record.message
+    )
+}
+
+/// Header fields must be printable ascii without spaces and are limited in length
+fn syslog_header_field(value: &str, max_len: usize) -> String {
+    let field: String = value
+        .chars()
+        .filter(|c| c.is_ascii_graphic())
+        .take(max_len)
+        .collect();
+    if field.is_empty() {
+        "-".to_owned()
+    } else {
+        field
+    }
+}
+
+fn escape_sd_param(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace(']', "\\]")
+}
+
+/// Format for the native journald protocol. Values containing a newline use the binary encoding with an explicit length.
+pub fn format_journald(record: &Record) -> Vec<u8> {
+    let mut fields = vec![
+        ("MESSAGE", record.message.to_owned()),
+        ("PRIORITY", record.severity.to_string()),
+        ("SYSLOG_FACILITY", record.facility.to_string()),
+        ("SYSLOG_IDENTIFIER", record.identifier.to_owned()),
+        ("UNIT", record.unit.to_owned()),
+        ("RUSTYSD_STREAM", record.source.as_str().to_owned()),
+    ];
+    if let Some(pid) = record.pid {
+        fields.push(("SYSLOG_PID", pid.to_string()));
+    }
+
+    let mut datagram = Vec::new();
+    for (name, value) in fields {
+        datagram.extend(name.as_bytes());
+        if value.contains('\n') {
+            datagram.push(b'\n');
+            datagram.extend(&(value.len() as u64).to_le_bytes());
+            datagram.extend(value.as_bytes());
+        } else {
+            datagram.push(b'=');
+            datagram.extend(value.as_bytes());
+        }
+        datagram.push(b'\n');
+    }
+    datagram
+}
+
+enum SinkSocket {
+    Unix(UnixDatagram, PathBuf),
+    Udp(UdpSocket, SocketAddr),
+}
+
+pub struct Sink {
+    target: SinkTarget,
+    socket: SinkSocket,
+    hostname: String,
+}
+
+impl Sink {
+    pub fn new(target: SinkTarget) -> Result<Self, String> {
+        let socket = match &target {
+            SinkTarget::SyslogUnix(path) | SinkTarget::Journald(path) => SinkSocket::Unix(
+                UnixDatagram::unbound()
+                    .map_err(|e| format!("Could not create socket for the log sink: {}", e))?,
+                path.clone(),
+            ),
+            SinkTarget::SyslogUdp(addr) => {
+                let bind_addr = if addr.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                SinkSocket::Udp(
+                    UdpSocket::bind(bind_addr)
+                        .map_err(|e| format!("Could not create socket for the log sink: {}", e))?,
+                    *addr,
+                )
+            }
+        };
+        let mut buf = [0u8; 256];
+        let hostname = nix::unistd::gethostname(&mut buf)
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(Sink {
+            target,
+            socket,
+            hostname,
+        })
+    }
+
+    pub fn send(&self, record: &Record) -> std::io::Result<()> {
+        let datagram = match self.target {
+            SinkTarget::Journald(_) => format_journald(record),
+            SinkTarget::SyslogUnix(_) | SinkTarget::SyslogUdp(_) => {
+                let timestamp = chrono::Local::now().format("%Y-%m-%dT%H:%M:%S%.6f%:z");
+                format_syslog(record, &self.hostname, &timestamp.to_string()).into_bytes()
+            }
+        };
+        match &self.socket {
+            SinkSocket::Unix(socket, path) => socket.send_to(&datagram, path).map(|_| ()),
+            SinkSocket::Udp(socket, addr) => socket.send_to(&datagram, addr).map(|_| ()),
+        }
+    }
+}
+
+/// Create the sink for the runtime info. Nothing is forwarded if there is no target.
+pub fn init(target: Option<&str>) -> Result<ArcLogSink, String> {
+    let sink = match target {
+        Some(target) => Some(Sink::new(parse_sink_target(target)?)?),
+        None => None,
+    };
+    Ok(Arc::new(sink))
+}
+
+/// Forward a line of output (or a status message) of this service to the sink (if there is one)
+pub fn forward(sink: &ArcLogSink, srvc: &Service, unit: &str, source: RecordSource, message: &str) {
+    let sink = match &**sink {
+        Some(sink) => sink,
+        None => return,
+    };
+    let exec_config = &srvc.service_config.exec_config;
+    let identifier = match &exec_config.syslog_identifier {
+        Some(identifier) => identifier.as_str(),
+        None => srvc
+            .service_config
+            .exec
+            .first()
+            .map(|cmd| cmd.cmd.rsplit('/').next().unwrap_or(&cmd.cmd))
+            .unwrap_or(unit),
+    };
+    let severity = match source {
+        RecordSource::Stdout | RecordSource::Stderr => SEVERITY_INFO,
+        RecordSource::Status => SEVERITY_NOTICE,
+    };
+    let record = Record {
+        unit,
+        identifier,
+        facility: exec_config.syslog_facility,
+        severity,
+        pid: srvc.pid.map(|pid| pid.as_raw()),
+        source,
+        message,
+    };
+    if let Err(e) = sink.send(&record) {
+        trace!(
+            "Could not forward output of {} to the log sink: {}",
+            unit,
+            e
+        );
+    }
+}
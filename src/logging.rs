@@ -1,25 +1,56 @@
-pub fn setup_logging(conf: &crate::config::LoggingConfig) -> Result<(), String> {
-    let mut logger = fern::Dispatch::new()
-        .format(|out, message, record| {
-            out.finish(format_args!(
-                "{}[{}][{}] {}",
-                chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
-                record.target(),
-                record.level(),
-                message
-            ))
-        })
-        .level(log::LevelFilter::Trace);
+/// Setup the log of rustysd. Returns the journal and the log sink for the runtime info.
+pub fn setup_logging(
+    conf: &crate::config::LoggingConfig,
+) -> Result<(crate::journal::ArcMutJournal, crate::log_sink::ArcLogSink), String> {
+    let journal = crate::journal::init(if conf.log_to_disk {
+        Some(conf.log_dir.as_path())
+    } else {
+        None
+    })?;
+    let log_sink = crate::log_sink::init(conf.sink.as_deref())?;
+
+    let mut logger = fern::Dispatch::new().level(log::LevelFilter::Trace);
 
     if conf.log_to_stdout {
-        logger = logger.chain(std::io::stdout());
+        let stdout_logger = fern::Dispatch::new()
+            .format(|out, message, record| {
+                out.finish(format_args!(
+                    "{}[{}][{}] {}",
+                    chrono::Local::now().format("[%Y-%m-%d][%H:%M:%S]"),
+                    record.target(),
+                    record.level(),
+                    message
+                ))
+            })
+            .chain(std::io::stdout());
+        logger = logger.chain(stdout_logger);
     }
 
     if conf.log_to_disk {
-        unimplemented!("Logging to disk is currently not supported. Pipe the stdout logs to your preferred logging solution");
+        // the journal has its own timestamps. Trace logging is too noisy to be kept around on disk.
+        let journal = journal.clone();
+        let journal_logger =
+            fern::Dispatch::new()
+                .level(log::LevelFilter::Info)
+                .chain(fern::Output::call(move |record| {
+                    crate::journal::log(
+                        &journal,
+                        crate::journal::RUSTYSD_LOG_NAME,
+                        crate::journal::LogStream::Rustysd,
+                        Some(nix::unistd::getpid()),
+                        &format!(
+                            "[{}][{}] {}",
+                            record.target(),
+                            record.level(),
+                            record.args()
+                        ),
+                    );
+                }));
+        logger = logger.chain(journal_logger);
     }
 
     logger
         .apply()
-        .map_err(|e| format!("Error while stting up logger: {}", e))
+        .map_err(|e| format!("Error while stting up logger: {}", e))?;
+    Ok((journal, log_sink))
 }
@@ -1,12 +1,18 @@
 //! collect the different streams from the services
-//! Stdout and stderr get redirected to the normal stdout/err but are prefixed with a unique string to identify their output
+//! Stdout and stderr get redirected to the normal stdout/err but are prefixed with a unique string to identify their output.
+//! They are also written to the journal if logging to disk is enabled and forwarded to the log sink if one is configured.
 //! streams from the notification sockets get parsed and applied to the respective service
 
 use crate::platform::reset_event_fd;
 use crate::platform::EventFd;
 use crate::services::Service;
 use crate::units::*;
-use std::{collections::HashMap, io::Write, os::unix::io::AsRawFd};
+use std::{
+    collections::HashMap,
+    io::Write,
+    os::unix::io::{AsRawFd, RawFd},
+    os::unix::net::UnixDatagram,
+};
 
 fn collect_from_srvc<F>(unit_table: ArcMutUnitTable, f: F) -> HashMap<i32, UnitId>
 where
@@ -25,7 +31,8 @@ where
         })
 }
 
-pub fn handle_all_streams(eventfd: EventFd, unit_table: ArcMutUnitTable) {
+pub fn handle_all_streams(eventfd: EventFd, run_info: ArcRuntimeInfo) {
+    let unit_table = run_info.unit_table.clone();
     loop {
         // need to collect all again. There might be a newly started service
         let fd_to_srvc_id = collect_from_srvc(unit_table.clone(), |map, srvc, id| {
@@ -71,14 +78,14 @@ pub fn handle_all_streams(eventfd: EventFd, unit_table: ArcMutUnitTable) {
                                         nix::fcntl::FcntlArg::F_SETFL(new_flags),
                                     )
                                     .unwrap();
-                                    let bytes = {
+                                    let (bytes, fds, sender) = {
                                         let socket_locked = socket.lock().unwrap();
-                                        match socket_locked.recv(&mut buf[..]) {
-                                            Ok(b) => b,
-                                            Err(e) => match e.kind() {
-                                                std::io::ErrorKind::WouldBlock => 0,
-                                                _ => panic!("{}", e),
-                                            },
+                                        match recv_notification(&socket_locked, &mut buf[..]) {
+                                            Ok(received) => received,
+                                            Err(nix::Error::Sys(nix::errno::Errno::EAGAIN)) => {
+                                                (0, Vec::new(), None)
+                                            }
+                                            Err(e) => panic!("{}", e),
                                         }
                                     };
                                     nix::fcntl::fcntl(
@@ -86,13 +93,34 @@ pub fn handle_all_streams(eventfd: EventFd, unit_table: ArcMutUnitTable) {
                                         nix::fcntl::FcntlArg::F_SETFL(old_flags),
                                     )
                                     .unwrap();
-                                    let note_str =
-                                        String::from_utf8(buf[..bytes].to_vec()).unwrap();
-                                    srvc.notifications_buffer.push_str(&note_str);
-                                    crate::notification_handler::handle_notifications_from_buffer(
+                                    if bytes > 0
+                                        && !notify_access_allowed(
+                                            srvc,
+                                            *id,
+                                            &srvc_unit_locked.conf.name(),
+                                            sender,
+                                            &run_info.pid_table,
+                                        )
+                                    {
+                                        close_received_fds(fds);
+                                        continue;
+                                    }
+                                    let note_str = String::from_utf8_lossy(&buf[..bytes]);
+                                    let new_status = handle_notification_datagram(
+                                        &note_str,
+                                        fds,
                                         srvc,
+                                        *id,
                                         &srvc_unit_locked.conf.name(),
+                                        &run_info,
                                     );
+                                    if let Some(new_status) = new_status {
+                                        apply_notified_status(
+                                            *id,
+                                            new_status,
+                                            &run_info.status_table,
+                                        );
+                                    }
                                 }
                             }
                         }
@@ -106,7 +134,8 @@ pub fn handle_all_streams(eventfd: EventFd, unit_table: ArcMutUnitTable) {
     }
 }
 
-pub fn handle_all_std_out(eventfd: EventFd, unit_table: ArcMutUnitTable) {
+pub fn handle_all_std_out(eventfd: EventFd, run_info: ArcRuntimeInfo) {
+    let unit_table = run_info.unit_table.clone();
     loop {
         // need to collect all again. There might be a newly started service
         let fd_to_srvc_id = collect_from_srvc(unit_table.clone(), |map, srvc, id| {
@@ -188,6 +217,21 @@ pub fn handle_all_std_out(eventfd: EventFd, unit_table: ArcMutUnitTable) {
                                     outbuf.extend(line);
                                     outbuf.push(b'\n');
                                     std::io::stdout().write_all(&outbuf).unwrap();
+                                    let line = String::from_utf8_lossy(line);
+                                    crate::journal::log(
+                                        &run_info.journal,
+                                        &name,
+                                        crate::journal::LogStream::Stdout,
+                                        srvc.pid,
+                                        &line,
+                                    );
+                                    crate::log_sink::forward(
+                                        &run_info.log_sink,
+                                        srvc,
+                                        &name,
+                                        crate::log_sink::RecordSource::Stdout,
+                                        &line,
+                                    );
                                 }
                             }
                         }
@@ -201,7 +245,8 @@ pub fn handle_all_std_out(eventfd: EventFd, unit_table: ArcMutUnitTable) {
     }
 }
 
-pub fn handle_all_std_err(eventfd: EventFd, unit_table: ArcMutUnitTable) {
+pub fn handle_all_std_err(eventfd: EventFd, run_info: ArcRuntimeInfo) {
+    let unit_table = run_info.unit_table.clone();
     loop {
         // need to collect all again. There might be a newly started service
         let fd_to_srvc_id = collect_from_srvc(unit_table.clone(), |map, srvc, id| {
@@ -283,6 +328,21 @@ pub fn handle_all_std_err(eventfd: EventFd, unit_table: ArcMutUnitTable) {
                                     outbuf.extend(line);
                                     outbuf.push(b'\n');
                                     std::io::stderr().write_all(&outbuf).unwrap();
+                                    let line = String::from_utf8_lossy(line);
+                                    crate::journal::log(
+                                        &run_info.journal,
+                                        &name,
+                                        crate::journal::LogStream::Stderr,
+                                        srvc.pid,
+                                        &line,
+                                    );
+                                    crate::log_sink::forward(
+                                        &run_info.log_sink,
+                                        srvc,
+                                        &name,
+                                        crate::log_sink::RecordSource::Stderr,
+                                        &line,
+                                    );
                                 }
                             }
                         }
@@ -296,11 +356,28 @@ pub fn handle_all_std_err(eventfd: EventFd, unit_table: ArcMutUnitTable) {
     }
 }
 
-pub fn handle_notification_message(msg: &str, srvc: &mut Service, name: &str) {
-    let split: Vec<_> = msg.split('=').collect();
+/// Apply a single notification to the service. If the service announced a change of it's status the new status is returned.
+/// The caller should only apply it if the service is currently running, starting and stopping is managed by rustysd itself.
+pub fn handle_notification_message(
+    msg: &str,
+    srvc: &mut Service,
+    id: UnitId,
+    name: &str,
+    pid_table: &ArcMutPidTable,
+    log_sink: &crate::log_sink::ArcLogSink,
+) -> Option<UnitStatus> {
+    let split: Vec<_> = msg.splitn(2, '=').collect();
+    let value = split.get(1).copied().unwrap_or("");
     match split[0] {
         "STATUS" => {
-            srvc.status_msgs.push(split[1].to_owned());
+            srvc.status_msgs.push(value.to_owned());
+            crate::log_sink::forward(
+                log_sink,
+                srvc,
+                name,
+                crate::log_sink::RecordSource::Status,
+                value,
+            );
             trace!(
                 "New status message pushed from service {}: {}",
                 name,
@@ -309,21 +386,271 @@ pub fn handle_notification_message(msg: &str, srvc: &mut Service, name: &str) {
         }
         "READY" => {
             srvc.signaled_ready = true;
+            return Some(UnitStatus::Started);
+        }
+        "RELOADING" => {
+            trace!("Service {} is reloading", name);
+            return Some(UnitStatus::Reloading);
+        }
+        "STOPPING" => {
+            trace!("Service {} is stopping", name);
+            return Some(UnitStatus::StoppingGracefully);
+        }
+        "MAINPID" => match value.parse::<i32>() {
+            Ok(raw_pid) if raw_pid > 0 => {
+                let new_pid = nix::unistd::Pid::from_raw(raw_pid);
+                if srvc.pid != Some(new_pid) {
+                    trace!("Service {} changed it's main pid to {}", name, new_pid);
+                    let mut pid_table_locked = pid_table.lock().unwrap();
+                    if let Some(old_pid) = srvc.pid {
+                        // If the old main process exits it is now treated like any other orphan
+                        pid_table_locked.remove(&old_pid);
+                    }
+                    pid_table_locked.insert(
+                        new_pid,
+                        PidEntry::Service(id, srvc.service_config.srcv_type),
+                    );
+                    srvc.pid = Some(new_pid);
+                }
+            }
+            _ => {
+                warn!("Service {} sent an invalid MAINPID: {}", name, value);
+            }
+        },
+        "FDSTORE" => {
+            srvc.fd_store_request.store = value == "1";
+        }
+        "FDSTOREREMOVE" => {
+            srvc.fd_store_request.remove = value == "1";
+        }
+        "FDNAME" => {
+            srvc.fd_store_request.name = Some(value.to_owned());
         }
+        "FDPOLL" => {
+            // we never poll the stored fds so there is nothing to disable
+        }
+        "ERRNO" => match value.parse::<i32>() {
+            Ok(errno) => {
+                srvc.notified_errno = Some(errno);
+                trace!(
+                    "Service {} reported {}",
+                    name,
+                    srvc.notified_errno_description().unwrap()
+                );
+            }
+            Err(_) => {
+                warn!("Service {} sent an invalid ERRNO: {}", name, value);
+            }
+        },
+        "WATCHDOG" => match value {
+            "1" => {
+                if srvc.watchdog_timeout.is_some() && !srvc.watchdog_triggered {
+                    srvc.watchdog_last_ping = Some(std::time::Instant::now());
+                }
+            }
+            "trigger" => {
+                warn!("Service {} triggered it's own watchdog", name);
+                srvc.trigger_watchdog(name);
+            }
+            value => {
+                warn!(
+                    "Unknown value for WATCHDOG from service {}: {}",
+                    name, value
+                );
+            }
+        },
+        "WATCHDOG_USEC" => match value.parse::<u64>() {
+            Ok(usec) => {
+                trace!("Service {} set it's watchdog timeout to {}us", name, usec);
+                srvc.watchdog_timeout = Some(std::time::Duration::from_micros(usec));
+                srvc.watchdog_last_ping = Some(std::time::Instant::now());
+            }
+            Err(_) => {
+                warn!(
+                    "Service {} sent an invalid value for WATCHDOG_USEC: {}",
+                    name, value
+                );
+            }
+        },
         _ => {
             warn!("Unknown notification name{}", split[0]);
         }
     }
+    None
+}
+
+/// Every datagram is one complete message. All assignments in it are applied before the fd store request they make is
+/// applied to the fds that came with it. Returns the last status change the service announced in the message.
+pub fn handle_notification_datagram(
+    msg: &str,
+    fds: Vec<RawFd>,
+    srvc: &mut Service,
+    id: UnitId,
+    name: &str,
+    run_info: &RuntimeInfo,
+) -> Option<UnitStatus> {
+    let mut new_status = None;
+    for line in msg.split('\n').filter(|line| !line.is_empty()) {
+        if let Some(status) = handle_notification_message(
+            line,
+            srvc,
+            id,
+            name,
+            &run_info.pid_table,
+            &run_info.log_sink,
+        ) {
+            new_status = Some(status);
+        }
+    }
+    handle_fd_store_request(srvc, name, fds, &run_info.fd_store);
+    new_status
+}
+
+/// Receive one notification message together with all fds that were sent along with it via SCM_RIGHTS and the pid of
+/// the sender if the platform provides it (SCM_CREDENTIALS on linux)
+pub fn recv_notification(
+    stream: &UnixDatagram,
+    buf: &mut [u8],
+) -> nix::Result<(usize, Vec<RawFd>, Option<nix::unistd::Pid>)> {
+    let iov = [nix::sys::uio::IoVec::from_mut_slice(buf)];
+    // 253 is the most fds the kernel allows in one message (SCM_MAX_FD)
+    #[cfg(target_os = "linux")]
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 253], libc::ucred);
+    #[cfg(not(target_os = "linux"))]
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 253]);
+    let msg = nix::sys::socket::recvmsg(
+        stream.as_raw_fd(),
+        &iov,
+        Some(&mut cmsg_buf),
+        // the fds must not leak into services that get started later
+        nix::sys::socket::MsgFlags::MSG_CMSG_CLOEXEC,
+    )?;
+    let mut fds = Vec::new();
+    let mut sender = None;
+    for cmsg in msg.cmsgs() {
+        match cmsg {
+            nix::sys::socket::ControlMessageOwned::ScmRights(received) => fds.extend(received),
+            #[cfg(target_os = "linux")]
+            nix::sys::socket::ControlMessageOwned::ScmCredentials(cred) => {
+                sender = Some(nix::unistd::Pid::from_raw(cred.pid));
+            }
+            _ => {}
+        }
+    }
+    Ok((msg.bytes, fds, sender))
+}
+
+/// Check NotifyAccess= of the service for the sender of a notification. If the platform does not tell who sent the
+/// message only NotifyAccess=none is enforced.
+pub fn notify_access_allowed(
+    srvc: &Service,
+    id: UnitId,
+    name: &str,
+    sender: Option<nix::unistd::Pid>,
+    pid_table: &ArcMutPidTable,
+) -> bool {
+    let access = srvc.service_config.notifyaccess;
+    let sender = match sender {
+        Some(sender) => sender,
+        None => return access != NotifyKind::None,
+    };
+    let is_main = srvc.pid == Some(sender);
+    let is_exec = || match pid_table.lock().unwrap().get(&sender) {
+        Some(PidEntry::Helper(helper_id, _)) => *helper_id == id,
+        _ => false,
+    };
+    let allowed = match access {
+        NotifyKind::None => false,
+        NotifyKind::Main => is_main,
+        NotifyKind::Exec => is_main || is_exec(),
+        NotifyKind::All => {
+            is_main || is_exec() || crate::services::process_belongs_to_service(srvc, sender)
+        }
+    };
+    if !allowed {
+        warn!(
+            "Rejected notification for service {} from pid {} because of NotifyAccess={:?}",
+            name, sender, access
+        );
+    }
+    allowed
+}
+
+/// Close fds that were sent with a rejected notification
+pub fn close_received_fds(fds: Vec<RawFd>) {
+    for fd in fds {
+        let _ = nix::unistd::close(fd);
+    }
 }
 
-pub fn handle_notifications_from_buffer(srvc: &mut Service, name: &str) {
-    while srvc.notifications_buffer.contains('\n') {
-        let (line, rest) = srvc
-            .notifications_buffer
-            .split_at(srvc.notifications_buffer.find('\n').unwrap());
-        let line = line.to_owned();
-        srvc.notifications_buffer = rest[1..].to_owned();
+/// Apply the FDSTORE=1 / FDSTOREREMOVE=1 the service sent with the last message to the fds that came with it.
+/// All fds that do not end up in the fd store get closed.
+pub fn handle_fd_store_request(
+    srvc: &mut Service,
+    name: &str,
+    mut fds: Vec<RawFd>,
+    fd_store: &ArcMutFDStore,
+) {
+    let request = std::mem::take(&mut srvc.fd_store_request);
+    let fd_name = request.name.unwrap_or_else(|| "stored".to_owned());
+    let mut fd_store_locked = fd_store.write().unwrap();
+
+    if request.remove {
+        trace!("Service {} removed stored fds with name {}", name, fd_name);
+        if let Some(removed) = fd_store_locked.remove_service_stored(&name.to_owned(), &fd_name) {
+            for fd in removed {
+                let _ = nix::unistd::close(*fd);
+            }
+        }
+    }
+
+    let to_close = if request.store && !fds.is_empty() {
+        let max = srvc.service_config.fd_store_max as usize;
+        let free = max.saturating_sub(fd_store_locked.service_stored_count(name));
+        if free < fds.len() {
+            warn!(
+                "Service {} tried to store {} fds but only {} more are allowed by FileDescriptorStoreMax={}",
+                name,
+                fds.len(),
+                free,
+                max
+            );
+        }
+        let rest = fds.split_off(free.min(fds.len()));
+        if !fds.is_empty() {
+            trace!(
+                "Service {} stored {} fds with name {}",
+                name,
+                fds.len(),
+                fd_name
+            );
+            fd_store_locked.insert_service_stored(
+                name.to_owned(),
+                fd_name,
+                fds.into_iter().map(Box::new).collect(),
+            );
+        }
+        rest
+    } else {
+        fds
+    };
+
+    for fd in to_close {
+        let _ = nix::unistd::close(fd);
+    }
+}
 
-        handle_notification_message(&line, srvc, name);
+fn apply_notified_status(id: UnitId, new_status: UnitStatus, status_table: &ArcMutStatusTable) {
+    let status_table_locked = status_table.read().unwrap();
+    if let Some(status) = status_table_locked.get(&id) {
+        let mut status_locked = status.lock().unwrap();
+        match *status_locked {
+            UnitStatus::Started | UnitStatus::Reloading | UnitStatus::StoppingGracefully => {
+                *status_locked = new_status;
+            }
+            _ => {
+                // starting and stopping is managed by rustysd
+            }
+        }
     }
 }
@@ -0,0 +1,412 @@
+//! Watch the paths of path units with inotify and activate their units when the conditions are met
+//!
+//! PathExists=, PathExistsGlob= and DirectoryNotEmpty= are checked whenever something happens in the watched
+//! directories (and at least once a second, in case a watched directory did not exist yet). They trigger again as long as
+//! they are fulfilled and the unit is not active, so the service should remove the files it processed.
+//! PathChanged= and PathModified= trigger on the inotify events of the path.
+
+use crate::platform::EventFd;
+use crate::units::*;
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify, InotifyEvent, WatchDescriptor};
+use std::collections::{HashMap, HashSet};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// paths that don't exist yet can't be watched, so check regularly
+const POLL_INTERVAL_MILLIS: i32 = 1000;
+
+#[derive(Debug)]
+pub struct PathWatch {
+    pub config: PathConfig,
+    /// The unit named in Unit=, resolved after all units are loaded
+    pub unit_id: Option<UnitId>,
+}
+
+impl PathWatch {
+    pub fn new(config: PathConfig) -> Self {
+        PathWatch {
+            config,
+            unit_id: None,
+        }
+    }
+}
+
+fn wake_up_flags() -> AddWatchFlags {
+    AddWatchFlags::IN_CREATE | AddWatchFlags::IN_MOVED_TO
+}
+
+fn changed_flags() -> AddWatchFlags {
+    AddWatchFlags::IN_CLOSE_WRITE
+        | AddWatchFlags::IN_ATTRIB
+        | AddWatchFlags::IN_CREATE
+        | AddWatchFlags::IN_DELETE
+        | AddWatchFlags::IN_DELETE_SELF
+        | AddWatchFlags::IN_MOVE_SELF
+        | AddWatchFlags::IN_MOVED_FROM
+        | AddWatchFlags::IN_MOVED_TO
+}
+
+fn modified_flags() -> AddWatchFlags {
+    changed_flags() | AddWatchFlags::IN_MODIFY
+}
+
+/// The paths that need to be watched for this condition and with which flags
+fn watches_for_condition(condition: &PathCondition) -> Vec<(PathBuf, AddWatchFlags)> {
+    match condition {
+        PathCondition::Exists(path) => parent_dir(path)
+            .map(|parent| vec![(parent, wake_up_flags())])
+            .unwrap_or_default(),
+        PathCondition::ExistsGlob(pattern) => vec![(glob_base_dir(pattern), wake_up_flags())],
+        PathCondition::DirectoryNotEmpty(path) => {
+            let mut watches = vec![(path.clone(), wake_up_flags())];
+            if let Some(parent) = parent_dir(path) {
+                watches.push((parent, wake_up_flags()));
+            }
+            watches
+        }
+        PathCondition::Changed(path) | PathCondition::Modified(path) => {
+            let flags = if let PathCondition::Modified(_) = condition {
+                modified_flags()
+            } else {
+                changed_flags()
+            };
+            let mut watches = vec![(path.clone(), flags)];
+            if let Some(parent) = parent_dir(path) {
+                watches.push((parent, flags));
+            }
+            watches
+        }
+    }
+}
+
+fn parent_dir(path: &Path) -> Option<PathBuf> {
+    path.parent().map(|parent| {
+        if parent.as_os_str().is_empty() {
+            PathBuf::from(".")
+        } else {
+            parent.to_owned()
+        }
+    })
+}
+
+fn has_glob_chars(component: &str) -> bool {
+    component.contains(&['*', '?', '['][..])
+}
+
+/// The directory up to the first component that contains a glob character
+fn glob_base_dir(pattern: &str) -> PathBuf {
+    let mut base = PathBuf::new();
+    let mut components = Path::new(pattern).components().peekable();
+    while let Some(component) = components.next() {
+        let is_last = components.peek().is_none();
+        if is_last || has_glob_chars(&component.as_os_str().to_string_lossy()) {
+            break;
+        }
+        base.push(component);
+    }
+    if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        base
+    }
+}
+
+/// Match one path component against a pattern with *, ? and [...] (with ranges and ! or ^ for negation)
+pub fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    glob_matches_chars(&pattern, &name)
+}
+
+fn glob_matches_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| glob_matches_chars(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && glob_matches_chars(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let end = match pattern.iter().skip(2).position(|c| *c == ']') {
+                Some(pos) => pos + 2,
+                // no closing bracket, match it literally
+                None => {
+                    return name.first() == Some(&'[')
+                        && glob_matches_chars(&pattern[1..], &name[1..])
+                }
+            };
+            let c = match name.first() {
+                Some(c) => *c,
+                None => return false,
+            };
+            let mut class = &pattern[1..end];
+            let negate = class.first() == Some(&'!') || class.first() == Some(&'^');
+            if negate {
+                class = &class[1..];
+            }
+            let mut in_class = false;
+            let mut idx = 0;
+            while idx < class.len() {
+                if idx + 2 < class.len() && class[idx + 1] == '-' {
+                    in_class |= class[idx] <= c && c <= class[idx + 2];
+                    idx += 3;
+                } else {
+                    in_class |= class[idx] == c;
+                    idx += 1;
+                }
+            }
+            in_class != negate && glob_matches_chars(&pattern[end + 1..], &name[1..])
+        }
+        Some(p) => name.first() == Some(p) && glob_matches_chars(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Check if any file matches the glob pattern. Globs can be used in every component of the path.
+pub fn glob_exists(pattern: &str) -> bool {
+    let components: Vec<String> = Path::new(pattern)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    glob_exists_in(PathBuf::new(), &components)
+}
+
+fn glob_exists_in(base: PathBuf, components: &[String]) -> bool {
+    let (component, rest) = match components.split_first() {
+        Some(split) => split,
+        None => return base.exists(),
+    };
+    if !has_glob_chars(component) {
+        return glob_exists_in(base.join(component), rest);
+    }
+    let dir = if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        base.clone()
+    };
+    let entries = match std::fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+    entries.filter_map(|entry| entry.ok()).any(|entry| {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // like in the shell, wildcards don't match hidden files
+        (!name.starts_with('.') || component.starts_with('.'))
+            && glob_matches(component, &name)
+            && glob_exists_in(base.join(&name), rest)
+    })
+}
+
+fn directory_not_empty(path: &Path) -> bool {
+    match std::fs::read_dir(path) {
+        Ok(mut entries) => entries.next().is_some(),
+        Err(_) => false,
+    }
+}
+
+/// Check if the condition is met, either because of the state of the filesystem or because of one of the events
+fn condition_met(
+    condition: &PathCondition,
+    events: &[InotifyEvent],
+    watched_paths: &HashMap<WatchDescriptor, PathBuf>,
+) -> bool {
+    match condition {
+        PathCondition::Exists(path) => path.exists(),
+        PathCondition::ExistsGlob(pattern) => glob_exists(pattern),
+        PathCondition::DirectoryNotEmpty(path) => directory_not_empty(path),
+        PathCondition::Changed(path) | PathCondition::Modified(path) => {
+            let flags = if let PathCondition::Modified(_) = condition {
+                modified_flags()
+            } else {
+                changed_flags()
+            };
+            events.iter().any(|event| {
+                if !event.mask.intersects(flags) {
+                    return false;
+                }
+                let watched = match watched_paths.get(&event.wd) {
+                    Some(watched) => watched,
+                    None => return false,
+                };
+                match &event.name {
+                    // events about the path itself or something in the directory at path
+                    _ if watched == path => true,
+                    // events about the path in it's parent directory
+                    Some(name) => {
+                        parent_dir(path).as_ref() == Some(watched)
+                            && path.file_name() == Some(name.as_os_str())
+                    }
+                    None => false,
+                }
+            })
+        }
+    }
+}
+
+struct Watcher {
+    inotify: Inotify,
+    watched_paths: HashMap<WatchDescriptor, PathBuf>,
+}
+
+impl Watcher {
+    /// Add watches for all paths that should be watched and remove the ones that are not needed anymore.
+    /// Paths that don't exist can't be watched yet, they are tried again on the next update.
+    fn update_watches(&mut self, wanted: &[(PathBuf, AddWatchFlags)]) {
+        let mut flags_per_path: HashMap<&PathBuf, AddWatchFlags> = HashMap::new();
+        for (path, flags) in wanted {
+            *flags_per_path
+                .entry(path)
+                .or_insert_with(AddWatchFlags::empty) |= *flags;
+        }
+
+        let mut new_watched_paths = HashMap::new();
+        for (path, flags) in flags_per_path {
+            if let Ok(wd) = self.inotify.add_watch(path, flags) {
+                new_watched_paths.insert(wd, path.clone());
+            }
+        }
+        for (wd, path) in &self.watched_paths {
+            if !new_watched_paths.contains_key(wd) {
+                trace!("Stop watching path: {:?}", path);
+                // fails if the watch was already removed because the path was deleted
+                let _ = self.inotify.rm_watch(*wd);
+            }
+        }
+        self.watched_paths = new_watched_paths;
+    }
+
+    /// Wait until something happens in the watched paths or the poll interval runs out
+    fn wait_for_events(&self) -> Result<Vec<InotifyEvent>, String> {
+        let mut fds = [nix::poll::PollFd::new(
+            self.inotify.as_raw_fd(),
+            nix::poll::PollFlags::POLLIN,
+        )];
+        match nix::poll::poll(&mut fds, POLL_INTERVAL_MILLIS) {
+            Ok(0) => Ok(Vec::new()),
+            Ok(_) => {
+                let mut events = Vec::new();
+                loop {
+                    match self.inotify.read_events() {
+                        Ok(new_events) => events.extend(new_events),
+                        Err(nix::Error::Sys(nix::errno::Errno::EAGAIN)) => break,
+                        Err(e) => return Err(format!("Error while reading inotify events: {}", e)),
+                    }
+                }
+                Ok(events)
+            }
+            Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => Ok(Vec::new()),
+            Err(e) => Err(format!("Error while polling the inotify fd: {}", e)),
+        }
+    }
+}
+
+/// The path units that are started with the unit they activate and their conditions
+fn collect_active_path_units(
+    run_info: &ArcRuntimeInfo,
+) -> Vec<(String, UnitId, Vec<PathCondition>)> {
+    let mut path_units = Vec::new();
+    let unit_table_locked = run_info.unit_table.read().unwrap();
+    // the other units stay locked while they are starting, so only lock the path units
+    for (_, unit) in unit_table_locked
+        .iter()
+        .filter(|(id, _)| id.0 == UnitIdKind::Path)
+    {
+        let unit_locked = unit.lock().unwrap();
+        if let UnitSpecialized::Path(path) = &unit_locked.specialized {
+            let unit_id = match path.unit_id {
+                Some(id) => id,
+                None => continue,
+            };
+            let started = match run_info.status_table.read().unwrap().get(&unit_locked.id) {
+                Some(status) => *status.lock().unwrap() == UnitStatus::Started,
+                None => false,
+            };
+            if started {
+                path_units.push((
+                    unit_locked.conf.name(),
+                    unit_id,
+                    path.config.conditions.clone(),
+                ));
+            }
+        }
+    }
+    path_units
+}
+
+fn is_unit_active(run_info: &ArcRuntimeInfo, id: UnitId) -> bool {
+    match run_info.status_table.read().unwrap().get(&id) {
+        Some(status) => status.lock().unwrap().is_active(),
+        None => false,
+    }
+}
+
+pub fn start_pathactivation_thread(
+    run_info: ArcRuntimeInfo,
+    note_sock_path: PathBuf,
+    eventfds: Arc<Vec<EventFd>>,
+) {
+    std::thread::spawn(move || {
+        let inotify = match Inotify::init(InitFlags::IN_NONBLOCK | InitFlags::IN_CLOEXEC) {
+            Ok(inotify) => inotify,
+            Err(e) => {
+                error!("Could not setup inotify for path activation: {}", e);
+                return;
+            }
+        };
+        let mut watcher = Watcher {
+            inotify,
+            watched_paths: HashMap::new(),
+        };
+        let mut events = Vec::new();
+        // the units that are triggered but might not have changed their status to starting yet
+        let triggered = Arc::new(Mutex::new(HashSet::new()));
+        loop {
+            let path_units = collect_active_path_units(&run_info);
+            for (name, unit_id, conditions) in &path_units {
+                if triggered.lock().unwrap().contains(unit_id)
+                    || is_unit_active(&run_info, *unit_id)
+                {
+                    continue;
+                }
+                if conditions
+                    .iter()
+                    .any(|condition| condition_met(condition, &events, &watcher.watched_paths))
+                {
+                    trace!("Path unit {} triggers unit {}", name, unit_id);
+                    triggered.lock().unwrap().insert(*unit_id);
+                    let name = name.clone();
+                    let unit_id = *unit_id;
+                    let run_info = run_info.clone();
+                    let note_sock_path = note_sock_path.clone();
+                    let eventfds = eventfds.clone();
+                    let triggered = triggered.clone();
+                    // starting a oneshot service blocks until it is done, don't stop watching the paths meanwhile
+                    std::thread::spawn(move || {
+                        if let Err(e) = crate::units::run_unit_job(
+                            unit_id,
+                            crate::units::JobKind::Start,
+                            run_info,
+                            note_sock_path,
+                            eventfds,
+                        ) {
+                            error!("Error while activating unit for path unit {}: {}", name, e);
+                        }
+                        triggered.lock().unwrap().remove(&unit_id);
+                    });
+                }
+            }
+
+            // the events refer to the current watches, so only update them after the events were handled
+            let wanted: Vec<_> = path_units
+                .iter()
+                .flat_map(|(_, _, conditions)| conditions.iter().flat_map(watches_for_condition))
+                .collect();
+            watcher.update_watches(&wanted);
+
+            events = match watcher.wait_for_events() {
+                Ok(events) => events,
+                Err(e) => {
+                    error!("Error in path activation loop: {}", e);
+                    break;
+                }
+            };
+        }
+    });
+}
@@ -3,35 +3,44 @@ pub struct PwEntry {
     pub pw: Option<Vec<u8>>,
     pub uid: nix::unistd::Uid,
     pub gid: nix::unistd::Gid,
+    pub home: Option<std::path::PathBuf>,
 }
 
 fn make_user_from_libc(username: &str, user: &libc::passwd) -> Result<PwEntry, String> {
     let uid = nix::unistd::Uid::from_raw(user.pw_uid);
     let gid = nix::unistd::Gid::from_raw(user.pw_gid);
-    let pw = if !user.pw_passwd.is_null() {
-        let mut vec = Vec::new();
-        let mut ptr = user.pw_passwd;
-        loop {
-            let byte = unsafe { *ptr } as u8;
-            if byte == b'\0' {
-                break;
-            } else {
-                vec.push(byte);
-            }
-            unsafe { ptr = ptr.add(1) };
-        }
-        Some(vec)
-    } else {
-        None
-    };
+    let pw = read_c_string(user.pw_passwd);
+    let home = read_c_string(user.pw_dir).map(|dir| {
+        use std::os::unix::ffi::OsStringExt;
+        std::path::PathBuf::from(std::ffi::OsString::from_vec(dir))
+    });
     Ok(PwEntry {
         name: username.to_string(),
         uid,
         gid,
         pw,
+        home,
     })
 }
 
+fn read_c_string(ptr: *const libc::c_char) -> Option<Vec<u8>> {
+    if ptr.is_null() {
+        return None;
+    }
+    let mut vec = Vec::new();
+    let mut ptr = ptr;
+    loop {
+        let byte = unsafe { *ptr } as u8;
+        if byte == b'\0' {
+            break;
+        } else {
+            vec.push(byte);
+        }
+        unsafe { ptr = ptr.add(1) };
+    }
+    Some(vec)
+}
+
 // TODO PR to nix
 #[cfg(target_os = "linux")]
 #[allow(dead_code)]
@@ -81,7 +90,12 @@ fn make_new_pw() -> libc::passwd {
 
 #[cfg(any(target_os = "freebsd", target_os = "linux"))]
 pub fn getpwnam_r(username: &str) -> Result<PwEntry, String> {
-    let username_i8 = username.bytes().map(|x| x as i8).collect::<Vec<_>>();
+    // needs to be nul terminated for libc
+    let username_i8 = username
+        .bytes()
+        .chain(Some(0))
+        .map(|x| x as i8)
+        .collect::<Vec<_>>();
     let pointer: *const i8 = username_i8.as_ptr();
     let mut buf_size = 32;
     let mut user = make_new_pw();
@@ -114,7 +128,54 @@ pub fn getpwnam_r(username: &str) -> Result<PwEntry, String> {
     }
 }
 
+#[cfg(any(target_os = "freebsd", target_os = "linux"))]
+pub fn getpwuid_r(uid: nix::unistd::Uid) -> Result<PwEntry, String> {
+    let mut buf_size = 32;
+    let mut user = make_new_pw();
+    let user_ptr = &mut user;
+    let user_ptr_ptr = &mut (user_ptr as *mut libc::passwd);
+    loop {
+        let mut buf = Vec::with_capacity(buf_size);
+        buf.resize(buf_size, 0i8);
+
+        let errno = unsafe {
+            libc::getpwuid_r(
+                uid.as_raw(),
+                user_ptr,
+                buf.as_mut_ptr(),
+                buf_size,
+                user_ptr_ptr,
+            )
+        };
+
+        if user_ptr_ptr.is_null() {
+            // error case
+            if errno == libc::ERANGE {
+                // need more bytes in buf
+                buf_size = buf_size * 2;
+            } else {
+                return Err(format!("Error calling getpwuid_r: {}", errno));
+            }
+        } else {
+            // just for safety check this, but this is the happy result
+            if (user_ptr as *mut libc::passwd).eq(&*user_ptr_ptr) {
+                let username = read_c_string(user_ptr.pw_name)
+                    .map(|name| String::from_utf8_lossy(&name).into_owned())
+                    .unwrap_or_else(|| uid.to_string());
+                return make_user_from_libc(&username, &*user_ptr);
+            } else {
+                return Err(format!("The **user ({:?}) should have pointed to the same location as the *user ({:?})", user_ptr_ptr, user_ptr));
+            }
+        }
+    }
+}
+
 #[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
 pub fn getpwnam_r(_username: &str) -> Result<PwEntry, String> {
     compile_error!("getpwnam_r is not yet implemented for this platform");
 }
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+pub fn getpwuid_r(_uid: nix::unistd::Uid) -> Result<PwEntry, String> {
+    compile_error!("getpwuid_r is not yet implemented for this platform");
+}
@@ -0,0 +1,112 @@
+//! Build the environment a service is started with. Nothing from the environment of rustysd is passed on
+//! unless it is listed in PassEnvironment=
+
+use crate::units::*;
+
+/// Collect the environment from the settings in this order (later ones override earlier ones):
+/// 1. PATH
+/// 1. PassEnvironment=
+/// 1. Environment=
+/// 1. EnvironmentFile=
+/// 1. UnsetEnvironment= removes variables again
+pub fn build_environment(exec_config: &ExecConfig) -> Result<Vec<(String, String)>, String> {
+    let mut env = Vec::new();
+    set_var(&mut env, "PATH", &EXECUTABLE_SEARCH_PATH.join(":"));
+
+    for name in &exec_config.pass_environment {
+        if let Ok(value) = std::env::var(name) {
+            set_var(&mut env, name, &value);
+        }
+    }
+    for (key, value) in &exec_config.environment {
+        set_var(&mut env, key, value);
+    }
+    for (path, optional) in &exec_config.environment_files {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) => {
+                if *optional {
+                    trace!("Skipping optional environment file {:?}: {}", path, e);
+                    continue;
+                }
+                return Err(format!("Could not read environment file {:?}: {}", path, e));
+            }
+        };
+        for (key, value) in parse_environment_file(&content) {
+            set_var(&mut env, &key, &value);
+        }
+    }
+    for unset in &exec_config.unset_environment {
+        if unset.contains('=') {
+            env.retain(|(key, value)| format!("{}={}", key, value) != *unset);
+        } else {
+            env.retain(|(key, _)| key != unset);
+        }
+    }
+    Ok(env)
+}
+
+pub fn set_var(env: &mut Vec<(String, String)>, key: &str, value: &str) {
+    match env.iter_mut().find(|(k, _)| k == key) {
+        Some(entry) => entry.1 = value.to_owned(),
+        None => env.push((key.to_owned(), value.to_owned())),
+    }
+}
+
+/// Parse the content of an EnvironmentFile=. Lines look like KEY=value, empty lines and lines starting
+/// with '#' or ';' are ignored. Values can be quoted and lines ending in a backslash are continued.
+pub fn parse_environment_file(content: &str) -> Vec<(String, String)> {
+    let mut env = Vec::new();
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        let mut line = line.trim().to_owned();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        while line.ends_with('\\') {
+            line.pop();
+            match lines.next() {
+                Some(next) => line.push_str(next.trim()),
+                None => break,
+            }
+        }
+        let (key, value) = match line.find('=') {
+            Some(pos) => (line[..pos].trim(), line[pos + 1..].trim()),
+            None => {
+                warn!("Ignoring invalid line in environment file: {}", line);
+                continue;
+            }
+        };
+        let value = unquote(value);
+        match split_env_assignment(&format!("{}={}", key, value)) {
+            Some(assignment) => env.push(assignment),
+            None => warn!("Ignoring invalid line in environment file: {}", line),
+        }
+    }
+    env
+}
+
+/// Remove surrounding quotes. Inside double quotes backslash escapes for '"', '\\', '`' and '$' are removed
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+        return value[1..value.len() - 1].to_owned();
+    }
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        let mut unquoted = String::new();
+        let mut chars = value[1..value.len() - 1].chars().peekable();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                match chars.peek() {
+                    Some('"') | Some('\\') | Some('`') | Some('$') => {
+                        unquoted.push(chars.next().unwrap());
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            unquoted.push(c);
+        }
+        return unquoted;
+    }
+    value.to_owned()
+}
@@ -1,7 +1,9 @@
 use crate::fd_store::FDStore;
-use crate::platform::setenv;
 use crate::services::Service;
+use crate::units::{Commandline, ExecConfig, StandardInput, StandardOutput};
+use nix::fcntl::OFlag;
 use std::os::unix::io::RawFd;
+use std::path::Path;
 
 fn close_all_unneeded_fds(_srvc: &mut Service, _fd_store: &FDStore) {
     // This is not really necessary since we mark all fds with FD_CLOEXEC but just to be safe...
@@ -25,73 +27,142 @@ fn close_all_unneeded_fds(_srvc: &mut Service, _fd_store: &FDStore) {
     //}
 }
 
-fn setup_env_vars(socket_names: Vec<String>, notify_socket_env_var: &str) {
-    // The following two lines do deadlock after fork and before exec... I would have loved to just use these
-    // This has probably something to do with the global env_lock() that is being used in the std
-    // std::env::set_var("LISTEN_FDS", format!("{}", srvc.file_descriptors.len()));
-    // std::env::set_var("LISTEN_PID", format!("{}", pid));
-
-    // so lets use some unsafe instead, and use the same libc::setenv that the std uses but we dont care about the lock
-    // This is the only thread in this process that is still running so we dont need any lock
-
-    // TODO Maybe it would be better to have a simple wrapper that we can exec with a few sensible args
-    // 1. list filedescriptors to keep open (maybe not event that. FD handling can be done here probably?)
-    // 2. at least the number of fds
-    // 3. the actual executable that should be run + their args
-    //
-    // This wrapper then does:
-    // 1. Maybe close and dup2 fds
-    // 2. Set appropriate env variables
-    // 3. exec the actual executable we are trying to start here
+fn prepare_envp(
+    env: Vec<(String, String)>,
+    socket_names: Vec<String>,
+    notify_socket_env_var: &str,
+    watchdog_timeout: Option<std::time::Duration>,
+) -> Vec<std::ffi::CString> {
+    // std::env::set_var would deadlock after fork because of the global env_lock() that is being used in the std.
+    // Building the envp for execve ourselves also means nothing of the environment of rustysd leaks into the service.
 
     // This is all just that complicated because systemd promises to pass the correct PID in the env-var LISTEN_PID...
 
     let num_fds = socket_names.len();
     let pid = nix::unistd::getpid();
-    let pid_str = &format!("{}", pid);
-    let fds_str = &format!("{}", num_fds);
+    let pid_str = format!("{}", pid);
+    let fds_str = format!("{}", num_fds);
 
     let full_name_list = socket_names.join(":");
-    unsafe {
-        setenv("LISTEN_FDS", fds_str);
+
+    let mut env = env;
+    super::environment::set_var(&mut env, "LISTEN_FDS", &fds_str);
+    super::environment::set_var(&mut env, "LISTEN_PID", &pid_str);
+    super::environment::set_var(&mut env, "LISTEN_FDNAMES", &full_name_list);
+    super::environment::set_var(&mut env, "NOTIFY_SOCKET", notify_socket_env_var);
+    if let Some(timeout) = watchdog_timeout {
+        super::environment::set_var(
+            &mut env,
+            "WATCHDOG_USEC",
+            &format!("{}", timeout.as_micros()),
+        );
+        super::environment::set_var(&mut env, "WATCHDOG_PID", &pid_str);
     }
-    unsafe {
-        setenv("LISTEN_PID", pid_str);
+
+    env.iter()
+        .filter_map(|(key, value)| std::ffi::CString::new(format!("{}={}", key, value)).ok())
+        .collect()
+}
+
+/// Connect stdin, stdout and stderr as configured with StandardInput=, StandardOutput= and StandardError=.
+/// The socket fds are the ones of the sockets of this service, not yet duped to 3..n
+pub fn setup_stdio(
+    srvc: &Service,
+    socket_fds: &[RawFd],
+    socket_names: &[String],
+) -> Result<(), String> {
+    let exec_config = &srvc.service_config.exec_config;
+    match &exec_config.stdin {
+        StandardInput::Null => open_and_dup(Path::new("/dev/null"), OFlag::O_RDONLY, 0)?,
+        StandardInput::Socket => dup_to(single_socket(socket_fds)?, 0)?,
+        StandardInput::File(path) => open_and_dup(path, OFlag::O_RDONLY, 0)?,
+        StandardInput::Tty => open_and_dup(Path::new("/dev/console"), OFlag::O_RDWR, 0)?,
     }
-    unsafe {
-        setenv("LISTEN_FDNAMES", &full_name_list);
+    // inheriting a read-only stdin would make every write fail, so use a writable /dev/null like systemd does
+    let stdout = match (&exec_config.stdout, &exec_config.stdin) {
+        (StandardOutput::Inherit, StandardInput::Null)
+        | (StandardOutput::Inherit, StandardInput::File(_)) => &StandardOutput::Null,
+        (stdout, _) => stdout,
+    };
+    setup_output(stdout, 1, srvc.stdout_dup, socket_fds, socket_names)?;
+    setup_output(
+        &exec_config.stderr,
+        2,
+        srvc.stderr_dup,
+        socket_fds,
+        socket_names,
+    )?;
+    Ok(())
+}
+
+fn setup_output(
+    output: &StandardOutput,
+    target: RawFd,
+    pipe: Option<(RawFd, RawFd)>,
+    socket_fds: &[RawFd],
+    socket_names: &[String],
+) -> Result<(), String> {
+    match output {
+        // The other end of the pipe will be read from the service daemon
+        StandardOutput::Journal => match pipe {
+            Some((_, write_end)) => dup_to(write_end, target),
+            None => Err(format!("No pipe was prepared for fd {}", target)),
+        },
+        // stdout inherits from stdin, stderr from stdout
+        StandardOutput::Inherit => dup_to(target - 1, target),
+        StandardOutput::Null => open_and_dup(Path::new("/dev/null"), OFlag::O_WRONLY, target),
+        StandardOutput::File(path) => open_and_dup(path, OFlag::O_WRONLY | OFlag::O_CREAT, target),
+        StandardOutput::Append(path) => open_and_dup(
+            path,
+            OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_APPEND,
+            target,
+        ),
+        StandardOutput::Truncate(path) => open_and_dup(
+            path,
+            OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC,
+            target,
+        ),
+        StandardOutput::Socket => dup_to(single_socket(socket_fds)?, target),
+        StandardOutput::Fd(name) => match socket_names.iter().position(|n| n == name) {
+            Some(idx) => dup_to(socket_fds[idx], target),
+            None => Err(format!("No socket with the FileDescriptorName={}", name)),
+        },
     }
-    unsafe {
-        setenv("NOTIFY_SOCKET", notify_socket_env_var);
+}
+
+fn single_socket(socket_fds: &[RawFd]) -> Result<RawFd, String> {
+    if socket_fds.len() == 1 {
+        Ok(socket_fds[0])
+    } else {
+        Err(format!(
+            "Connecting stdio to the socket needs exactly one socket but there are {}",
+            socket_fds.len()
+        ))
     }
+}
 
-    //trace!(
-    //    "[FORK_CHILD {}] pid: {}, ENV: LISTEN_PID: {}  LISTEN_FD: {}, LISTEN_FDNAMES: {}",
-    //    name,
-    //    pid,
-    //    pid_str,
-    //    fds_str,
-    //    full_name_list
-    //);
+fn open_and_dup(path: &Path, flags: OFlag, target: RawFd) -> Result<(), String> {
+    let fd = nix::fcntl::open(
+        path,
+        flags | OFlag::O_CLOEXEC,
+        nix::sys::stat::Mode::from_bits_truncate(0o644),
+    )
+    .map_err(|e| format!("Could not open {:?}: {}", path, e))?;
+    let res = dup_to(fd, target);
+    let _ = nix::unistd::close(fd);
+    res
 }
 
-fn dup_stdio(new_stdout: RawFd, new_stderr: RawFd) {
-    // dup new stdout to fd 1. The other end of the pipe will be read from the service daemon
-    let actual_new_fd = nix::unistd::dup2(new_stdout, 1).unwrap();
-    if actual_new_fd != 1 {
-        panic!(
-            "Could not dup the pipe to stdout. Got duped to: {}",
-            actual_new_fd
-        );
-    }
-    // dup new stderr to fd 2. The other end of the pipe will be read from the service daemon
-    let actual_new_fd = nix::unistd::dup2(new_stderr, 2).unwrap();
-    if actual_new_fd != 2 {
-        panic!(
-            "Could not dup the pipe to stderr. Got duped to: {}",
-            actual_new_fd
-        );
+fn dup_to(fd: RawFd, target: RawFd) -> Result<(), String> {
+    let actual_new_fd = nix::unistd::dup2(fd, target)
+        .map_err(|e| format!("Could not dup fd {} to {}: {}", fd, target, e))?;
+    if actual_new_fd != target {
+        return Err(format!(
+            "Could not dup fd {} to {}. Got duped to: {}",
+            fd, target, actual_new_fd
+        ));
     }
+    Ok(())
 }
 
 fn dup_fds(name: &str, sockets: Vec<RawFd>) -> Result<(), String> {
@@ -131,26 +202,47 @@ fn dup_fds(name: &str, sockets: Vec<RawFd>) -> Result<(), String> {
     Ok(())
 }
 
-fn prepare_exec_args(srvc: &Service) -> (std::ffi::CString, Vec<std::ffi::CString>) {
-    let split: Vec<&str> = srvc.service_config.exec.split(' ').collect();
+fn prepare_exec_args(
+    srvc: &Service,
+    cmdline: &Commandline,
+) -> (std::ffi::CString, Vec<std::ffi::CString>) {
+    let cmd_path = crate::units::resolve_executable(&cmdline.cmd);
 
-    let cmd = std::ffi::CString::new(split[0]).unwrap();
+    let cmd = std::ffi::CString::new(cmd_path.to_str().unwrap()).unwrap();
     let mut args = Vec::new();
 
-    let exec_name = std::path::PathBuf::from(split[0]);
-    let exec_name = exec_name.file_name().unwrap();
-    let exec_name: Vec<u8> = exec_name.to_str().unwrap().bytes().collect();
-    let exec_name = std::ffi::CString::new(exec_name).unwrap();
-    args.push(exec_name);
-    for arg in &split[1..] {
-        if !arg.is_empty() {
-            args.push(std::ffi::CString::new(*arg).unwrap());
-        }
+    let exec_name = match cmdline.argv0() {
+        Some(argv0) => argv0.to_owned(),
+        None => cmd_path.file_name().unwrap().to_str().unwrap().to_owned(),
+    };
+    args.push(std::ffi::CString::new(exec_name).unwrap());
+    for arg in cmdline.expanded_args(&srvc.cmd_env()) {
+        args.push(std::ffi::CString::new(arg).unwrap());
     }
 
     (cmd, args)
 }
 
+/// Apply RootDirectory=, WorkingDirectory= and UMask=. Used for the main process and the helper processes
+pub fn setup_exec_context(exec_config: &ExecConfig) -> Result<(), String> {
+    if let Some(root) = &exec_config.root_directory {
+        nix::unistd::chroot(root.as_path())
+            .map_err(|e| format!("could not chroot to {:?}: {}", root, e))?;
+        nix::unistd::chdir("/").map_err(|e| format!("could not chdir to /: {}", e))?;
+    }
+    if let Some((dir, optional)) = &exec_config.working_directory {
+        if let Err(e) = nix::unistd::chdir(dir.as_path()) {
+            if !optional {
+                return Err(format!("could not chdir to {:?}: {}", dir, e));
+            }
+        }
+    }
+    if let Some(umask) = exec_config.umask {
+        nix::sys::stat::umask(umask);
+    }
+    Ok(())
+}
+
 fn move_into_new_process_group() {
     //make this process the process group leader
     nix::unistd::setpgid(nix::unistd::getpid(), nix::unistd::Pid::from_raw(0)).unwrap();
@@ -161,8 +253,8 @@ pub fn after_fork_child(
     name: &str,
     fd_store: &FDStore,
     notify_socket_env_var: &str,
-    new_stdout: RawFd,
-    new_stderr: RawFd,
+    exec_pipe: Option<RawFd>,
+    cmdline: &Commandline,
 ) {
     if let Err(e) = super::fork_os_specific::post_fork_os_specific(srvc) {
         eprintln!("[FORK_CHILD {}] postfork error: {}", name, e);
@@ -180,8 +272,6 @@ pub fn after_fork_child(
 
     close_all_unneeded_fds(srvc, fd_store);
 
-    dup_stdio(new_stdout, new_stderr);
-
     let mut fds = Vec::new();
     let mut names = Vec::new();
 
@@ -204,15 +294,54 @@ pub fn after_fork_child(
         names.extend(sock_names);
     }
 
+    // instances started by a socket with Accept=yes get the connection
+    if let Some((fd_name, fd)) = fd_store.get_connection(name) {
+        fds.push(*fd);
+        names.push(fd_name.clone());
+    }
+
+    if let Err(e) = setup_stdio(srvc, &fds, &names) {
+        eprintln!("[FORK_CHILD {}] error while setting up stdio: {}", name, e);
+        std::process::exit(1);
+    }
+
+    // fds that were stored with FDSTORE=1 by a previous run of this service
+    for (fd_name, fd) in fd_store.get_all_service_stored(name) {
+        fds.push(fd);
+        names.push(fd_name);
+    }
+
+    // move the pipe for Type=exec out of the way of the fds that get duped to 3..n below
+    let exec_pipe = exec_pipe.map(|fd| {
+        let min_fd = 3 + fds.len() as i32;
+        match nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_DUPFD_CLOEXEC(min_fd)) {
+            Ok(new_fd) => new_fd,
+            Err(e) => {
+                eprintln!("[FORK_CHILD {}] could not move exec pipe: {}", name, e);
+                std::process::exit(1);
+            }
+        }
+    });
+
     if let Err(e) = dup_fds(name, fds) {
         eprintln!("[FORK_CHILD {}] error while duping fds: {}", name, e);
         std::process::exit(1);
     }
 
-    setup_env_vars(names, notify_socket_env_var);
-    let (cmd, args) = prepare_exec_args(srvc);
+    let envp = prepare_envp(
+        srvc.cmd_env(),
+        names,
+        notify_socket_env_var,
+        srvc.watchdog_timeout,
+    );
+    let (cmd, args) = prepare_exec_args(srvc, cmdline);
 
-    if nix::unistd::getuid().is_root() {
+    if let Err(e) = setup_exec_context(&srvc.service_config.exec_config) {
+        eprintln!("[FORK_CHILD {}] {}", name, e);
+        std::process::exit(1);
+    }
+
+    if nix::unistd::getuid().is_root() && !cmdline.keeps_privileges() {
         match crate::platform::drop_privileges(srvc.gid, &srvc.supp_gids, srvc.uid) {
             Ok(()) => { /* Happy */ }
             Err(e) => {
@@ -225,16 +354,19 @@ pub fn after_fork_child(
         }
     }
 
-    eprintln!("EXECV: {:?} {:?}", &cmd, &args);
-    match nix::unistd::execv(&cmd, &args) {
+    match nix::unistd::execve(&cmd, &args, &envp) {
         Ok(_) => {
             eprintln!(
-                "[FORK_CHILD {}] execv returned Ok()... This should never happen",
+                "[FORK_CHILD {}] execve returned Ok()... This should never happen",
                 name
             );
         }
         Err(e) => {
-            eprintln!("[FORK_CHILD {}] execv errored: {:?}", name, e);
+            eprintln!("[FORK_CHILD {}] execve errored: {:?}", name, e);
+            if let Some(fd) = exec_pipe {
+                let errno = e.as_errno().map(|errno| errno as i32).unwrap_or(0);
+                let _ = nix::unistd::write(fd, &errno.to_ne_bytes());
+            }
             std::process::exit(1);
         }
     }
@@ -1,4 +1,5 @@
 use crate::services::Service;
+use crate::units::PidTable;
 
 #[cfg(feature = "cgroups")]
 use crate::platform::cgroups;
@@ -32,3 +33,150 @@ pub fn post_fork_os_specific(srvc: &mut Service) -> Result<(), String> {
     let _ = srvc;
     Ok(())
 }
+
+/// Find the daemon a service of type forking left behind. With cgroups this is the only process left in the cgroup.
+/// Otherwise the daemon is either still in the process group of the initial process or, if it called setsid(), it was
+/// reparented to rustysd (which is a subreaper) without being in the pid table yet. Those are told apart from the
+/// daemons of other services by the NOTIFY_SOCKET they inherited. This only succeeds if there is exactly one
+/// candidate, so the caller needs to hold the lock of the pid table.
+pub fn guess_main_pid(srvc: &Service, pid_table: &PidTable) -> Option<nix::unistd::Pid> {
+    #[cfg(feature = "cgroups")]
+    {
+        if nix::unistd::getuid().is_root() {
+            if let Ok(pids) = cgroups::get_all_procs(&srvc.platform_specific.cgroup_path) {
+                return if pids.len() == 1 { Some(pids[0]) } else { None };
+            }
+        }
+    }
+    // the process group is stored negated so it can be used directly with kill()
+    let pgid = nix::unistd::Pid::from_raw(-srvc.process_group?.as_raw());
+    let own_pid = nix::unistd::getpid();
+    let notify_socket = srvc.notifications_path.as_ref()?.to_string_lossy();
+    select_main_pid(
+        &list_processes(own_pid),
+        pgid,
+        own_pid,
+        &notify_socket,
+        |pid| pid_table.contains_key(&pid),
+    )
+}
+
+/// A running process as seen by guess_main_pid
+#[derive(Clone, Debug)]
+pub struct ProcessInfo {
+    pub pid: nix::unistd::Pid,
+    pub ppid: nix::unistd::Pid,
+    pub pgid: nix::unistd::Pid,
+    /// Only read for children of rustysd. None if the environment could not be read or does not contain it
+    pub notify_socket: Option<String>,
+}
+
+/// The candidates are all processes in the process group of the initial process and all unknown children of rustysd
+/// that do not belong to another service. Of those only the ones whose parent is not a candidate too are considered,
+/// so workers of the daemon do not count.
+pub fn select_main_pid<F: Fn(nix::unistd::Pid) -> bool>(
+    processes: &[ProcessInfo],
+    pgid: nix::unistd::Pid,
+    own_pid: nix::unistd::Pid,
+    notify_socket: &str,
+    is_known: F,
+) -> Option<nix::unistd::Pid> {
+    let is_orphan = |proc: &ProcessInfo| {
+        proc.ppid == own_pid
+            && !is_known(proc.pid)
+            && proc
+                .notify_socket
+                .as_ref()
+                .map(|other| other == notify_socket)
+                .unwrap_or(true)
+    };
+    let candidates: Vec<_> = processes
+        .iter()
+        .filter(|proc| proc.pgid == pgid || is_orphan(proc))
+        .collect();
+    let roots: Vec<_> = candidates
+        .iter()
+        .filter(|proc| !candidates.iter().any(|parent| parent.pid == proc.ppid))
+        .map(|proc| proc.pid)
+        .collect();
+    if roots.len() == 1 {
+        Some(roots[0])
+    } else {
+        None
+    }
+}
+
+/// Check if the process is part of the service. With cgroups this is the cgroup of the service, otherwise the process
+/// group, which processes can leave.
+pub fn process_belongs_to_service(srvc: &Service, pid: nix::unistd::Pid) -> bool {
+    #[cfg(feature = "cgroups")]
+    {
+        if nix::unistd::getuid().is_root() {
+            if let Ok(pids) = cgroups::get_all_procs(&srvc.platform_specific.cgroup_path) {
+                return pids.contains(&pid);
+            }
+        }
+    }
+    match (srvc.process_group, nix::unistd::getpgid(Some(pid))) {
+        // the process group is stored negated so it can be used directly with kill()
+        (Some(pgid), Ok(pid_pgid)) => -pgid.as_raw() == pid_pgid.as_raw(),
+        _ => false,
+    }
+}
+
+/// All processes that are not zombies
+#[cfg(target_os = "linux")]
+fn list_processes(own_pid: nix::unistd::Pid) -> Vec<ProcessInfo> {
+    let mut processes = Vec::new();
+    let entries = match std::fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return processes,
+    };
+    for entry in entries.flatten() {
+        let pid = match entry.file_name().to_str().map(str::parse::<i32>) {
+            Some(Ok(pid)) => pid,
+            _ => continue,
+        };
+        if let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) {
+            // the executable name can contain spaces and parens, the other fields come after the last ')'
+            // the fields are: state, ppid, pgrp, ...
+            let fields = match stat.rfind(')') {
+                Some(idx) => stat[idx + 1..].split_whitespace().collect::<Vec<_>>(),
+                None => continue,
+            };
+            if fields.first() == Some(&"Z") {
+                continue;
+            }
+            let field = |idx: usize| fields.get(idx).and_then(|field| field.parse::<i32>().ok());
+            if let (Some(ppid), Some(pgid)) = (field(1), field(2)) {
+                let ppid = nix::unistd::Pid::from_raw(ppid);
+                let notify_socket = if ppid == own_pid {
+                    read_notify_socket(&entry.path())
+                } else {
+                    None
+                };
+                processes.push(ProcessInfo {
+                    pid: nix::unistd::Pid::from_raw(pid),
+                    ppid,
+                    pgid: nix::unistd::Pid::from_raw(pgid),
+                    notify_socket,
+                });
+            }
+        }
+    }
+    processes
+}
+
+#[cfg(target_os = "linux")]
+fn read_notify_socket(proc_dir: &std::path::Path) -> Option<String> {
+    let environ = std::fs::read(proc_dir.join("environ")).ok()?;
+    environ
+        .split(|byte| *byte == 0)
+        .find_map(|var| var.strip_prefix(b"NOTIFY_SOCKET="))
+        .map(|value| String::from_utf8_lossy(value).into_owned())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn list_processes(_own_pid: nix::unistd::Pid) -> Vec<ProcessInfo> {
+    Vec::new()
+}
@@ -5,10 +5,13 @@ use std::os::unix::net::UnixDatagram;
 
 pub fn wait_for_service(
     srvc: &mut Service,
+    id: UnitId,
     name: &str,
     stream: &UnixDatagram,
-    pid_table: ArcMutPidTable,
+    run_info: &RuntimeInfo,
+    cmdline: &Commandline,
 ) -> Result<(), RunCmdError> {
+    let pid_table = &run_info.pid_table;
     trace!(
         "[FORK_PARENT] Service: {} forked with pid: {}",
         name,
@@ -32,7 +35,7 @@ pub fn wait_for_service(
                     if duration_elapsed > duration_timeout {
                         trace!("[FORK_PARENT] Service {} notification timed out", name);
                         return Err(RunCmdError::Timeout(
-                            srvc.service_config.exec.clone(),
+                            cmdline.to_string(),
                             format!("{:?}", duration_timeout),
                         ));
                     } else {
@@ -42,16 +45,29 @@ pub fn wait_for_service(
                             .unwrap();
                     }
                 }
-                let bytes = match stream.recv(&mut buf[..]) {
-                    Ok(bytes) => bytes,
-                    Err(e) => match e.kind() {
-                        std::io::ErrorKind::WouldBlock => 0,
-                        _ => panic!("{}", e),
-                    },
-                };
-                srvc.notifications_buffer
-                    .push_str(&String::from_utf8(buf[..bytes].to_vec()).unwrap());
-                crate::notification_handler::handle_notifications_from_buffer(srvc, &name);
+                let (bytes, fds, sender) =
+                    match crate::notification_handler::recv_notification(stream, &mut buf[..]) {
+                        Ok(received) => received,
+                        Err(nix::Error::Sys(nix::errno::Errno::EAGAIN)) => (0, Vec::new(), None),
+                        Err(e) => panic!("{}", e),
+                    };
+                if bytes > 0
+                    && !crate::notification_handler::notify_access_allowed(
+                        srvc, id, name, sender, pid_table,
+                    )
+                {
+                    crate::notification_handler::close_received_fds(fds);
+                    continue;
+                }
+                // the status is managed by the starting code until the service is started
+                let _ = crate::notification_handler::handle_notification_datagram(
+                    &String::from_utf8_lossy(&buf[..bytes]),
+                    fds,
+                    srvc,
+                    id,
+                    &name,
+                    run_info,
+                );
                 if srvc.signaled_ready {
                     srvc.signaled_ready = false;
                     trace!("[FORK_PARENT] Service {} sent READY=1 notification", name);
@@ -62,9 +78,12 @@ pub fn wait_for_service(
             }
             stream.set_read_timeout(None).unwrap();
         }
-        ServiceType::Simple => {
+        ServiceType::Simple | ServiceType::Idle => {
             trace!("[FORK_PARENT] service {} doesnt notify", name);
         }
+        ServiceType::Exec => {
+            trace!("[FORK_PARENT] service {} was exec'd successfully", name);
+        }
         ServiceType::OneShot => {
             trace!(
                 "[FORK_PARENT] Waiting for oneshot service to exit: {}",
@@ -72,12 +91,12 @@ pub fn wait_for_service(
             );
             let mut counter = 1u64;
             let pid = srvc.pid.unwrap();
-            loop {
+            let termination = loop {
                 if let Some(time_out) = duration_timeout {
                     if start_time.elapsed() >= time_out {
                         error!("oneshot service {} reached timeout", name);
                         return Err(RunCmdError::Timeout(
-                            srvc.service_config.exec.clone(),
+                            cmdline.to_string(),
                             format!("{:?}", duration_timeout),
                         ));
                     }
@@ -90,10 +109,11 @@ pub fn wait_for_service(
                                 PidEntry::Service(_, _) => {
                                     // Still running. Wait more
                                 }
-                                PidEntry::OneshotExited(_) => {
+                                PidEntry::OneshotExited(code) => {
                                     trace!("End wait for {}", name);
+                                    let code = *code;
                                     let _entry_owned = pid_table_locked.remove(&pid).unwrap();
-                                    break;
+                                    break code;
                                 }
                                 PidEntry::Helper(_, _) => {
                                     // Should never happen
@@ -127,7 +147,95 @@ pub fn wait_for_service(
                     counter = counter * 2;
                 }
                 std::thread::sleep(sleep_dur);
+            };
+            if !termination.success() && !cmdline.has_prefix(CommandlinePrefix::Minus) {
+                return Err(RunCmdError::BadExitCode(cmdline.to_string(), termination));
+            }
+        }
+        ServiceType::Forking => {
+            trace!(
+                "[FORK_PARENT] Waiting for the initial process of forking service {} to exit",
+                name
+            );
+            let mut counter = 1u64;
+            let pid = srvc.pid.unwrap();
+            let termination = loop {
+                if let Some(time_out) = duration_timeout {
+                    if start_time.elapsed() >= time_out {
+                        error!("forking service {} reached timeout", name);
+                        return Err(RunCmdError::Timeout(
+                            cmdline.to_string(),
+                            format!("{:?}", duration_timeout),
+                        ));
+                    }
+                }
+                {
+                    let mut pid_table_locked = pid_table.lock().unwrap();
+                    if let Some(PidEntry::HelperExited(_)) = pid_table_locked.get(&pid) {
+                        if let Some(PidEntry::HelperExited(termination)) =
+                            pid_table_locked.remove(&pid)
+                        {
+                            break termination;
+                        }
+                    }
+                }
+                // same backoff as for oneshot services
+                let sleep_dur = std::time::Duration::from_micros(counter * 50);
+                let sleep_cap = std::time::Duration::from_millis(10);
+                let sleep_dur = sleep_dur.min(sleep_cap);
+                if sleep_dur < sleep_cap {
+                    counter *= 2;
+                }
+                std::thread::sleep(sleep_dur);
+            };
+            if !termination.success() {
+                srvc.pid = None;
+                return Err(RunCmdError::BadExitCode(cmdline.to_string(), termination));
             }
+
+            // the daemon might write the PIDFile after the initial process exited
+            let main_pid = match &srvc.service_config.pid_file {
+                Some(pid_file) => {
+                    let remaining = duration_timeout
+                        .map(|timeout| timeout.saturating_sub(start_time.elapsed()));
+                    match wait_for_pid_file(pid_file, remaining) {
+                        Ok(main_pid) => Some(main_pid),
+                        Err(e) => {
+                            srvc.pid = None;
+                            return Err(RunCmdError::Generic(e));
+                        }
+                    }
+                }
+                None => None,
+            };
+            let mut pid_table_locked = pid_table.lock().unwrap();
+            let main_pid = match main_pid
+                .or_else(|| super::fork_os_specific::guess_main_pid(srvc, &pid_table_locked))
+            {
+                Some(main_pid) => main_pid,
+                None => {
+                    srvc.pid = None;
+                    return Err(RunCmdError::Generic(format!(
+                        "Could not determine the main pid of forking service {}. Set PIDFile= to fix this",
+                        name
+                    )));
+                }
+            };
+            trace!(
+                "[FORK_PARENT] Forking service {} has main pid {}",
+                name,
+                main_pid
+            );
+            // if the daemon already exited the exit handler did not recognize it, so check here
+            if nix::sys::signal::kill(main_pid, None).is_err() {
+                srvc.pid = None;
+                return Err(RunCmdError::Generic(format!(
+                    "The main process {} of service {} exited during startup",
+                    main_pid, name
+                )));
+            }
+            pid_table_locked.insert(main_pid, PidEntry::Service(id, ServiceType::Forking));
+            srvc.pid = Some(main_pid);
         }
         ServiceType::Dbus => {
             if let Some(dbus_name) = &srvc.service_config.dbus_name {
@@ -140,14 +248,14 @@ pub fn wait_for_service(
                         crate::dbus_wait::WaitResult::Timedout => {
                             warn!("[FORK_PARENT] Did not find dbus name on bus: {}", dbus_name);
                             return Err(RunCmdError::Timeout(
-                                srvc.service_config.exec.clone(),
+                                cmdline.to_string(),
                                 format!("{:?}", duration_timeout),
                             ));
                         }
                     },
                     Err(e) => {
                         return Err(RunCmdError::WaitError(
-                            srvc.service_config.exec.clone(),
+                            cmdline.to_string(),
                             format!("Error while waiting for dbus name: {}", e),
                         ));
                     }
@@ -162,3 +270,47 @@ pub fn wait_for_service(
     }
     Ok(())
 }
+
+/// Poll the PIDFile until it contains a valid pid or the timeout is reached. None waits forever.
+pub fn wait_for_pid_file(
+    pid_file: &std::path::Path,
+    timeout: Option<std::time::Duration>,
+) -> Result<nix::unistd::Pid, String> {
+    let start_time = std::time::Instant::now();
+    let mut counter = 1u64;
+    loop {
+        let err = match read_pid_file(pid_file) {
+            Ok(pid) => return Ok(pid),
+            Err(e) => e,
+        };
+        if let Some(timeout) = timeout {
+            if start_time.elapsed() >= timeout {
+                return Err(format!(
+                    "{} after waiting for {:?}",
+                    err,
+                    start_time.elapsed()
+                ));
+            }
+        }
+        // same backoff as for oneshot services
+        let sleep_dur = std::time::Duration::from_micros(counter * 50);
+        let sleep_cap = std::time::Duration::from_millis(10);
+        let sleep_dur = sleep_dur.min(sleep_cap);
+        if sleep_dur < sleep_cap {
+            counter *= 2;
+        }
+        std::thread::sleep(sleep_dur);
+    }
+}
+
+fn read_pid_file(pid_file: &std::path::Path) -> Result<nix::unistd::Pid, String> {
+    let content = std::fs::read_to_string(pid_file)
+        .map_err(|e| format!("Could not read PIDFile {:?}: {}", pid_file, e))?;
+    match content.trim().parse::<i32>() {
+        Ok(pid) if pid > 0 => Ok(nix::unistd::Pid::from_raw(pid)),
+        _ => Err(format!(
+            "PIDFile {:?} does not contain a valid pid: {}",
+            pid_file, content
+        )),
+    }
+}
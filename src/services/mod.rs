@@ -3,6 +3,7 @@
 //! 2. processgroupid setting
 //! 3. duping of filedescriptors
 //! 4. signaling processgroup on kill
+mod environment;
 mod fork_child;
 mod fork_os_specific;
 mod fork_parent;
@@ -11,5 +12,9 @@ mod prepare_service;
 mod service_exit_handler;
 mod services;
 mod start_service;
+pub use environment::{build_environment, parse_environment_file};
+pub use fork_child::setup_stdio;
+pub use fork_os_specific::{process_belongs_to_service, select_main_pid, ProcessInfo};
+pub use fork_parent::wait_for_pid_file;
 pub use service_exit_handler::*;
 pub use services::*;
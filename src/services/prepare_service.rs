@@ -34,6 +34,15 @@ pub fn prepare_service(
             nix::fcntl::FcntlArg::F_SETFD(nix::fcntl::FdFlag::FD_CLOEXEC),
         )
         .unwrap();
+        // the kernel adds the pid of the sender to each message, needed to enforce NotifyAccess=
+        #[cfg(target_os = "linux")]
+        nix::sys::socket::setsockopt(new_listener_fd, nix::sys::socket::sockopt::PassCred, &true)
+            .map_err(|e| {
+            format!(
+                "Could not enable SO_PASSCRED on {:?}: {}",
+                notify_socket_env_var, e
+            )
+        })?;
         let new_stream = Arc::new(Mutex::new(stream));
         srvc.notifications = Some(new_stream);
     }
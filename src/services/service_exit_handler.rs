@@ -114,38 +114,119 @@ pub fn service_exit_handler(
                 srvc.kill_all_remaining_processes(&unit_locked.conf.name());
                 return Ok(());
             }
+            if srvc.pid.is_some() && srvc.pid != Some(pid) {
+                // the service was stopped and started again before this exit of the old main process was handled
+                trace!(
+                    "Ignore exit of old main process {} of service {}",
+                    pid,
+                    unit_locked.conf.name()
+                );
+                return Ok(());
+            }
         }
     }
 
     trace!("Check if we want to restart the unit");
-    let (name, sockets, restart_unit) = {
+    let mut succeeded = false;
+    let (name, sockets, restart_decision, errno_description) = {
         let unit_locked = &mut *unit.lock().unwrap();
         let name = unit_locked.conf.name();
+        let start_limit_interval = unit_locked.conf.start_limit_interval.clone();
+        let start_limit_burst = unit_locked.conf.start_limit_burst;
         if let UnitSpecialized::Service(srvc) = &mut unit_locked.specialized {
             trace!(
                 "Service with id: {:?}, name: {} pid: {} exited with: {:?}",
                 srvc_id,
-                unit_locked.conf.name(),
+                name,
                 pid,
                 code
             );
 
-            if srvc.service_config.restart == ServiceRestart::Always {
-                let sockets = srvc.socket_names.clone();
-                (name, sockets, true)
+            crate::journal::log(
+                &run_info.journal,
+                &name,
+                crate::journal::LogStream::Rustysd,
+                Some(pid),
+                &format!("Main process exited with: {:?}", code),
+            );
+            if srvc.watchdog_triggered {
+                warn!("Service {} was killed by the watchdog", name);
+                crate::journal::log(
+                    &run_info.journal,
+                    &name,
+                    crate::journal::LogStream::Rustysd,
+                    Some(pid),
+                    "Killed by the watchdog",
+                );
+            }
+            let errno_description = srvc.notified_errno_description();
+            // with the '-' prefix a failing exit code counts as success
+            let code = match code {
+                ChildTermination::Exit(_)
+                    if srvc
+                        .service_config
+                        .exec
+                        .iter()
+                        .any(|cmd| cmd.has_prefix(CommandlinePrefix::Minus)) =>
+                {
+                    ChildTermination::Exit(0)
+                }
+                _ => code,
+            };
+            let reason = if srvc.watchdog_triggered {
+                ExitReason::Watchdog
+            } else if srvc.timeout_triggered {
+                ExitReason::Timeout
             } else {
-                (name, Vec::new(), false)
+                ExitReason::Exited
+            };
+            succeeded = is_clean_exit(code) && reason == ExitReason::Exited;
+            if !restart_policy_applies(&srvc.service_config.restart, code, reason) {
+                (
+                    name,
+                    Vec::new(),
+                    RestartDecision::DontRestart,
+                    errno_description,
+                )
+            } else if let Some(restart_sec) = srvc.get_restart_sec() {
+                if srvc.record_restart_if_allowed(&start_limit_interval, start_limit_burst) {
+                    let sockets = srvc.socket_names.clone();
+                    (
+                        name,
+                        sockets,
+                        RestartDecision::Restart(restart_sec),
+                        errno_description,
+                    )
+                } else {
+                    (
+                        name,
+                        Vec::new(),
+                        RestartDecision::StartLimitHit,
+                        errno_description,
+                    )
+                }
+            } else {
+                (
+                    name,
+                    Vec::new(),
+                    RestartDecision::DontRestart,
+                    errno_description,
+                )
             }
         } else {
-            (name, Vec::new(), false)
+            (name, Vec::new(), RestartDecision::DontRestart, None)
         }
     };
 
-    let restart_unit = if restart_unit {
+    let restart_unit = if let RestartDecision::Restart(restart_sec) = restart_decision {
+        // this runs in it's own thread so just sleeping here is fine
+        trace!("Wait {:?} before restarting service {}", restart_sec, name);
+        std::thread::sleep(restart_sec);
+
         let status_table_locked = run_info.status_table.read().unwrap();
         let status_locked = &*status_table_locked.get(&srvc_id).unwrap().lock().unwrap();
         // if thats not the case this service got killed by something else so dont interfere
-        *status_locked == UnitStatus::Started
+        is_running(status_locked)
     } else {
         false
     };
@@ -163,20 +244,154 @@ pub fn service_exit_handler(
             }
         }
         trace!("Restart service {} after it died", name);
-        crate::units::reactivate_unit(
+        crate::units::run_unit_job(
             srvc_id,
+            crate::units::JobKind::Restart,
             run_info,
             notification_socket_path,
             Arc::new(eventfds.to_vec()),
-        )
-        .map_err(|e| format!("{}", e))?;
+        )?;
     } else {
+        let was_running = {
+            let status_table_locked = run_info.status_table.read().unwrap();
+            let status_locked = &*status_table_locked.get(&srvc_id).unwrap().lock().unwrap();
+            is_running(status_locked)
+        };
+
         trace!(
             "Recursively killing all services requiring service {}",
             name
         );
         crate::units::deactivate_unit_recursive(srvc_id, true, run_info.clone())
             .map_err(|e| format!("{}", e))?;
+
+        let failure_reason = if let RestartDecision::StartLimitHit = restart_decision {
+            warn!(
+                "Service {} was restarted too often. Leaving it in a failed state",
+                name
+            );
+            Some(format!("Start limit hit after exiting with: {:?}", code))
+        } else {
+            errno_description
+                .map(|errno| format!("Exited with: {:?}, the service reported {}", code, errno))
+        };
+        if was_running {
+            if let Some(reason) = failure_reason {
+                crate::journal::log(
+                    &run_info.journal,
+                    &name,
+                    crate::journal::LogStream::Rustysd,
+                    None,
+                    &reason,
+                );
+                let status_table_locked = run_info.status_table.read().unwrap();
+                let mut status_locked = status_table_locked.get(&srvc_id).unwrap().lock().unwrap();
+                *status_locked = UnitStatus::StoppedFinal(reason);
+            }
+            let unit_result = match restart_decision {
+                RestartDecision::DontRestart if succeeded => UnitResult::Success,
+                _ => UnitResult::Failure,
+            };
+            crate::units::start_result_handlers(
+                srvc_id,
+                unit_result,
+                run_info.clone(),
+                notification_socket_path,
+                Arc::new(eventfds.to_vec()),
+            );
+        }
+        crate::socket_activation::finish_connection(srvc_id, &run_info);
     }
     Ok(())
 }
+
+/// If the service is not in one of these states it was stopped by rustysd and the exit is expected
+fn is_running(status: &UnitStatus) -> bool {
+    matches!(
+        status,
+        UnitStatus::Started | UnitStatus::Reloading | UnitStatus::StoppingGracefully
+    )
+}
+
+/// Whether the main process exited on its own or rustysd killed it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitReason {
+    Exited,
+    /// Killed because the watchdog timed out or was triggered
+    Watchdog,
+    /// Killed because a start or stop timeout was reached
+    Timeout,
+}
+
+enum RestartDecision {
+    Restart(std::time::Duration),
+    DontRestart,
+    StartLimitHit,
+}
+
+/// Signals that are treated as a clean exit, the same way systemd handles them
+fn is_clean_signal(signal: nix::sys::signal::Signal) -> bool {
+    matches!(
+        signal,
+        nix::sys::signal::Signal::SIGHUP
+            | nix::sys::signal::Signal::SIGINT
+            | nix::sys::signal::Signal::SIGTERM
+            | nix::sys::signal::Signal::SIGPIPE
+    )
+}
+
+/// Exit code 0 or one of the clean signals
+pub fn is_clean_exit(code: ChildTermination) -> bool {
+    match code {
+        ChildTermination::Exit(exit_code) => exit_code == 0,
+        ChildTermination::Signal(signal) => is_clean_signal(signal),
+    }
+}
+
+/// Decide with the table from the systemd docs whether this kind of exit should lead to a restart
+pub fn restart_policy_applies(
+    restart: &ServiceRestart,
+    code: ChildTermination,
+    reason: ExitReason,
+) -> bool {
+    match reason {
+        // the signals we sent are not an abort by the service itself
+        ExitReason::Watchdog => {
+            return match restart {
+                ServiceRestart::Always
+                | ServiceRestart::OnFailure
+                | ServiceRestart::OnAbnormal
+                | ServiceRestart::OnWatchdog => true,
+                ServiceRestart::No | ServiceRestart::OnSuccess | ServiceRestart::OnAbort => false,
+            };
+        }
+        ExitReason::Timeout => {
+            return match restart {
+                ServiceRestart::Always | ServiceRestart::OnFailure | ServiceRestart::OnAbnormal => {
+                    true
+                }
+                ServiceRestart::No
+                | ServiceRestart::OnSuccess
+                | ServiceRestart::OnAbort
+                | ServiceRestart::OnWatchdog => false,
+            };
+        }
+        ExitReason::Exited => {}
+    }
+
+    let clean_exit = is_clean_exit(code);
+    let unclean_signal = match code {
+        ChildTermination::Exit(_) => false,
+        ChildTermination::Signal(signal) => !is_clean_signal(signal),
+    };
+
+    match restart {
+        ServiceRestart::Always => true,
+        ServiceRestart::No => false,
+        ServiceRestart::OnSuccess => clean_exit,
+        ServiceRestart::OnFailure => !clean_exit,
+        ServiceRestart::OnAbnormal => unclean_signal,
+        ServiceRestart::OnAbort => unclean_signal,
+        ServiceRestart::OnWatchdog => false,
+    }
+}
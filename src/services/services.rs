@@ -11,6 +11,22 @@ use std::sync::Mutex;
 pub struct ServiceRuntimeInfo {
     pub restarted: u64,
     pub up_since: Option<std::time::Instant>,
+    /// Points in time of the recent restarts. Used to enforce StartLimitIntervalSec= / StartLimitBurst=
+    pub restart_times: Vec<std::time::Instant>,
+}
+
+// Same defaults as systemd uses
+const DEFAULT_START_LIMIT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+const DEFAULT_START_LIMIT_BURST: u64 = 5;
+const DEFAULT_RESTART_SEC: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Collected from FDSTORE=1, FDSTOREREMOVE=1 and FDNAME= while handling a notification message.
+/// It is applied together with the fds that were sent along with that message.
+#[derive(Debug, Default)]
+pub struct FdStoreRequest {
+    pub store: bool,
+    pub remove: bool,
+    pub name: Option<String>,
 }
 
 #[cfg(target_os = "linux")]
@@ -37,12 +53,24 @@ pub struct Service {
     pub runtime_info: ServiceRuntimeInfo,
     pub signaled_ready: bool,
 
+    /// The currently active watchdog timeout. Starts as WatchdogSec= but can be changed with WATCHDOG_USEC=
+    pub watchdog_timeout: Option<std::time::Duration>,
+    pub watchdog_last_ping: Option<std::time::Instant>,
+    pub watchdog_triggered: bool,
+    /// Set when rustysd killed the service because a start or stop timeout was reached
+    pub timeout_triggered: bool,
+
+    /// Set by the service with ERRNO=
+    pub notified_errno: Option<i32>,
+    pub fd_store_request: FdStoreRequest,
+    /// Built from Environment= and friends every time the service is started
+    pub environment: Vec<(String, String)>,
+
     pub notifications: Option<Arc<Mutex<UnixDatagram>>>,
     pub notifications_path: Option<std::path::PathBuf>,
 
     pub stdout_dup: Option<(RawFd, RawFd)>,
     pub stderr_dup: Option<(RawFd, RawFd)>,
-    pub notifications_buffer: String,
     pub stdout_buffer: Vec<u8>,
     pub stderr_buffer: Vec<u8>,
     pub uid: nix::unistd::Uid,
@@ -55,6 +83,8 @@ pub struct Service {
 pub enum RunCmdError {
     Timeout(String, String),
     SpawnError(String, String),
+    /// The child of a Type=exec service could not exec the command and sent back this errno
+    ExecFailed(String, nix::errno::Errno),
     WaitError(String, String),
     BadExitCode(String, crate::signal_handler::ChildTermination),
     Generic(String),
@@ -65,6 +95,12 @@ impl std::fmt::Display for RunCmdError {
         let msg = match self {
             RunCmdError::BadExitCode(cmd, exit) => format!("{} exited with: {:?}", cmd, exit),
             RunCmdError::SpawnError(cmd, err) => format!("{} failed to spawn with: {:?}", cmd, err),
+            RunCmdError::ExecFailed(cmd, errno) => format!(
+                "{} failed to exec with errno {} ({})",
+                cmd,
+                *errno as i32,
+                errno.desc()
+            ),
             RunCmdError::WaitError(cmd, err) => {
                 format!("{} could not be waited on because: {:?}", cmd, err)
             }
@@ -86,6 +122,7 @@ pub enum ServiceErrorReason {
     StartFailed(RunCmdError),
     PoststopFailed(RunCmdError),
     StopFailed(RunCmdError),
+    ReloadFailed(RunCmdError),
 
     PrestartAndPoststopFailed(RunCmdError, RunCmdError),
     PoststartAndPoststopFailed(RunCmdError, RunCmdError),
@@ -105,6 +142,7 @@ impl std::fmt::Display for ServiceErrorReason {
             ServiceErrorReason::PoststartFailed(e) => format!("Poststart failed: {}", e),
             ServiceErrorReason::StartFailed(e) => format!("Start failed: {}", e),
             ServiceErrorReason::StopFailed(e) => format!("Stop failed: {}", e),
+            ServiceErrorReason::ReloadFailed(e) => format!("Reload failed: {}", e),
             ServiceErrorReason::PoststopFailed(e) => format!("Poststop failed: {}", e),
 
             // Both failed
@@ -142,28 +180,31 @@ impl Service {
         &mut self,
         id: UnitId,
         name: &str,
-        fd_store: ArcMutFDStore,
-        pid_table: ArcMutPidTable,
+        run_info: &RuntimeInfo,
         notification_socket_path: std::path::PathBuf,
         eventfds: &[EventFd],
         allow_ignore: bool,
     ) -> Result<StartResult, ServiceErrorReason> {
+        let pid_table = &run_info.pid_table;
         if let Some(pid) = self.pid {
             return Err(ServiceErrorReason::AlreadyHasPID(pid));
         }
         if let Some(pgid) = self.process_group {
             return Err(ServiceErrorReason::AlreadyHasPID(pgid));
         }
-        if self.service_config.accept {
-            return Err(ServiceErrorReason::Generic(
-                "Inetd style activation is not supported".into(),
-            ));
-        }
         if !allow_ignore || self.socket_names.is_empty() {
             trace!("Start service {}", name);
 
             super::prepare_service::prepare_service(self, name, &notification_socket_path)
                 .map_err(|e| ServiceErrorReason::PreparingFailed(e))?;
+            self.environment =
+                super::environment::build_environment(&self.service_config.exec_config)
+                    .map_err(ServiceErrorReason::PreparingFailed)?;
+            self.watchdog_timeout = self.get_watchdog_sec();
+            self.watchdog_last_ping = None;
+            self.watchdog_triggered = false;
+            self.timeout_triggered = false;
+            self.notified_errno = None;
             self.run_prestart(id, name, pid_table.clone())
                 .map_err(
                     |prestart_err| match self.run_poststop(id, name, pid_table.clone()) {
@@ -174,37 +215,25 @@ impl Service {
                         ),
                     },
                 )?;
-            {
-                let mut pid_table_locked = pid_table.lock().unwrap();
-                // This mainly just forks the process. The waiting (if necessary) is done below
-                // Doing it under the lock of the pid_table prevents races between processes exiting very
-                // fast and inserting the new pid into the pid table
-                start_service(self, name.clone(), &*fd_store.read().unwrap())
-                    .map_err(|e| ServiceErrorReason::StartFailed(e))?;
-                if let Some(new_pid) = self.pid {
-                    pid_table_locked.insert(
-                        new_pid,
-                        PidEntry::Service(id, self.service_config.srcv_type),
-                    );
-                    crate::platform::notify_event_fds(&eventfds);
+            if self.service_config.srcv_type == ServiceType::OneShot {
+                let cmds = self.service_config.exec.clone();
+                for cmd in &cmds {
+                    self.start_main_process(id, name, cmd, run_info, eventfds)?;
+                    // every command gets a fresh process group, don't let the leftovers of this one linger
+                    self.kill_all_remaining_processes(name);
+                    self.pid = None;
+                    self.process_group = None;
                 }
-            }
-            if let Some(sock) = &self.notifications {
-                let sock = sock.clone();
-                super::fork_parent::wait_for_service(
-                    self,
-                    name,
-                    &*sock.lock().unwrap(),
-                    pid_table.clone(),
-                )
-                .map_err(|start_err| {
-                    match self.run_poststop(id, name, pid_table.clone()) {
-                        Ok(_) => ServiceErrorReason::StartFailed(start_err),
-                        Err(poststop_err) => {
-                            ServiceErrorReason::StartAndPoststopFailed(start_err, poststop_err)
-                        }
+            } else {
+                let cmd = match self.service_config.exec.first() {
+                    Some(cmd) => cmd.clone(),
+                    None => {
+                        return Err(ServiceErrorReason::StartFailed(RunCmdError::Generic(
+                            format!("Service {} has no ExecStart= set", name),
+                        )))
                     }
-                })?;
+                };
+                self.start_main_process(id, name, &cmd, run_info, eventfds)?;
             }
             self.run_poststart(id, name, pid_table.clone())
                 .map_err(
@@ -216,6 +245,10 @@ impl Service {
                         ),
                     },
                 )?;
+            if self.watchdog_timeout.is_some() {
+                // the watchdog starts counting when the service is up and running
+                self.watchdog_last_ping = Some(std::time::Instant::now());
+            }
             Ok(StartResult::Started)
         } else {
             trace!(
@@ -227,6 +260,98 @@ impl Service {
         }
     }
 
+    /// Fork off one command of ExecStart= and wait until it counts as started
+    fn start_main_process(
+        &mut self,
+        id: UnitId,
+        name: &str,
+        cmd: &Commandline,
+        run_info: &RuntimeInfo,
+        eventfds: &[EventFd],
+    ) -> Result<(), ServiceErrorReason> {
+        let pid_table = &run_info.pid_table;
+        let pending_exec = {
+            let mut pid_table_locked = pid_table.lock().unwrap();
+            // This mainly just forks the process. The waiting (if necessary) is done below
+            // Doing it under the lock of the pid_table prevents races between processes exiting very
+            // fast and inserting the new pid into the pid table
+            let pending_exec = start_service(self, name, &*run_info.fd_store.read().unwrap(), cmd)
+                .map_err(ServiceErrorReason::StartFailed)?;
+            if let Some(new_pid) = self.pid {
+                if self.service_config.srcv_type == ServiceType::Forking || pending_exec.is_some() {
+                    // The initial process of a forking service is expected to exit after forking off the actual
+                    // daemon and a Type=exec child exits if the exec fails. Treat them like helpers so their exit
+                    // gets collected here instead of being handled as an exit of the running service
+                    pid_table_locked.insert(new_pid, PidEntry::Helper(id, name.to_string()));
+                } else {
+                    pid_table_locked.insert(
+                        new_pid,
+                        PidEntry::Service(id, self.service_config.srcv_type),
+                    );
+                }
+                crate::platform::notify_event_fds(eventfds);
+            }
+            pending_exec
+        };
+        if let Some(pending_exec) = pending_exec {
+            self.finish_exec(id, name, cmd, pending_exec, pid_table)
+                .map_err(ServiceErrorReason::StartFailed)?;
+        }
+        if let Some(sock) = &self.notifications {
+            let sock = sock.clone();
+            super::fork_parent::wait_for_service(
+                self,
+                id,
+                name,
+                &*sock.lock().unwrap(),
+                run_info,
+                cmd,
+            )
+            .map_err(|start_err| {
+                if let RunCmdError::Timeout(_, _) = start_err {
+                    self.timeout_triggered = true;
+                }
+                match self.run_poststop(id, name, pid_table.clone()) {
+                    Ok(_) => ServiceErrorReason::StartFailed(start_err),
+                    Err(poststop_err) => {
+                        ServiceErrorReason::StartAndPoststopFailed(start_err, poststop_err)
+                    }
+                }
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Wait for the exec of a Type=exec service without holding the pid table lock and make the child the main process
+    fn finish_exec(
+        &mut self,
+        id: UnitId,
+        name: &str,
+        cmd: &Commandline,
+        pending_exec: super::start_service::PendingExec,
+        pid_table: &ArcMutPidTable,
+    ) -> Result<(), RunCmdError> {
+        let pid = self.pid;
+        let exec_result = super::start_service::wait_for_exec(self, pending_exec, cmd);
+        let pid = match pid {
+            Some(pid) => pid,
+            None => return exec_result,
+        };
+        let mut pid_table_locked = pid_table.lock().unwrap();
+        let entry = pid_table_locked.remove(&pid);
+        exec_result?;
+        if let Some(PidEntry::HelperExited(termination)) = entry {
+            self.pid = None;
+            self.process_group = None;
+            return Err(RunCmdError::Generic(format!(
+                "The main process {} of service {} exited during startup with: {:?}",
+                pid, name, termination
+            )));
+        }
+        pid_table_locked.insert(pid, PidEntry::Service(id, ServiceType::Exec));
+        Ok(())
+    }
+
     pub fn kill_all_remaining_processes(&mut self, name: &str) {
         if let Some(proc_group) = self.process_group {
             // TODO handle these errors
@@ -253,7 +378,16 @@ impl Service {
         pid_table: ArcMutPidTable,
     ) -> Result<(), RunCmdError> {
         let stop_res = self.run_stop_cmd(id, name, pid_table.clone());
+        if let Err(RunCmdError::Timeout(_, _)) = stop_res {
+            self.timeout_triggered = true;
+        }
 
+        if self.service_config.srcv_type == ServiceType::Forking {
+            // the daemon most likely left the process group of the initial process
+            if let Some(pid) = self.pid {
+                let _ = nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGKILL);
+            }
+        }
         if self.service_config.srcv_type != ServiceType::OneShot {
             // already happened when the oneshot process exited in the exit handler
             self.kill_all_remaining_processes(name);
@@ -261,6 +395,7 @@ impl Service {
 
         self.pid = None;
         self.process_group = None;
+        self.watchdog_last_ping = None;
         stop_res
     }
 
@@ -280,6 +415,41 @@ impl Service {
         })
     }
 
+    /// Run the ExecReload= commands
+    pub fn reload(
+        &mut self,
+        id: UnitId,
+        name: &str,
+        pid_table: ArcMutPidTable,
+    ) -> Result<(), ServiceErrorReason> {
+        if self.service_config.reload.is_empty() {
+            return Err(ServiceErrorReason::Generic(format!(
+                "Service {} has no ExecReload= set",
+                name
+            )));
+        }
+        if self.pid.is_none() {
+            return Err(ServiceErrorReason::Generic(format!(
+                "Service {} is not running",
+                name
+            )));
+        }
+        let cmds = self.service_config.reload.clone();
+        let timeout = self.get_reload_timeout();
+        self.run_all_cmds(&cmds, id, name, timeout, pid_table)
+            .map_err(ServiceErrorReason::ReloadFailed)
+    }
+
+    /// The environment of the service plus $MAINPID if the main process is known. Used for the helper
+    /// processes and to expand variables in the command lines
+    pub fn cmd_env(&self) -> Vec<(String, String)> {
+        let mut env = self.environment.clone();
+        if let Some(pid) = self.pid {
+            super::environment::set_var(&mut env, "MAINPID", &format!("{}", pid));
+        }
+        env
+    }
+
     pub fn get_start_timeout(&self) -> Option<std::time::Duration> {
         if let Some(timeout) = &self.service_config.starttimeout {
             match timeout {
@@ -299,6 +469,95 @@ impl Service {
         }
     }
 
+    /// Describes the errno the service reported with ERRNO= if any
+    pub fn notified_errno_description(&self) -> Option<String> {
+        self.notified_errno.map(|errno| {
+            format!(
+                "errno {} ({})",
+                errno,
+                nix::errno::Errno::from_i32(errno).desc()
+            )
+        })
+    }
+
+    /// WatchdogSec=0 and WatchdogSec=infinity both disable the watchdog
+    pub fn get_watchdog_sec(&self) -> Option<std::time::Duration> {
+        match &self.service_config.watchdog_sec {
+            Some(Timeout::Duration(dur)) if dur.as_nanos() > 0 => Some(*dur),
+            _ => None,
+        }
+    }
+
+    /// Either the watchdog timed out or the service asked for it with WATCHDOG=trigger.
+    /// Like systemd we send SIGABRT to the main process and let the exit handler decide whether to restart the service.
+    pub fn trigger_watchdog(&mut self, name: &str) {
+        self.watchdog_triggered = true;
+        self.watchdog_last_ping = None;
+        if let Some(pid) = self.pid {
+            match nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGABRT) {
+                Ok(_) => trace!("Sent SIGABRT to service {} because of the watchdog", name),
+                Err(e) => error!(
+                    "Error sending SIGABRT to service {} because of the watchdog: {}",
+                    name, e
+                ),
+            }
+        }
+    }
+
+    /// None means the service should wait forever, so it should never be restarted
+    pub fn get_restart_sec(&self) -> Option<std::time::Duration> {
+        match &self.service_config.restart_sec {
+            Some(Timeout::Duration(dur)) => Some(*dur),
+            Some(Timeout::Infinity) => None,
+            None => Some(DEFAULT_RESTART_SEC),
+        }
+    }
+
+    /// Check whether another restart is allowed by the start rate limiting. If it is allowed it is recorded
+    /// and counts towards the limit for the next restarts.
+    pub fn record_restart_if_allowed(
+        &mut self,
+        interval: &Option<Timeout>,
+        burst: Option<u64>,
+    ) -> bool {
+        let burst = burst.unwrap_or(DEFAULT_START_LIMIT_BURST);
+        let interval = match interval {
+            Some(Timeout::Duration(dur)) => Some(*dur),
+            Some(Timeout::Infinity) => None,
+            None => Some(DEFAULT_START_LIMIT_INTERVAL),
+        };
+        let now = std::time::Instant::now();
+        match interval {
+            // an interval of 0 disables the rate limiting
+            Some(interval) if interval.as_nanos() == 0 => {}
+            Some(interval) => {
+                self.runtime_info
+                    .restart_times
+                    .retain(|time| now.duration_since(*time) < interval);
+                if self.runtime_info.restart_times.len() as u64 >= burst {
+                    return false;
+                }
+            }
+            None => {
+                if self.runtime_info.restart_times.len() as u64 >= burst {
+                    return false;
+                }
+            }
+        }
+        self.runtime_info.restart_times.push(now);
+        self.runtime_info.restarted += 1;
+        true
+    }
+
+    /// Without TimeoutReloadSec= the start timeout applies to the reload commands too
+    pub fn get_reload_timeout(&self) -> Option<std::time::Duration> {
+        match &self.service_config.reloadtimeout {
+            Some(Timeout::Duration(dur)) => Some(*dur),
+            Some(Timeout::Infinity) => None,
+            None => self.get_start_timeout(),
+        }
+    }
+
     fn get_stop_timeout(&self) -> Option<std::time::Duration> {
         if let Some(timeout) = &self.service_config.stoptimeout {
             match timeout {
@@ -320,16 +579,29 @@ impl Service {
 
     fn run_cmd(
         &mut self,
-        cmd_str: &str,
+        cmdline: &Commandline,
         id: UnitId,
         name: &str,
         timeout: Option<std::time::Duration>,
         pid_table: ArcMutPidTable,
     ) -> Result<(), RunCmdError> {
-        let split = cmd_str.split(' ').collect::<Vec<_>>();
-        let mut cmd = Command::new(split[0]);
-        for part in &split[1..] {
-            cmd.arg(part);
+        let cmd_str = &cmdline.to_string();
+        let env = self.cmd_env();
+        let mut cmd = Command::new(crate::units::resolve_executable(&cmdline.cmd));
+        if let Some(argv0) = cmdline.argv0() {
+            use std::os::unix::process::CommandExt;
+            cmd.arg0(argv0);
+        }
+        cmd.args(cmdline.expanded_args(&env));
+        cmd.env_clear();
+        cmd.envs(env);
+        let exec_config = self.service_config.exec_config.clone();
+        unsafe {
+            use std::os::unix::process::CommandExt;
+            cmd.pre_exec(move || {
+                super::fork_child::setup_exec_context(&exec_config)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))
+            });
         }
         cmd.stdout(Stdio::piped());
         cmd.stderr(Stdio::piped());
@@ -359,7 +631,8 @@ impl Service {
                         }
                         WaitResult::InTime(Ok(exitstatus)) => {
                             trace!("success running {} for service: {}", cmd_str, name);
-                            if exitstatus.success() {
+                            if exitstatus.success() || cmdline.has_prefix(CommandlinePrefix::Minus)
+                            {
                                 Ok(())
                             } else {
                                 Err(RunCmdError::BadExitCode(cmd_str.to_owned(), exitstatus))
@@ -403,7 +676,7 @@ impl Service {
 
     fn run_all_cmds(
         &mut self,
-        cmds: &Vec<String>,
+        cmds: &[Commandline],
         id: UnitId,
         name: &str,
         timeout: Option<std::time::Duration>,
@@ -2,23 +2,28 @@ use super::fork_child;
 use crate::fd_store::FDStore;
 use crate::services::RunCmdError;
 use crate::services::Service;
+use crate::units::{Commandline, ServiceType, StandardInput, StandardOutput};
+use std::os::unix::io::RawFd;
 
 fn start_service_with_filedescriptors(
     srvc: &mut Service,
     name: &str,
     fd_store: &FDStore,
-) -> Result<(), RunCmdError> {
-    // check if executable even exists
-    let split: Vec<&str> = srvc.service_config.exec.split(' ').collect();
-
-    let cmd = std::path::PathBuf::from(split[0]);
+    cmdline: &Commandline,
+) -> Result<Option<PendingExec>, RunCmdError> {
+    // check if executable even exists. With RootDirectory= it has to exist inside of the new root
+    let cmd = crate::units::resolve_executable(&cmdline.cmd);
+    let cmd = match &srvc.service_config.exec_config.root_directory {
+        Some(root) => root.join(cmd.strip_prefix("/").unwrap_or(&cmd)),
+        None => cmd,
+    };
     if !cmd.exists() {
         error!(
             "The service {} specified an executable that does not exist: {:?}",
             name, &cmd
         );
         return Err(RunCmdError::SpawnError(
-            split[0].to_owned(),
+            cmdline.cmd.clone(),
             format!("Executable does not exist"),
         ));
     }
@@ -28,11 +33,13 @@ fn start_service_with_filedescriptors(
             name, &cmd
         );
         return Err(RunCmdError::SpawnError(
-            split[0].to_owned(),
+            cmdline.cmd.clone(),
             format!("Executable does not exist (is a directory)"),
         ));
     }
 
+    check_stdio_sockets(srvc, name, fd_store)?;
+
     // 1. fork
     // 1. in fork use dup2 to map all relevant file desrciptors to 3..x
     // 1. in fork mark all other file descriptors with FD_CLOEXEC
@@ -42,11 +49,27 @@ fn start_service_with_filedescriptors(
 
     super::fork_os_specific::pre_fork_os_specific(srvc).map_err(|e| RunCmdError::Generic(e))?;
 
+    // For Type=exec the child reports a failing execv over this pipe. The write end gets closed
+    // by a successful exec because of O_CLOEXEC, so reading EOF means the exec worked.
+    let exec_pipe = if srvc.service_config.srcv_type == ServiceType::Exec {
+        Some(
+            nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC).map_err(|e| {
+                RunCmdError::Generic(format!("Could not create pipe for Type=exec: {}", e))
+            })?,
+        )
+    } else {
+        None
+    };
+
     // make sure we have the lock that the child will need
     match nix::unistd::fork() {
         Ok(nix::unistd::ForkResult::Parent { child, .. }) => {
             srvc.pid = Some(child);
             srvc.process_group = Some(nix::unistd::Pid::from_raw(-child.as_raw()));
+            if let Some((read_end, write_end)) = exec_pipe {
+                let _ = nix::unistd::close(write_end);
+                return Ok(Some(PendingExec(read_end)));
+            }
         }
         Ok(nix::unistd::ForkResult::Child) => {
             let notifications_path = {
@@ -56,40 +79,93 @@ fn start_service_with_filedescriptors(
                     unreachable!();
                 }
             };
-            let stdout = {
-                if let Some(rwpair) = &srvc.stdout_dup {
-                    rwpair.1
-                } else {
-                    unreachable!();
-                }
-            };
-            let stderr = {
-                if let Some(rwpair) = &srvc.stderr_dup {
-                    rwpair.1
-                } else {
-                    unreachable!();
-                }
-            };
             fork_child::after_fork_child(
                 srvc,
                 &name,
                 fd_store,
                 &notifications_path,
-                stdout,
-                stderr,
+                exec_pipe.map(|(_, write_end)| write_end),
+                cmdline,
             );
         }
         Err(e) => error!("Fork for service: {} failed with: {}", name, e),
     }
+    Ok(None)
+}
+
+/// StandardInput=socket etc. only work if there is exactly one socket. Check that before forking, errors of
+/// the child are not easily visible
+fn check_stdio_sockets(srvc: &Service, name: &str, fd_store: &FDStore) -> Result<(), RunCmdError> {
+    let exec_config = &srvc.service_config.exec_config;
+    let needs_single_socket = exec_config.stdin == StandardInput::Socket
+        || exec_config.stdout == StandardOutput::Socket
+        || exec_config.stderr == StandardOutput::Socket;
+    if !needs_single_socket {
+        return Ok(());
+    }
+    let socket_count: usize = srvc
+        .socket_names
+        .iter()
+        .filter_map(|socket| fd_store.get_global(socket))
+        .map(|fds| fds.len())
+        .sum::<usize>()
+        + fd_store.get_connection(name).map_or(0, |_| 1);
+    if socket_count != 1 {
+        return Err(RunCmdError::Generic(format!(
+            "Connecting stdio to the socket needs exactly one socket but there are {}",
+            socket_count
+        )));
+    }
+    Ok(())
+}
+
+/// Wait until the child either exec'd (EOF on the pipe) or reported the errno of the failed execv
+/// The read end of the pipe the child of a Type=exec service reports a failing execv on
+pub struct PendingExec(RawFd);
+
+/// Block until the child of a Type=exec service called execv. This can take a while, so the pid table must not be
+/// locked while waiting.
+pub fn wait_for_exec(
+    srvc: &mut Service,
+    pending: PendingExec,
+    cmdline: &Commandline,
+) -> Result<(), RunCmdError> {
+    let exec_result = read_exec_result(pending.0);
+    let _ = nix::unistd::close(pending.0);
+    if let Err(errno) = exec_result {
+        srvc.pid = None;
+        srvc.process_group = None;
+        return Err(RunCmdError::ExecFailed(cmdline.to_string(), errno));
+    }
     Ok(())
 }
 
+fn read_exec_result(read_end: RawFd) -> Result<(), nix::errno::Errno> {
+    let mut buf = [0u8; 4];
+    let mut bytes = 0;
+    while bytes < buf.len() {
+        match nix::unistd::read(read_end, &mut buf[bytes..]) {
+            Ok(0) => break,
+            Ok(n) => bytes += n,
+            Err(nix::Error::Sys(nix::errno::Errno::EINTR)) => continue,
+            Err(_) => break,
+        }
+    }
+    if bytes == buf.len() {
+        Err(nix::errno::Errno::from_i32(i32::from_ne_bytes(buf)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Fork the main process. For Type=exec the caller has to finish the start with wait_for_exec
 pub fn start_service(
     srvc: &mut Service,
     name: &str,
     fd_store: &FDStore,
-) -> Result<(), super::RunCmdError> {
-    start_service_with_filedescriptors(srvc, name, fd_store)?;
+    cmdline: &Commandline,
+) -> Result<Option<PendingExec>, super::RunCmdError> {
+    let pending_exec = start_service_with_filedescriptors(srvc, name, fd_store, cmdline)?;
     srvc.runtime_info.up_since = Some(std::time::Instant::now());
-    Ok(())
+    Ok(pending_exec)
 }
@@ -111,7 +111,7 @@ pub fn shutdown_sequence(run_info: ArcRuntimeInfo) {
                 UnitSpecialized::Socket(_) => {
                     // closed below
                 }
-                UnitSpecialized::Target => {
+                UnitSpecialized::Target | UnitSpecialized::Timer(_) | UnitSpecialized::Path(_) => {
                     // Nothing to do
                 }
             }
@@ -166,7 +166,7 @@ pub fn shutdown_sequence(run_info: ArcRuntimeInfo) {
                         *status_locked = UnitStatus::Stopping;
                     }
                 }
-                UnitSpecialized::Target => {
+                UnitSpecialized::Target | UnitSpecialized::Timer(_) | UnitSpecialized::Path(_) => {
                     // Nothing to do
                 }
             }
@@ -1,7 +1,9 @@
 //! Wait for sockets to activate their respective services
 
 use crate::platform::EventFd;
+use crate::sockets::Connection;
 use crate::units::*;
+use std::os::unix::io::RawFd;
 
 pub fn start_socketactivation_thread(
     run_info: ArcRuntimeInfo,
@@ -16,7 +18,19 @@ pub fn start_socketactivation_thread(
             run_info.fd_store.clone(),
         ) {
             Ok(ids) => {
-                for socket_id in ids {
+                for (socket_id, fd) in ids {
+                    if is_accepting(socket_id, &run_info) {
+                        if let Err(e) = accept_connection(
+                            socket_id,
+                            fd,
+                            run_info.clone(),
+                            note_sock_path.clone(),
+                            eventfds.clone(),
+                        ) {
+                            error!("Error while accepting a connection: {}", e);
+                        }
+                        continue;
+                    }
                     let unit_table_locked = run_info.unit_table.read().unwrap();
                     {
                         let socket_name = {
@@ -64,31 +78,31 @@ pub fn start_socketactivation_thread(
                                         sock.activated = true;
                                     }
                                 } else {
-                                    match crate::units::activate_unit(
-                                        srvc_unit_id,
-                                        run_info.clone(),
-                                        note_sock_path.clone(),
-                                        eventfds.clone(),
-                                        false,
-                                    ) {
-                                        Ok(_) => {
-                                            let sock_unit =
-                                                unit_table_locked.get(&socket_id).unwrap();
-                                            let mut sock_unit_locked = sock_unit.lock().unwrap();
-                                            if let crate::units::UnitSpecialized::Socket(sock) =
-                                                &mut sock_unit_locked.specialized
-                                            {
-                                                sock.activated = true;
-                                            }
-                                        }
-                                        Err(e) => {
-                                            format!(
+                                    let sock_unit = unit_table_locked.get(&socket_id).unwrap();
+                                    let mut sock_unit_locked = sock_unit.lock().unwrap();
+                                    if let crate::units::UnitSpecialized::Socket(sock) =
+                                        &mut sock_unit_locked.specialized
+                                    {
+                                        sock.activated = true;
+                                    }
+                                    let run_info = run_info.clone();
+                                    let note_sock_path = note_sock_path.clone();
+                                    let eventfds = eventfds.clone();
+                                    // the start job waits for it's turn, don't hold up the other sockets meanwhile
+                                    std::thread::spawn(move || {
+                                        if let Err(e) = crate::units::run_unit_job(
+                                            srvc_unit_id,
+                                            crate::units::JobKind::Start,
+                                            run_info,
+                                            note_sock_path,
+                                            eventfds,
+                                        ) {
+                                            error!(
                                                 "Error while starting service from socket activation: {}",
                                                 e
                                             );
-                                            break;
                                         }
-                                    }
+                                    });
                                 }
                             }
                         }
@@ -107,7 +121,7 @@ pub fn wait_for_socket(
     eventfd: EventFd,
     unit_table: ArcMutUnitTable,
     fd_store: ArcMutFDStore,
-) -> Result<Vec<UnitId>, String> {
+) -> Result<Vec<(UnitId, RawFd)>, String> {
     let fd_to_sock_id = fd_store.read().unwrap().global_fds_to_ids();
 
     let mut fdset = nix::sys::select::FdSet::new();
@@ -136,7 +150,7 @@ pub fn wait_for_socket(
             } else {
                 for (fd, id) in &fd_to_sock_id {
                     if fdset.contains(*fd) {
-                        activated_ids.push(*id);
+                        activated_ids.push((*id, *fd));
                     }
                 }
             }
@@ -151,3 +165,224 @@ pub fn wait_for_socket(
         }
     }
 }
+
+fn is_accepting(socket_id: UnitId, run_info: &ArcRuntimeInfo) -> bool {
+    let unit_table_locked = run_info.unit_table.read().unwrap();
+    match unit_table_locked.get(&socket_id) {
+        Some(unit) => match &unit.lock().unwrap().specialized {
+            UnitSpecialized::Socket(sock) => sock.accept.is_some(),
+            _ => false,
+        },
+        None => false,
+    }
+}
+
+/// Describe the peer of a connection. Returns the part of the instance name and the source that is
+/// counted for MaxConnectionsPerSource=
+fn describe_peer(conn_fd: RawFd) -> (String, String) {
+    // nix can not convert the unnamed addresses of unix socket peers, so only ask it for ip addresses
+    if is_inet_socket(conn_fd) {
+        if let Ok(nix::sys::socket::SockAddr::Inet(addr)) = nix::sys::socket::getpeername(conn_fd) {
+            let addr = addr.to_std();
+            return (addr.to_string(), addr.ip().to_string());
+        }
+    }
+    describe_unix_peer(conn_fd)
+}
+
+fn is_inet_socket(conn_fd: RawFd) -> bool {
+    let mut addr: libc::sockaddr_storage = unsafe { std::mem::zeroed() };
+    let mut len = std::mem::size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+    let res = unsafe {
+        libc::getsockname(
+            conn_fd,
+            &mut addr as *mut libc::sockaddr_storage as *mut libc::sockaddr,
+            &mut len,
+        )
+    };
+    res == 0
+        && (i32::from(addr.ss_family) == libc::AF_INET
+            || i32::from(addr.ss_family) == libc::AF_INET6)
+}
+
+#[cfg(target_os = "linux")]
+fn describe_unix_peer(conn_fd: RawFd) -> (String, String) {
+    match nix::sys::socket::getsockopt(conn_fd, nix::sys::socket::sockopt::PeerCredentials) {
+        Ok(creds) => (
+            format!("{}-{}", creds.pid(), creds.uid()),
+            creds.uid().to_string(),
+        ),
+        Err(_) => ("unknown".to_owned(), "unknown".to_owned()),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn describe_unix_peer(_conn_fd: RawFd) -> (String, String) {
+    ("unknown".to_owned(), "unknown".to_owned())
+}
+
+/// Accept a connection on a socket with Accept=yes and start a new instance of the template service
+/// (foo@.service for foo.socket) that gets the connection as fd 3
+fn accept_connection(
+    socket_id: UnitId,
+    listen_fd: RawFd,
+    run_info: ArcRuntimeInfo,
+    note_sock_path: std::path::PathBuf,
+    eventfds: std::sync::Arc<Vec<EventFd>>,
+) -> Result<(), String> {
+    let conn_fd = nix::sys::socket::accept(listen_fd)
+        .map_err(|e| format!("Could not accept connection: {}", e))?;
+    nix::fcntl::fcntl(
+        conn_fd,
+        nix::fcntl::FcntlArg::F_SETFD(nix::fcntl::FdFlag::FD_CLOEXEC),
+    )
+    .map_err(|e| format!("Could not set FD_CLOEXEC on connection: {}", e))?;
+    let (peer, source) = describe_peer(conn_fd);
+
+    let this_id = {
+        let last_id = &mut *run_info.last_id.lock().unwrap();
+        *last_id += 1;
+        *last_id
+    };
+
+    // check the limits and reserve the instance name
+    let (srvc_name, fd_name) = {
+        let unit_table_locked = run_info.unit_table.read().unwrap();
+        let sock_unit = unit_table_locked.get(&socket_id).unwrap();
+        let mut sock_unit_locked = sock_unit.lock().unwrap();
+        let prefix = sock_unit_locked.conf.name_without_suffix();
+        let sock = match &mut sock_unit_locked.specialized {
+            UnitSpecialized::Socket(sock) => sock,
+            _ => unreachable!(),
+        };
+        let limits = sock.accept.unwrap();
+        let from_source = sock
+            .connections
+            .iter()
+            .filter(|conn| conn.source == source)
+            .count();
+        if sock.connections.len() >= limits.max_connections
+            || from_source >= limits.max_connections_per_source
+        {
+            let _ = nix::unistd::close(conn_fd);
+            warn!(
+                "Refused connection from {} on socket {}. Too many connections",
+                peer, prefix
+            );
+            return Ok(());
+        }
+        let srvc_name = format!("{}@{}-{}.service", prefix, sock.accepted, peer);
+        sock.accepted += 1;
+        sock.connections.push(Connection {
+            service_name: srvc_name.clone(),
+            service_id: UnitId(UnitIdKind::Service, this_id),
+            source,
+        });
+        (srvc_name, sock.name.clone())
+    };
+    run_info
+        .fd_store
+        .write()
+        .unwrap()
+        .insert_connection(srvc_name.clone(), fd_name, conn_fd);
+
+    trace!("Start {} for new connection from {}", srvc_name, peer);
+    // starting a oneshot service blocks until it is done, don't hold up the other sockets
+    std::thread::spawn(move || {
+        if let Err(e) =
+            start_connection_service(this_id, &srvc_name, run_info, note_sock_path, eventfds)
+        {
+            error!(
+                "Error while starting {} for connection from {}: {}",
+                srvc_name, peer, e
+            );
+        }
+    });
+    Ok(())
+}
+
+/// Load and start the service instance for an accepted connection. The connection is closed if the start fails
+/// or if the service is a oneshot that is already done.
+fn start_connection_service(
+    this_id: u64,
+    srvc_name: &str,
+    run_info: ArcRuntimeInfo,
+    note_sock_path: std::path::PathBuf,
+    eventfds: std::sync::Arc<Vec<EventFd>>,
+) -> Result<(), String> {
+    let srvc_id = UnitId(UnitIdKind::Service, this_id);
+    let start_result = load_new_unit(&run_info.config.unit_dirs, srvc_name, this_id)
+        .and_then(|unit| insert_new_unit(unit, run_info.clone()))
+        .and_then(|_| {
+            run_unit_job(
+                srvc_id,
+                JobKind::Start,
+                run_info.clone(),
+                note_sock_path,
+                eventfds,
+            )
+        });
+    if let Err(e) = start_result {
+        finish_connection(srvc_id, &run_info);
+        return Err(e);
+    }
+
+    // oneshot services are done at this point, the others are cleaned up when their main process exits
+    let finished = match run_info.status_table.read().unwrap().get(&srvc_id) {
+        Some(status) => !status.lock().unwrap().is_active(),
+        None => true,
+    };
+    let oneshot = match run_info.unit_table.read().unwrap().get(&srvc_id) {
+        Some(unit) => match &unit.lock().unwrap().specialized {
+            UnitSpecialized::Service(srvc) => srvc.service_config.srcv_type == ServiceType::OneShot,
+            _ => false,
+        },
+        None => false,
+    };
+    if finished && oneshot {
+        finish_connection(srvc_id, &run_info);
+    }
+    Ok(())
+}
+
+/// If this service handled a connection for a socket with Accept=yes close the connection and forget
+/// about the service instance. Does nothing for all other services.
+pub fn finish_connection(srvc_id: UnitId, run_info: &ArcRuntimeInfo) {
+    let mut srvc_name = None;
+    {
+        let unit_table_locked = run_info.unit_table.read().unwrap();
+        for unit in unit_table_locked.values() {
+            let mut unit_locked = unit.lock().unwrap();
+            if let UnitSpecialized::Socket(sock) = &mut unit_locked.specialized {
+                if let Some(idx) = sock
+                    .connections
+                    .iter()
+                    .position(|conn| conn.service_id == srvc_id)
+                {
+                    srvc_name = Some(sock.connections.remove(idx).service_name);
+                }
+            }
+        }
+    }
+    let srvc_name = match srvc_name {
+        Some(name) => name,
+        None => return,
+    };
+    trace!("Connection handled by {} is finished", srvc_name);
+
+    if let Some((_, fd)) = run_info
+        .fd_store
+        .write()
+        .unwrap()
+        .remove_connection(&srvc_name)
+    {
+        let _ = nix::unistd::close(fd);
+    }
+
+    let unit_table_locked = &mut *run_info.unit_table.write().unwrap();
+    unit_table_locked.remove(&srvc_id);
+    for unit in unit_table_locked.values() {
+        unit.lock().unwrap().install.remove_references(srvc_id);
+    }
+    run_info.status_table.write().unwrap().remove(&srvc_id);
+}
@@ -264,6 +264,22 @@ impl UdpSocketConfig {
     }
 }
 
+/// Limits for sockets with Accept=yes
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub struct AcceptConfig {
+    pub max_connections: usize,
+    pub max_connections_per_source: usize,
+}
+
+/// A connection accepted by a socket with Accept=yes and the service instance that handles it
+#[derive(Clone, Debug)]
+pub struct Connection {
+    pub service_name: String,
+    pub service_id: UnitId,
+    /// The peer address for ip sockets, the uid of the peer for unix sockets
+    pub source: String,
+}
+
 #[derive(Clone, Debug)]
 pub struct Socket {
     pub name: String,
@@ -271,6 +287,11 @@ pub struct Socket {
     pub sockets: Vec<SocketConfig>,
     pub services: Vec<String>,
     pub activated: bool,
+    /// Set for Accept=yes. Then each connection is handled by a new instance of the template service
+    pub accept: Option<AcceptConfig>,
+    pub connections: Vec<Connection>,
+    /// Number of connections accepted so far, used to name the service instances
+    pub accepted: u64,
 }
 
 impl Socket {
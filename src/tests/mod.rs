@@ -81,19 +81,2194 @@ fn test_service_parsing() {
         panic!("No install config found, but there should be one");
     }
     if let crate::units::UnitSpecialized::Service(srvc) = service.specialized {
-        assert_eq!(srvc.service_config.exec, service_execstart);
-        assert_eq!(srvc.service_config.startpre, vec![service_execpre]);
-        assert_eq!(srvc.service_config.startpost, vec![service_execpost]);
-        assert_eq!(srvc.service_config.stop, vec![service_stop]);
+        assert_eq!(
+            srvc.service_config.exec,
+            vec![crate::units::Commandline {
+                cmd: "/path/to/startbin".into(),
+                args: vec!["arg1".into(), "arg2".into(), "arg3".into()],
+                prefixes: vec![],
+            }]
+        );
+        let to_strings = |cmds: &Vec<crate::units::Commandline>| {
+            cmds.iter().map(|cmd| cmd.to_string()).collect::<Vec<_>>()
+        };
+        assert_eq!(
+            to_strings(&srvc.service_config.exec),
+            vec![service_execstart]
+        );
+        assert_eq!(
+            to_strings(&srvc.service_config.startpre),
+            vec![service_execpre]
+        );
+        assert_eq!(
+            to_strings(&srvc.service_config.startpost),
+            vec![service_execpost]
+        );
+        assert_eq!(to_strings(&srvc.service_config.stop), vec![service_stop]);
         assert_eq!(
             srvc.service_config.sockets,
             vec!["socket_name1".to_owned(), "socket_name2".to_owned()]
         );
     } else {
-        panic!("Not a service, but it should be");
+        panic!("Not a service, but it should be");
+    }
+}
+
+#[test]
+fn test_cmdline_parsing() {
+    use crate::units::{parse_cmdlines, Commandline, CommandlinePrefix};
+
+    let cmds = parse_cmdlines(r#"echo one ; echo "two two""#).unwrap();
+    assert_eq!(
+        cmds,
+        vec![
+            Commandline {
+                cmd: "echo".into(),
+                args: vec!["one".into()],
+                prefixes: vec![],
+            },
+            Commandline {
+                cmd: "echo".into(),
+                args: vec!["two two".into()],
+                prefixes: vec![],
+            },
+        ]
+    );
+
+    // escaped semicolons and line continuation are handled by parse_file
+    let parsed_file = crate::units::parse_file(
+        "[Service]\nExecStart=echo / >/dev/null & \\; \\\nls\nExecStop=/bin/kill -HUP $MAINPID\n",
+    )
+    .unwrap();
+    let section = parsed_file.get("[Service]").unwrap();
+    let cmds = parse_cmdlines(&section.get("EXECSTART").unwrap()[0].1).unwrap();
+    assert_eq!(cmds.len(), 1);
+    assert_eq!(cmds[0].cmd, "echo");
+    assert_eq!(cmds[0].args, vec!["/", ">/dev/null", "&", ";", "ls"]);
+    // values are no longer split on commas
+    assert_eq!(section.get("EXECSTOP").unwrap().len(), 1);
+
+    let cmds = parse_cmdlines(r#"sh -c 'dmesg | tac'"#).unwrap();
+    assert_eq!(cmds[0].args, vec!["-c", "dmesg | tac"]);
+
+    let cmds = parse_cmdlines(r#"/bin/echo a,b "\x41\102\s\t" \\"#).unwrap();
+    assert_eq!(cmds[0].args, vec!["a,b", "AB \t", "\\"]);
+    let cmds = parse_cmdlines(r#"/bin/echo \xc3\xa9 "caf\303\251""#).unwrap();
+    assert_eq!(cmds[0].args, vec!["é", "café"]);
+
+    let cmds = parse_cmdlines("-@/bin/sleep sleepy 10").unwrap();
+    assert_eq!(
+        cmds[0].prefixes,
+        vec![CommandlinePrefix::Minus, CommandlinePrefix::AtSign]
+    );
+    assert_eq!(cmds[0].cmd, "/bin/sleep");
+    assert_eq!(cmds[0].argv0(), Some("sleepy"));
+    assert_eq!(cmds[0].expanded_args(&[]), vec!["10"]);
+    assert_eq!(cmds[0].to_string(), "-@/bin/sleep sleepy 10");
+
+    let cmds = parse_cmdlines("!!:/bin/true").unwrap();
+    assert_eq!(
+        cmds[0].prefixes,
+        vec![
+            CommandlinePrefix::DoubleExclamation,
+            CommandlinePrefix::Colon
+        ]
+    );
+    assert!(cmds[0].keeps_privileges());
+
+    assert!(parse_cmdlines("+!/bin/true").is_err());
+    assert!(parse_cmdlines("--/bin/true").is_err());
+    assert!(parse_cmdlines("@/bin/true").is_err());
+    assert!(parse_cmdlines(r#"/bin/echo "unclosed"#).is_err());
+    assert!(parse_cmdlines(r#"/bin/echo "a"b"#).is_err());
+    assert!(parse_cmdlines(r#"/bin/echo a\x00b"#).is_err());
+    assert!(parse_cmdlines(r#"/bin/echo "a\0""#).is_err());
+    assert!(parse_cmdlines(r#"/bin/echo \xff"#).is_err());
+    assert!(crate::units::parse_cmdlines_setting(
+        "ExecStart",
+        Some(vec![((0, 0), r#"/bin/echo \000"#.to_owned())])
+    )
+    .is_err());
+}
+
+#[test]
+fn test_cmdline_env_expansion() {
+    use crate::units::parse_cmdlines;
+
+    let env = vec![
+        ("ONE".to_owned(), "one".to_owned()),
+        ("TWO".to_owned(), "two two".to_owned()),
+    ];
+    let cmds = parse_cmdlines("echo $ONE $TWO ${TWO}").unwrap();
+    assert_eq!(
+        cmds[0].expanded_args(&env),
+        vec!["one", "two", "two", "two two"]
+    );
+
+    let env = vec![
+        ("ONE".to_owned(), "'one'".to_owned()),
+        ("TWO".to_owned(), "'two two' too".to_owned()),
+        ("THREE".to_owned(), "".to_owned()),
+    ];
+    let cmds = parse_cmdlines("/bin/echo ${ONE} ${TWO} ${THREE}").unwrap();
+    assert_eq!(
+        cmds[0].expanded_args(&env),
+        vec!["'one'", "'two two' too", ""]
+    );
+    let cmds = parse_cmdlines("/bin/echo $ONE $TWO $THREE").unwrap();
+    assert_eq!(cmds[0].expanded_args(&env), vec!["one", "two two", "too"]);
+
+    let cmds = parse_cmdlines("/bin/echo $$ONE a${UNKNOWN}b x$ONE").unwrap();
+    assert_eq!(cmds[0].expanded_args(&env), vec!["$ONE", "ab", "x$ONE"]);
+
+    // no expansion with the ':' prefix
+    let cmds = parse_cmdlines(":/bin/echo $ONE ${TWO}").unwrap();
+    assert_eq!(cmds[0].expanded_args(&env), vec!["$ONE", "${TWO}"]);
+}
+
+#[test]
+fn test_oneshot_multiple_execstart() {
+    let parse = |content: &str| {
+        let parsed_file = crate::units::parse_file(content).unwrap();
+        crate::units::parse_service(
+            parsed_file,
+            &std::path::PathBuf::from("/path/to/unitfile.service"),
+            crate::units::UnitId(crate::units::UnitIdKind::Service, 10),
+        )
+    };
+
+    let service = parse(
+        r#"
+    [Service]
+    Type = oneshot
+    RemainAfterExit = yes
+    ExecStart = /bin/false
+    ExecStart =
+    ExecStart = /bin/echo one
+    ExecStart = -/bin/echo two ; /bin/echo three
+    "#,
+    )
+    .unwrap();
+    if let crate::units::UnitSpecialized::Service(srvc) = service.specialized {
+        let cmds = srvc
+            .service_config
+            .exec
+            .iter()
+            .map(|cmd| cmd.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            cmds,
+            vec!["/bin/echo one", "-/bin/echo two", "/bin/echo three"]
+        );
+        assert!(srvc.service_config.remain_after_exit);
+    } else {
+        panic!("Not a service, but it should be");
+    }
+
+    // only oneshot services may have more than one command
+    let err = parse(
+        r#"
+    [Service]
+    ExecStart = /bin/echo one
+    ExecStart = /bin/echo two
+    "#,
+    );
+    match err {
+        Err(crate::units::ParsingErrorReason::SettingTooManyValues(name, _)) => {
+            assert_eq!(name, "ExecStart")
+        }
+        _ => panic!("Multiple ExecStart= for a simple service should not be accepted"),
+    }
+}
+
+#[test]
+fn test_environment_settings() {
+    let dir = std::env::temp_dir().join(format!("rustysd_env_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let env_file = dir.join("env");
+    std::fs::write(
+        &env_file,
+        "# comment\n; comment\n\nFROM_FILE=file\nOVERRIDDEN = \"from \\\"file\\\"\"\nSINGLE='a b'\nCONTINUED=one\\\ntwo\ninvalid line\n",
+    )
+    .unwrap();
+    std::env::set_var("RUSTYSD_TEST_PASSED", "passed");
+
+    let content = format!(
+        r#"
+    [Service]
+    ExecStart = /bin/true
+    Environment = "ONE=one" 'TWO=two two' OVERRIDDEN=unit
+    Environment = THREE=3 UNSET_ME=1 UNSET_EXACT=x
+    EnvironmentFile = {}
+    EnvironmentFile = -/this/does/not/exist
+    PassEnvironment = RUSTYSD_TEST_PASSED RUSTYSD_TEST_NOT_SET
+    UnsetEnvironment = UNSET_ME UNSET_EXACT=y
+    "#,
+        env_file.to_str().unwrap()
+    );
+    let parsed_file = crate::units::parse_file(&content).unwrap();
+    let service = crate::units::parse_service(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/unitfile.service"),
+        crate::units::UnitId(crate::units::UnitIdKind::Service, 10),
+    )
+    .unwrap();
+    let exec_config = if let crate::units::UnitSpecialized::Service(srvc) = service.specialized {
+        srvc.service_config.exec_config
+    } else {
+        panic!("Not a service, but it should be");
+    };
+    assert_eq!(exec_config.environment_files.len(), 2);
+    assert!(!exec_config.environment_files[0].1);
+    assert!(exec_config.environment_files[1].1);
+
+    let env = crate::services::build_environment(&exec_config).unwrap();
+    let get = |key: &str| {
+        env.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    };
+    assert!(get("PATH").is_some());
+    assert_eq!(get("RUSTYSD_TEST_PASSED"), Some("passed"));
+    assert_eq!(get("RUSTYSD_TEST_NOT_SET"), None);
+    assert_eq!(get("ONE"), Some("one"));
+    assert_eq!(get("TWO"), Some("two two"));
+    assert_eq!(get("THREE"), Some("3"));
+    assert_eq!(get("FROM_FILE"), Some("file"));
+    assert_eq!(get("OVERRIDDEN"), Some("from \"file\""));
+    assert_eq!(get("SINGLE"), Some("a b"));
+    assert_eq!(get("CONTINUED"), Some("onetwo"));
+    assert_eq!(get("UNSET_ME"), None);
+    assert_eq!(get("UNSET_EXACT"), Some("x"));
+    // nothing else from the environment of the test process
+    assert_eq!(get("HOME"), None);
+
+    // a missing file without the '-' prefix is an error
+    let mut exec_config = exec_config;
+    exec_config.environment_files = vec![(dir.join("missing"), false)];
+    assert!(crate::services::build_environment(&exec_config).is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_exec_context_settings() {
+    let parse = |settings: &str| {
+        let content = format!("[Service]\nExecStart = /bin/true\n{}", settings);
+        let parsed_file = crate::units::parse_file(&content).unwrap();
+        crate::units::parse_service(
+            parsed_file,
+            &std::path::PathBuf::from("/path/to/unitfile.service"),
+            crate::units::UnitId(crate::units::UnitIdKind::Service, 10),
+        )
+        .map(|unit| {
+            if let crate::units::UnitSpecialized::Service(srvc) = unit.specialized {
+                srvc.service_config.exec_config
+            } else {
+                panic!("Not a service, but it should be");
+            }
+        })
+    };
+
+    let exec_config =
+        parse("WorkingDirectory = -/var/lib/foo\nRootDirectory = /srv/root\nUMask = 0027\n")
+            .unwrap();
+    assert_eq!(
+        exec_config.working_directory,
+        Some((std::path::PathBuf::from("/var/lib/foo"), true))
+    );
+    assert_eq!(
+        exec_config.root_directory,
+        Some(std::path::PathBuf::from("/srv/root"))
+    );
+    assert_eq!(
+        exec_config.umask,
+        Some(nix::sys::stat::Mode::from_bits_truncate(0o027))
+    );
+
+    // the home of root if no user is set
+    let exec_config = parse("WorkingDirectory = ~\n").unwrap();
+    let (dir, optional) = exec_config.working_directory.unwrap();
+    assert!(dir.is_absolute());
+    assert!(!optional);
+
+    let exec_config = parse("").unwrap();
+    assert_eq!(exec_config.working_directory, None);
+    assert_eq!(exec_config.umask, None);
+
+    assert!(parse("WorkingDirectory = relative/path\n").is_err());
+    assert!(parse("RootDirectory = relative/path\n").is_err());
+    assert!(parse("UMask = 0999\n").is_err());
+    assert!(parse("UMask = 01777\n").is_err());
+}
+
+#[test]
+fn test_stdio_settings() {
+    use crate::units::{StandardInput, StandardOutput};
+    let parse = |settings: &str| {
+        let content = format!("[Service]\nExecStart = /bin/true\n{}", settings);
+        let parsed_file = crate::units::parse_file(&content).unwrap();
+        crate::units::parse_service(
+            parsed_file,
+            &std::path::PathBuf::from("/path/to/unitfile.service"),
+            crate::units::UnitId(crate::units::UnitIdKind::Service, 10),
+        )
+        .map(|unit| {
+            if let crate::units::UnitSpecialized::Service(srvc) = unit.specialized {
+                let conf = srvc.service_config.exec_config;
+                (conf.stdin, conf.stdout, conf.stderr)
+            } else {
+                panic!("Not a service, but it should be");
+            }
+        })
+    };
+
+    assert_eq!(
+        parse("").unwrap(),
+        (
+            StandardInput::Null,
+            StandardOutput::Journal,
+            StandardOutput::Inherit
+        )
+    );
+    // stdout follows stdin if that is a socket
+    assert_eq!(
+        parse("StandardInput = socket\n").unwrap(),
+        (
+            StandardInput::Socket,
+            StandardOutput::Inherit,
+            StandardOutput::Inherit
+        )
+    );
+    assert_eq!(
+        parse("StandardInput = file:/etc/input\nStandardOutput = append:/var/log/out\nStandardError = truncate:/var/log/err\n")
+            .unwrap(),
+        (
+            StandardInput::File("/etc/input".into()),
+            StandardOutput::Append("/var/log/out".into()),
+            StandardOutput::Truncate("/var/log/err".into())
+        )
+    );
+    assert_eq!(
+        parse(
+            "StandardInput = tty\nStandardOutput = file:/var/log/out\nStandardError = fd:errsock\n"
+        )
+        .unwrap(),
+        (
+            StandardInput::Tty,
+            StandardOutput::File("/var/log/out".into()),
+            StandardOutput::Fd("errsock".into())
+        )
+    );
+    assert_eq!(
+        parse("StandardOutput = null\nStandardError = socket\n").unwrap(),
+        (
+            StandardInput::Null,
+            StandardOutput::Null,
+            StandardOutput::Socket
+        )
+    );
+
+    assert!(parse("StandardInput = journal\n").is_err());
+    assert!(parse("StandardOutput = file:relative/path\n").is_err());
+    assert!(parse("StandardError = fd:\n").is_err());
+}
+
+#[test]
+fn test_restart_policy() {
+    use crate::services::{is_clean_exit, restart_policy_applies, ExitReason};
+    use crate::signal_handler::ChildTermination;
+    use crate::units::{ServiceRestart, Timeout, UnitSpecialized};
+    use nix::sys::signal::Signal;
+    use std::time::Duration;
+
+    let clean_exit = ChildTermination::Exit(0);
+    let unclean_exit = ChildTermination::Exit(1);
+    let clean_signal = ChildTermination::Signal(Signal::SIGTERM);
+    let unclean_signal = ChildTermination::Signal(Signal::SIGKILL);
+    let abort = ChildTermination::Signal(Signal::SIGABRT);
+
+    assert!(is_clean_exit(clean_exit));
+    assert!(!is_clean_exit(unclean_exit));
+    assert!(is_clean_exit(clean_signal));
+    assert!(is_clean_exit(ChildTermination::Signal(Signal::SIGHUP)));
+    assert!(is_clean_exit(ChildTermination::Signal(Signal::SIGINT)));
+    assert!(is_clean_exit(ChildTermination::Signal(Signal::SIGPIPE)));
+    assert!(!is_clean_exit(unclean_signal));
+    assert!(!is_clean_exit(abort));
+
+    // the table from systemd.service(5). Columns: clean exit, unclean exit, clean signal, unclean signal, timeout, watchdog
+    let matrix = [
+        (
+            ServiceRestart::No,
+            [false, false, false, false, false, false],
+        ),
+        (ServiceRestart::Always, [true, true, true, true, true, true]),
+        (
+            ServiceRestart::OnSuccess,
+            [true, false, true, false, false, false],
+        ),
+        (
+            ServiceRestart::OnFailure,
+            [false, true, false, true, true, true],
+        ),
+        (
+            ServiceRestart::OnAbnormal,
+            [false, false, false, true, true, true],
+        ),
+        (
+            ServiceRestart::OnAbort,
+            [false, false, false, true, false, false],
+        ),
+        (
+            ServiceRestart::OnWatchdog,
+            [false, false, false, false, false, true],
+        ),
+    ];
+    for (restart, expected) in &matrix {
+        let cases = [
+            (clean_exit, ExitReason::Exited),
+            (unclean_exit, ExitReason::Exited),
+            (clean_signal, ExitReason::Exited),
+            (unclean_signal, ExitReason::Exited),
+            (unclean_signal, ExitReason::Timeout),
+            (abort, ExitReason::Watchdog),
+        ];
+        for ((code, reason), expected) in cases.iter().zip(expected.iter()) {
+            assert_eq!(
+                restart_policy_applies(restart, *code, *reason),
+                *expected,
+                "Restart={:?} with {:?} ({:?})",
+                restart,
+                code,
+                reason
+            );
+        }
+    }
+
+    let parse = |settings: &str| {
+        let parsed_file =
+            crate::units::parse_file(&format!("[Service]\nExecStart = /bin/true\n{}", settings))
+                .unwrap();
+        let unit = crate::units::parse_service(
+            parsed_file,
+            &std::path::PathBuf::from("/path/to/unitfile.service"),
+            crate::units::UnitId(crate::units::UnitIdKind::Service, 10),
+        )
+        .unwrap();
+        if let UnitSpecialized::Service(srvc) = unit.specialized {
+            srvc
+        } else {
+            panic!("Not a service, but it should be");
+        }
+    };
+
+    assert_eq!(
+        parse("").get_restart_sec(),
+        Some(Duration::from_millis(100))
+    );
+    assert_eq!(
+        parse("RestartSec = 5\n").get_restart_sec(),
+        Some(Duration::from_secs(5))
+    );
+    assert_eq!(parse("RestartSec = infinity\n").get_restart_sec(), None);
+    assert_eq!(
+        parse("RestartSec = 5sec\n").get_restart_sec(),
+        Some(Duration::from_secs(5))
+    );
+    assert_eq!(
+        parse("RestartSec = 100msec\n").get_restart_sec(),
+        Some(Duration::from_millis(100))
+    );
+    assert_eq!(
+        parse("RestartSec = 1min 30s\n").get_restart_sec(),
+        Some(Duration::from_secs(90))
+    );
+    assert_eq!(
+        crate::units::parse_timeout("RestartSec", "2hrs").unwrap(),
+        Timeout::Duration(Duration::from_secs(2 * 60 * 60))
+    );
+    assert!(crate::units::parse_timeout("RestartSec", "xs").is_err());
+    assert!(crate::units::parse_service(
+        crate::units::parse_file("[Service]\nExecStart = /bin/true\nRestartSec = xs\n").unwrap(),
+        &std::path::PathBuf::from("/path/to/unitfile.service"),
+        crate::units::UnitId(crate::units::UnitIdKind::Service, 10),
+    )
+    .is_err());
+
+    // the default is 5 restarts in 10 seconds
+    let mut srvc = parse("Restart = always\n");
+    for _ in 0..5 {
+        assert!(srvc.record_restart_if_allowed(&None, None));
+    }
+    assert!(!srvc.record_restart_if_allowed(&None, None));
+    assert_eq!(srvc.runtime_info.restarted, 5);
+
+    // restarts that are older than the interval do not count anymore
+    let interval = Some(Timeout::Duration(Duration::from_millis(50)));
+    let mut srvc = parse("Restart = always\n");
+    assert!(srvc.record_restart_if_allowed(&interval, Some(2)));
+    assert!(srvc.record_restart_if_allowed(&interval, Some(2)));
+    assert!(!srvc.record_restart_if_allowed(&interval, Some(2)));
+    std::thread::sleep(Duration::from_millis(60));
+    assert!(srvc.record_restart_if_allowed(&interval, Some(2)));
+    assert_eq!(srvc.runtime_info.restarted, 3);
+
+    // an infinite interval never forgets restarts and an interval of 0 disables the limit
+    let mut srvc = parse("Restart = always\n");
+    assert!(srvc.record_restart_if_allowed(&Some(Timeout::Infinity), Some(1)));
+    assert!(!srvc.record_restart_if_allowed(&Some(Timeout::Infinity), Some(1)));
+    let zero = Some(Timeout::Duration(Duration::from_secs(0)));
+    let mut srvc = parse("Restart = always\n");
+    for _ in 0..10 {
+        assert!(srvc.record_restart_if_allowed(&zero, Some(1)));
+    }
+}
+
+#[test]
+fn test_forking_main_pid() {
+    use crate::services::{select_main_pid, wait_for_pid_file, ProcessInfo};
+    use crate::units::{ServiceType, UnitId, UnitIdKind, UnitSpecialized};
+    use nix::unistd::Pid;
+    use std::time::Duration;
+
+    let parse = |settings: &str| {
+        let parsed_file = crate::units::parse_file(&format!(
+            "[Service]\nExecStart = /usr/sbin/daemon\nType = forking\n{}",
+            settings
+        ))
+        .unwrap();
+        let unit = crate::units::parse_service(
+            parsed_file,
+            &std::path::PathBuf::from("/path/to/daemon.service"),
+            UnitId(UnitIdKind::Service, 10),
+        )
+        .unwrap();
+        if let UnitSpecialized::Service(srvc) = unit.specialized {
+            srvc
+        } else {
+            panic!("Not a service, but it should be");
+        }
+    };
+    let srvc = parse("PIDFile = /var/run/daemon.pid\n");
+    assert_eq!(srvc.service_config.srcv_type, ServiceType::Forking);
+    assert_eq!(
+        srvc.service_config.pid_file,
+        Some(std::path::PathBuf::from("/var/run/daemon.pid"))
+    );
+    let srvc = parse("PIDFile = daemon/daemon.pid\n");
+    assert_eq!(
+        srvc.service_config.pid_file,
+        Some(std::path::PathBuf::from("/run/daemon/daemon.pid"))
+    );
+    assert_eq!(parse("").service_config.pid_file, None);
+
+    // the PIDFile is polled until the daemon wrote it or the timeout is reached
+    let pid_file = std::env::temp_dir().join(format!("rustysd_test_{}.pid", std::process::id()));
+    let _ = std::fs::remove_file(&pid_file);
+    assert!(wait_for_pid_file(&pid_file, Some(Duration::from_millis(20))).is_err());
+    let writer = {
+        let pid_file = pid_file.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            std::fs::write(&pid_file, "4242\n").unwrap();
+        })
+    };
+    assert_eq!(
+        wait_for_pid_file(&pid_file, Some(Duration::from_secs(10))),
+        Ok(Pid::from_raw(4242))
+    );
+    writer.join().unwrap();
+    std::fs::write(&pid_file, "not a pid").unwrap();
+    assert!(wait_for_pid_file(&pid_file, Some(Duration::from_millis(20))).is_err());
+    std::fs::remove_file(&pid_file).unwrap();
+
+    // without a PIDFile the daemon is guessed
+    let own_pid = Pid::from_raw(1);
+    let pgid = Pid::from_raw(100);
+    let notify_socket = "/run/rustysd/daemon.service.notify_socket";
+    let proc = |pid: i32, ppid: i32, pgid: i32| ProcessInfo {
+        pid: Pid::from_raw(pid),
+        ppid: Pid::from_raw(ppid),
+        pgid: Pid::from_raw(pgid),
+        notify_socket: Some(notify_socket.to_owned()),
+    };
+    let other_service = |pid: i32| ProcessInfo {
+        notify_socket: Some("/run/rustysd/other.service.notify_socket".to_owned()),
+        ..proc(pid, 1, pid)
+    };
+    let unreadable = |pid: i32| ProcessInfo {
+        notify_socket: None,
+        ..proc(pid, 1, pid)
+    };
+    let known = |pid: Pid| pid == Pid::from_raw(50);
+    let cases = vec![
+        // the daemon stayed in the process group and has workers
+        (
+            vec![proc(102, 1, 100), proc(103, 102, 100), proc(104, 102, 100)],
+            Some(102),
+        ),
+        // the daemon called setsid() and was reparented to rustysd, the main process of another service is known
+        (
+            vec![
+                proc(102, 1, 102),
+                proc(103, 102, 102),
+                proc(50, 1, 50),
+                proc(60, 30, 60),
+            ],
+            Some(102),
+        ),
+        // daemons of other services that are started at the same time are not candidates
+        (
+            vec![proc(102, 1, 102), other_service(110), other_service(111)],
+            Some(102),
+        ),
+        // two unknown processes are ambiguous
+        (vec![proc(102, 1, 102), proc(110, 1, 110)], None),
+        (vec![proc(102, 1, 100), proc(110, 1, 110)], None),
+        (vec![proc(102, 1, 102), unreadable(110)], None),
+        // the daemon already exited
+        (vec![proc(50, 1, 50), proc(60, 30, 60)], None),
+    ];
+    for (processes, expected) in cases {
+        assert_eq!(
+            select_main_pid(&processes, pgid, own_pid, notify_socket, known),
+            expected.map(Pid::from_raw),
+            "{:?}",
+            processes
+        );
+    }
+}
+
+#[test]
+fn test_exec_and_idle_services() {
+    use crate::services::{RunCmdError, ServiceErrorReason};
+    use crate::units::{
+        ActivationJobs, PidEntry, ServiceType, UnitId, UnitIdKind, UnitSpecialized,
+    };
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    let dir = std::env::temp_dir().join(format!("rustysd_test_exec_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let not_executable = dir.join("not_executable");
+    std::fs::write(&not_executable, "#!/bin/sh\n").unwrap();
+    let parse = |exec_start: &std::path::Path| {
+        let parsed_file = crate::units::parse_file(&format!(
+            "[Service]\nType = exec\nExecStart = {}\n",
+            exec_start.to_str().unwrap()
+        ))
+        .unwrap();
+        let unit = crate::units::parse_service(
+            parsed_file,
+            &std::path::PathBuf::from("/path/to/exec.service"),
+            UnitId(UnitIdKind::Service, 10),
+        )
+        .unwrap();
+        if let UnitSpecialized::Service(srvc) = unit.specialized {
+            srvc
+        } else {
+            panic!("Not a service, but it should be");
+        }
+    };
+    let id = UnitId(UnitIdKind::Service, 10);
+    let run_info = make_run_info(std::collections::HashMap::new(), "exec.service", 10);
+    let pid_table = run_info.pid_table.clone();
+
+    // a successful exec makes the child the main process
+    let mut srvc = parse(std::path::Path::new("/bin/sleep"));
+    srvc.service_config.exec[0].args = vec!["10".to_owned()];
+    assert_eq!(srvc.service_config.srcv_type, ServiceType::Exec);
+    assert!(srvc
+        .start(id, "exec.service", &run_info, dir.clone(), &[], false)
+        .is_ok());
+    let pid = srvc.pid.unwrap();
+    assert!(matches!(
+        pid_table.lock().unwrap().get(&pid),
+        Some(PidEntry::Service(entry_id, ServiceType::Exec)) if *entry_id == id
+    ));
+    nix::sys::signal::kill(pid, nix::sys::signal::Signal::SIGKILL).unwrap();
+    nix::sys::wait::waitpid(pid, None).unwrap();
+    pid_table.lock().unwrap().clear();
+
+    // a failing exec fails the start and the child is not treated as the main process
+    let mut srvc = parse(&not_executable);
+    match srvc.start(id, "exec.service", &run_info, dir.clone(), &[], false) {
+        Err(ServiceErrorReason::StartFailed(RunCmdError::ExecFailed(_, errno))) => {
+            assert_eq!(errno, nix::errno::Errno::EACCES)
+        }
+        _ => panic!("The exec should have failed with EACCES"),
+    }
+    assert!(srvc.pid.is_none());
+    assert!(pid_table.lock().unwrap().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Type=idle jobs are held back until the other jobs are done without blocking the only thread of the pool
+    let jobs = Arc::new(ActivationJobs::new(threadpool::ThreadPool::new(1)));
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let idle_job = |name: &'static str| {
+        let order = order.clone();
+        move || order.lock().unwrap().push(name)
+    };
+    let start = std::time::Instant::now();
+    {
+        let jobs_copy = jobs.clone();
+        let idle1 = idle_job("idle1");
+        let idle2 = idle_job("idle2");
+        let order = order.clone();
+        jobs.execute(move || {
+            jobs_copy.execute_when_idle(idle1);
+            jobs_copy.execute_when_idle(idle2);
+            jobs_copy.execute(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                order.lock().unwrap().push("slow");
+            });
+        });
+    }
+    jobs.join();
+    assert_eq!(order.lock().unwrap()[0], "slow");
+    assert_eq!(order.lock().unwrap().len(), 3);
+    assert!(start.elapsed() < Duration::from_secs(4));
+
+    // a job that panics does not count anymore
+    let start = std::time::Instant::now();
+    {
+        let jobs_copy = jobs.clone();
+        let idle3 = idle_job("idle3");
+        jobs.execute(move || {
+            jobs_copy.execute_when_idle(idle3);
+            panic!("activation job panicked");
+        });
+    }
+    jobs.join();
+    assert_eq!(order.lock().unwrap().last(), Some(&"idle3"));
+    assert!(start.elapsed() < Duration::from_secs(4));
+}
+
+#[test]
+fn test_reload() {
+    use crate::units::{Commandline, UnitId, UnitIdKind, UnitSpecialized, UnitStatus};
+
+    let mut unit_table = std::collections::HashMap::new();
+    let unit_strs = [
+        "[Service]\nExecStart = /bin/sleep 10\nExecReload = /bin/kill -HUP $MAINPID\nExecReload = /bin/true\n",
+        "[Service]\nExecStart = /bin/sleep 10\n",
+        "[Service]\nExecStart = /bin/sleep 10\nExecReload = /bin/true\nExecReload =\n",
+    ];
+    for (idx, unit_str) in unit_strs.iter().enumerate() {
+        let unit = crate::units::parse_service(
+            crate::units::parse_file(unit_str).unwrap(),
+            &std::path::PathBuf::from(format!("/path/to/{}.service", idx + 1)),
+            UnitId(UnitIdKind::Service, idx as u64 + 1),
+        )
+        .unwrap();
+        unit_table.insert(unit.id, unit);
+    }
+    let id = |idx: u64| UnitId(UnitIdKind::Service, idx);
+
+    if let UnitSpecialized::Service(srvc) = &unit_table[&id(1)].specialized {
+        assert_eq!(
+            srvc.service_config.reload,
+            vec![
+                Commandline {
+                    cmd: "/bin/kill".to_owned(),
+                    args: vec!["-HUP".to_owned(), "$MAINPID".to_owned()],
+                    prefixes: vec![],
+                },
+                Commandline {
+                    cmd: "/bin/true".to_owned(),
+                    args: vec![],
+                    prefixes: vec![],
+                },
+            ]
+        );
+    } else {
+        panic!("Not a service, but it should be");
+    }
+    // an empty value resets the list
+    if let UnitSpecialized::Service(srvc) = &unit_table[&id(3)].specialized {
+        assert!(srvc.service_config.reload.is_empty());
+    } else {
+        panic!("Not a service, but it should be");
+    }
+
+    // TimeoutReloadSec= falls back to the start timeout
+    let reload_timeout = |settings: &str| {
+        let unit = crate::units::parse_service(
+            crate::units::parse_file(&format!(
+                "[Service]\nExecStart = /bin/sleep 10\n{}",
+                settings
+            ))
+            .unwrap(),
+            &std::path::PathBuf::from("/path/to/timeout.service"),
+            UnitId(UnitIdKind::Service, 10),
+        )
+        .unwrap();
+        if let UnitSpecialized::Service(srvc) = unit.specialized {
+            srvc.get_reload_timeout()
+        } else {
+            panic!("Not a service, but it should be");
+        }
+    };
+    assert_eq!(reload_timeout(""), None);
+    assert_eq!(
+        reload_timeout("TimeoutStartSec = 5\n"),
+        Some(std::time::Duration::from_secs(5))
+    );
+    assert_eq!(
+        reload_timeout("TimeoutStartSec = 5\nTimeoutReloadSec = 2\n"),
+        Some(std::time::Duration::from_secs(2))
+    );
+    assert_eq!(
+        reload_timeout("TimeoutStartSec = 5\nTimeoutReloadSec = infinity\n"),
+        None
+    );
+
+    let run_info = make_run_info(unit_table, "default.target", 3);
+    let set_status = |idx: u64, status: UnitStatus| {
+        *run_info.status_table.read().unwrap()[&id(idx)]
+            .lock()
+            .unwrap() = status;
+    };
+    let status = |idx: u64| {
+        run_info.status_table.read().unwrap()[&id(idx)]
+            .lock()
+            .unwrap()
+            .clone()
+    };
+
+    // only running units can be reloaded
+    assert!(!crate::units::can_reload(id(1), &run_info));
+    assert!(crate::units::reload_unit(id(1), run_info.clone()).is_err());
+    assert_eq!(status(1), UnitStatus::NeverStarted);
+
+    for idx in 1..=3 {
+        set_status(idx, UnitStatus::Started);
+    }
+    assert!(crate::units::can_reload(id(1), &run_info));
+
+    // units without ExecReload= are rejected and stay running
+    for idx in 2..=3 {
+        assert!(!crate::units::can_reload(id(idx), &run_info));
+        assert!(crate::units::reload_unit(id(idx), run_info.clone()).is_err());
+        assert_eq!(status(idx), UnitStatus::Started);
+    }
+
+    // the same for reload jobs from the control interface
+    let finished = crate::units::run_transaction(
+        id(2),
+        crate::units::JobKind::Reload,
+        crate::units::JobMode::Replace,
+        run_info.clone(),
+        std::env::temp_dir(),
+        std::sync::Arc::new(Vec::new()),
+    )
+    .unwrap();
+    assert_eq!(finished.len(), 1);
+    assert!(matches!(
+        finished[0].result,
+        crate::units::JobResult::Failed(_)
+    ));
+    assert_eq!(status(2), UnitStatus::Started);
+}
+
+#[test]
+fn test_inherited_output_is_writable() {
+    let parsed_file = crate::units::parse_file(
+        "[Service]\nExecStart = /bin/true\nStandardOutput = inherit\nStandardError = inherit\n",
+    )
+    .unwrap();
+    let unit = crate::units::parse_service(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/unitfile.service"),
+        crate::units::UnitId(crate::units::UnitIdKind::Service, 10),
+    )
+    .unwrap();
+    let srvc = if let crate::units::UnitSpecialized::Service(srvc) = unit.specialized {
+        srvc
+    } else {
+        panic!("Not a service, but it should be");
+    };
+
+    // stdin is /dev/null opened read-only, writing to stdout and stderr must still work
+    match nix::unistd::fork().unwrap() {
+        nix::unistd::ForkResult::Child => {
+            let written = crate::services::setup_stdio(&srvc, &[], &[]).is_ok()
+                && nix::unistd::write(1, b"stdout\n").is_ok()
+                && nix::unistd::write(2, b"stderr\n").is_ok();
+            unsafe { libc::_exit(if written { 0 } else { 1 }) };
+        }
+        nix::unistd::ForkResult::Parent { child } => {
+            assert_eq!(
+                nix::sys::wait::waitpid(child, None).unwrap(),
+                nix::sys::wait::WaitStatus::Exited(child, 0)
+            );
+        }
+    }
+}
+
+#[test]
+fn test_journal() {
+    use crate::journal::*;
+    let dir = std::env::temp_dir().join(format!("rustysd_journal_test_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let entry = |unit: &str, timestamp: u64, message: &str| LogEntry {
+        unit: unit.to_owned(),
+        timestamp,
+        stream: LogStream::Stdout,
+        pid: Some(42),
+        message: message.to_owned(),
+    };
+
+    // messages with tabs, newlines and backslashes survive the round trip
+    let tricky = LogEntry {
+        stream: LogStream::Rustysd,
+        pid: None,
+        ..entry("a.service", 5, "tab\there\nnewline \\n \\ end")
+    };
+    let line = tricky.to_line();
+    assert_eq!(line.matches('\t').count(), 3);
+    assert_eq!(line.matches('\n').count(), 1);
+    assert_eq!(
+        LogEntry::from_line("a.service", line.trim_end_matches('\n')),
+        Some(tricky)
+    );
+
+    // every line is 21 bytes, so 50 bytes hold two entries and the third one gets rotated
+    let mut journal = Journal::new(&dir, 50, 2).unwrap();
+    for i in 0..10 {
+        journal
+            .append(&entry("a.service", 1000 + i, "aaaaa"))
+            .unwrap();
+        journal
+            .append(&entry("b.service", 1000 + i, "bbbbb"))
+            .unwrap();
+    }
+    assert!(dir.join("a.service.log").exists());
+    assert!(dir.join("a.service.log.1").exists());
+    assert!(dir.join("a.service.log.2").exists());
+    assert!(!dir.join("a.service.log.3").exists());
+    let mut units = journal.units().unwrap();
+    units.sort();
+    assert_eq!(units, vec!["a.service".to_owned(), "b.service".to_owned()]);
+
+    // only the newest 6 entries (3 files with 2 entries each) are kept
+    let filter = LogFilter {
+        unit: Some("a.service".into()),
+        ..LogFilter::default()
+    };
+    let entries = journal.query(&filter).unwrap();
+    assert_eq!(
+        entries.iter().map(|e| e.timestamp).collect::<Vec<_>>(),
+        vec![1004, 1005, 1006, 1007, 1008, 1009]
+    );
+    assert!(entries
+        .iter()
+        .all(|e| e.unit == "a.service" && e.pid == Some(42)));
+
+    let filter = LogFilter {
+        since: Some(1008),
+        ..LogFilter::default()
+    };
+    let entries = journal.query(&filter).unwrap();
+    assert_eq!(entries.len(), 4);
+    assert!(entries.iter().all(|e| e.timestamp >= 1008));
+
+    let filter = LogFilter {
+        unit: Some("b.service".into()),
+        lines: Some(2),
+        ..LogFilter::default()
+    };
+    let entries = journal.query(&filter).unwrap();
+    assert_eq!(
+        entries.iter().map(|e| e.timestamp).collect::<Vec<_>>(),
+        vec![1008, 1009]
+    );
+
+    // followers only get new entries of their unit
+    let receiver = journal.subscribe(Some("b.service".into()));
+    journal.append(&entry("a.service", 2000, "new")).unwrap();
+    journal.append(&entry("b.service", 2001, "new")).unwrap();
+    assert_eq!(receiver.try_recv().unwrap().timestamp, 2001);
+    assert!(receiver.try_recv().is_err());
+
+    // reopening the journal keeps the entries
+    drop(journal);
+    let journal = Journal::new(&dir, 50, 2).unwrap();
+    let entries = journal.query(&LogFilter::default()).unwrap();
+    assert_eq!(entries.last().unwrap().timestamp, 2001);
+    drop(journal);
+
+    // the shared journal only opens the files while it is locked, later entries only reach the followers
+    let shared = init(Some(&dir)).unwrap();
+    let filter = LogFilter {
+        unit: Some("b.service".into()),
+        ..LogFilter::default()
+    };
+    let (entries, receiver) = follow(&shared, &filter).unwrap();
+    assert_eq!(entries.last().unwrap().timestamp, 2001);
+    log(&shared, "b.service", LogStream::Stdout, None, "followed");
+    assert_eq!(receiver.try_recv().unwrap().message, "followed");
+    assert_eq!(
+        query(&shared, &filter).unwrap().last().unwrap().message,
+        "followed"
+    );
+    assert!(query(&init(None).unwrap(), &filter).is_err());
+
+    assert_eq!(parse_since("10").unwrap(), 10_000_000);
+    assert!(parse_since("-10m").is_ok());
+    assert!(parse_since("2020-01-01 12:00:00").is_ok());
+    assert!(parse_since("2020-01-01").is_ok());
+    assert!(parse_since("yesterday").is_err());
+    assert!(parse_since("-10x").is_err());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_log_sink() {
+    use crate::log_sink::*;
+
+    let content = r#"
+    [Service]
+    ExecStart = /usr/bin/mydaemon --foreground
+    SyslogIdentifier = my-daemon
+    SyslogFacility = local3
+    "#;
+    let parsed_file = crate::units::parse_file(content).unwrap();
+    let service = crate::units::parse_service(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/unitfile.service"),
+        crate::units::UnitId(crate::units::UnitIdKind::Service, 10),
+    )
+    .unwrap();
+    if let crate::units::UnitSpecialized::Service(srvc) = service.specialized {
+        let exec_config = srvc.service_config.exec_config;
+        assert_eq!(exec_config.syslog_identifier, Some("my-daemon".to_owned()));
+        assert_eq!(exec_config.syslog_facility, 19);
+    } else {
+        panic!("Not a service, but it should be");
+    }
+    let parsed_file =
+        crate::units::parse_file("[Service]\nExecStart=/bin/true\nSyslogFacility=local8\n")
+            .unwrap();
+    assert!(crate::units::parse_service(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/unitfile.service"),
+        crate::units::UnitId(crate::units::UnitIdKind::Service, 10),
+    )
+    .is_err());
+
+    assert_eq!(
+        parse_sink_target("journald").unwrap(),
+        SinkTarget::Journald(JOURNALD_SOCKET.into())
+    );
+    assert_eq!(
+        parse_sink_target("syslog:udp:127.0.0.1:514").unwrap(),
+        SinkTarget::SyslogUdp("127.0.0.1:514".parse().unwrap())
+    );
+    assert!(parse_sink_target("syslog:udp:nope").is_err());
+    assert!(parse_sink_target("kmsg").is_err());
+
+    let record = Record {
+        unit: "test.service",
+        identifier: "my daemon",
+        facility: 3,
+        severity: 6,
+        pid: Some(42),
+        source: RecordSource::Stderr,
+        message: "line one\nline two",
+    };
+
+    // syslog to a local unix socket
+    let dir = std::env::temp_dir().join(format!("rustysd_sink_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let syslog_path = dir.join("syslog");
+    let listener = std::os::unix::net::UnixDatagram::bind(&syslog_path).unwrap();
+    let sink = Sink::new(SinkTarget::SyslogUnix(syslog_path.clone())).unwrap();
+    sink.send(&record).unwrap();
+    let mut buf = [0u8; 1024];
+    let len = listener.recv(&mut buf).unwrap();
+    let msg = String::from_utf8(buf[..len].to_vec()).unwrap();
+    assert!(msg.starts_with("<30>1 "));
+    assert!(msg.contains(
+        " mydaemon 42 - [rustysd@32473 unit=\"test.service\" stream=\"stderr\"] line one\nline two"
+    ));
+
+    // syslog over udp
+    let listener = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+    let sink = Sink::new(SinkTarget::SyslogUdp(listener.local_addr().unwrap())).unwrap();
+    sink.send(&Record {
+        source: RecordSource::Status,
+        severity: 5,
+        message: "Ready",
+        ..record.clone()
+    })
+    .unwrap();
+    let len = listener.recv(&mut buf).unwrap();
+    let msg = String::from_utf8(buf[..len].to_vec()).unwrap();
+    assert!(msg.starts_with("<29>1 "));
+    assert!(msg.ends_with("stream=\"status\"] Ready"));
+
+    // native journald protocol
+    let journald_path = dir.join("journald");
+    let listener = std::os::unix::net::UnixDatagram::bind(&journald_path).unwrap();
+    let sink = Sink::new(SinkTarget::Journald(journald_path.clone())).unwrap();
+    sink.send(&record).unwrap();
+    let len = listener.recv(&mut buf).unwrap();
+    let mut expected = b"MESSAGE\n".to_vec();
+    expected.extend(&17u64.to_le_bytes());
+    expected.extend(b"line one\nline two\n");
+    expected.extend(
+        &b"PRIORITY=6\nSYSLOG_FACILITY=3\nSYSLOG_IDENTIFIER=my daemon\nUNIT=test.service\nRUSTYSD_STREAM=stderr\nSYSLOG_PID=42\n"[..],
+    );
+    assert_eq!(&buf[..len], &expected[..]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn test_notify_access() {
+    use crate::notification_handler::{notify_access_allowed, recv_notification};
+    use crate::units::{NotifyKind, PidEntry, UnitId, UnitIdKind, UnitSpecialized};
+
+    let parsed_file =
+        crate::units::parse_file("[Service]\nExecStart=/bin/true\nNotifyAccess=main\n").unwrap();
+    let id = UnitId(UnitIdKind::Service, 10);
+    let unit = crate::units::parse_service(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/unitfile.service"),
+        id,
+    )
+    .unwrap();
+    let mut srvc = if let UnitSpecialized::Service(srvc) = unit.specialized {
+        srvc
+    } else {
+        panic!("Not a service, but it should be");
+    };
+    assert_eq!(srvc.service_config.notifyaccess, NotifyKind::Main);
+
+    // the kernel tells us who sent the message
+    let (sender, receiver) = std::os::unix::net::UnixDatagram::pair().unwrap();
+    nix::sys::socket::setsockopt(
+        std::os::unix::io::AsRawFd::as_raw_fd(&receiver),
+        nix::sys::socket::sockopt::PassCred,
+        &true,
+    )
+    .unwrap();
+    sender.send(b"READY=1\n").unwrap();
+    let mut buf = [0u8; 64];
+    let (bytes, fds, sender_pid) = recv_notification(&receiver, &mut buf[..]).unwrap();
+    assert_eq!(&buf[..bytes], b"READY=1\n");
+    assert!(fds.is_empty());
+    let own_pid = nix::unistd::getpid();
+    assert_eq!(sender_pid, Some(own_pid));
+
+    let pid_table = std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new()));
+    let other_pid = nix::unistd::Pid::from_raw(own_pid.as_raw() + 1);
+    srvc.pid = Some(own_pid);
+    assert!(notify_access_allowed(
+        &srvc,
+        id,
+        "test",
+        Some(own_pid),
+        &pid_table
+    ));
+    srvc.pid = Some(other_pid);
+    assert!(!notify_access_allowed(
+        &srvc,
+        id,
+        "test",
+        Some(own_pid),
+        &pid_table
+    ));
+
+    // ExecStartPre= and friends are allowed with exec
+    pid_table
+        .lock()
+        .unwrap()
+        .insert(own_pid, PidEntry::Helper(id, "/bin/true".to_owned()));
+    assert!(!notify_access_allowed(
+        &srvc,
+        id,
+        "test",
+        Some(own_pid),
+        &pid_table
+    ));
+    srvc.service_config.notifyaccess = NotifyKind::Exec;
+    assert!(notify_access_allowed(
+        &srvc,
+        id,
+        "test",
+        Some(own_pid),
+        &pid_table
+    ));
+    assert!(!notify_access_allowed(
+        &srvc,
+        UnitId(UnitIdKind::Service, 11),
+        "test",
+        Some(own_pid),
+        &pid_table
+    ));
+
+    srvc.service_config.notifyaccess = NotifyKind::None;
+    srvc.pid = Some(own_pid);
+    assert!(!notify_access_allowed(
+        &srvc,
+        id,
+        "test",
+        Some(own_pid),
+        &pid_table
+    ));
+    assert!(!notify_access_allowed(&srvc, id, "test", None, &pid_table));
+}
+
+#[test]
+fn test_notification_datagrams() {
+    use crate::notification_handler::{handle_notification_datagram, recv_notification};
+    use crate::units::{UnitId, UnitIdKind, UnitStatus};
+    use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags};
+    use nix::sys::uio::IoVec;
+    use std::os::unix::io::AsRawFd;
+
+    let id = UnitId(UnitIdKind::Service, 0);
+    let parsed_file =
+        crate::units::parse_file("[Service]\nExecStart=/bin/true\nFileDescriptorStoreMax=4\n")
+            .unwrap();
+    let unit = crate::units::parse_service(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/datagram.service"),
+        id,
+    )
+    .unwrap();
+    let mut unit_table = std::collections::HashMap::new();
+    unit_table.insert(id, unit);
+    let run_info = make_run_info(unit_table, "default.target", 1);
+    let unit = run_info
+        .unit_table
+        .read()
+        .unwrap()
+        .get(&id)
+        .unwrap()
+        .clone();
+    let mut unit_locked = unit.lock().unwrap();
+    let srvc = match &mut unit_locked.specialized {
+        crate::units::UnitSpecialized::Service(srvc) => srvc,
+        _ => unreachable!(),
+    };
+
+    let (sender, receiver) = std::os::unix::net::UnixDatagram::pair().unwrap();
+    let mut send = |msg: &[u8], fds: &[i32]| {
+        let iov = [IoVec::from_slice(msg)];
+        let cmsgs = [ControlMessage::ScmRights(fds)];
+        let cmsgs: &[ControlMessage] = if fds.is_empty() { &[] } else { &cmsgs };
+        sendmsg(sender.as_raw_fd(), &iov, cmsgs, MsgFlags::empty(), None).unwrap();
+        let mut buf = [0u8; 512];
+        let (bytes, fds, _) = recv_notification(&receiver, &mut buf[..]).unwrap();
+        let msg = String::from_utf8_lossy(&buf[..bytes]).into_owned();
+        handle_notification_datagram(&msg, fds, srvc, id, "datagram.service", &run_info)
+    };
+
+    // the name comes after FDSTORE=1 and the message does not end with a newline, it still names the fds
+    let (read, write) = nix::unistd::pipe2(nix::fcntl::OFlag::O_CLOEXEC).unwrap();
+    assert_eq!(send(b"FDSTORE=1\nFDNAME=foo", &[read, write]), None);
+    nix::unistd::close(read).unwrap();
+    nix::unistd::close(write).unwrap();
+    let stored = run_info
+        .fd_store
+        .read()
+        .unwrap()
+        .get_all_service_stored("datagram.service");
+    assert_eq!(stored.len(), 2);
+    assert!(stored.iter().all(|(name, _)| name == "foo"));
+
+    // nothing of the last message is left over for the next one
+    assert_eq!(send(b"FDSTORE=1", &[]), None);
+    assert_eq!(
+        run_info
+            .fd_store
+            .read()
+            .unwrap()
+            .service_stored_count("datagram.service"),
+        2
+    );
+
+    // the last status change in a message wins
+    assert_eq!(
+        send(b"RELOADING=1\nREADY=1", &[]),
+        Some(UnitStatus::Started)
+    );
+
+    for fd in run_info
+        .fd_store
+        .write()
+        .unwrap()
+        .remove_all_service_stored("datagram.service")
+    {
+        nix::unistd::close(*fd).unwrap();
+    }
+}
+
+#[test]
+fn test_watchdog() {
+    use crate::notification_handler::handle_notification_message;
+    use crate::units::{UnitId, UnitIdKind, UnitSpecialized, UnitStatus};
+    use std::os::unix::process::ExitStatusExt;
+    use std::time::Duration;
+
+    let parsed_file =
+        crate::units::parse_file("[Service]\nExecStart=/bin/true\nWatchdogSec=10s\n").unwrap();
+    let id = UnitId(UnitIdKind::Service, 10);
+    let mut unit = crate::units::parse_service(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/watchdog.service"),
+        id,
+    )
+    .unwrap();
+    let pid_table = std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new()));
+    let log_sink = std::sync::Arc::new(None);
+    {
+        let srvc = if let UnitSpecialized::Service(srvc) = &mut unit.specialized {
+            srvc
+        } else {
+            panic!("Not a service, but it should be");
+        };
+        assert_eq!(srvc.get_watchdog_sec(), Some(Duration::from_secs(10)));
+
+        // pings are ignored while there is no watchdog
+        assert_eq!(
+            handle_notification_message("WATCHDOG=1", srvc, id, "test", &pid_table, &log_sink),
+            None
+        );
+        assert!(srvc.watchdog_last_ping.is_none());
+        srvc.watchdog_timeout = srvc.get_watchdog_sec();
+        assert_eq!(
+            handle_notification_message("WATCHDOG=1", srvc, id, "test", &pid_table, &log_sink),
+            None
+        );
+        assert!(srvc.watchdog_last_ping.is_some());
+
+        // the service can change the timeout, which also counts as a ping
+        srvc.watchdog_last_ping = None;
+        assert_eq!(
+            handle_notification_message(
+                "WATCHDOG_USEC=20000",
+                srvc,
+                id,
+                "test",
+                &pid_table,
+                &log_sink
+            ),
+            None
+        );
+        assert_eq!(srvc.watchdog_timeout, Some(Duration::from_millis(20)));
+        assert!(srvc.watchdog_last_ping.is_some());
+        assert_eq!(
+            handle_notification_message(
+                "WATCHDOG_USEC=soon",
+                srvc,
+                id,
+                "test",
+                &pid_table,
+                &log_sink
+            ),
+            None
+        );
+        assert_eq!(srvc.watchdog_timeout, Some(Duration::from_millis(20)));
+
+        // triggering the watchdog aborts the main process and stops listening to pings
+        let mut child = std::process::Command::new("/bin/sleep")
+            .arg("10")
+            .spawn()
+            .unwrap();
+        srvc.pid = Some(nix::unistd::Pid::from_raw(child.id() as i32));
+        assert_eq!(
+            handle_notification_message(
+                "WATCHDOG=trigger",
+                srvc,
+                id,
+                "test",
+                &pid_table,
+                &log_sink
+            ),
+            None
+        );
+        assert!(srvc.watchdog_triggered);
+        assert!(srvc.watchdog_last_ping.is_none());
+        assert_eq!(child.wait().unwrap().signal(), Some(libc::SIGABRT));
+        handle_notification_message("WATCHDOG=1", srvc, id, "test", &pid_table, &log_sink);
+        assert!(srvc.watchdog_last_ping.is_none());
+
+        srvc.pid = None;
+        srvc.watchdog_triggered = false;
+        srvc.watchdog_timeout = Some(Duration::from_millis(50));
+        srvc.watchdog_last_ping = Some(std::time::Instant::now());
+    }
+
+    let mut unit_table = std::collections::HashMap::new();
+    unit_table.insert(id, unit);
+    let run_info = make_run_info(unit_table, "default.target", 10);
+    let set_status = |status: UnitStatus| {
+        *run_info
+            .status_table
+            .read()
+            .unwrap()
+            .get(&id)
+            .unwrap()
+            .lock()
+            .unwrap() = status;
+    };
+    let is_triggered = || {
+        let unit_table_locked = run_info.unit_table.read().unwrap();
+        let unit_locked = unit_table_locked.get(&id).unwrap().lock().unwrap();
+        match &unit_locked.specialized {
+            UnitSpecialized::Service(srvc) => srvc.watchdog_triggered,
+            _ => unreachable!(),
+        }
+    };
+
+    set_status(UnitStatus::Started);
+    let next_check = crate::watchdog::check_all_watchdogs(&run_info);
+    assert!(next_check <= Duration::from_millis(50));
+    assert!(!is_triggered());
+
+    // only running services are checked, starting and stopping have their own timeouts
+    std::thread::sleep(Duration::from_millis(60));
+    set_status(UnitStatus::Starting);
+    crate::watchdog::check_all_watchdogs(&run_info);
+    assert!(!is_triggered());
+    set_status(UnitStatus::Started);
+    crate::watchdog::check_all_watchdogs(&run_info);
+    assert!(is_triggered());
+}
+
+#[test]
+fn test_notification_messages() {
+    use crate::notification_handler::handle_notification_message;
+    use crate::units::{PidEntry, ServiceType, UnitId, UnitIdKind, UnitSpecialized, UnitStatus};
+    use nix::unistd::Pid;
+
+    let parsed_file =
+        crate::units::parse_file("[Service]\nExecStart=/bin/true\nType=notify\n").unwrap();
+    let id = UnitId(UnitIdKind::Service, 10);
+    let unit = crate::units::parse_service(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/notify.service"),
+        id,
+    )
+    .unwrap();
+    let mut srvc = if let UnitSpecialized::Service(srvc) = unit.specialized {
+        srvc
+    } else {
+        panic!("Not a service, but it should be");
+    };
+    let pid_table = std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new()));
+    let log_sink = std::sync::Arc::new(None);
+
+    assert_eq!(
+        handle_notification_message("READY=1", &mut srvc, id, "test", &pid_table, &log_sink),
+        Some(UnitStatus::Started)
+    );
+    assert!(srvc.signaled_ready);
+    assert_eq!(
+        handle_notification_message("RELOADING=1", &mut srvc, id, "test", &pid_table, &log_sink),
+        Some(UnitStatus::Reloading)
+    );
+    assert_eq!(
+        handle_notification_message("STOPPING=1", &mut srvc, id, "test", &pid_table, &log_sink),
+        Some(UnitStatus::StoppingGracefully)
+    );
+    assert_eq!(
+        handle_notification_message(
+            "STATUS=Working hard",
+            &mut srvc,
+            id,
+            "test",
+            &pid_table,
+            &log_sink
+        ),
+        None
+    );
+    assert_eq!(srvc.status_msgs, vec!["Working hard".to_owned()]);
+
+    // ERRNO= is only remembered to be shown as the reason if the service fails
+    assert_eq!(
+        handle_notification_message("ERRNO=2", &mut srvc, id, "test", &pid_table, &log_sink),
+        None
+    );
+    assert_eq!(srvc.notified_errno, Some(2));
+    assert!(srvc
+        .notified_errno_description()
+        .unwrap()
+        .starts_with("errno 2 ("));
+    assert_eq!(
+        handle_notification_message("ERRNO=none", &mut srvc, id, "test", &pid_table, &log_sink),
+        None
+    );
+    assert_eq!(srvc.notified_errno, Some(2));
+
+    // MAINPID= moves the pid table entry from the old to the new main process
+    let old_pid = Pid::from_raw(100_000);
+    let new_pid = Pid::from_raw(100_001);
+    srvc.pid = Some(old_pid);
+    pid_table
+        .lock()
+        .unwrap()
+        .insert(old_pid, PidEntry::Service(id, ServiceType::Notify));
+    assert_eq!(
+        handle_notification_message(
+            "MAINPID=100001",
+            &mut srvc,
+            id,
+            "test",
+            &pid_table,
+            &log_sink
+        ),
+        None
+    );
+    assert_eq!(srvc.pid, Some(new_pid));
+    {
+        let pid_table_locked = pid_table.lock().unwrap();
+        assert!(pid_table_locked.get(&old_pid).is_none());
+        assert!(matches!(
+            pid_table_locked.get(&new_pid),
+            Some(PidEntry::Service(entry_id, ServiceType::Notify)) if *entry_id == id
+        ));
+    }
+    for invalid in &["MAINPID=0", "MAINPID=-1", "MAINPID=abc"] {
+        assert_eq!(
+            handle_notification_message(invalid, &mut srvc, id, "test", &pid_table, &log_sink),
+            None
+        );
+        assert_eq!(srvc.pid, Some(new_pid));
+        assert_eq!(pid_table.lock().unwrap().len(), 1);
+    }
+}
+
+#[test]
+fn test_fd_store_requests() {
+    use crate::notification_handler::{
+        close_received_fds, handle_fd_store_request, handle_notification_message,
+    };
+    use crate::units::{UnitId, UnitIdKind, UnitSpecialized};
+    use nix::fcntl::OFlag;
+
+    let parsed_file =
+        crate::units::parse_file("[Service]\nExecStart=/bin/true\nFileDescriptorStoreMax=2\n")
+            .unwrap();
+    let id = UnitId(UnitIdKind::Service, 10);
+    let unit = crate::units::parse_service(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/fdstore.service"),
+        id,
+    )
+    .unwrap();
+    let mut srvc = if let UnitSpecialized::Service(srvc) = unit.specialized {
+        srvc
+    } else {
+        panic!("Not a service, but it should be");
+    };
+    assert_eq!(srvc.service_config.fd_store_max, 2);
+    let pid_table = std::sync::Arc::new(std::sync::Mutex::new(std::collections::HashMap::new()));
+    let log_sink = std::sync::Arc::new(None);
+    let fd_store = std::sync::Arc::new(std::sync::RwLock::new(crate::fd_store::FDStore::default()));
+
+    // the write ends are sent to rustysd, the read ends show if they have been closed without relying on fd numbers
+    // that might get reused by other tests running in parallel
+    let mut read_ends = Vec::new();
+    let mut make_fds = |count: usize| {
+        (0..count)
+            .map(|_| {
+                let (read, write) =
+                    nix::unistd::pipe2(OFlag::O_NONBLOCK | OFlag::O_CLOEXEC).unwrap();
+                read_ends.push(read);
+                (read, write)
+            })
+            .collect::<Vec<_>>()
+    };
+    let is_closed = |read: i32| nix::unistd::read(read, &mut [0u8; 1]) == Ok(0);
+
+    // only FileDescriptorStoreMax= fds are stored, the rest is closed
+    let pipes = make_fds(3);
+    handle_notification_message(
+        "FDSTORE=1",
+        &mut srvc,
+        id,
+        "fdstore.service",
+        &pid_table,
+        &log_sink,
+    );
+    handle_notification_message(
+        "FDNAME=first",
+        &mut srvc,
+        id,
+        "fdstore.service",
+        &pid_table,
+        &log_sink,
+    );
+    handle_fd_store_request(
+        &mut srvc,
+        "fdstore.service",
+        pipes.iter().map(|(_, write)| *write).collect(),
+        &fd_store,
+    );
+    assert_eq!(
+        fd_store
+            .read()
+            .unwrap()
+            .service_stored_count("fdstore.service"),
+        2
+    );
+    assert!(!is_closed(pipes[0].0));
+    assert!(!is_closed(pipes[1].0));
+    assert!(is_closed(pipes[2].0));
+
+    // the request is reset after each message, so fds without FDSTORE=1 are closed
+    let unrequested = make_fds(1);
+    handle_fd_store_request(
+        &mut srvc,
+        "fdstore.service",
+        vec![unrequested[0].1],
+        &fd_store,
+    );
+    assert!(is_closed(unrequested[0].0));
+
+    // the store is full
+    let full = make_fds(1);
+    handle_notification_message(
+        "FDSTORE=1",
+        &mut srvc,
+        id,
+        "fdstore.service",
+        &pid_table,
+        &log_sink,
+    );
+    handle_fd_store_request(&mut srvc, "fdstore.service", vec![full[0].1], &fd_store);
+    assert!(is_closed(full[0].0));
+    assert_eq!(
+        fd_store
+            .read()
+            .unwrap()
+            .service_stored_count("fdstore.service"),
+        2
+    );
+
+    // FDSTOREREMOVE=1 closes all fds with that name
+    handle_notification_message(
+        "FDSTOREREMOVE=1",
+        &mut srvc,
+        id,
+        "fdstore.service",
+        &pid_table,
+        &log_sink,
+    );
+    handle_notification_message(
+        "FDNAME=other",
+        &mut srvc,
+        id,
+        "fdstore.service",
+        &pid_table,
+        &log_sink,
+    );
+    handle_fd_store_request(&mut srvc, "fdstore.service", Vec::new(), &fd_store);
+    assert_eq!(
+        fd_store
+            .read()
+            .unwrap()
+            .service_stored_count("fdstore.service"),
+        2
+    );
+    handle_notification_message(
+        "FDSTOREREMOVE=1",
+        &mut srvc,
+        id,
+        "fdstore.service",
+        &pid_table,
+        &log_sink,
+    );
+    handle_notification_message(
+        "FDNAME=first",
+        &mut srvc,
+        id,
+        "fdstore.service",
+        &pid_table,
+        &log_sink,
+    );
+    handle_fd_store_request(&mut srvc, "fdstore.service", Vec::new(), &fd_store);
+    assert_eq!(
+        fd_store
+            .read()
+            .unwrap()
+            .service_stored_count("fdstore.service"),
+        0
+    );
+    assert!(is_closed(pipes[0].0));
+    assert!(is_closed(pipes[1].0));
+
+    // fds of notifications that were rejected by NotifyAccess= are closed
+    let rejected = make_fds(2);
+    close_received_fds(rejected.iter().map(|(_, write)| *write).collect());
+    assert!(is_closed(rejected[0].0));
+    assert!(is_closed(rejected[1].0));
+
+    for read in read_ends {
+        nix::unistd::close(read).unwrap();
+    }
+}
+
+#[test]
+fn test_timer_parsing() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    assert_eq!(
+        crate::units::parse_timespan("90").unwrap(),
+        Duration::from_secs(90)
+    );
+    assert_eq!(
+        crate::units::parse_timespan("5min 30s").unwrap(),
+        Duration::from_secs(330)
+    );
+    assert_eq!(
+        crate::units::parse_timespan("1h30m").unwrap(),
+        Duration::from_secs(5400)
+    );
+    assert_eq!(
+        crate::units::parse_timespan("250ms").unwrap(),
+        Duration::from_millis(250)
+    );
+    assert!(crate::units::parse_timespan("5 parsecs").is_err());
+
+    let timer_str = "
+    [Unit]
+    Description = Cleanup
+
+    [Timer]
+    OnBootSec = 15min
+    OnUnitActiveSec = 1d
+    OnCalendar = Mon..Fri *-*-* 10:00
+    OnCalendar = daily
+    Persistent = yes
+    ";
+    let parsed_file = crate::units::parse_file(timer_str).unwrap();
+    let timer_unit = crate::units::parse_timer(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/cleanup.timer"),
+        crate::units::UnitId(crate::units::UnitIdKind::Timer, 10),
+    )
+    .unwrap();
+
+    if let crate::units::UnitSpecialized::Timer(timer) = timer_unit.specialized {
+        let conf = &timer.config;
+        assert_eq!(conf.unit, "cleanup.service");
+        assert_eq!(conf.on_boot, vec![Duration::from_secs(15 * 60)]);
+        assert_eq!(conf.on_unit_active, vec![Duration::from_secs(24 * 60 * 60)]);
+        assert!(conf.on_active.is_empty());
+        assert!(conf.persistent);
+        assert_eq!(conf.on_calendar.len(), 2);
+        assert_eq!(conf.on_calendar[0].expression, "Mon..Fri *-*-* 10:00");
+        assert_eq!(conf.on_calendar[0].weekdays, Some(0b0011111));
+    } else {
+        panic!("Timer unit was not parsed as a timer");
+    }
+
+    // a timer needs at least one trigger
+    let parsed_file = crate::units::parse_file("[Unit]\n[Timer]\nUnit=other.service\n").unwrap();
+    assert!(crate::units::parse_timer(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/empty.timer"),
+        crate::units::UnitId(crate::units::UnitIdKind::Timer, 11),
+    )
+    .is_err());
+
+    assert!(crate::units::parse_calendar("Mon..Fry 10:00").is_err());
+    assert!(crate::units::parse_calendar("*-13-01").is_err());
+
+    // 2020-01-01 00:00:00 UTC was a wednesday
+    let base = UNIX_EPOCH + Duration::from_secs(1_577_836_800);
+    let cases = vec![
+        // the next full hour
+        ("hourly UTC", 1_577_840_400),
+        // friday at 10:00
+        ("Fri *-*-* 10:00 UTC", 1_577_836_800 + 2 * 86400 + 10 * 3600),
+        // every 15 minutes, strictly after the base
+        ("*:0/15 UTC", 1_577_836_800 + 15 * 60),
+        // first of the next month
+        ("monthly UTC", 1_580_515_200),
+        // weekday ranges wrap around the end of the week
+        ("Sat..Mon 12:00 UTC", 1_577_836_800 + 3 * 86400 + 12 * 3600),
+        ("2021-02-29 UTC", 0),
+        ("2024-02-29 12:30:15 UTC", 1_709_209_815),
+    ];
+    for (expression, expected) in cases {
+        let spec = crate::units::parse_calendar(expression).unwrap();
+        let next = spec.next_after(base);
+        if expected == 0 {
+            assert_eq!(next, None, "{}", expression);
+        } else {
+            assert_eq!(
+                next,
+                Some(UNIX_EPOCH + Duration::from_secs(expected)),
+                "{}",
+                expression
+            );
+        }
+    }
+}
+
+#[test]
+fn test_path_parsing() {
+    use crate::path_activation::{glob_exists, glob_matches};
+    use crate::units::PathCondition;
+    use std::path::PathBuf;
+
+    let path_str = "
+    [Unit]
+    Description = Spool watcher
+
+    [Path]
+    PathExists = /run/old-flag
+    PathExists =
+    DirectoryNotEmpty = /var/spool/jobs
+    PathExistsGlob = /var/spool/jobs/*.job
+    PathModified = /etc/jobs.conf
+    Unit = process-jobs.service
+    ";
+    let parsed_file = crate::units::parse_file(path_str).unwrap();
+    let path_unit = crate::units::parse_path(
+        parsed_file,
+        &PathBuf::from("/path/to/jobs.path"),
+        crate::units::UnitId(crate::units::UnitIdKind::Path, 10),
+    )
+    .unwrap();
+
+    if let crate::units::UnitSpecialized::Path(path) = path_unit.specialized {
+        assert_eq!(path.config.unit, "process-jobs.service");
+        assert_eq!(
+            path.config.conditions,
+            vec![
+                PathCondition::DirectoryNotEmpty(PathBuf::from("/var/spool/jobs")),
+                PathCondition::ExistsGlob("/var/spool/jobs/*.job".to_owned()),
+                PathCondition::Modified(PathBuf::from("/etc/jobs.conf")),
+            ]
+        );
+    } else {
+        panic!("Path unit was not parsed as a path unit");
+    }
+
+    let parsed_file = crate::units::parse_file("[Unit]\n[Path]\nPathChanged = /etc/a\n").unwrap();
+    let path_unit = crate::units::parse_path(
+        parsed_file,
+        &PathBuf::from("/path/to/a.path"),
+        crate::units::UnitId(crate::units::UnitIdKind::Path, 11),
+    )
+    .unwrap();
+    if let crate::units::UnitSpecialized::Path(path) = path_unit.specialized {
+        assert_eq!(path.config.unit, "a.service");
+    }
+
+    assert!(glob_matches("*.job", "print.job"));
+    assert!(!glob_matches("*.job", "print.job.tmp"));
+    assert!(glob_matches("file?.[a-c]", "file1.b"));
+    assert!(!glob_matches("file?.[!a-c]", "file1.b"));
+    assert!(glob_matches("*", ""));
+
+    let dir = std::env::temp_dir().join(format!("rustysd_path_test_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("incoming")).unwrap();
+    let pattern = format!("{}/*/*.job", dir.to_str().unwrap());
+    assert!(!glob_exists(&pattern));
+    std::fs::write(dir.join("incoming").join(".hidden.job"), "").unwrap();
+    assert!(!glob_exists(&pattern));
+    std::fs::write(dir.join("incoming").join("print.job"), "").unwrap();
+    assert!(glob_exists(&pattern));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_template_units() {
+    use crate::units::{expand_specifiers, template_name, unescape_instance};
+
+    assert_eq!(
+        template_name("getty@tty1.service"),
+        Some("getty@.service".to_owned())
+    );
+    assert_eq!(template_name("getty@.service"), None);
+    assert_eq!(template_name("getty.service"), None);
+    assert!(crate::units::is_template_name("getty@.service"));
+    assert_eq!(unescape_instance("dev-disk\\x2dlabel"), "dev/disk-label");
+    // a multibyte char after \x is kept as it is
+    assert_eq!(unescape_instance("a\\xé1"), "a\\xé1");
+    assert_eq!(unescape_instance("a\\x1é"), "a\\x1é");
+    assert_eq!(unescape_instance("\\xc3\\xa9t\\xc3\\xa9"), "été");
+
+    let mut parsed_file = crate::units::parse_file(
+        "
+    [Service]
+    ExecStart = /bin/echo %n %N %p %i %I %t 100%%
+    ",
+    )
+    .unwrap();
+    expand_specifiers(&mut parsed_file, "mount@var-log.service").unwrap();
+    let exec = &parsed_file["[Service]"]["EXECSTART"][0].1;
+    let runtime_dir = if nix::unistd::getuid().is_root() {
+        "/run".to_owned()
+    } else {
+        std::env::var("XDG_RUNTIME_DIR")
+            .unwrap_or_else(|_| format!("/run/user/{}", nix::unistd::getuid()))
+    };
+    assert_eq!(
+        exec,
+        &format!(
+            "/bin/echo mount@var-log.service mount@var-log mount var-log var/log {} 100%",
+            runtime_dir
+        )
+    );
+
+    let dir = std::env::temp_dir().join(format!("rustysd_template_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("default.target"), "[Unit]\n").unwrap();
+    std::fs::write(
+        dir.join("echo@.service"),
+        "[Service]\nExecStart = /bin/echo %i\n[Install]\nWantedBy = default.target\nDefaultInstance = def\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("other.service"),
+        "[Unit]\nWants = echo@foo.service\n[Service]\nExecStart = /bin/true\n",
+    )
+    .unwrap();
+
+    let mut base_id = 0;
+    let unit_table = crate::units::load_all_units(&[dir.clone()], &mut base_id).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let mut names: Vec<String> = unit_table.values().map(|unit| unit.conf.name()).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec![
+            "default.target",
+            "echo@def.service",
+            "echo@foo.service",
+            "other.service"
+        ]
+    );
+    for unit in unit_table.values() {
+        if let crate::units::UnitSpecialized::Service(srvc) = &unit.specialized {
+            if unit.conf.name() == "echo@foo.service" {
+                assert_eq!(srvc.service_config.exec[0].args, vec!["foo".to_owned()]);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_dropins() {
+    use crate::units::{read_unit_file, ServiceRestart, UnitSpecialized};
+
+    let dir = std::env::temp_dir().join(format!("rustysd_dropin_test_{}", std::process::id()));
+    for dropin_dir in &[
+        "foo-bar.service.d",
+        "foo-.service.d",
+        "-.service.d",
+        "service.d",
+    ] {
+        std::fs::create_dir_all(dir.join(dropin_dir)).unwrap();
+    }
+    let unit_path = dir.join("foo-bar.service");
+    std::fs::write(
+        &unit_path,
+        "[Unit]\nAfter = a.target\n[Service]\nExecStart = /bin/false\nRestart = no\nEnvironment = A=1\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("foo-bar.service.d").join("10-override.conf"),
+        "[Service]\nExecStart =\nExecStart = /bin/true\nRestart = always\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("foo-.service.d").join("20-env.conf"),
+        "# only comments and settings for the list\n[Service]\nEnvironment = B=2\n[Unit]\nAfter = b.target\n",
+    )
+    .unwrap();
+    // an empty value also resets the values before it in the same file
+    std::fs::write(
+        dir.join("-.service.d").join("25-type.conf"),
+        "[Unit]\nWants = x.target\nWants =\nWants = y.target z.target\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("service.d").join("30-all.conf"),
+        "[Unit]\nAfter =\n",
+    )
+    .unwrap();
+    // ignored, drop-ins need the .conf suffix
+    std::fs::write(
+        dir.join("service.d").join("40-all.txt"),
+        "[Unit]\nAfter = c.target\n",
+    )
+    .unwrap();
+
+    let (parsed_file, sources) =
+        read_unit_file(&unit_path, "foo-bar.service", &[dir.clone()]).unwrap();
+    assert_eq!(
+        sources,
+        vec![
+            unit_path.clone(),
+            dir.join("foo-bar.service.d").join("10-override.conf"),
+            dir.join("foo-.service.d").join("20-env.conf"),
+            dir.join("-.service.d").join("25-type.conf"),
+            dir.join("service.d").join("30-all.conf"),
+        ]
+    );
+    let srvc_unit = crate::units::parse_service(
+        parsed_file,
+        &unit_path,
+        crate::units::UnitId(crate::units::UnitIdKind::Service, 0),
+    )
+    .unwrap();
+    assert!(srvc_unit.conf.after.is_empty());
+    assert_eq!(
+        srvc_unit.conf.wants,
+        vec!["y.target".to_owned(), "z.target".to_owned()]
+    );
+    if let UnitSpecialized::Service(srvc) = &srvc_unit.specialized {
+        assert_eq!(srvc.service_config.exec.len(), 1);
+        assert_eq!(srvc.service_config.exec[0].cmd, "/bin/true");
+        assert_eq!(srvc.service_config.restart, ServiceRestart::Always);
+        assert_eq!(
+            srvc.service_config.exec_config.environment,
+            vec![
+                ("A".to_owned(), "1".to_owned()),
+                ("B".to_owned(), "2".to_owned())
+            ]
+        );
+    } else {
+        panic!("Service unit was not parsed as a service unit");
+    }
+
+    // errors point to the drop-in that contains the offending setting
+    std::fs::write(
+        dir.join("foo-bar.service.d").join("50-broken.conf"),
+        "[Service]\n\nNotASetting = 1\n",
+    )
+    .unwrap();
+    let (parsed_file, sources) =
+        read_unit_file(&unit_path, "foo-bar.service", &[dir.clone()]).unwrap();
+    let reason = crate::units::parse_service(
+        parsed_file.clone(),
+        &unit_path,
+        crate::units::UnitId(crate::units::UnitIdKind::Service, 0),
+    )
+    .err()
+    .unwrap();
+    let err = crate::units::locate_error(reason, &parsed_file, &sources);
+    let msg = format!("{}", err);
+    assert!(msg.contains("50-broken.conf"), "{}", msg);
+    assert!(msg.contains("line 3"), "{}", msg);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // the same for the sockets of a socket unit, across all the Listen*= settings
+    let socket_unit = crate::units::parse_socket(
+        crate::units::parse_file(
+            "[Unit]\n[Socket]\nListenStream = /run/a\nListenDatagram =\nListenFifo = /run/b\n",
+        )
+        .unwrap(),
+        &std::path::PathBuf::from("/path/to/reset.socket"),
+        crate::units::UnitId(crate::units::UnitIdKind::Socket, 0),
+    )
+    .unwrap();
+    if let UnitSpecialized::Socket(sock) = &socket_unit.specialized {
+        assert_eq!(sock.sockets.len(), 1);
+        assert_eq!(
+            sock.sockets[0].kind,
+            crate::sockets::SocketKind::Fifo("/run/b".to_owned())
+        );
+    } else {
+        panic!("Socket unit was not parsed as a socket unit");
     }
 }
 
+#[test]
+fn test_unit_search_path() {
+    let dir = std::env::temp_dir().join(format!("rustysd_search_path_test_{}", std::process::id()));
+    let etc = dir.join("etc");
+    let lib = dir.join("lib");
+    std::fs::create_dir_all(&etc).unwrap();
+    std::fs::create_dir_all(&lib).unwrap();
+
+    let service = "[Service]\nExecStart = /bin/true\n";
+    std::fs::write(lib.join("foo.service"), service).unwrap();
+    std::fs::write(
+        etc.join("foo.service"),
+        "[Unit]\nDescription = etc\n[Service]\nExecStart = /bin/true\n",
+    )
+    .unwrap();
+    std::fs::write(lib.join("masked.service"), service).unwrap();
+    std::os::unix::fs::symlink("/dev/null", etc.join("masked.service")).unwrap();
+    std::fs::write(lib.join("real.service"), service).unwrap();
+    std::os::unix::fs::symlink(lib.join("real.service"), etc.join("linked.service")).unwrap();
+    std::fs::write(
+        lib.join("user.service"),
+        "[Unit]\nWants = linked.service named.service\n[Service]\nExecStart = /bin/true\n",
+    )
+    .unwrap();
+    std::fs::write(
+        lib.join("named.service"),
+        "[Service]\nExecStart = /bin/true\n[Install]\nAlias = other-name.service\n",
+    )
+    .unwrap();
+
+    let unit_files = crate::units::find_unit_files(&[etc.clone(), lib.clone()]).unwrap();
+    assert_eq!(unit_files["foo.service"].path, etc.join("foo.service"));
+    assert_eq!(
+        unit_files["foo.service"].shadowed,
+        vec![lib.join("foo.service")]
+    );
+    assert_eq!(
+        unit_files["masked.service"].kind,
+        crate::units::UnitFileKind::Masked
+    );
+    assert_eq!(
+        unit_files["linked.service"].kind,
+        crate::units::UnitFileKind::Alias("real.service".to_owned())
+    );
+
+    let mut last_id = 0;
+    let units = crate::units::load_all_units(&[etc.clone(), lib.clone()], &mut last_id).unwrap();
+    let find = |name: &str| units.values().find(|unit| unit.conf.name() == name);
+
+    let foo = find("foo.service").unwrap();
+    assert_eq!(foo.conf.description, "etc");
+    assert_eq!(foo.conf.shadowed, vec![lib.join("foo.service")]);
+    assert!(find("masked.service").is_none());
+    assert!(find("linked.service").is_none());
+    assert_eq!(
+        find("real.service").unwrap().conf.aliases,
+        vec!["linked.service".to_owned()]
+    );
+    assert_eq!(
+        find("named.service").unwrap().conf.aliases,
+        vec!["other-name.service".to_owned()]
+    );
+    assert_eq!(
+        find("user.service").unwrap().conf.wants,
+        vec!["real.service".to_owned(), "named.service".to_owned()]
+    );
+
+    // loading a masked unit while running fails, an alias loads the unit it points to
+    assert!(
+        crate::units::load_new_unit(&[etc.clone(), lib.clone()], "masked.service", 100).is_err()
+    );
+    let unit =
+        crate::units::load_new_unit(&[etc.clone(), lib.clone()], "linked.service", 100).unwrap();
+    assert_eq!(unit.conf.name(), "real.service");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
 #[test]
 fn test_socket_parsing() {
     let descr = "This is a description";
@@ -271,6 +2446,83 @@ fn test_socket_parsing() {
     }
 }
 
+#[test]
+fn test_accept_socket_parsing() {
+    use crate::sockets::AcceptConfig;
+    use std::path::PathBuf;
+
+    let parsed_file = crate::units::parse_file(
+        "
+    [Unit]
+    [Socket]
+    ListenStream = 127.0.0.1:8080
+    Accept = yes
+    MaxConnections = 10
+    MaxConnectionsPerSource = 2
+    ",
+    )
+    .unwrap();
+    let socket_unit = crate::units::parse_socket(
+        parsed_file,
+        &PathBuf::from("/path/to/echo.socket"),
+        crate::units::UnitId(crate::units::UnitIdKind::Socket, 0),
+    )
+    .unwrap();
+    if let crate::units::UnitSpecialized::Socket(sock) = &socket_unit.specialized {
+        assert_eq!(
+            sock.accept,
+            Some(AcceptConfig {
+                max_connections: 10,
+                max_connections_per_source: 2,
+            })
+        );
+    } else {
+        panic!("Socket unit was not parsed as a socket unit");
+    }
+
+    let parsed_file =
+        crate::units::parse_file("[Unit]\n[Socket]\nListenStream = /run/echo\nAccept = true\n")
+            .unwrap();
+    let socket_unit = crate::units::parse_socket(
+        parsed_file,
+        &PathBuf::from("/path/to/echo.socket"),
+        crate::units::UnitId(crate::units::UnitIdKind::Socket, 0),
+    )
+    .unwrap();
+    if let crate::units::UnitSpecialized::Socket(sock) = &socket_unit.specialized {
+        assert_eq!(
+            sock.accept,
+            Some(AcceptConfig {
+                max_connections: 64,
+                max_connections_per_source: 64,
+            })
+        );
+    }
+
+    // datagram sockets have no connections to accept
+    let parsed_file = crate::units::parse_file(
+        "[Unit]\n[Socket]\nListenDatagram = 127.0.0.1:8080\nAccept = yes\n",
+    )
+    .unwrap();
+    assert!(crate::units::parse_socket(
+        parsed_file,
+        &PathBuf::from("/path/to/echo.socket"),
+        crate::units::UnitId(crate::units::UnitIdKind::Socket, 0),
+    )
+    .is_err());
+
+    // Accept= belongs into the [Socket] section
+    let parsed_file =
+        crate::units::parse_file("[Unit]\n[Service]\nExecStart = /bin/true\nAccept = yes\n")
+            .unwrap();
+    assert!(crate::units::parse_service(
+        parsed_file,
+        &PathBuf::from("/path/to/echo.service"),
+        crate::units::UnitId(crate::units::UnitIdKind::Service, 0),
+    )
+    .is_err());
+}
+
 #[test]
 fn test_unit_ordering() {
     let target1_str = format!(
@@ -369,6 +2621,672 @@ fn test_unit_ordering() {
     assert!(unit_table.get(&id3).unwrap().install.after.contains(&id1));
 }
 
+/// A RuntimeInfo with all units in the NeverStarted state
+fn make_run_info(
+    unit_table: std::collections::HashMap<crate::units::UnitId, crate::units::Unit>,
+    target_unit: &str,
+    last_id: u64,
+) -> crate::units::ArcRuntimeInfo {
+    use crate::units::UnitStatus;
+    use std::sync::{Arc, Mutex, RwLock};
+
+    let unit_table: std::collections::HashMap<_, _> = unit_table
+        .into_iter()
+        .map(|(id, unit)| (id, Arc::new(Mutex::new(unit))))
+        .collect();
+    let status_table = unit_table
+        .keys()
+        .map(|id| (*id, Arc::new(Mutex::new(UnitStatus::NeverStarted))))
+        .collect();
+    Arc::new(crate::units::RuntimeInfo {
+        unit_table: Arc::new(RwLock::new(unit_table)),
+        status_table: Arc::new(RwLock::new(status_table)),
+        pid_table: Arc::new(Mutex::new(std::collections::HashMap::new())),
+        fd_store: Arc::new(RwLock::new(crate::fd_store::FDStore::default())),
+        config: crate::config::Config {
+            unit_dirs: Vec::new(),
+            target_unit: target_unit.to_owned(),
+            notification_sockets_dir: std::env::temp_dir(),
+            state_dir: std::env::temp_dir(),
+        },
+        last_id: Arc::new(Mutex::new(last_id)),
+        job_table: Arc::new(crate::units::JobTable::default()),
+        journal: Arc::new(Mutex::new(None)),
+        log_sink: Arc::new(None),
+    })
+}
+
+#[test]
+fn test_dependency_types() {
+    use crate::units::{UnitId, UnitIdKind, UnitStatus};
+    use std::sync::Arc;
+
+    let unit_strs = [
+        "[Unit]\nConflicts = 2.target\nBindsTo = 3.target\nPartOf = 4.target\nRequisite = 5.target\n[Install]\nWantedBy = 6.target\n",
+        "[Unit]\n[Install]\nWantedBy = 6.target\n",
+        "[Unit]\n",
+        "[Unit]\n",
+        "[Unit]\n",
+        "[Unit]\n",
+    ];
+    let mut unit_table = std::collections::HashMap::new();
+    for (idx, unit_str) in unit_strs.iter().enumerate() {
+        let parsed_file = crate::units::parse_file(unit_str).unwrap();
+        let unit = crate::units::parse_target(
+            parsed_file,
+            &std::path::PathBuf::from(format!("/path/to/{}.target", idx + 1)),
+            UnitId(UnitIdKind::Target, idx as u64 + 1),
+        )
+        .unwrap();
+        unit_table.insert(unit.id, unit);
+    }
+    let id = |idx: u64| UnitId(UnitIdKind::Target, idx);
+
+    crate::units::fill_dependencies(&mut unit_table);
+    let install = |idx: u64| &unit_table.get(&id(idx)).unwrap().install;
+    assert_eq!(install(1).conflicts, vec![id(2)]);
+    assert_eq!(install(2).conflicts, vec![id(1)]);
+    assert_eq!(install(1).binds_to, vec![id(3)]);
+    assert_eq!(install(3).bound_by, vec![id(1)]);
+    assert_eq!(install(1).part_of, vec![id(4)]);
+    assert_eq!(install(4).consists_of, vec![id(1)]);
+    assert_eq!(install(1).requisite, vec![id(5)]);
+    assert_eq!(install(5).requisite_of, vec![id(1)]);
+    assert!(install(1).after.is_empty());
+    // stopping any of these also stops 1.target, the conflicting unit is not affected
+    for idx in 3..=5 {
+        assert_eq!(install(idx).stopped_with(), vec![id(1)]);
+    }
+    assert!(install(2).stopped_with().is_empty());
+
+    let run_info = make_run_info(unit_table, "6.target", 6);
+    let status = |idx: u64| {
+        run_info.status_table.read().unwrap()[&id(idx)]
+            .lock()
+            .unwrap()
+            .clone()
+    };
+    let activate = |idx: u64| {
+        crate::units::activate_unit(
+            id(idx),
+            run_info.clone(),
+            std::env::temp_dir(),
+            Arc::new(Vec::new()),
+            true,
+        )
+    };
+
+    // Requisite= units are not started, they have to be active already
+    assert!(activate(1).is_err());
+    assert!(matches!(status(1), UnitStatus::StoppedFinal(_)));
+
+    *run_info.status_table.read().unwrap()[&id(1)]
+        .lock()
+        .unwrap() = UnitStatus::NeverStarted;
+    for idx in 2..=5 {
+        assert!(activate(idx).is_ok());
+    }
+    assert!(activate(1).is_ok());
+    assert_eq!(status(1), UnitStatus::Started);
+    // starting 1.target stopped the conflicting unit
+    assert!(matches!(status(2), UnitStatus::StoppedFinal(_)));
+
+    // PartOf= propagates restarts
+    crate::units::reactivate_unit_recursive(
+        id(4),
+        run_info.clone(),
+        std::env::temp_dir(),
+        Arc::new(Vec::new()),
+    )
+    .ok()
+    .unwrap();
+    assert_eq!(status(4), UnitStatus::Started);
+    assert_eq!(status(1), UnitStatus::Started);
+
+    // BindsTo= stops 1.target together with 3.target
+    assert!(crate::units::deactivate_unit_recursive(id(3), true, run_info.clone()).is_ok());
+    assert!(matches!(status(3), UnitStatus::StoppedFinal(_)));
+    assert!(matches!(status(1), UnitStatus::StoppedFinal(_)));
+    assert_eq!(status(4), UnitStatus::Started);
+}
+
+#[test]
+fn test_units_only_triggered_at_boot() {
+    use crate::units::{UnitId, UnitIdKind, UnitSpecialized, UnitStatus};
+
+    // 1.target is only started by the timer, 2.target is ordered after it but must not wait for it while booting
+    let mut unit_table = std::collections::HashMap::new();
+    for (idx, unit_str) in ["[Unit]\n", "[Unit]\nAfter = 1.target\n"]
+        .iter()
+        .enumerate()
+    {
+        let unit = crate::units::parse_target(
+            crate::units::parse_file(unit_str).unwrap(),
+            &std::path::PathBuf::from(format!("/path/to/{}.target", idx + 1)),
+            UnitId(UnitIdKind::Target, idx as u64 + 1),
+        )
+        .unwrap();
+        unit_table.insert(unit.id, unit);
+    }
+    let mut timer = crate::units::parse_timer(
+        crate::units::parse_file("[Unit]\n[Timer]\nOnBootSec = 1h\nUnit = 1.target\n").unwrap(),
+        &std::path::PathBuf::from("/path/to/3.timer"),
+        UnitId(UnitIdKind::Timer, 3),
+    )
+    .unwrap();
+    if let UnitSpecialized::Timer(timer) = &mut timer.specialized {
+        timer.unit_id = Some(UnitId(UnitIdKind::Target, 1));
+    }
+    unit_table.insert(timer.id, timer);
+    crate::units::fill_dependencies(&mut unit_table);
+
+    let run_info = make_run_info(unit_table, "2.target", 3);
+    crate::units::activate_units(run_info.clone(), std::env::temp_dir(), Vec::new());
+    let status = |id: UnitId| {
+        run_info.status_table.read().unwrap()[&id]
+            .lock()
+            .unwrap()
+            .clone()
+    };
+    assert_eq!(
+        status(UnitId(UnitIdKind::Target, 1)),
+        UnitStatus::NeverStarted
+    );
+    assert_eq!(status(UnitId(UnitIdKind::Target, 2)), UnitStatus::Started);
+    assert_eq!(status(UnitId(UnitIdKind::Timer, 3)), UnitStatus::Started);
+}
+
+#[test]
+fn test_result_handlers() {
+    use crate::units::{UnitResult, UnitStatus};
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join(format!("rustysd_handler_test_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("failing.target"),
+        "[Unit]\nOnFailure = notify-admin@%n.target\nOnSuccess = a.target b.target\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("notify-admin@failing.target.target"), "[Unit]\n").unwrap();
+
+    let mut base_id = 0;
+    let unit_table = crate::units::load_all_units(&[dir.clone()], &mut base_id).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let find_id = |name: &str| {
+        unit_table
+            .values()
+            .find(|unit| unit.conf.name() == name)
+            .unwrap()
+            .id
+    };
+    let failing_id = find_id("failing.target");
+    let handler_id = find_id("notify-admin@failing.target.target");
+    let conf = &unit_table[&failing_id].conf;
+    assert_eq!(conf.on_failure, vec!["notify-admin@failing.target.target"]);
+    assert_eq!(conf.on_success, vec!["a.target", "b.target"]);
+
+    let run_info = make_run_info(unit_table, "failing.target", base_id);
+
+    crate::units::start_result_handlers(
+        failing_id,
+        UnitResult::Failure,
+        run_info.clone(),
+        std::env::temp_dir(),
+        Arc::new(Vec::new()),
+    );
+    assert_eq!(
+        run_info.unit_table.read().unwrap()[&failing_id]
+            .lock()
+            .unwrap()
+            .triggered_handlers,
+        vec!["OnFailure=notify-admin@failing.target.target"]
+    );
+    // the handlers are started in their own thread
+    let mut handler_started = false;
+    for _ in 0..100 {
+        let status = run_info.status_table.read().unwrap()[&handler_id]
+            .lock()
+            .unwrap()
+            .clone();
+        if status == UnitStatus::Started {
+            handler_started = true;
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(handler_started);
+}
+
+#[test]
+fn test_oneshot_result_handlers() {
+    use crate::signal_handler::ChildTermination;
+    use crate::units::{PidEntry, UnitStatus};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join(format!(
+        "rustysd_oneshot_handler_test_{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    let units = [
+        (
+            "remain.service",
+            "ExecStart = /bin/true\nRemainAfterExit = yes\n",
+        ),
+        ("exits.service", "ExecStart = /bin/true\n"),
+        (
+            "fails.service",
+            "ExecStart = /bin/false\nRemainAfterExit = yes\n",
+        ),
+    ];
+    for (name, service) in &units {
+        std::fs::write(
+            dir.join(name),
+            format!(
+                "[Unit]\nOnSuccess = success@%n.target\nOnFailure = failure@%n.target\n[Service]\nType = oneshot\n{}",
+                service
+            ),
+        )
+        .unwrap();
+        std::fs::write(dir.join(format!("success@{}.target", name)), "[Unit]\n").unwrap();
+        std::fs::write(dir.join(format!("failure@{}.target", name)), "[Unit]\n").unwrap();
+    }
+    let mut base_id = 0;
+    let unit_table = crate::units::load_all_units(&[dir.clone()], &mut base_id).unwrap();
+    let ids: Vec<_> = units
+        .iter()
+        .map(|(name, _)| {
+            unit_table
+                .values()
+                .find(|unit| unit.conf.name() == *name)
+                .unwrap()
+                .id
+        })
+        .collect();
+    let run_info = make_run_info(unit_table, "remain.service", base_id);
+
+    // the signal handler is not running in the tests, so collect the exits of the oneshot processes here. Like the
+    // signal handler every exit is handled in it's own thread, the exit of the last run may still wait for the unit
+    let done = Arc::new(AtomicBool::new(false));
+    let reaper = {
+        let run_info = run_info.clone();
+        let done = done.clone();
+        let ids = ids.clone();
+        std::thread::spawn(move || {
+            while !done.load(Ordering::SeqCst) {
+                let pids: Vec<_> = run_info
+                    .pid_table
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .filter(
+                        |(_, entry)| matches!(entry, PidEntry::Service(id, _) if ids.contains(id)),
+                    )
+                    .map(|(pid, _)| *pid)
+                    .collect();
+                for pid in pids {
+                    if let Ok(nix::sys::wait::WaitStatus::Exited(pid, code)) =
+                        nix::sys::wait::waitpid(pid, Some(nix::sys::wait::WaitPidFlag::WNOHANG))
+                    {
+                        crate::services::service_exit_handler_new_thread(
+                            pid,
+                            ChildTermination::Exit(code),
+                            run_info.clone(),
+                            std::env::temp_dir(),
+                            Vec::new(),
+                        );
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_millis(5));
+            }
+        })
+    };
+
+    let expected = [
+        (
+            UnitStatus::Started,
+            "OnSuccess=success@remain.service.target",
+        ),
+        (UnitStatus::Exited, "OnSuccess=success@exits.service.target"),
+        (
+            UnitStatus::Stopped,
+            "OnFailure=failure@fails.service.target",
+        ),
+    ];
+    for (id, (status, handler)) in ids.iter().zip(expected.iter()) {
+        let result = crate::units::activate_unit(
+            *id,
+            run_info.clone(),
+            dir.clone(),
+            Arc::new(Vec::new()),
+            false,
+        );
+        assert_eq!(result.is_ok(), *status != UnitStatus::Stopped);
+        let status_locked = run_info.status_table.read().unwrap()[id]
+            .lock()
+            .unwrap()
+            .clone();
+        match status {
+            UnitStatus::Stopped => assert!(matches!(status_locked, UnitStatus::StoppedFinal(_))),
+            status => assert_eq!(status_locked, *status),
+        }
+        assert_eq!(
+            run_info.unit_table.read().unwrap()[id]
+                .lock()
+                .unwrap()
+                .triggered_handlers,
+            vec![handler.to_owned()]
+        );
+    }
+
+    // a start job runs a oneshot service that exited again every time
+    for _ in 0..2 {
+        run_info.unit_table.read().unwrap()[&ids[1]]
+            .lock()
+            .unwrap()
+            .triggered_handlers
+            .clear();
+        crate::units::run_unit_job(
+            ids[1],
+            crate::units::JobKind::Start,
+            run_info.clone(),
+            dir.clone(),
+            Arc::new(Vec::new()),
+        )
+        .unwrap();
+        assert_eq!(
+            run_info.unit_table.read().unwrap()[&ids[1]]
+                .lock()
+                .unwrap()
+                .triggered_handlers,
+            vec![expected[1].1.to_owned()]
+        );
+    }
+    done.store(true, Ordering::SeqCst);
+    reaper.join().unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_conditions() {
+    use crate::units::{check_conditions, ConditionKind, UnitId, UnitIdKind, UnitStatus};
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join(format!("rustysd_condition_test_{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("empty_dir")).unwrap();
+    std::fs::write(dir.join("empty_file"), "").unwrap();
+    std::fs::write(dir.join("file"), "content").unwrap();
+    let path = |name: &str| dir.join(name).to_str().unwrap().to_owned();
+
+    let parsed_file = crate::units::parse_file(&format!(
+        "[Unit]\nConditionPathExists = {}\nConditionPathExists = !{}\nConditionFileNotEmpty = |{}\nConditionDirectoryNotEmpty = |!{}\nConditionUser = {}\nAssertPathIsDirectory = {}\n",
+        path("file"),
+        path("missing"),
+        path("empty_file"),
+        path("empty_dir"),
+        nix::unistd::getuid(),
+        path("empty_dir"),
+    ))
+    .unwrap();
+    let unit = crate::units::parse_target(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/1.target"),
+        UnitId(UnitIdKind::Target, 1),
+    )
+    .unwrap();
+    let conditions = &unit.conf.conditions;
+    assert_eq!(conditions.len(), 5);
+    assert!(!conditions[0].negate && !conditions[0].trigger);
+    assert!(conditions[1].negate && !conditions[1].trigger);
+    assert_eq!(conditions[1].value, path("missing"));
+    assert!(conditions[3].negate && conditions[3].trigger);
+    assert_eq!(conditions[4].kind, ConditionKind::User);
+    assert_eq!(unit.conf.asserts.len(), 1);
+
+    // the '|' conditions only need one of them to be met
+    assert!(!conditions[2].is_met());
+    assert!(conditions[3].is_met());
+    assert!(check_conditions(conditions, "Condition").is_ok());
+    assert!(check_conditions(&unit.conf.asserts, "Assert").is_ok());
+    assert_eq!(
+        check_conditions(&conditions[2..3], "Condition"),
+        Err(format!(
+            "None of ConditionFileNotEmpty=|{} was met",
+            path("empty_file")
+        ))
+    );
+    assert!(check_conditions(&conditions[1..2], "Assert").is_ok());
+    std::fs::write(dir.join("missing"), "").unwrap();
+    assert_eq!(
+        check_conditions(&conditions[1..2], "Assert"),
+        Err(format!("AssertPathExists=!{} was not met", path("missing")))
+    );
+
+    // relative paths are not allowed
+    let parsed_file =
+        crate::units::parse_file("[Unit]\nConditionPathExists = relative/path\n").unwrap();
+    assert!(crate::units::parse_target(
+        parsed_file,
+        &std::path::PathBuf::from("/path/to/1.target"),
+        UnitId(UnitIdKind::Target, 1),
+    )
+    .is_err());
+
+    // a skipped unit does not block the units ordered after it
+    let unit_strs = [
+        format!("[Unit]\nConditionPathExists = {}\n", path("still_missing")),
+        "[Unit]\nAfter = 1.target\n".to_owned(),
+        format!(
+            "[Unit]\nAssertPathExists = {}\nOnFailure = notify-admin.target\n",
+            path("still_missing")
+        ),
+    ];
+    std::fs::remove_dir_all(&dir).unwrap();
+    let mut unit_table = std::collections::HashMap::new();
+    for (idx, unit_str) in unit_strs.iter().enumerate() {
+        let parsed_file = crate::units::parse_file(unit_str).unwrap();
+        let unit = crate::units::parse_target(
+            parsed_file,
+            &std::path::PathBuf::from(format!("/path/to/{}.target", idx + 1)),
+            UnitId(UnitIdKind::Target, idx as u64 + 1),
+        )
+        .unwrap();
+        unit_table.insert(unit.id, unit);
+    }
+    crate::units::fill_dependencies(&mut unit_table);
+    let run_info = make_run_info(unit_table, "2.target", 3);
+    let id = |idx: u64| UnitId(UnitIdKind::Target, idx);
+    let status = |idx: u64| {
+        run_info.status_table.read().unwrap()[&id(idx)]
+            .lock()
+            .unwrap()
+            .clone()
+    };
+    let activate = |idx: u64| {
+        crate::units::activate_unit(
+            id(idx),
+            run_info.clone(),
+            std::env::temp_dir(),
+            Arc::new(Vec::new()),
+            true,
+        )
+    };
+
+    assert!(activate(1).is_ok());
+    assert!(matches!(status(1), UnitStatus::Skipped(_)));
+    assert!(activate(2).is_ok());
+    assert_eq!(status(2), UnitStatus::Started);
+    assert!(activate(3).is_err());
+    assert!(matches!(status(3), UnitStatus::StoppedFinal(_)));
+    // a failed assertion is a failed start and triggers OnFailure=
+    assert_eq!(
+        run_info.unit_table.read().unwrap()[&id(3)]
+            .lock()
+            .unwrap()
+            .triggered_handlers,
+        vec!["OnFailure=notify-admin.target"]
+    );
+}
+
+#[test]
+fn test_jobs() {
+    use crate::units::{JobKind, JobMode, JobResult, UnitId, UnitIdKind, UnitStatus};
+    use std::sync::Arc;
+
+    let unit_strs = [
+        "[Unit]\nRequires = 2.target\nWants = 3.target\nAfter = 2.target\n",
+        "[Unit]\n",
+        "[Unit]\nAfter = 1.target\n",
+        "[Unit]\nConflicts = 1.target\n",
+        "[Unit]\n",
+    ];
+    let mut unit_table = std::collections::HashMap::new();
+    for (idx, unit_str) in unit_strs.iter().enumerate() {
+        let parsed_file = crate::units::parse_file(unit_str).unwrap();
+        let unit = crate::units::parse_target(
+            parsed_file,
+            &std::path::PathBuf::from(format!("/path/to/{}.target", idx + 1)),
+            UnitId(UnitIdKind::Target, idx as u64 + 1),
+        )
+        .unwrap();
+        unit_table.insert(unit.id, unit);
+    }
+    crate::units::fill_dependencies(&mut unit_table);
+    let run_info = make_run_info(unit_table, "5.target", 5);
+    let id = |idx: u64| UnitId(UnitIdKind::Target, idx);
+    let status = |idx: u64| {
+        run_info.status_table.read().unwrap()[&id(idx)]
+            .lock()
+            .unwrap()
+            .clone()
+    };
+    let run = |idx: u64, kind: JobKind, mode: JobMode| {
+        crate::units::run_transaction(
+            id(idx),
+            kind,
+            mode,
+            run_info.clone(),
+            std::env::temp_dir(),
+            Arc::new(Vec::new()),
+        )
+        .map(|finished| {
+            finished
+                .into_iter()
+                .map(|job| (job.unit_name, job.kind, job.result))
+                .collect::<Vec<_>>()
+        })
+    };
+
+    // Requires= and Wants= are pulled in and started in the order of After=
+    assert_eq!(
+        run(1, JobKind::Start, JobMode::Replace).unwrap(),
+        vec![
+            ("2.target".to_owned(), JobKind::Start, JobResult::Done),
+            ("1.target".to_owned(), JobKind::Start, JobResult::Done),
+            ("3.target".to_owned(), JobKind::Start, JobResult::Done),
+        ]
+    );
+    assert!(run_info.job_table.list().is_empty());
+    assert!(run_info.job_table.cancel(1).is_err());
+
+    // the conflicting unit is stopped first
+    assert_eq!(
+        run(4, JobKind::Start, JobMode::Fail).unwrap(),
+        vec![
+            ("1.target".to_owned(), JobKind::Stop, JobResult::Done),
+            ("4.target".to_owned(), JobKind::Start, JobResult::Done),
+        ]
+    );
+    assert!(matches!(status(1), UnitStatus::StoppedFinal(_)));
+    assert_eq!(status(4), UnitStatus::Started);
+
+    // units that require the stopped unit are stopped before it
+    run(1, JobKind::Start, JobMode::Replace).unwrap();
+    assert!(matches!(status(4), UnitStatus::StoppedFinal(_)));
+    assert_eq!(
+        run(2, JobKind::Stop, JobMode::Replace).unwrap(),
+        vec![
+            ("1.target".to_owned(), JobKind::Stop, JobResult::Done),
+            ("2.target".to_owned(), JobKind::Stop, JobResult::Done),
+        ]
+    );
+
+    // with ignore-dependencies nothing is pulled in
+    assert_eq!(
+        run(2, JobKind::Start, JobMode::IgnoreDependencies).unwrap(),
+        vec![("2.target".to_owned(), JobKind::Start, JobResult::Done)]
+    );
+
+    // isolate stops everything that is not needed
+    assert!(run(5, JobKind::Stop, JobMode::Isolate).is_err());
+    run(5, JobKind::Start, JobMode::Isolate).unwrap();
+    assert_eq!(status(5), UnitStatus::Started);
+    for idx in 1..=3 {
+        assert!(matches!(status(idx), UnitStatus::StoppedFinal(_)));
+    }
+
+    // jobs that are merged into a waiting job get the result of that job
+    let spawn = |idx: u64, mode: JobMode| {
+        let run_info = run_info.clone();
+        std::thread::spawn(move || {
+            crate::units::run_transaction(
+                id(idx),
+                JobKind::Start,
+                mode,
+                run_info,
+                std::env::temp_dir(),
+                Arc::new(Vec::new()),
+            )
+            .unwrap()
+            .into_iter()
+            .map(|job| (job.id, job.result))
+            .collect::<Vec<_>>()
+        })
+    };
+    let wait_for_jobs = |count: usize| {
+        while run_info.job_table.list().len() < count {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+    };
+    let status_table_locked = run_info.status_table.write().unwrap();
+    // the running job blocks on the status table, so the other jobs keep waiting
+    let running = spawn(5, JobMode::IgnoreDependencies);
+    wait_for_jobs(1);
+    let started = spawn(2, JobMode::Replace);
+    wait_for_jobs(2);
+    let canceled = spawn(3, JobMode::IgnoreDependencies);
+    wait_for_jobs(3);
+    let jobs = run_info.job_table.list();
+    let merged_started = spawn(2, JobMode::Replace);
+    let merged_canceled = spawn(3, JobMode::IgnoreDependencies);
+    // merged jobs are not listed on their own
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    assert_eq!(run_info.job_table.list().len(), 3);
+    run_info.job_table.cancel(jobs[2].id).unwrap();
+    drop(status_table_locked);
+
+    assert_eq!(running.join().unwrap(), vec![(jobs[0].id, JobResult::Done)]);
+    assert_eq!(started.join().unwrap(), vec![(jobs[1].id, JobResult::Done)]);
+    assert_eq!(
+        merged_started.join().unwrap(),
+        vec![(jobs[1].id, JobResult::Done)]
+    );
+    assert_eq!(
+        canceled.join().unwrap(),
+        vec![(jobs[2].id, JobResult::Canceled)]
+    );
+    assert_eq!(
+        merged_canceled.join().unwrap(),
+        vec![(jobs[2].id, JobResult::Canceled)]
+    );
+    assert!(run_info.job_table.list().is_empty());
+    assert_eq!(status(2), UnitStatus::Started);
+    assert!(matches!(status(3), UnitStatus::StoppedFinal(_)));
+}
+
 #[test]
 fn test_circle() {
     let target1_str = format!(
@@ -0,0 +1,352 @@
+//! Timer units activate another unit (by default the service with the same name) at certain points in time.
+//! Both monotonic timers (relative to some event like the activation of the timer) and calendar timers are supported.
+//!
+//! All active timers are checked by one thread that sleeps until the next timer elapses (or at most a second, so changes
+//! in the status of the triggered units are noticed in time).
+
+use crate::platform::EventFd;
+use crate::units::*;
+use chrono::{Datelike, TimeZone, Timelike};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// check at least this often, so changes of the triggered units are picked up in time
+const MAX_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Calendar expressions are not evaluated beyond this year
+const MAX_YEAR: i32 = 2199;
+
+/// OnBootSec= is counted from when the timer thread was started. Rustysd is usually the first process in the
+/// container, so that is close enough to the boot.
+static BOOT_TIME: Mutex<Option<Instant>> = Mutex::new(None);
+
+fn boot_time() -> Instant {
+    *BOOT_TIME.lock().unwrap().get_or_insert_with(Instant::now)
+}
+
+/// One comma separated part of a component in a calendar expression: start..end/step
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CalendarRange {
+    pub start: u32,
+    pub end: u32,
+    pub step: u32,
+}
+
+/// The allowed values of one component (year, month, ...). No ranges means every value is allowed
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CalendarComponent(pub Vec<CalendarRange>);
+
+impl CalendarComponent {
+    pub fn matches(&self, value: u32) -> bool {
+        self.0.is_empty()
+            || self.0.iter().any(|range| {
+                value >= range.start
+                    && value <= range.end
+                    && (value - range.start).is_multiple_of(range.step)
+            })
+    }
+}
+
+/// A parsed calendar expression like "Mon..Fri *-*-* 10:00:00", see systemd.time(7)
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CalendarSpec {
+    /// The expression as written in the unit file
+    pub expression: String,
+    /// Bit 0 is monday, bit 6 is sunday. None allows all days
+    pub weekdays: Option<u8>,
+    pub years: CalendarComponent,
+    pub months: CalendarComponent,
+    pub days: CalendarComponent,
+    pub hours: CalendarComponent,
+    pub minutes: CalendarComponent,
+    pub seconds: CalendarComponent,
+    /// Evaluate in UTC instead of the local timezone
+    pub utc: bool,
+}
+
+impl CalendarSpec {
+    fn matches_day(&self, date: chrono::NaiveDate) -> bool {
+        let weekday_ok = match self.weekdays {
+            Some(mask) => mask & (1 << date.weekday().num_days_from_monday()) != 0,
+            None => true,
+        };
+        weekday_ok && self.days.matches(date.day())
+    }
+
+    /// The first point in time strictly after `after` that matches, in the timezone of the expression
+    fn next_after_naive(&self, after: chrono::NaiveDateTime) -> Option<chrono::NaiveDateTime> {
+        let mut t = after.with_nanosecond(0)? + chrono::Duration::seconds(1);
+        loop {
+            if t.year() > MAX_YEAR {
+                return None;
+            }
+            if !self.years.matches(t.year() as u32) {
+                t = chrono::NaiveDate::from_ymd_opt(t.year() + 1, 1, 1)?.and_hms(0, 0, 0);
+                continue;
+            }
+            if !self.months.matches(t.month()) {
+                t = if t.month() == 12 {
+                    chrono::NaiveDate::from_ymd_opt(t.year() + 1, 1, 1)?
+                } else {
+                    chrono::NaiveDate::from_ymd_opt(t.year(), t.month() + 1, 1)?
+                }
+                .and_hms(0, 0, 0);
+                continue;
+            }
+            if !self.matches_day(t.date()) {
+                t = t.date().succ_opt()?.and_hms(0, 0, 0);
+                continue;
+            }
+            if !self.hours.matches(t.hour()) {
+                t = t.date().and_hms(t.hour(), 0, 0) + chrono::Duration::hours(1);
+                continue;
+            }
+            if !self.minutes.matches(t.minute()) {
+                t = t.date().and_hms(t.hour(), t.minute(), 0) + chrono::Duration::minutes(1);
+                continue;
+            }
+            if !self.seconds.matches(t.second()) {
+                t += chrono::Duration::seconds(1);
+                continue;
+            }
+            return Some(t);
+        }
+    }
+
+    /// The next point in time strictly after `after` that matches this expression
+    pub fn next_after(&self, after: SystemTime) -> Option<SystemTime> {
+        let secs = after.duration_since(UNIX_EPOCH).ok()?.as_secs() as i64;
+        if self.utc {
+            let next = self.next_after_naive(chrono::NaiveDateTime::from_timestamp(secs, 0))?;
+            return Some(UNIX_EPOCH + Duration::from_secs(next.timestamp() as u64));
+        }
+        let mut base = chrono::Local.timestamp(secs, 0).naive_local();
+        loop {
+            let next = self.next_after_naive(base)?;
+            // times that fall into a DST gap don't exist, times in a DST fold might be before `after`
+            match chrono::Local.from_local_datetime(&next).earliest() {
+                Some(time) if time.timestamp() > secs => {
+                    return Some(UNIX_EPOCH + Duration::from_secs(time.timestamp() as u64));
+                }
+                _ => base = next,
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Timer {
+    pub config: TimerConfig,
+    /// The unit named in Unit=, resolved after all units are loaded
+    pub unit_id: Option<UnitId>,
+    /// When the timer unit itself got activated. None if it is not active
+    pub activated_at: Option<Instant>,
+    pub last_trigger: Option<Instant>,
+    pub last_trigger_realtime: Option<SystemTime>,
+    /// Calendar timers elapse at the next matching time after this
+    pub calendar_base: Option<SystemTime>,
+    pub unit_last_active: Option<Instant>,
+    pub unit_last_inactive: Option<Instant>,
+    pub unit_was_active: bool,
+}
+
+impl Timer {
+    pub fn new(config: TimerConfig) -> Self {
+        Timer {
+            config,
+            unit_id: None,
+            activated_at: None,
+            last_trigger: None,
+            last_trigger_realtime: None,
+            calendar_base: None,
+            unit_last_active: None,
+            unit_last_inactive: None,
+            unit_was_active: false,
+        }
+    }
+
+    /// How long until the timer elapses next. Zero if it is due, None if it will never elapse again.
+    /// Monotonic timers only count if they lie after the last trigger, so each of them elapses only once
+    /// per event they are relative to.
+    pub fn next_elapse(
+        &self,
+        boot: Instant,
+        now: Instant,
+        now_realtime: SystemTime,
+    ) -> Option<Duration> {
+        let activated_at = self.activated_at?;
+        let mut monotonic = Vec::new();
+        monotonic.extend(self.config.on_active.iter().map(|d| activated_at + *d));
+        monotonic.extend(self.config.on_boot.iter().map(|d| boot + *d));
+        if let Some(active) = self.unit_last_active {
+            monotonic.extend(self.config.on_unit_active.iter().map(|d| active + *d));
+        }
+        if let Some(inactive) = self.unit_last_inactive {
+            monotonic.extend(self.config.on_unit_inactive.iter().map(|d| inactive + *d));
+        }
+        let next_monotonic = monotonic
+            .into_iter()
+            .filter(|time| match self.last_trigger {
+                Some(last) => *time > last,
+                None => true,
+            })
+            .min()
+            .map(|time| time.saturating_duration_since(now));
+
+        let next_calendar = self.calendar_base.and_then(|base| {
+            self.config
+                .on_calendar
+                .iter()
+                .filter_map(|spec| spec.next_after(base))
+                .min()
+                .map(|time| time.duration_since(now_realtime).unwrap_or_default())
+        });
+
+        match (next_monotonic, next_calendar) {
+            (Some(mono), Some(cal)) => Some(mono.min(cal)),
+            (mono, cal) => mono.or(cal),
+        }
+    }
+
+    /// The next elapse as a point in wall clock time, for displaying it
+    pub fn next_elapse_realtime(&self) -> Option<SystemTime> {
+        let now_realtime = SystemTime::now();
+        self.next_elapse(boot_time(), Instant::now(), now_realtime)
+            .map(|until| now_realtime + until)
+    }
+}
+
+fn stamp_path(state_dir: &Path, timer_name: &str) -> PathBuf {
+    state_dir
+        .join("timers")
+        .join(format!("{}.stamp", timer_name))
+}
+
+/// The last time a persistent timer elapsed, stored as seconds since the epoch
+pub fn read_stamp(state_dir: &Path, timer_name: &str) -> Option<SystemTime> {
+    let content = std::fs::read_to_string(stamp_path(state_dir, timer_name)).ok()?;
+    let secs = content.trim().parse::<u64>().ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+pub fn write_stamp(state_dir: &Path, timer_name: &str, time: SystemTime) -> Result<(), String> {
+    let path = stamp_path(state_dir, timer_name);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Could not create dir {:?}: {}", parent, e))?;
+    }
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    std::fs::write(&path, format!("{}\n", secs))
+        .map_err(|e| format!("Could not write timer stamp {:?}: {}", path, e))
+}
+
+pub fn start_timer_thread(
+    run_info: ArcRuntimeInfo,
+    notification_socket_path: PathBuf,
+    eventfds: Arc<Vec<EventFd>>,
+) {
+    let boot = boot_time();
+    std::thread::spawn(move || loop {
+        let (next_check, to_trigger) = check_all_timers(&run_info, boot);
+        for (timer_name, id) in to_trigger {
+            let run_info = run_info.clone();
+            let notification_socket_path = notification_socket_path.clone();
+            let eventfds = eventfds.clone();
+            // starting a oneshot service blocks until it is done, don't hold up the other timers
+            std::thread::spawn(move || {
+                if let Err(e) = crate::units::run_unit_job(
+                    id,
+                    crate::units::JobKind::Start,
+                    run_info,
+                    notification_socket_path,
+                    eventfds,
+                ) {
+                    error!(
+                        "Error while activating unit for timer {}: {}",
+                        timer_name, e
+                    );
+                }
+            });
+        }
+        std::thread::sleep(next_check);
+    });
+}
+
+/// Update the state of all active timers and collect the units of the timers that elapsed.
+/// Returns how long it can wait until the next check.
+fn check_all_timers(run_info: &ArcRuntimeInfo, boot: Instant) -> (Duration, Vec<(String, UnitId)>) {
+    let mut next_check = MAX_CHECK_INTERVAL;
+    let mut to_trigger = Vec::new();
+
+    let unit_table_locked = run_info.unit_table.read().unwrap();
+    for unit in unit_table_locked.values() {
+        let mut unit_locked = unit.lock().unwrap();
+        let name = unit_locked.conf.name();
+        let id = unit_locked.id;
+        if let UnitSpecialized::Timer(timer) = &mut unit_locked.specialized {
+            let unit_id = match timer.unit_id {
+                Some(unit_id) => unit_id,
+                None => continue,
+            };
+            let status_table_locked = run_info.status_table.read().unwrap();
+            let timer_active = match status_table_locked.get(&id) {
+                Some(status) => *status.lock().unwrap() == UnitStatus::Started,
+                None => false,
+            };
+            if !timer_active || timer.activated_at.is_none() {
+                continue;
+            }
+
+            let now = Instant::now();
+            let now_realtime = SystemTime::now();
+            // follow the triggered unit for OnUnitActiveSec= and OnUnitInactiveSec=
+            let unit_active = match status_table_locked.get(&unit_id) {
+                Some(status) => status.lock().unwrap().is_active(),
+                None => false,
+            };
+            if unit_active && !timer.unit_was_active {
+                timer.unit_last_active = Some(now);
+            }
+            if !unit_active && timer.unit_was_active {
+                timer.unit_last_inactive = Some(now);
+            }
+            timer.unit_was_active = unit_active;
+
+            if timer.calendar_base.is_none() {
+                let stamp = if timer.config.persistent {
+                    read_stamp(&run_info.config.state_dir, &name)
+                } else {
+                    None
+                };
+                timer.calendar_base = Some(stamp.unwrap_or(now_realtime));
+            }
+
+            match timer.next_elapse(boot, now, now_realtime) {
+                Some(until) if until == Duration::from_secs(0) => {
+                    trace!("Timer {} elapsed", name);
+                    timer.last_trigger = Some(now);
+                    timer.last_trigger_realtime = Some(now_realtime);
+                    timer.calendar_base = Some(now_realtime);
+                    timer.unit_last_active = Some(now);
+                    timer.unit_was_active = true;
+                    if timer.config.persistent {
+                        if let Err(e) = write_stamp(&run_info.config.state_dir, &name, now_realtime)
+                        {
+                            warn!("{}", e);
+                        }
+                    }
+                    to_trigger.push((name.clone(), unit_id));
+                    // check again right away, there might be more elapsed events
+                    next_check = Duration::from_millis(10);
+                }
+                Some(until) => next_check = next_check.min(until),
+                None => {}
+            }
+        }
+    }
+    (next_check, to_trigger)
+}
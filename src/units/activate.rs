@@ -1,6 +1,7 @@
 //! Activate units (recursively and parallel along the dependency tree)
 
 use super::units::*;
+use super::{start_result_handlers, UnitResult};
 use crate::platform::EventFd;
 use crate::services::ServiceErrorReason;
 use std::sync::{Arc, Mutex};
@@ -15,10 +16,12 @@ pub struct UnitOperationError {
 pub enum UnitOperationErrorReason {
     GenericStartError(String),
     GenericStopError(String),
+    GenericReloadError(String),
     SocketOpenError(String),
     SocketCloseError(String),
     ServiceStartError(ServiceErrorReason),
     ServiceStopError(ServiceErrorReason),
+    ServiceReloadError(ServiceErrorReason),
 }
 
 impl std::fmt::Display for UnitOperationError {
@@ -38,6 +41,13 @@ impl std::fmt::Display for UnitOperationError {
                     self.unit_name, self.unit_id, msg
                 )?;
             }
+            UnitOperationErrorReason::GenericReloadError(msg) => {
+                write!(
+                    f,
+                    "Unit {} (ID {}) failed to reload because: {}",
+                    self.unit_name, self.unit_id, msg
+                )?;
+            }
             UnitOperationErrorReason::ServiceStartError(msg) => {
                 write!(
                     f,
@@ -52,6 +62,13 @@ impl std::fmt::Display for UnitOperationError {
                     self.unit_name, self.unit_id, msg
                 )?;
             }
+            UnitOperationErrorReason::ServiceReloadError(msg) => {
+                write!(
+                    f,
+                    "Service {} (ID {}) failed to reload because: {}",
+                    self.unit_name, self.unit_id, msg
+                )?;
+            }
             UnitOperationErrorReason::SocketOpenError(msg) => {
                 write!(
                     f,
@@ -74,43 +91,50 @@ impl std::fmt::Display for UnitOperationError {
 fn activate_units_recursive(
     ids_to_start: Vec<UnitId>,
     run_info: ArcRuntimeInfo,
-    tpool: ThreadPool,
+    jobs: Arc<ActivationJobs>,
     notification_socket_path: std::path::PathBuf,
     eventfds: Arc<Vec<EventFd>>,
     errors: Arc<Mutex<Vec<UnitOperationError>>>,
+    ids_to_skip: Arc<Vec<UnitId>>,
 ) {
     for id in ids_to_start {
+        if ids_to_skip.contains(&id) {
+            continue;
+        }
         let run_info_copy = run_info.clone();
-        let tpool_copy = tpool.clone();
+        let jobs_copy = jobs.clone();
         let note_sock_copy = notification_socket_path.clone();
         let eventfds_copy = eventfds.clone();
         let errors_copy = errors.clone();
-        tpool.execute(move || {
+        let ids_to_skip_copy = ids_to_skip.clone();
+        let job = move || {
             let run_info_copy2 = run_info_copy.clone();
-            let tpool_copy2 = tpool_copy.clone();
+            let jobs_copy2 = jobs_copy.clone();
             let note_sock_copy2 = note_sock_copy.clone();
             let eventfds_copy2 = eventfds_copy.clone();
             let errors_copy2 = errors_copy.clone();
 
-            match activate_unit(
+            match activate_unit_skipping(
                 id,
                 run_info_copy.clone(),
                 note_sock_copy,
                 eventfds_copy,
                 true,
+                &ids_to_skip_copy,
             ) {
                 Ok(StartResult::Started(next_services_ids)) => {
                     let next_services_job = move || {
                         activate_units_recursive(
                             next_services_ids,
                             run_info_copy2,
-                            tpool_copy2,
+                            jobs_copy2,
                             note_sock_copy2,
                             eventfds_copy2,
                             errors_copy2,
+                            ids_to_skip_copy,
                         );
                     };
-                    tpool_copy.execute(next_services_job);
+                    jobs_copy.execute(next_services_job);
                 }
                 Ok(StartResult::WaitForDependencies) => {
                     // Thats ok. The unit is waiting for more dependencies and will be
@@ -121,8 +145,206 @@ fn activate_units_recursive(
                     errors_copy.lock().unwrap().push(e);
                 }
             }
+        };
+        if is_idle_service(id, &run_info) {
+            jobs.execute_when_idle(job);
+        } else {
+            jobs.execute(job);
+        }
+    }
+}
+
+/// Type=idle services wait at most this long for the other jobs, same as systemd
+const IDLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+fn is_idle_service(id: UnitId, run_info: &ArcRuntimeInfo) -> bool {
+    let unit_table_locked = run_info.unit_table.read().unwrap();
+    match unit_table_locked.get(&id) {
+        Some(unit) => match &unit.lock().unwrap().specialized {
+            UnitSpecialized::Service(srvc) => srvc.service_config.srcv_type == ServiceType::Idle,
+            _ => false,
+        },
+        None => false,
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// Runs the jobs of one activate_units call and counts them, so the jobs of Type=idle services can be held back until
+/// all other jobs are done
+pub struct ActivationJobs {
+    tpool: ThreadPool,
+    state: Mutex<JobCounts>,
+}
+
+#[derive(Default)]
+struct JobCounts {
+    /// Queued or running, not counting the held back idle jobs
+    jobs: usize,
+    idle: Vec<Job>,
+    /// Counts how often the idle jobs were released, so an old timeout does not release newer idle jobs early
+    idle_releases: u64,
+}
+
+/// Counts a job until it is dropped, which also happens if the job panics
+struct JobGuard(Arc<ActivationJobs>);
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        let mut state = self.0.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.jobs -= 1;
+        if state.jobs == 0 {
+            self.0.release_idle_jobs(&mut state);
+        }
+    }
+}
+
+impl ActivationJobs {
+    pub fn new(tpool: ThreadPool) -> Self {
+        ActivationJobs {
+            tpool,
+            state: Mutex::new(JobCounts::default()),
+        }
+    }
+
+    /// Run the job in the pool. It counts as a job from now on until it returned
+    pub fn execute<F: FnOnce() + Send + 'static>(self: &Arc<Self>, job: F) {
+        let mut state = self.state.lock().unwrap();
+        self.spawn(&mut state, Box::new(job));
+    }
+
+    /// Hold the job back until all other jobs are done, but at most for IDLE_TIMEOUT. No thread of the pool is blocked
+    /// while it waits.
+    pub fn execute_when_idle<F: FnOnce() + Send + 'static>(self: &Arc<Self>, job: F) {
+        let mut state = self.state.lock().unwrap();
+        if state.jobs == 0 {
+            self.spawn(&mut state, Box::new(job));
+            return;
+        }
+        state.idle.push(Box::new(job));
+        if state.idle.len() == 1 {
+            let releases = state.idle_releases;
+            let jobs = self.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(IDLE_TIMEOUT);
+                let mut state = jobs.state.lock().unwrap_or_else(|e| e.into_inner());
+                if state.idle_releases == releases {
+                    jobs.release_idle_jobs(&mut state);
+                }
+            });
+        }
+    }
+
+    /// Block until all jobs are done
+    pub fn join(&self) {
+        self.tpool.join();
+    }
+
+    /// Jobs are only put into the pool while the state is locked. As long as a job is counted the pool is busy, so
+    /// join can not return between a job finishing and the idle jobs being released.
+    fn spawn(self: &Arc<Self>, state: &mut JobCounts, job: Job) {
+        state.jobs += 1;
+        let guard = JobGuard(self.clone());
+        self.tpool.execute(move || {
+            let _guard = guard;
+            job();
         });
     }
+
+    fn release_idle_jobs(self: &Arc<Self>, state: &mut JobCounts) {
+        state.idle_releases += 1;
+        for job in std::mem::take(&mut state.idle) {
+            self.spawn(state, job);
+        }
+    }
+}
+
+/// Units in these states count as active for After= and Requisite=
+fn is_active(status: &UnitStatus) -> bool {
+    *status == UnitStatus::Started
+        || *status == UnitStatus::StartedWaitingForSocket
+        || *status == UnitStatus::Reloading
+        || *status == UnitStatus::Exited
+}
+
+/// The units this unit is ordered after that are not yet active. Skipped units count as done, so do the units that are
+/// not started in this activation at all
+fn unstarted_dependencies(
+    unit: &Unit,
+    status_table: &StatusTable,
+    ids_to_skip: &[UnitId],
+) -> Vec<UnitId> {
+    unit.install
+        .after
+        .iter()
+        .filter(|id| !ids_to_skip.contains(id))
+        .filter(|id| {
+            let status = status_table.get(id).unwrap().lock().unwrap();
+            !is_active(&status) && !matches!(*status, UnitStatus::Skipped(_))
+        })
+        .copied()
+        .collect()
+}
+
+/// Starting the unit fails immediately if one of the units in Requisite= is not active
+fn check_requisites(unit: &Unit, status_table: &StatusTable) -> Result<(), String> {
+    let mut names = unit.conf.requisite.clone();
+    names.sort();
+    names.dedup();
+    if names.len() > unit.install.requisite.len() {
+        return Err(format!(
+            "Not all units of Requisite={} are loaded",
+            unit.conf.requisite.join(" ")
+        ));
+    }
+    for id in &unit.install.requisite {
+        let active = status_table
+            .get(id)
+            .map(|status| is_active(&status.lock().unwrap()))
+            .unwrap_or(false);
+        if !active {
+            return Err(format!("Requisite unit {} is not active", id));
+        }
+    }
+    Ok(())
+}
+
+/// The units that conflict with this unit and have to be stopped before it can start
+fn conflicts_to_stop(
+    unit: &Arc<Mutex<Unit>>,
+    run_info: &ArcRuntimeInfo,
+    ids_to_skip: &[UnitId],
+) -> Vec<UnitId> {
+    let unit_locked = unit.lock().unwrap();
+    if unit_locked.install.conflicts.is_empty() {
+        return Vec::new();
+    }
+    let status_table_locked = run_info.status_table.read().unwrap();
+    let will_start = match status_table_locked.get(&unit_locked.id) {
+        Some(status) => matches!(
+            *status.lock().unwrap(),
+            UnitStatus::NeverStarted
+                | UnitStatus::Stopped
+                | UnitStatus::Exited
+                | UnitStatus::Skipped(_)
+        ),
+        None => false,
+    };
+    if !will_start
+        || !unstarted_dependencies(&unit_locked, &status_table_locked, ids_to_skip).is_empty()
+    {
+        return Vec::new();
+    }
+    unit_locked
+        .install
+        .conflicts
+        .iter()
+        .filter(|id| match status_table_locked.get(id) {
+            Some(status) => is_active(&status.lock().unwrap()),
+            None => false,
+        })
+        .copied()
+        .collect()
 }
 
 pub enum StartResult {
@@ -136,6 +358,25 @@ pub fn activate_unit(
     notification_socket_path: std::path::PathBuf,
     eventfds: Arc<Vec<EventFd>>,
     allow_ignore: bool,
+) -> std::result::Result<StartResult, UnitOperationError> {
+    activate_unit_skipping(
+        id_to_start,
+        run_info,
+        notification_socket_path,
+        eventfds,
+        allow_ignore,
+        &[],
+    )
+}
+
+/// Like activate_unit but does not wait for the units in ids_to_skip, they are not started in this activation
+fn activate_unit_skipping(
+    id_to_start: UnitId,
+    run_info: ArcRuntimeInfo,
+    notification_socket_path: std::path::PathBuf,
+    eventfds: Arc<Vec<EventFd>>,
+    allow_ignore: bool,
+    ids_to_skip: &[UnitId],
 ) -> std::result::Result<StartResult, UnitOperationError> {
     trace!("Activate id: {:?}", id_to_start);
 
@@ -160,6 +401,16 @@ pub fn activate_unit(
             }
         }
     };
+    // Stopping the conflicting units locks them, so this unit must not be locked while doing that
+    for conflict_id in conflicts_to_stop(&unit, &run_info, ids_to_skip) {
+        trace!(
+            "Stop unit {} because it conflicts with {}",
+            conflict_id,
+            id_to_start
+        );
+        crate::units::deactivate_unit_recursive(conflict_id, true, run_info.clone())?;
+    }
+
     trace!("Lock unit: {}", id_to_start);
     let mut unit_locked = unit.lock().unwrap();
     trace!("Locked unit: {}", id_to_start);
@@ -169,20 +420,7 @@ pub fn activate_unit(
 
     // if not all dependencies are yet started ignore this call. This unit will be activated again when
     // the next dependency gets ready
-    let unstarted_deps = unit_locked
-        .install
-        .after
-        .iter()
-        .fold(Vec::new(), |mut acc, elem| {
-            let status = status_table_locked.get(elem).unwrap();
-            let status_locked = status.lock().unwrap();
-            let ready = *status_locked == UnitStatus::Started
-                || *status_locked == UnitStatus::StartedWaitingForSocket;
-            if !ready {
-                acc.push(elem);
-            }
-            acc
-        });
+    let unstarted_deps = unstarted_dependencies(&unit_locked, &status_table_locked, ids_to_skip);
     if !unstarted_deps.is_empty() {
         trace!(
             "Unit: {} ignores activation. Not all dependencies have been started (still waiting for: {:?})",
@@ -191,9 +429,10 @@ pub fn activate_unit(
         );
         return Ok(StartResult::WaitForDependencies);
     }
+    let requisites = check_requisites(&unit_locked, &status_table_locked);
 
     // Check if the unit is currently starting. Update the status to starting if not
-    {
+    let failed_assertion = {
         let status = status_table_locked.get(&id_to_start).unwrap();
         trace!("Lock status for: {}", name);
         let mut status_locked = status.lock().unwrap();
@@ -202,8 +441,10 @@ pub fn activate_unit(
         // if status is already on Started then allow ignore must be false. This happens when socket activation is happening
         // TODO make this relation less weird. Maybe add a separate code path for socket activation
         let wait_for_socket_act = *status_locked == UnitStatus::Started && allow_ignore;
-        let needs_intial_run =
-            *status_locked == UnitStatus::NeverStarted || *status_locked == UnitStatus::Stopped;
+        let needs_intial_run = *status_locked == UnitStatus::NeverStarted
+            || *status_locked == UnitStatus::Stopped
+            || *status_locked == UnitStatus::Exited
+            || matches!(*status_locked, UnitStatus::Skipped(_));
         if wait_for_socket_act && !needs_intial_run {
             trace!(
                 "Don't activate Unit: {:?}. Has status: {:?}",
@@ -213,19 +454,84 @@ pub fn activate_unit(
             return Ok(StartResult::WaitForDependencies);
         }
         if needs_intial_run {
-            *status_locked = UnitStatus::Starting;
+            if let Err(reason) = requisites {
+                *status_locked = UnitStatus::StoppedFinal(reason.clone());
+                return Err(UnitOperationError {
+                    reason: UnitOperationErrorReason::GenericStartError(reason),
+                    unit_name: name,
+                    unit_id: id_to_start,
+                });
+            }
+            if let Err(reason) = super::check_conditions(&unit_locked.conf.conditions, "Condition")
+            {
+                trace!("Skip unit {}: {}", name, reason);
+                crate::journal::log(
+                    &run_info.journal,
+                    &name,
+                    crate::journal::LogStream::Rustysd,
+                    None,
+                    &format!("Skipped because {}", reason),
+                );
+                *status_locked = UnitStatus::Skipped(reason);
+                // the units ordered after this one can start now
+                return Ok(StartResult::Started(unit_locked.install.before.clone()));
+            }
+            if let Err(reason) = super::check_conditions(&unit_locked.conf.asserts, "Assert") {
+                let reason = format!("Assertion failed: {}", reason);
+                crate::journal::log(
+                    &run_info.journal,
+                    &name,
+                    crate::journal::LogStream::Rustysd,
+                    None,
+                    &reason,
+                );
+                *status_locked = UnitStatus::StoppedFinal(reason.clone());
+                Some(reason)
+            } else {
+                *status_locked = UnitStatus::Starting;
+                None
+            }
+        } else {
+            None
         }
+    };
+    if let Some(reason) = failed_assertion {
+        // a failed assertion is a failed start. The handlers lock this unit
+        drop(status_table_locked);
+        drop(unit_locked);
+        start_result_handlers(
+            id_to_start,
+            UnitResult::Failure,
+            run_info,
+            notification_socket_path,
+            eventfds,
+        );
+        return Err(UnitOperationError {
+            reason: UnitOperationErrorReason::GenericStartError(reason),
+            unit_name: name,
+            unit_id: id_to_start,
+        });
     }
     let next_services_ids = unit_locked.install.before.clone();
+    let is_oneshot = match &unit_locked.specialized {
+        UnitSpecialized::Service(srvc) => srvc.service_config.srcv_type == ServiceType::OneShot,
+        _ => false,
+    };
 
-    unit_locked
-        .activate(
-            run_info.pid_table.clone(),
-            run_info.fd_store.clone(),
-            notification_socket_path.clone(),
-            &eventfds,
-            allow_ignore,
-        )
+    let activation = unit_locked.activate(
+        &run_info,
+        notification_socket_path.clone(),
+        &eventfds,
+        allow_ignore,
+    );
+    // oneshot services are done when they are activated, even with RemainAfterExit=yes. The start only succeeds
+    // if all commands exited successfully, so the exit handler does not need to handle them
+    let unit_result = match &activation {
+        Ok(UnitStatus::Started) | Ok(UnitStatus::Exited) if is_oneshot => Some(UnitResult::Success),
+        Ok(_) => None,
+        Err(_) => Some(UnitResult::Failure),
+    };
+    let result = activation
         .map(|new_status| {
             // Update the status while we still lock the unit
             let status_table_locked = run_info.status_table.read().unwrap();
@@ -240,9 +546,47 @@ pub fn activate_unit(
             let status = status_table_locked.get(&unit_locked.id).unwrap();
             let mut status_locked = status.lock().unwrap();
             *status_locked = UnitStatus::StoppedFinal(format!("{}", e));
+            crate::journal::log(
+                &run_info.journal,
+                &unit_locked.conf.name(),
+                crate::journal::LogStream::Rustysd,
+                None,
+                &format!("Failed to start: {}", e),
+            );
             e
+        });
+    // the handlers lock this unit
+    drop(status_table_locked);
+    drop(unit_locked);
+    if let Some(unit_result) = unit_result {
+        start_result_handlers(
+            id_to_start,
+            unit_result,
+            run_info,
+            notification_socket_path,
+            eventfds,
+        );
+    }
+    result
+}
+
+/// Units that are only there because a timer or path unit triggers them
+fn units_only_triggered(unit_table: &UnitTable) -> Vec<UnitId> {
+    let triggered: Vec<_> = unit_table
+        .values()
+        .filter_map(|unit| unit.lock().unwrap().triggered_unit())
+        .collect();
+    triggered
+        .into_iter()
+        .filter(|id| match unit_table.get(id) {
+            Some(unit) => {
+                let unit_locked = unit.lock().unwrap();
+                unit_locked.install.wanted_by.is_empty()
+                    && unit_locked.install.required_by.is_empty()
+            }
+            None => false,
         })
-    // drop all the locks "at once". Ordering of dropping should be irrelevant?
+        .collect()
 }
 
 pub fn activate_units(
@@ -251,29 +595,38 @@ pub fn activate_units(
     eventfds: Vec<EventFd>,
 ) {
     let mut root_units = Vec::new();
+    // these are started when the timer elapses or the path changes, not while booting
+    let ids_to_skip = units_only_triggered(&run_info.unit_table.read().unwrap());
 
     for (id, unit) in &*run_info.unit_table.read().unwrap() {
         let unit_locked = unit.lock().unwrap();
-        if unit_locked.install.after.is_empty() {
+        // units that are only ordered after skipped units do not wait for them
+        let waits_for_nothing = unit_locked
+            .install
+            .after
+            .iter()
+            .all(|after| ids_to_skip.contains(after));
+        if waits_for_nothing && !ids_to_skip.contains(id) {
             root_units.push(*id);
             trace!("Root unit: {}", unit_locked.conf.name());
         }
     }
 
     // TODO make configurable or at least make guess about amount fo threads
-    let tpool = ThreadPool::new(6);
+    let jobs = Arc::new(ActivationJobs::new(ThreadPool::new(6)));
     let eventfds_arc = Arc::new(eventfds);
     let errors = Arc::new(Mutex::new(Vec::new()));
     activate_units_recursive(
         root_units,
         run_info,
-        tpool.clone(),
+        jobs.clone(),
         notification_socket_path,
         eventfds_arc,
         errors.clone(),
+        Arc::new(ids_to_skip),
     );
 
-    tpool.join();
+    jobs.join();
     // TODO can we handle errors in a more meaningful way?
     for err in &*errors.lock().unwrap() {
         error!("{}", err);
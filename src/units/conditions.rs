@@ -0,0 +1,206 @@
+//! Evaluate the Condition*= and Assert*= settings before a unit is activated
+
+use crate::units::*;
+use std::path::Path;
+
+impl ConditionKind {
+    pub fn all() -> [ConditionKind; 9] {
+        [
+            ConditionKind::PathExists,
+            ConditionKind::PathIsDirectory,
+            ConditionKind::FileNotEmpty,
+            ConditionKind::DirectoryNotEmpty,
+            ConditionKind::Environment,
+            ConditionKind::Host,
+            ConditionKind::KernelCommandLine,
+            ConditionKind::Virtualization,
+            ConditionKind::User,
+        ]
+    }
+
+    /// The setting name without the Condition/Assert prefix
+    pub fn name(self) -> &'static str {
+        match self {
+            ConditionKind::PathExists => "PathExists",
+            ConditionKind::PathIsDirectory => "PathIsDirectory",
+            ConditionKind::FileNotEmpty => "FileNotEmpty",
+            ConditionKind::DirectoryNotEmpty => "DirectoryNotEmpty",
+            ConditionKind::Environment => "Environment",
+            ConditionKind::Host => "Host",
+            ConditionKind::KernelCommandLine => "KernelCommandLine",
+            ConditionKind::Virtualization => "Virtualization",
+            ConditionKind::User => "User",
+        }
+    }
+}
+
+impl Condition {
+    /// Check this condition on the running system, with the '!' prefix applied
+    pub fn is_met(&self) -> bool {
+        self.check() != self.negate
+    }
+
+    fn check(&self) -> bool {
+        let path = Path::new(&self.value);
+        match self.kind {
+            ConditionKind::PathExists => path.exists(),
+            ConditionKind::PathIsDirectory => path.is_dir(),
+            ConditionKind::FileNotEmpty => std::fs::metadata(path)
+                .map(|meta| meta.is_file() && meta.len() > 0)
+                .unwrap_or(false),
+            ConditionKind::DirectoryNotEmpty => std::fs::read_dir(path)
+                .map(|mut entries| entries.next().is_some())
+                .unwrap_or(false),
+            ConditionKind::Environment => check_environment(&self.value),
+            ConditionKind::Host => check_host(&self.value),
+            ConditionKind::KernelCommandLine => check_kernel_cmdline(&self.value),
+            ConditionKind::Virtualization => check_virtualization(&self.value),
+            ConditionKind::User => check_user(&self.value),
+        }
+    }
+
+    fn describe(&self, prefix: &str) -> String {
+        format!(
+            "{}{}={}{}{}",
+            prefix,
+            self.kind.name(),
+            if self.trigger { "|" } else { "" },
+            if self.negate { "!" } else { "" },
+            self.value
+        )
+    }
+}
+
+/// All conditions without '|' must be met and at least one of those with '|' (if there are any).
+/// The prefix (Condition or Assert) is only used to describe the condition that was not met.
+pub fn check_conditions(conditions: &[Condition], prefix: &str) -> Result<(), String> {
+    for condition in conditions.iter().filter(|condition| !condition.trigger) {
+        if !condition.is_met() {
+            return Err(format!("{} was not met", condition.describe(prefix)));
+        }
+    }
+    let triggers: Vec<_> = conditions
+        .iter()
+        .filter(|condition| condition.trigger)
+        .collect();
+    if !triggers.is_empty() && !triggers.iter().any(|condition| condition.is_met()) {
+        return Err(format!(
+            "None of {} was met",
+            triggers
+                .iter()
+                .map(|condition| condition.describe(prefix))
+                .collect::<Vec<_>>()
+                .join(" ")
+        ));
+    }
+    Ok(())
+}
+
+/// Either VAR (is set) or VAR=value, checked against the environment of rustysd
+fn check_environment(value: &str) -> bool {
+    match value.find('=') {
+        Some(pos) => std::env::var_os(&value[..pos])
+            .map(|var| var.to_string_lossy() == value[pos + 1..])
+            .unwrap_or(false),
+        None => std::env::var_os(value).is_some(),
+    }
+}
+
+/// The hostname or the machine id
+fn check_host(value: &str) -> bool {
+    let mut buf = [0u8; 256];
+    if let Ok(hostname) = nix::unistd::gethostname(&mut buf) {
+        if hostname.to_string_lossy().eq_ignore_ascii_case(value) {
+            return true;
+        }
+    }
+    std::fs::read_to_string("/etc/machine-id")
+        .map(|id| id.trim().eq_ignore_ascii_case(value))
+        .unwrap_or(false)
+}
+
+/// Either a whole word of the kernel command line or the name of a KEY=value word
+fn check_kernel_cmdline(value: &str) -> bool {
+    let cmdline = match std::fs::read_to_string("/proc/cmdline") {
+        Ok(cmdline) => cmdline,
+        Err(_) => return false,
+    };
+    cmdline.split_whitespace().any(|word| {
+        word == value || (!value.contains('=') && word.starts_with(&format!("{}=", value)))
+    })
+}
+
+/// A boolean, vm, container or the name of a specific technology like kvm or docker
+fn check_virtualization(value: &str) -> bool {
+    let detected = detect_virtualization();
+    match value {
+        "yes" | "true" | "on" | "1" => detected.is_some(),
+        "no" | "false" | "off" | "0" => detected.is_none(),
+        "vm" => matches!(detected, Some((false, _))),
+        "container" => matches!(detected, Some((true, _))),
+        tech => matches!(detected, Some((_, name)) if name == tech),
+    }
+}
+
+/// Returns if it is a container and the name of the technology. Only the common ones are detected
+fn detect_virtualization() -> Option<(bool, String)> {
+    if let Some(container) = std::env::var_os("container") {
+        return Some((true, container.to_string_lossy().into_owned()));
+    }
+    if Path::new("/.dockerenv").exists() {
+        return Some((true, "docker".to_owned()));
+    }
+    if Path::new("/run/.containerenv").exists() {
+        return Some((true, "podman".to_owned()));
+    }
+
+    let dmi_vendors = [
+        ("KVM", "kvm"),
+        ("QEMU", "qemu"),
+        ("VMware", "vmware"),
+        ("VMW", "vmware"),
+        ("innotek GmbH", "oracle"),
+        ("VirtualBox", "oracle"),
+        ("Xen", "xen"),
+        ("Microsoft Corporation", "microsoft"),
+        ("Amazon EC2", "amazon"),
+    ];
+    for file in &["product_name", "sys_vendor", "board_vendor", "bios_vendor"] {
+        if let Ok(content) = std::fs::read_to_string(Path::new("/sys/class/dmi/id").join(file)) {
+            for (vendor, name) in &dmi_vendors {
+                if content.starts_with(vendor) {
+                    return Some((false, (*name).to_owned()));
+                }
+            }
+        }
+    }
+    if let Ok(hypervisor) = std::fs::read_to_string("/sys/hypervisor/type") {
+        if hypervisor.trim() == "xen" {
+            return Some((false, "xen".to_owned()));
+        }
+    }
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let has_hypervisor_flag = cpuinfo
+        .lines()
+        .filter(|line| line.starts_with("flags"))
+        .any(|line| line.split_whitespace().any(|flag| flag == "hypervisor"));
+    if has_hypervisor_flag {
+        return Some((false, "vm-other".to_owned()));
+    }
+    None
+}
+
+/// A user name, a numeric uid or @system, checked against the user running rustysd
+fn check_user(value: &str) -> bool {
+    let uid = nix::unistd::getuid();
+    if value == "@system" {
+        // the default SYS_UID_MAX of login.defs
+        return uid.as_raw() < 1000;
+    }
+    if let Ok(raw) = value.parse::<u32>() {
+        return uid.as_raw() == raw;
+    }
+    crate::platform::pwnam::getpwnam_r(value)
+        .map(|entry| entry.uid == uid)
+        .unwrap_or(false)
+}
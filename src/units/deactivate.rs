@@ -13,7 +13,8 @@ pub fn deactivate_unit_recursive(
             unit_table_locked.get(&id_to_kill).unwrap().clone()
         };
         let unit_locked = &mut *unit.lock().unwrap();
-        unit_locked.install.required_by.clone()
+        // Requires=, BindsTo=, PartOf= and Requisite= of other units
+        unit_locked.install.stopped_with()
     };
 
     deactivate_units_recursive(kill_before_this, killfinal, run_info.clone())?;
@@ -36,18 +37,37 @@ pub fn deactivate_unit(
         let status = status_table_locked.get(&id_to_kill).unwrap();
         let status_locked = &mut *status.lock().unwrap();
         match *status_locked {
-            UnitStatus::Started | UnitStatus::StartedWaitingForSocket | UnitStatus::Starting => {
+            UnitStatus::Started
+            | UnitStatus::StartedWaitingForSocket
+            | UnitStatus::Starting
+            | UnitStatus::Reloading
+            | UnitStatus::StoppingGracefully => {
                 *status_locked = UnitStatus::Stopping;
             }
             UnitStatus::NeverStarted
             | UnitStatus::Stopped
+            | UnitStatus::Exited
             | UnitStatus::StoppedFinal(_)
+            | UnitStatus::Skipped(_)
             | UnitStatus::Stopping => {
                 return Ok(());
             }
         }
     }
     unit_locked.deactivate(run_info.pid_table.clone(), run_info.fd_store.clone())?;
+    if killfinal {
+        if let UnitSpecialized::Service(_) = &unit_locked.specialized {
+            // the fd store only keeps fds over restarts, not over a full stop
+            let stored_fds = run_info
+                .fd_store
+                .write()
+                .unwrap()
+                .remove_all_service_stored(&unit_locked.conf.name());
+            for fd in stored_fds {
+                let _ = nix::unistd::close(*fd);
+            }
+        }
+    }
     {
         let status_table_locked = run_info.status_table.read().unwrap();
         let status = status_table_locked.get(&id_to_kill).unwrap();
@@ -99,3 +119,132 @@ pub fn reactivate_unit(
     )
     .map(|_| ())
 }
+
+/// Restart the unit together with the running units that are bound to it or part of it. These are stopped before and
+/// started again after this unit.
+pub fn reactivate_unit_recursive(
+    id_to_restart: UnitId,
+    run_info: ArcRuntimeInfo,
+    notification_socket_path: std::path::PathBuf,
+    eventfds: Arc<Vec<EventFd>>,
+) -> std::result::Result<(), UnitOperationError> {
+    let mut dependents = Vec::new();
+    let mut to_check = vec![id_to_restart];
+    while let Some(id) = to_check.pop() {
+        let ids = {
+            let unit_table_locked = run_info.unit_table.read().unwrap();
+            let unit_locked = unit_table_locked.get(&id).unwrap().lock().unwrap();
+            let mut ids = unit_locked.install.bound_by.clone();
+            ids.extend(unit_locked.install.consists_of.iter().copied());
+            ids
+        };
+        let status_table_locked = run_info.status_table.read().unwrap();
+        for dependent in ids {
+            let running = match status_table_locked.get(&dependent) {
+                Some(status) => matches!(
+                    *status.lock().unwrap(),
+                    UnitStatus::Started
+                        | UnitStatus::StartedWaitingForSocket
+                        | UnitStatus::Reloading
+                ),
+                None => false,
+            };
+            if running && dependent != id_to_restart && !dependents.contains(&dependent) {
+                dependents.push(dependent);
+                to_check.push(dependent);
+            }
+        }
+    }
+
+    for id in dependents.iter().rev() {
+        deactivate_unit(*id, false, run_info.clone())?;
+    }
+    reactivate_unit(
+        id_to_restart,
+        run_info.clone(),
+        notification_socket_path.clone(),
+        eventfds.clone(),
+    )?;
+    for id in dependents {
+        crate::units::activate_unit(
+            id,
+            run_info.clone(),
+            notification_socket_path.clone(),
+            eventfds.clone(),
+            true,
+        )?;
+    }
+    Ok(())
+}
+
+pub fn reload_unit(
+    id_to_reload: UnitId,
+    run_info: ArcRuntimeInfo,
+) -> std::result::Result<(), UnitOperationError> {
+    let unit = {
+        let unit_table_locked = run_info.unit_table.read().unwrap();
+        unit_table_locked.get(&id_to_reload).unwrap().clone()
+    };
+    let unit_locked = &mut *unit.lock().unwrap();
+
+    {
+        let status_table_locked = run_info.status_table.read().unwrap();
+        let status = status_table_locked.get(&id_to_reload).unwrap();
+        let status_locked = &mut *status.lock().unwrap();
+        match *status_locked {
+            UnitStatus::Started => {
+                *status_locked = UnitStatus::Reloading;
+            }
+            _ => {
+                return Err(UnitOperationError {
+                    unit_name: unit_locked.conf.name(),
+                    unit_id: id_to_reload,
+                    reason: UnitOperationErrorReason::GenericReloadError(format!(
+                        "Unit is not running but: {:?}",
+                        status_locked
+                    )),
+                });
+            }
+        }
+    }
+    let reload_result = unit_locked.reload(run_info.pid_table.clone());
+    {
+        let status_table_locked = run_info.status_table.read().unwrap();
+        let status = status_table_locked.get(&id_to_reload).unwrap();
+        let status_locked = &mut *status.lock().unwrap();
+        // the service might have exited while reloading, that is handled by the exit handler
+        if *status_locked == UnitStatus::Reloading {
+            *status_locked = UnitStatus::Started;
+        }
+    }
+    reload_result
+}
+
+/// A started service with ExecReload=
+pub fn can_reload(id: UnitId, run_info: &ArcRuntimeInfo) -> bool {
+    let unit = {
+        let unit_table_locked = run_info.unit_table.read().unwrap();
+        unit_table_locked.get(&id).unwrap().clone()
+    };
+    let unit_locked = unit.lock().unwrap();
+    let status_table_locked = run_info.status_table.read().unwrap();
+    let is_started = *status_table_locked.get(&id).unwrap().lock().unwrap() == UnitStatus::Started;
+    match &unit_locked.specialized {
+        UnitSpecialized::Service(srvc) => is_started && !srvc.service_config.reload.is_empty(),
+        _ => false,
+    }
+}
+
+/// Reload the unit if it is running and supports reloading, restart it otherwise
+pub fn reload_or_reactivate_unit(
+    id: UnitId,
+    run_info: ArcRuntimeInfo,
+    notification_socket_path: std::path::PathBuf,
+    eventfds: Arc<Vec<EventFd>>,
+) -> std::result::Result<(), UnitOperationError> {
+    if can_reload(id, &run_info) {
+        reload_unit(id, run_info)
+    } else {
+        reactivate_unit_recursive(id, run_info, notification_socket_path, eventfds)
+    }
+}
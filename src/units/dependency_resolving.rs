@@ -85,6 +85,19 @@ pub fn prune_units(
             .map(|id| *id)
             .collect();
 
+        // a requisite that was pruned is never active, starting this unit fails because of the missing id
+        for ids in &mut [
+            &mut unit.install.conflicts,
+            &mut unit.install.binds_to,
+            &mut unit.install.bound_by,
+            &mut unit.install.part_of,
+            &mut unit.install.consists_of,
+            &mut unit.install.requisite,
+            &mut unit.install.requisite_of,
+        ] {
+            ids.retain(|id| ids_to_keep.contains(id));
+        }
+
         unit.dedup_dependencies();
     }
     Ok(())
@@ -115,6 +128,15 @@ fn find_needed_units_recursive(
     for new_id in &unit.install.wanted_by {
         new_needed_ids.push(*new_id);
     }
+    for new_id in &unit.install.binds_to {
+        new_needed_ids.push(*new_id);
+    }
+    for new_id in &unit.install.bound_by {
+        new_needed_ids.push(*new_id);
+    }
+    if let Some(new_id) = unit.triggered_unit() {
+        new_needed_ids.push(new_id);
+    }
     new_needed_ids.sort();
     new_needed_ids.dedup();
 
@@ -161,6 +183,55 @@ pub fn add_implicit_before_after(units: &mut HashMap<UnitId, Unit>) {
     }
 }
 
+/// The relations that do not influence the order in which the units are started
+#[derive(Clone, Copy, Debug)]
+pub enum RelationKind {
+    Conflicts,
+    BindsTo,
+    PartOf,
+    Requisite,
+}
+
+impl RelationKind {
+    pub fn ids_mut(self, install: &mut Install) -> &mut Vec<UnitId> {
+        match self {
+            RelationKind::Conflicts => &mut install.conflicts,
+            RelationKind::BindsTo => &mut install.binds_to,
+            RelationKind::PartOf => &mut install.part_of,
+            RelationKind::Requisite => &mut install.requisite,
+        }
+    }
+
+    /// The list of the other unit that holds the opposite side of the relation
+    pub fn reverse_ids_mut(self, install: &mut Install) -> &mut Vec<UnitId> {
+        match self {
+            // conflicts are symmetric
+            RelationKind::Conflicts => &mut install.conflicts,
+            RelationKind::BindsTo => &mut install.bound_by,
+            RelationKind::PartOf => &mut install.consists_of,
+            RelationKind::Requisite => &mut install.requisite_of,
+        }
+    }
+
+    pub fn names(self, conf: &UnitConfig) -> &Vec<String> {
+        match self {
+            RelationKind::Conflicts => &conf.conflicts,
+            RelationKind::BindsTo => &conf.binds_to,
+            RelationKind::PartOf => &conf.part_of,
+            RelationKind::Requisite => &conf.requisite,
+        }
+    }
+
+    pub fn all() -> [RelationKind; 4] {
+        [
+            RelationKind::Conflicts,
+            RelationKind::BindsTo,
+            RelationKind::PartOf,
+            RelationKind::Requisite,
+        ]
+    }
+}
+
 // make edges between units visible on bot sides: required <-> required_by  after <-> before
 pub fn fill_dependencies(units: &mut HashMap<UnitId, Unit>) {
     let mut name_to_id = HashMap::new();
@@ -174,6 +245,8 @@ pub fn fill_dependencies(units: &mut HashMap<UnitId, Unit>) {
     let mut wanted_by: Vec<(UnitId, UnitId)> = Vec::new();
     let mut before = Vec::new();
     let mut after = Vec::new();
+    // (unit, other unit, kind) for relations that also need to be known by the other unit
+    let mut reverse = Vec::new();
 
     for unit in (*units).values_mut() {
         let conf = &unit.conf;
@@ -197,6 +270,21 @@ pub fn fill_dependencies(units: &mut HashMap<UnitId, Unit>) {
             unit.install.after.push(id);
             before.push((unit.id, id))
         }
+        for kind in &RelationKind::all() {
+            for name in kind.names(conf) {
+                match name_to_id.get(name.as_str()) {
+                    Some(id) => {
+                        kind.ids_mut(&mut unit.install).push(*id);
+                        reverse.push((*id, unit.id, *kind));
+                    }
+                    None => warn!(
+                        "Unit {} references unit {} which is not loaded",
+                        conf.name(),
+                        name
+                    ),
+                }
+            }
+        }
 
         if let Some(conf) = &unit.install.install_config {
             for name in &conf.wanted_by {
@@ -233,6 +321,11 @@ pub fn fill_dependencies(units: &mut HashMap<UnitId, Unit>) {
         unit.install.after.push(after);
     }
 
+    for (id, other_id, kind) in reverse {
+        let unit = units.get_mut(&id).unwrap();
+        kind.reverse_ids_mut(&mut unit.install).push(other_id);
+    }
+
     for srvc in units.values_mut() {
         srvc.dedup_dependencies();
     }
@@ -258,6 +351,10 @@ pub fn apply_sockets_to_services(
         let mut counter = 0;
 
         if let UnitSpecialized::Socket(sock) = &sock_unit.specialized {
+            if sock.accept.is_some() {
+                // the connections are handled by instances of a template service that are created on demand
+                continue;
+            }
             trace!("Searching services for socket: {}", sock_unit.conf.name());
             for srvc_unit in service_table.values_mut() {
                 let srvc = &mut srvc_unit.specialized;
@@ -351,3 +448,35 @@ pub fn apply_sockets_to_services(
 
     Ok(())
 }
+
+/// Find the units that the timers and path units activate
+pub fn apply_triggers_to_units(units: &mut HashMap<UnitId, Unit>) -> Result<(), String> {
+    let mut name_to_id = HashMap::new();
+    for (id, unit) in &*units {
+        name_to_id.insert(unit.conf.name(), *id);
+    }
+
+    for trigger_unit in units.values_mut() {
+        let trigger_name = trigger_unit.conf.name();
+        let (unit_name, unit_id) = match &mut trigger_unit.specialized {
+            UnitSpecialized::Timer(timer) => (&timer.config.unit, &mut timer.unit_id),
+            UnitSpecialized::Path(path) => (&path.config.unit, &mut path.unit_id),
+            UnitSpecialized::Socket(_) | UnitSpecialized::Service(_) | UnitSpecialized::Target => {
+                continue
+            }
+        };
+        match name_to_id.get(unit_name) {
+            Some(id) => {
+                trace!("{} activates unit {}", trigger_name, unit_name);
+                *unit_id = Some(*id);
+            }
+            None => {
+                return Err(format!(
+                    "{} activates unit {} which does not exist",
+                    trigger_name, unit_name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
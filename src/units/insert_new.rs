@@ -1,80 +1,50 @@
 use crate::units;
-use std::fs;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
-fn find_new_unit_path(unit_dirs: &[PathBuf], find_name: &str) -> Result<Option<PathBuf>, String> {
-    for dir in unit_dirs {
-        for entry in
-            fs::read_dir(dir).map_err(|e| format!("Error while opening dir {:?}: {}", dir, e))?
-        {
-            let entry = entry.unwrap();
-            let meta = entry.metadata().unwrap();
-            if meta.file_type().is_file() {
-                if entry.file_name() == find_name {
-                    return Ok(Some(entry.path()));
-                }
-            }
-            if meta.file_type().is_dir() {
-                if let Some(p) = find_new_unit_path(&[entry.path()], find_name)? {
-                    return Ok(Some(p));
-                }
-            }
-        }
-    }
-
-    Ok(None)
-}
-
-/// Loads a unit with a given name. It searches all pathes recursively until it finds a file with a matching name
+/// Loads a unit with a given name from the unit file that has the highest precedence in the unit dirs.
+/// Instances like foo@bar.service are created from the template foo@.service if there is no file for the instance.
+/// Aliases load the unit they point to, masked units can not be loaded.
 pub fn load_new_unit(
     unit_dirs: &[PathBuf],
     find_name: &str,
     next_id: u64,
 ) -> Result<units::Unit, String> {
-    if let Some(unit_path) = find_new_unit_path(unit_dirs, find_name)? {
-        let content = fs::read_to_string(&unit_path).map_err(|e| {
-            format!(
-                "{}",
-                units::ParsingError::new(
-                    units::ParsingErrorReason::from(Box::new(e)),
-                    unit_path.clone()
-                )
-            )
-        })?;
-        let parsed = units::parse_file(&content)
-            .map_err(|e| format!("{}", units::ParsingError::new(e, unit_path.clone())))?;
-        let unit = if find_name.ends_with(".service") {
-            units::parse_service(
-                parsed,
-                &unit_path,
-                units::UnitId(units::UnitIdKind::Service, next_id),
-            )
-            .map_err(|e| format!("{}", units::ParsingError::new(e, unit_path)))?
-        } else if find_name.ends_with(".socket") {
-            units::parse_socket(
-                parsed,
-                &unit_path,
-                units::UnitId(units::UnitIdKind::Socket, next_id),
-            )
-            .map_err(|e| format!("{}", units::ParsingError::new(e, unit_path)))?
-        } else if find_name.ends_with(".target") {
-            units::parse_target(
-                parsed,
-                &unit_path,
-                units::UnitId(units::UnitIdKind::Target, next_id),
-            )
-            .map_err(|e| format!("{}", units::ParsingError::new(e, unit_path)))?
-        } else {
-            return Err(format!(
-                "File suffix not recognized for file {:?}",
-                unit_path
-            ));
-        };
+    let unit_files = units::find_unit_files(unit_dirs).map_err(|e| format!("{}", e))?;
+    let mut name = find_name.to_owned();
+    let mut unit_file = unit_files.get(&name);
+    if let Some(units::UnitFile {
+        kind: units::UnitFileKind::Alias(target),
+        ..
+    }) = unit_file
+    {
+        trace!("{} is an alias for {}", find_name, target);
+        name = target.clone();
+        unit_file = unit_files.get(&name);
+    }
+    if unit_file.is_none() {
+        unit_file = units::template_name(&name).and_then(|template| unit_files.get(&template));
+    }
 
-        Ok(unit)
-    } else {
-        Err(format!("Cannot find unit file for unit: {}", find_name))
+    match unit_file {
+        Some(unit_file) if unit_file.kind == units::UnitFileKind::Masked => {
+            Err(format!("Unit {} is masked by {:?}", name, unit_file.path))
+        }
+        Some(unit_file) => {
+            let unit_path = unit_file.path.with_file_name(&name);
+            match super::loading::parse_unit(&unit_file.path, &unit_path, unit_dirs, next_id) {
+                Ok(Some(mut unit)) => {
+                    unit.conf.shadowed = unit_file.shadowed.clone();
+                    Ok(unit)
+                }
+                Ok(None) => Err(format!(
+                    "File suffix not recognized for file {:?}",
+                    unit_path
+                )),
+                Err(e) => Err(format!("{}", e)),
+            }
+        }
+        None => Err(format!("Cannot find unit file for unit: {}", find_name)),
     }
 }
 
@@ -94,6 +64,7 @@ pub fn insert_new_unit(
         let mut names_needed = Vec::new();
         names_needed.extend(new_unit.conf.after.iter().cloned());
         names_needed.extend(new_unit.conf.before.iter().cloned());
+        names_needed.extend(new_unit.conf.binds_to.iter().cloned());
 
         if let Some(conf) = &new_unit.install.install_config {
             names_needed.extend(conf.required_by.iter().cloned());
@@ -105,6 +76,12 @@ pub fn insert_new_unit(
         if let units::UnitSpecialized::Service(srvc) = &new_unit.specialized {
             names_needed.extend(srvc.service_config.sockets.iter().cloned());
         }
+        if let units::UnitSpecialized::Timer(timer) = &new_unit.specialized {
+            names_needed.push(timer.config.unit.clone());
+        }
+        if let units::UnitSpecialized::Path(path) = &new_unit.specialized {
+            names_needed.push(path.config.unit.clone());
+        }
         let mut names_needed: std::collections::HashMap<_, _> =
             names_needed.iter().map(|name| (name, ())).collect();
 
@@ -133,6 +110,15 @@ pub fn insert_new_unit(
             let mut unit_locked = unit.lock().unwrap();
             let name = unit_locked.conf.name();
             let id = unit_locked.id;
+            match &mut new_unit.specialized {
+                units::UnitSpecialized::Timer(timer) if timer.config.unit == name => {
+                    timer.unit_id = Some(id);
+                }
+                units::UnitSpecialized::Path(path) if path.config.unit == name => {
+                    path.unit_id = Some(id);
+                }
+                _ => {}
+            }
             if new_unit.conf.after.contains(&name) {
                 new_unit.install.after.push(id);
                 unit_locked.install.before.push(new_id);
@@ -150,6 +136,13 @@ pub fn insert_new_unit(
                 unit_locked.install.wanted_by.push(new_id);
             }
 
+            for kind in &units::RelationKind::all() {
+                if kind.names(&new_unit.conf).contains(&name) {
+                    kind.ids_mut(&mut new_unit.install).push(id);
+                    kind.reverse_ids_mut(&mut unit_locked.install).push(new_id);
+                }
+            }
+
             if let Some(conf) = &new_unit.install.install_config {
                 if conf.required_by.contains(&name) {
                     new_unit.install.required_by.push(id);
@@ -0,0 +1,676 @@
+//! Jobs for start, stop, restart, reload and verify-active requests.
+//!
+//! A request is turned into a transaction: the job for the requested unit plus the jobs it pulls in (Requires=, BindsTo=,
+//! Wants=, Requisite= and the units that have to be stopped). The transaction is checked against the jobs that are already
+//! installed, merged with them where possible and then executed. Transactions run one after another in the order they
+//! were installed so concurrent requests can not interleave. The jobs of a transaction run in dependency order.
+//!
+//! Activating all units while booting does not use jobs, it starts the units in parallel along the dependency tree.
+
+use crate::platform::EventFd;
+use crate::units::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Condvar, Mutex};
+
+pub type JobId = u64;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum JobKind {
+    Start,
+    Stop,
+    Restart,
+    Reload,
+    VerifyActive,
+}
+
+impl JobKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            JobKind::Start => "start",
+            JobKind::Stop => "stop",
+            JobKind::Restart => "restart",
+            JobKind::Reload => "reload",
+            JobKind::VerifyActive => "verify-active",
+        }
+    }
+
+    /// The job that does the work of both jobs, if there is one
+    fn merge(self, other: JobKind) -> Option<JobKind> {
+        match (self, other) {
+            (a, b) if a == b => Some(a),
+            (JobKind::Start, JobKind::VerifyActive) | (JobKind::VerifyActive, JobKind::Start) => {
+                Some(JobKind::Start)
+            }
+            (JobKind::Restart, JobKind::Start)
+            | (JobKind::Start, JobKind::Restart)
+            | (JobKind::Restart, JobKind::VerifyActive)
+            | (JobKind::VerifyActive, JobKind::Restart)
+            | (JobKind::Restart, JobKind::Reload)
+            | (JobKind::Reload, JobKind::Restart) => Some(JobKind::Restart),
+            _ => None,
+        }
+    }
+
+    /// A stop job conflicts with every other kind of job for the same unit
+    fn conflicts_with(self, other: JobKind) -> bool {
+        (self == JobKind::Stop) != (other == JobKind::Stop)
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum JobMode {
+    /// Replace conflicting jobs that are waiting
+    Replace,
+    /// Fail if there are conflicting jobs waiting
+    Fail,
+    /// Start the unit and stop all other units that are not needed by it
+    Isolate,
+    /// Only the job for the requested unit, nothing is pulled in
+    IgnoreDependencies,
+}
+
+impl JobMode {
+    pub fn from_name(name: &str) -> Result<JobMode, String> {
+        match name {
+            "replace" => Ok(JobMode::Replace),
+            "fail" => Ok(JobMode::Fail),
+            "isolate" => Ok(JobMode::Isolate),
+            "ignore-dependencies" => Ok(JobMode::IgnoreDependencies),
+            _ => Err(format!("Unknown job mode: {}", name)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum JobState {
+    Waiting,
+    Running,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum JobResult {
+    Done,
+    Failed(String),
+    Canceled,
+    /// A unit this unit depends on failed in the same transaction
+    Dependency,
+}
+
+impl std::fmt::Display for JobResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            JobResult::Done => write!(f, "done"),
+            JobResult::Failed(reason) => write!(f, "failed: {}", reason),
+            JobResult::Canceled => write!(f, "canceled"),
+            JobResult::Dependency => write!(f, "dependency failed"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub id: JobId,
+    pub unit_id: UnitId,
+    pub unit_name: String,
+    pub kind: JobKind,
+    pub state: JobState,
+}
+
+#[derive(Clone, Debug)]
+pub struct FinishedJob {
+    pub id: JobId,
+    pub unit_id: UnitId,
+    pub unit_name: String,
+    pub kind: JobKind,
+    pub result: JobResult,
+}
+
+#[derive(Default)]
+struct InstalledJobs {
+    jobs: Vec<Job>,
+    last_job_id: JobId,
+    last_transaction: u64,
+    finished_transactions: u64,
+    /// The jobs other jobs were merged into. Holds the result once the job is done and how many merged jobs still
+    /// have to collect it.
+    merged: HashMap<JobId, (Option<JobResult>, usize)>,
+}
+
+impl InstalledJobs {
+    fn remove_job(&mut self, id: JobId, result: JobResult) {
+        self.jobs.retain(|job| job.id != id);
+        if let Some((merged_result, _)) = self.merged.get_mut(&id) {
+            *merged_result = Some(result);
+        }
+    }
+}
+
+/// The jobs that are waiting or running
+#[derive(Default)]
+pub struct JobTable {
+    installed: Mutex<InstalledJobs>,
+    turn: Condvar,
+    job_done: Condvar,
+}
+
+pub type ArcJobTable = Arc<JobTable>;
+
+impl JobTable {
+    pub fn list(&self) -> Vec<Job> {
+        self.installed.lock().unwrap().jobs.clone()
+    }
+
+    /// Remove a waiting job. Running jobs can not be canceled
+    pub fn cancel(&self, id: JobId) -> Result<Job, String> {
+        let installed = &mut *self.installed.lock().unwrap();
+        match installed.jobs.iter().position(|job| job.id == id) {
+            Some(idx) if installed.jobs[idx].state == JobState::Waiting => {
+                let job = installed.jobs[idx].clone();
+                installed.remove_job(id, JobResult::Canceled);
+                self.job_done.notify_all();
+                Ok(job)
+            }
+            Some(_) => Err(format!("Job {} is already running", id)),
+            None => Err(format!("No job with id {}", id)),
+        }
+    }
+
+    /// Install the jobs of a transaction. Conflicting waiting jobs are replaced or make the transaction fail, depending
+    /// on the mode. Returns the number of the transaction and for each job the id of the new or the merged job.
+    fn install(
+        &self,
+        jobs: &[(UnitId, String, JobKind)],
+        mode: JobMode,
+    ) -> Result<(u64, Vec<Result<JobId, JobId>>), String> {
+        let installed = &mut *self.installed.lock().unwrap();
+        let waiting_job = |jobs: &[Job], unit_id: UnitId| {
+            jobs.iter()
+                .position(|job| job.unit_id == unit_id && job.state == JobState::Waiting)
+        };
+
+        if mode == JobMode::Fail {
+            for (unit_id, unit_name, kind) in jobs {
+                if let Some(idx) = waiting_job(&installed.jobs, *unit_id) {
+                    let other = &installed.jobs[idx];
+                    if other.kind.conflicts_with(*kind) {
+                        return Err(format!(
+                            "The {} job for {} conflicts with the waiting {} job {}",
+                            kind.name(),
+                            unit_name,
+                            other.kind.name(),
+                            other.id
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut ids = Vec::new();
+        for (unit_id, unit_name, kind) in jobs {
+            if let Some(idx) = waiting_job(&installed.jobs, *unit_id) {
+                let other = &mut installed.jobs[idx];
+                if other.kind.conflicts_with(*kind) {
+                    trace!("Job {} is replaced by a {} job", other.id, kind.name());
+                    let other_id = other.id;
+                    installed.remove_job(other_id, JobResult::Canceled);
+                    self.job_done.notify_all();
+                } else if let Some(merged) = other.kind.merge(*kind) {
+                    other.kind = merged;
+                    let other_id = other.id;
+                    installed.merged.entry(other_id).or_insert((None, 0)).1 += 1;
+                    ids.push(Err(other_id));
+                    continue;
+                }
+            }
+            installed.last_job_id += 1;
+            installed.jobs.push(Job {
+                id: installed.last_job_id,
+                unit_id: *unit_id,
+                unit_name: unit_name.clone(),
+                kind: *kind,
+                state: JobState::Waiting,
+            });
+            ids.push(Ok(installed.last_job_id));
+        }
+        installed.last_transaction += 1;
+        Ok((installed.last_transaction, ids))
+    }
+
+    /// Mark the job as running. Returns the kind of the job (which might have changed by merging) or None if it was canceled
+    fn start_job(&self, id: JobId) -> Option<JobKind> {
+        let installed = &mut *self.installed.lock().unwrap();
+        installed
+            .jobs
+            .iter_mut()
+            .find(|job| job.id == id)
+            .map(|job| {
+                job.state = JobState::Running;
+                job.kind
+            })
+    }
+
+    fn finish_job(&self, id: JobId, result: JobResult) {
+        let installed = &mut *self.installed.lock().unwrap();
+        installed.remove_job(id, result);
+        self.job_done.notify_all();
+    }
+
+    /// Block until the job another job was merged into is done and return it's result
+    fn wait_for_merged(&self, id: JobId) -> JobResult {
+        let mut installed = self.installed.lock().unwrap();
+        loop {
+            if let Some((Some(result), waiting)) = installed.merged.get_mut(&id) {
+                let result = result.clone();
+                *waiting -= 1;
+                if *waiting == 0 {
+                    installed.merged.remove(&id);
+                }
+                return result;
+            }
+            installed = self.job_done.wait(installed).unwrap();
+        }
+    }
+
+    fn wait_for_turn(&self, transaction: u64) -> TurnGuard<'_> {
+        let mut installed = self.installed.lock().unwrap();
+        while installed.finished_transactions + 1 != transaction {
+            installed = self.turn.wait(installed).unwrap();
+        }
+        TurnGuard { table: self }
+    }
+}
+
+/// Let the next transaction run when the current one is done, even if running it panicked
+struct TurnGuard<'a> {
+    table: &'a JobTable,
+}
+
+impl Drop for TurnGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut installed) = self.table.installed.lock() {
+            installed.finished_transactions += 1;
+        }
+        self.table.turn.notify_all();
+    }
+}
+
+fn get_unit(id: UnitId, run_info: &ArcRuntimeInfo) -> Result<Arc<Mutex<Unit>>, String> {
+    run_info
+        .unit_table
+        .read()
+        .unwrap()
+        .get(&id)
+        .cloned()
+        .ok_or_else(|| format!("No unit with id {}", id))
+}
+
+fn get_status(id: UnitId, run_info: &ArcRuntimeInfo) -> UnitStatus {
+    run_info
+        .status_table
+        .read()
+        .unwrap()
+        .get(&id)
+        .map(|status| status.lock().unwrap().clone())
+        .unwrap_or(UnitStatus::NeverStarted)
+}
+
+/// Running, on it's way up or down or a oneshot service that ran. A start job runs the oneshot service again
+fn is_up(status: &UnitStatus) -> bool {
+    status.is_active() || *status == UnitStatus::Exited
+}
+
+/// Collects the jobs of a transaction. Every unit gets at most one job
+#[derive(Default)]
+struct TransactionBuilder {
+    jobs: HashMap<UnitId, JobKind>,
+    stop_order: Vec<UnitId>,
+}
+
+impl TransactionBuilder {
+    fn add(&mut self, id: UnitId, kind: JobKind, run_info: &ArcRuntimeInfo) -> Result<(), String> {
+        let merged = match self.jobs.get(&id) {
+            None => kind,
+            Some(other) if other.conflicts_with(kind) => {
+                let name = get_unit(id, run_info)?.lock().unwrap().conf.name();
+                return Err(format!(
+                    "Unit {} would be stopped and started by the same transaction",
+                    name
+                ));
+            }
+            Some(other) => other.merge(kind).unwrap_or(*other),
+        };
+        self.jobs.insert(id, merged);
+        Ok(())
+    }
+
+    /// Stop the unit and all units that have to be stopped with it. The dependents are stopped first
+    fn add_stop(&mut self, id: UnitId, run_info: &ArcRuntimeInfo) -> Result<(), String> {
+        if self.stop_order.contains(&id) {
+            return Ok(());
+        }
+        self.add(id, JobKind::Stop, run_info)?;
+        // mark it before recursing so cycles end here
+        self.stop_order.push(id);
+        let dependents = get_unit(id, run_info)?
+            .lock()
+            .unwrap()
+            .install
+            .stopped_with();
+        for dependent in dependents {
+            if is_up(&get_status(dependent, run_info)) {
+                self.add_stop(dependent, run_info)?;
+            }
+        }
+        // move it behind it's dependents
+        self.stop_order.retain(|stop| *stop != id);
+        self.stop_order.push(id);
+        Ok(())
+    }
+
+    /// Pull in the units needed to start these units and stop the conflicting ones
+    fn add_start_dependencies(
+        &mut self,
+        ids: Vec<UnitId>,
+        run_info: &ArcRuntimeInfo,
+    ) -> Result<(), String> {
+        let mut to_check = ids;
+        let mut checked = HashSet::new();
+        while let Some(id) = to_check.pop() {
+            if !checked.insert(id) {
+                continue;
+            }
+            let (pulled, requisites, conflicts) = {
+                let unit = get_unit(id, run_info)?;
+                let unit_locked = unit.lock().unwrap();
+                let install = &unit_locked.install;
+                let mut pulled = install.requires.clone();
+                pulled.extend(install.binds_to.iter().copied());
+                pulled.extend(install.wants.iter().copied());
+                (pulled, install.requisite.clone(), install.conflicts.clone())
+            };
+            for dependency in pulled {
+                self.add(dependency, JobKind::Start, run_info)?;
+                to_check.push(dependency);
+            }
+            for requisite in requisites {
+                self.add(requisite, JobKind::VerifyActive, run_info)?;
+            }
+            for conflict in conflicts {
+                if is_up(&get_status(conflict, run_info)) || self.jobs.contains_key(&conflict) {
+                    self.add_stop(conflict, run_info)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The stop jobs first, then the others ordered by After=/Before=
+    fn into_ordered_jobs(
+        self,
+        run_info: &ArcRuntimeInfo,
+    ) -> Result<Vec<(UnitId, String, JobKind)>, String> {
+        let mut after = HashMap::new();
+        let mut names = HashMap::new();
+        for id in self.jobs.keys() {
+            let unit = get_unit(*id, run_info)?;
+            let unit_locked = unit.lock().unwrap();
+            after.insert(*id, unit_locked.install.after.clone());
+            names.insert(*id, unit_locked.conf.name());
+        }
+
+        let mut ordered: Vec<UnitId> = self.stop_order.clone();
+        let mut others: Vec<UnitId> = self
+            .jobs
+            .keys()
+            .filter(|id| !ordered.contains(id))
+            .copied()
+            .collect();
+        others.sort();
+        while !others.is_empty() {
+            let ready = others.iter().position(|id| {
+                after[id]
+                    .iter()
+                    .all(|dep| !others.contains(dep) || dep == id)
+            });
+            match ready {
+                Some(idx) => ordered.push(others.remove(idx)),
+                None => {
+                    let mut cycle: Vec<_> = others.iter().map(|id| names[id].clone()).collect();
+                    cycle.sort();
+                    return Err(format!("Ordering cycle between the units: {:?}", cycle));
+                }
+            }
+        }
+
+        Ok(ordered
+            .into_iter()
+            .map(|id| (id, names[&id].clone(), self.jobs[&id]))
+            .collect())
+    }
+}
+
+/// The jobs needed for this request in the order they have to run
+fn build_transaction(
+    id: UnitId,
+    kind: JobKind,
+    mode: JobMode,
+    run_info: &ArcRuntimeInfo,
+) -> Result<Vec<(UnitId, String, JobKind)>, String> {
+    let mut transaction = TransactionBuilder::default();
+    if mode == JobMode::Isolate && kind != JobKind::Start {
+        return Err("Only start jobs can isolate a unit".to_owned());
+    }
+    if kind == JobKind::Stop && mode != JobMode::IgnoreDependencies {
+        transaction.add_stop(id, run_info)?;
+    } else {
+        transaction.add(id, kind, run_info)?;
+    }
+    if mode != JobMode::IgnoreDependencies && (kind == JobKind::Start || kind == JobKind::Restart) {
+        transaction.add_start_dependencies(vec![id], run_info)?;
+    }
+    if mode == JobMode::Isolate {
+        let all_ids: Vec<UnitId> = run_info
+            .unit_table
+            .read()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect();
+        for other in all_ids {
+            if !transaction.jobs.contains_key(&other) && is_up(&get_status(other, run_info)) {
+                transaction.add_stop(other, run_info)?;
+            }
+        }
+    }
+    transaction.into_ordered_jobs(run_info)
+}
+
+/// Do the work of one job
+fn execute_job(
+    id: UnitId,
+    kind: JobKind,
+    run_info: &ArcRuntimeInfo,
+    notification_socket_path: &std::path::Path,
+    eventfds: &Arc<Vec<EventFd>>,
+) -> JobResult {
+    let result = match kind {
+        JobKind::Start => {
+            let status = get_status(id, run_info);
+            let socket_activated = is_socket_activated(id, &status, run_info);
+            if is_up(&status)
+                && status != UnitStatus::Starting
+                && status != UnitStatus::Exited
+                && !socket_activated
+            {
+                return JobResult::Done;
+            }
+            if let UnitStatus::StoppedFinal(_) = status {
+                // a failed or stopped unit can be started again by request
+                let status_table_locked = run_info.status_table.read().unwrap();
+                *status_table_locked.get(&id).unwrap().lock().unwrap() = UnitStatus::Stopped;
+            }
+            match activate_unit(
+                id,
+                run_info.clone(),
+                notification_socket_path.to_path_buf(),
+                eventfds.clone(),
+                !socket_activated,
+            ) {
+                Ok(StartResult::Started(_)) => Ok(()),
+                Ok(StartResult::WaitForDependencies) => {
+                    if is_up(&get_status(id, run_info)) {
+                        Ok(())
+                    } else {
+                        return JobResult::Failed(
+                            "Units it is ordered after are not started".to_owned(),
+                        );
+                    }
+                }
+                Err(e) => Err(e),
+            }
+        }
+        JobKind::Stop => deactivate_unit(id, true, run_info.clone()),
+        JobKind::Restart => reactivate_unit_recursive(
+            id,
+            run_info.clone(),
+            notification_socket_path.to_path_buf(),
+            eventfds.clone(),
+        ),
+        JobKind::Reload => reload_unit(id, run_info.clone()),
+        JobKind::VerifyActive => {
+            if is_up(&get_status(id, run_info)) {
+                Ok(())
+            } else {
+                return JobResult::Failed("Unit is not active".to_owned());
+            }
+        }
+    };
+    match result {
+        Ok(()) => JobResult::Done,
+        Err(e) => JobResult::Failed(format!("{}", e)),
+    }
+}
+
+/// A service that waits for activity on it's sockets or the instance for an accepted connection. A start job really
+/// starts it instead of waiting for the sockets again.
+fn is_socket_activated(id: UnitId, status: &UnitStatus, run_info: &ArcRuntimeInfo) -> bool {
+    if *status == UnitStatus::StartedWaitingForSocket {
+        return true;
+    }
+    let name = match get_unit(id, run_info) {
+        Ok(unit) => unit.lock().unwrap().conf.name(),
+        Err(_) => return false,
+    };
+    run_info
+        .fd_store
+        .read()
+        .unwrap()
+        .get_connection(&name)
+        .is_some()
+}
+
+/// Units in Requires=, BindsTo= or Requisite= of this unit
+fn required_units(id: UnitId, run_info: &ArcRuntimeInfo) -> Vec<UnitId> {
+    match get_unit(id, run_info) {
+        Ok(unit) => {
+            let unit_locked = unit.lock().unwrap();
+            let install = &unit_locked.install;
+            let mut ids = install.requires.clone();
+            ids.extend(install.binds_to.iter().copied());
+            ids.extend(install.requisite.iter().copied());
+            ids
+        }
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Build and install the transaction for this request and run it once the transactions installed before it are done.
+/// Returns the results of all jobs of the transaction.
+pub fn run_transaction(
+    id: UnitId,
+    kind: JobKind,
+    mode: JobMode,
+    run_info: ArcRuntimeInfo,
+    notification_socket_path: std::path::PathBuf,
+    eventfds: Arc<Vec<EventFd>>,
+) -> Result<Vec<FinishedJob>, String> {
+    let jobs = build_transaction(id, kind, mode, &run_info)?;
+    let (transaction, job_ids) = run_info.job_table.install(&jobs, mode)?;
+    trace!("Installed transaction {}: {:?}", transaction, job_ids);
+
+    let _turn = run_info.job_table.wait_for_turn(transaction);
+    let mut failed_units = Vec::new();
+    let mut finished = Vec::new();
+    for ((unit_id, unit_name, job_kind), job_id) in jobs.into_iter().zip(job_ids) {
+        let (id, result) = match job_id {
+            // that job belongs to an earlier transaction, so it is usually done when this transaction has it's turn
+            Err(merged_id) => (merged_id, run_info.job_table.wait_for_merged(merged_id)),
+            Ok(job_id) => {
+                let result = match run_info.job_table.start_job(job_id) {
+                    None => JobResult::Canceled,
+                    Some(kind) => {
+                        let dependency_failed = kind != JobKind::Stop
+                            && required_units(unit_id, &run_info)
+                                .iter()
+                                .any(|required| failed_units.contains(required));
+                        let result = if dependency_failed {
+                            JobResult::Dependency
+                        } else {
+                            trace!("Run {} job {} for {}", kind.name(), job_id, unit_name);
+                            execute_job(
+                                unit_id,
+                                kind,
+                                &run_info,
+                                &notification_socket_path,
+                                &eventfds,
+                            )
+                        };
+                        run_info.job_table.finish_job(job_id, result.clone());
+                        result
+                    }
+                };
+                (job_id, result)
+            }
+        };
+        if result != JobResult::Done {
+            failed_units.push(unit_id);
+        }
+        finished.push(FinishedJob {
+            id,
+            unit_id,
+            unit_name,
+            kind: job_kind,
+            result,
+        });
+    }
+    Ok(finished)
+}
+
+/// Run a job for a unit on behalf of rustysd itself, like restarting a service after it died or starting the unit of a
+/// timer. It is installed like the jobs requested over the control interface, so they do not interfere.
+pub fn run_unit_job(
+    id: UnitId,
+    kind: JobKind,
+    run_info: ArcRuntimeInfo,
+    notification_socket_path: std::path::PathBuf,
+    eventfds: Arc<Vec<EventFd>>,
+) -> Result<(), String> {
+    let finished = run_transaction(
+        id,
+        kind,
+        JobMode::Replace,
+        run_info,
+        notification_socket_path,
+        eventfds,
+    )?;
+    match finished.into_iter().find(|job| job.unit_id == id) {
+        Some(job) if job.result != JobResult::Done => Err(format!(
+            "Job {} ({} {}) {}",
+            job.id,
+            job.kind.name(),
+            job.unit_name,
+            job.result
+        )),
+        _ => Ok(()),
+    }
+}
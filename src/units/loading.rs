@@ -1,6 +1,6 @@
 use crate::units::*;
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 pub enum LoadingError {
@@ -31,22 +31,89 @@ impl std::convert::From<ParsingError> for LoadingError {
     }
 }
 
+/// The units sorted by their kind. Sockets need to be matched to services before all units are put into one table
+#[derive(Default)]
+struct UnitTables {
+    services: HashMap<UnitId, Unit>,
+    sockets: HashMap<UnitId, Unit>,
+    targets: HashMap<UnitId, Unit>,
+    timers: HashMap<UnitId, Unit>,
+    path_units: HashMap<UnitId, Unit>,
+}
+
+impl UnitTables {
+    fn insert(&mut self, unit: Unit) {
+        let table = match unit.id.0 {
+            UnitIdKind::Service => &mut self.services,
+            UnitIdKind::Socket => &mut self.sockets,
+            UnitIdKind::Target => &mut self.targets,
+            UnitIdKind::Timer => &mut self.timers,
+            UnitIdKind::Path => &mut self.path_units,
+        };
+        table.insert(unit.id, unit);
+    }
+
+    fn all_units_mut(&mut self) -> impl Iterator<Item = &mut Unit> {
+        self.services
+            .values_mut()
+            .chain(self.sockets.values_mut())
+            .chain(self.targets.values_mut())
+            .chain(self.timers.values_mut())
+            .chain(self.path_units.values_mut())
+    }
+
+    fn all_units(&self) -> impl Iterator<Item = &Unit> {
+        self.services
+            .values()
+            .chain(self.sockets.values())
+            .chain(self.targets.values())
+            .chain(self.timers.values())
+            .chain(self.path_units.values())
+    }
+
+    /// Names of units that are pulled in by other units. These might be instances of templates.
+    fn referenced_names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for unit in self.all_units() {
+            names.extend(unit.conf.wants.iter().cloned());
+            names.extend(unit.conf.requires.iter().cloned());
+            names.extend(unit.conf.binds_to.iter().cloned());
+            if let Some(conf) = &unit.install.install_config {
+                names.extend(conf.wanted_by.iter().cloned());
+                names.extend(conf.required_by.iter().cloned());
+            }
+            match &unit.specialized {
+                UnitSpecialized::Socket(sock) => names.extend(sock.services.iter().cloned()),
+                UnitSpecialized::Service(srvc) => {
+                    names.extend(srvc.service_config.sockets.iter().cloned())
+                }
+                UnitSpecialized::Timer(timer) => names.push(timer.config.unit.clone()),
+                UnitSpecialized::Path(path) => names.push(path.config.unit.clone()),
+                UnitSpecialized::Target => {}
+            }
+        }
+        names
+    }
+}
+
 pub fn load_all_units(
     paths: &[PathBuf],
     base_id: &mut u64,
 ) -> Result<HashMap<UnitId, Unit>, LoadingError> {
-    let mut service_unit_table = HashMap::new();
-    let mut socket_unit_table = HashMap::new();
-    let mut target_unit_table = HashMap::new();
-    for path in paths {
-        parse_all_units(
-            &mut service_unit_table,
-            &mut socket_unit_table,
-            &mut target_unit_table,
-            path,
-            base_id,
-        )?;
-    }
+    let mut tables = UnitTables::default();
+    let mut templates = HashMap::new();
+    let unit_files = find_unit_files(paths)?;
+    let aliases = parse_all_units(&mut tables, &mut templates, &unit_files, paths, base_id)?;
+    instantiate_templates(&mut tables, &templates, &unit_files, paths, base_id)?;
+    resolve_aliases(&mut tables, aliases);
+
+    let UnitTables {
+        services: mut service_unit_table,
+        sockets: mut socket_unit_table,
+        targets: mut target_unit_table,
+        timers: timer_unit_table,
+        path_units: path_unit_table,
+    } = tables;
 
     let mut socket_target_unit = None;
     for target in target_unit_table.values_mut() {
@@ -72,62 +139,239 @@ pub fn load_all_units(
     unit_table.extend(service_unit_table);
     unit_table.extend(socket_unit_table);
     unit_table.extend(target_unit_table);
+    unit_table.extend(timer_unit_table);
+    unit_table.extend(path_unit_table);
+    apply_triggers_to_units(&mut unit_table).map_err(|e| DependencyError { msg: e })?;
     fill_dependencies(&mut unit_table);
     Ok(unit_table)
 }
 
-fn parse_all_units(
-    services: &mut std::collections::HashMap<UnitId, Unit>,
-    sockets: &mut std::collections::HashMap<UnitId, Unit>,
-    targets: &mut std::collections::HashMap<UnitId, Unit>,
-    path: &PathBuf,
+/// Parse a unit file together with it's drop-ins. The name of the unit is taken from unit_path, for instances of
+/// templates file_path is the template and unit_path the path of the template with the name of the instance.
+/// Returns None if the file is not a unit file.
+pub(super) fn parse_unit(
+    file_path: &Path,
+    unit_path: &PathBuf,
+    unit_dirs: &[PathBuf],
+    id: u64,
+) -> Result<Option<Unit>, ParsingError> {
+    let name = unit_path.file_name().unwrap().to_str().unwrap().to_owned();
+    let kind = if name.ends_with(".service") {
+        UnitIdKind::Service
+    } else if name.ends_with(".socket") {
+        UnitIdKind::Socket
+    } else if name.ends_with(".target") {
+        UnitIdKind::Target
+    } else if name.ends_with(".timer") {
+        UnitIdKind::Timer
+    } else if name.ends_with(".path") {
+        UnitIdKind::Path
+    } else {
+        return Ok(None);
+    };
+
+    let (mut parsed_file, sources) = read_unit_file(file_path, &name, unit_dirs)?;
+    expand_specifiers(&mut parsed_file, &name)
+        .map_err(|e| ParsingError::new(e, file_path.to_path_buf()))?;
+    // kept to find the file and line of a setting if parsing fails
+    let settings = parsed_file.clone();
+
+    trace!("{:?}, {}", unit_path, id);
+    let new_id = UnitId(kind, id);
+    let unit = match kind {
+        UnitIdKind::Service => parse_service(parsed_file, unit_path, new_id),
+        UnitIdKind::Socket => parse_socket(parsed_file, unit_path, new_id),
+        UnitIdKind::Target => parse_target(parsed_file, unit_path, new_id),
+        UnitIdKind::Timer => parse_timer(parsed_file, unit_path, new_id),
+        UnitIdKind::Path => parse_path(parsed_file, unit_path, new_id),
+    };
+    match unit {
+        Ok(mut unit) => {
+            unit.conf.dropins = sources[1..].to_vec();
+            Ok(Some(unit))
+        }
+        Err(e) => Err(locate_error(e, &settings, &sources)),
+    }
+}
+
+/// Create instances of the templates for all referenced instance names and the DefaultInstance= of templates
+/// that would be pulled in by WantedBy= or RequiredBy=. Instances can reference other instances so repeat until
+/// nothing new is found.
+fn instantiate_templates(
+    tables: &mut UnitTables,
+    templates: &HashMap<String, PathBuf>,
+    unit_files: &HashMap<String, UnitFile>,
+    unit_dirs: &[PathBuf],
     last_id: &mut u64,
 ) -> Result<(), ParsingError> {
-    let files = get_file_list(path)
-        .map_err(|e| ParsingError::new(ParsingErrorReason::from(e), path.clone()))?;
-    for entry in files {
-        if entry.path().is_dir() {
-            parse_all_units(services, sockets, targets, path, last_id)?;
-        } else {
-            let raw = std::fs::read_to_string(&entry.path()).map_err(|e| {
-                ParsingError::new(ParsingErrorReason::from(Box::new(e)), path.clone())
-            })?;
-
-            let parsed_file = parse_file(&raw)
-                .map_err(|e| ParsingError::new(ParsingErrorReason::from(e), path.clone()))?;
-
-            if entry.path().to_str().unwrap().ends_with(".service") {
-                *last_id += 1;
-                trace!("{:?}, {}", entry.path(), last_id);
-                let new_id = UnitId(UnitIdKind::Service, *last_id);
-                services.insert(
-                    new_id,
-                    parse_service(parsed_file, &entry.path(), new_id.clone()).map_err(|e| {
-                        ParsingError::new(ParsingErrorReason::from(e), path.clone())
-                    })?,
-                );
-            } else if entry.path().to_str().unwrap().ends_with(".socket") {
-                *last_id += 1;
-                trace!("{:?}, {}", entry.path(), last_id);
-                let new_id = UnitId(UnitIdKind::Socket, *last_id);
-                sockets.insert(
-                    new_id,
-                    parse_socket(parsed_file, &entry.path(), new_id.clone()).map_err(|e| {
-                        ParsingError::new(ParsingErrorReason::from(e), path.clone())
-                    })?,
-                );
-            } else if entry.path().to_str().unwrap().ends_with(".target") {
+    let mut wanted_names = Vec::new();
+    for (template, template_path) in templates {
+        let (parsed_file, _) = read_unit_file(template_path, template, unit_dirs)?;
+        if let Some(install_section) = parsed_file.get("[Install]") {
+            let install_config = parse_install_section(install_section.clone())
+                .map_err(|e| ParsingError::new(e, template_path.clone()))?;
+            let enabled =
+                !install_config.wanted_by.is_empty() || !install_config.required_by.is_empty();
+            if let (Some(instance), true) = (install_config.default_instance, enabled) {
+                if let Some((prefix, _, suffix)) = split_instance_name(template) {
+                    wanted_names.push(format!("{}@{}{}", prefix, instance, suffix));
+                }
+            }
+        }
+    }
+
+    loop {
+        wanted_names.extend(tables.referenced_names());
+        let known_names: Vec<String> = tables.all_units().map(|unit| unit.conf.name()).collect();
+        let mut new_units = Vec::new();
+        for name in wanted_names.drain(..) {
+            let masked = unit_files
+                .get(&name)
+                .map(|file| file.kind == UnitFileKind::Masked)
+                .unwrap_or(false);
+            if masked
+                || known_names.contains(&name)
+                || new_units.iter().any(|unit: &Unit| unit.conf.name() == name)
+            {
+                continue;
+            }
+            let template_path = match template_name(&name).and_then(|t| templates.get(&t)) {
+                Some(path) => path,
+                None => continue,
+            };
+            trace!("Instantiate {} from {:?}", name, template_path);
+            let instance_path = template_path.with_file_name(&name);
+            if let Some(unit) = parse_unit(template_path, &instance_path, unit_dirs, *last_id + 1)?
+            {
                 *last_id += 1;
-                trace!("{:?}, {}", entry.path(), last_id);
-                let new_id = UnitId(UnitIdKind::Target, *last_id);
-                targets.insert(
-                    new_id,
-                    parse_target(parsed_file, &entry.path(), new_id.clone()).map_err(|e| {
-                        ParsingError::new(ParsingErrorReason::from(e), path.clone())
-                    })?,
-                );
+                new_units.push(unit);
             }
         }
+        if new_units.is_empty() {
+            break;
+        }
+        for unit in new_units {
+            tables.insert(unit);
+        }
     }
     Ok(())
 }
+
+/// Parse the unit files that won over the files with the same name in other unit dirs. Masked units are skipped.
+/// Returns the aliases that are defined by symlinks.
+fn parse_all_units(
+    tables: &mut UnitTables,
+    templates: &mut HashMap<String, PathBuf>,
+    unit_files: &HashMap<String, UnitFile>,
+    unit_dirs: &[PathBuf],
+    last_id: &mut u64,
+) -> Result<HashMap<String, String>, ParsingError> {
+    let mut aliases = HashMap::new();
+    let mut names: Vec<&String> = unit_files.keys().collect();
+    names.sort();
+    for name in names {
+        let unit_file = &unit_files[name];
+        for shadowed in &unit_file.shadowed {
+            trace!("{:?} is shadowed by {:?}", shadowed, unit_file.path);
+        }
+        match &unit_file.kind {
+            UnitFileKind::Masked => {
+                info!("Unit {} is masked by {:?}", name, unit_file.path);
+                continue;
+            }
+            UnitFileKind::Alias(target) => {
+                aliases.insert(name.clone(), target.clone());
+                continue;
+            }
+            UnitFileKind::Regular => {}
+        }
+        if is_template_name(name) {
+            // only instantiated when referenced
+            templates.insert(name.clone(), unit_file.path.clone());
+            continue;
+        }
+        let unit_path = unit_file.path.with_file_name(name);
+        if let Some(mut unit) = parse_unit(&unit_file.path, &unit_path, unit_dirs, *last_id + 1)? {
+            *last_id += 1;
+            unit.conf.shadowed = unit_file.shadowed.clone();
+            tables.insert(unit);
+        }
+    }
+    Ok(aliases)
+}
+
+/// Replace aliases with the name of the unit they point to in all references between units. Aliases come from
+/// symlinks in the unit dirs and from Alias= in the [Install] section.
+fn resolve_aliases(tables: &mut UnitTables, mut aliases: HashMap<String, String>) {
+    let names: Vec<String> = tables.all_units().map(|unit| unit.conf.name()).collect();
+    for unit in tables.all_units() {
+        let name = unit.conf.name();
+        if let Some(conf) = &unit.install.install_config {
+            for alias in &conf.alias {
+                let same_kind = alias.rsplit('.').next() == name.rsplit('.').next();
+                if !same_kind || names.contains(alias) {
+                    warn!("Ignoring Alias={} of unit {}", alias, name);
+                    continue;
+                }
+                aliases.insert(alias.clone(), name.clone());
+            }
+        }
+    }
+    aliases.retain(|alias, target| {
+        let known = names.contains(target) && !names.contains(alias);
+        if !known {
+            warn!(
+                "Ignoring alias {} for unit {} that is not loaded",
+                alias, target
+            );
+        }
+        known
+    });
+
+    let resolve = |names: &mut Vec<String>| {
+        for name in names.iter_mut() {
+            if let Some(target) = aliases.get(name) {
+                *name = target.clone();
+            }
+        }
+    };
+    for unit in tables.all_units_mut() {
+        let name = unit.conf.name();
+        unit.conf.aliases = aliases
+            .iter()
+            .filter(|(_, target)| **target == name)
+            .map(|(alias, _)| alias.clone())
+            .collect();
+        unit.conf.aliases.sort();
+
+        resolve(&mut unit.conf.wants);
+        resolve(&mut unit.conf.requires);
+        resolve(&mut unit.conf.before);
+        resolve(&mut unit.conf.after);
+        resolve(&mut unit.conf.conflicts);
+        resolve(&mut unit.conf.binds_to);
+        resolve(&mut unit.conf.part_of);
+        resolve(&mut unit.conf.requisite);
+        resolve(&mut unit.conf.on_failure);
+        resolve(&mut unit.conf.on_success);
+        if let Some(conf) = &mut unit.install.install_config {
+            resolve(&mut conf.wanted_by);
+            resolve(&mut conf.required_by);
+        }
+        match &mut unit.specialized {
+            UnitSpecialized::Socket(sock) => resolve(&mut sock.services),
+            UnitSpecialized::Service(srvc) => resolve(&mut srvc.service_config.sockets),
+            UnitSpecialized::Timer(timer) => {
+                if let Some(target) = aliases.get(&timer.config.unit) {
+                    timer.config.unit = target.clone();
+                }
+            }
+            UnitSpecialized::Path(path) => {
+                if let Some(target) = aliases.get(&path.config.unit) {
+                    path.config.unit = target.clone();
+                }
+            }
+            UnitSpecialized::Target => {}
+        }
+    }
+}
@@ -1,19 +1,27 @@
 //! The different parts of unit handling: parsing and activating
 
 mod activate;
+mod conditions;
 mod deactivate;
 mod dependency_resolving;
 mod insert_new;
+mod jobs;
 mod loading;
+mod result_handlers;
 mod unit_parsing;
 mod units;
 mod sanity_check;
+mod search_path;
 
 pub use activate::*;
+pub use conditions::*;
 pub use deactivate::*;
 pub use dependency_resolving::*;
 pub use insert_new::*;
+pub use jobs::*;
 pub use loading::load_all_units;
+pub use result_handlers::*;
 pub use unit_parsing::*;
 pub use units::*;
-pub use sanity_check::*;
\ No newline at end of file
+pub use sanity_check::*;
+pub use search_path::*;
\ No newline at end of file
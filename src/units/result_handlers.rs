@@ -0,0 +1,113 @@
+//! Start the units from OnFailure= and OnSuccess= when a unit failed or finished successfully
+
+use crate::platform::EventFd;
+use crate::units::*;
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum UnitResult {
+    Success,
+    Failure,
+}
+
+/// Find the unit with this name or load it from the unit dirs. Handlers like notify-admin@foo.service.service are
+/// usually instances of a template that are only created when they are needed.
+fn find_or_load_unit(name: &str, run_info: &ArcRuntimeInfo) -> Result<UnitId, String> {
+    for unit in run_info.unit_table.read().unwrap().values() {
+        let unit_locked = unit.lock().unwrap();
+        if unit_locked.conf.name() == name || unit_locked.conf.aliases.iter().any(|a| a == name) {
+            return Ok(unit_locked.id);
+        }
+    }
+
+    let next_id = {
+        let last_id = &mut *run_info.last_id.lock().unwrap();
+        *last_id += 1;
+        *last_id
+    };
+    let unit = load_new_unit(&run_info.config.unit_dirs, name, next_id)?;
+    let id = unit.id;
+    insert_new_unit(unit, run_info.clone())?;
+    Ok(id)
+}
+
+fn start_handler(
+    handler_name: &str,
+    unit_id: UnitId,
+    run_info: &ArcRuntimeInfo,
+    notification_socket_path: std::path::PathBuf,
+    eventfds: Arc<Vec<EventFd>>,
+) -> Result<(), String> {
+    let handler_id = find_or_load_unit(handler_name, run_info)?;
+    if handler_id == unit_id {
+        return Err("A unit can not be it's own handler".to_owned());
+    }
+    {
+        let status_table_locked = run_info.status_table.read().unwrap();
+        let mut status_locked = status_table_locked
+            .get(&handler_id)
+            .unwrap()
+            .lock()
+            .unwrap();
+        // handlers are started again every time they are triggered, even if they failed before
+        if let UnitStatus::StoppedFinal(_) = *status_locked {
+            *status_locked = UnitStatus::Stopped;
+        }
+    }
+    activate_unit(
+        handler_id,
+        run_info.clone(),
+        notification_socket_path,
+        eventfds,
+        true,
+    )
+    .map(|_| ())
+    .map_err(|e| format!("{}", e))
+}
+
+/// Start the OnFailure= or OnSuccess= units of this unit in a new thread. The unit must not be locked by the caller.
+pub fn start_result_handlers(
+    unit_id: UnitId,
+    result: UnitResult,
+    run_info: ArcRuntimeInfo,
+    notification_socket_path: std::path::PathBuf,
+    eventfds: Arc<Vec<EventFd>>,
+) {
+    let unit = match run_info.unit_table.read().unwrap().get(&unit_id) {
+        Some(unit) => unit.clone(),
+        None => return,
+    };
+    let (name, setting, handlers) = {
+        let mut unit_locked = unit.lock().unwrap();
+        let (setting, handlers) = match result {
+            UnitResult::Failure => ("OnFailure", unit_locked.conf.on_failure.clone()),
+            UnitResult::Success => ("OnSuccess", unit_locked.conf.on_success.clone()),
+        };
+        if handlers.is_empty() {
+            return;
+        }
+        unit_locked.triggered_handlers = handlers
+            .iter()
+            .map(|handler| format!("{}={}", setting, handler))
+            .collect();
+        (unit_locked.conf.name(), setting, handlers)
+    };
+
+    std::thread::spawn(move || {
+        for handler in handlers {
+            trace!("Start {}={} of unit {}", setting, handler, name);
+            if let Err(e) = start_handler(
+                &handler,
+                unit_id,
+                &run_info,
+                notification_socket_path.clone(),
+                eventfds.clone(),
+            ) {
+                error!(
+                    "Could not start {}={} of unit {}: {}",
+                    setting, handler, name, e
+                );
+            }
+        }
+    });
+}
@@ -0,0 +1,100 @@
+//! Find the unit files in the unit dirs. The dirs are searched in the order they are configured, a file in an earlier
+//! dir hides files with the same name in later dirs (like /etc/systemd/system hides /usr/lib/systemd/system).
+//! Symlinks to /dev/null mask a unit, symlinks with another name than their target are aliases.
+
+use crate::units::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum UnitFileKind {
+    Regular,
+    /// A symlink to /dev/null. The unit is not loaded
+    Masked,
+    /// A symlink to a unit with another name. Holds the name of the target
+    Alias(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct UnitFile {
+    /// The file that won over the files with the same name in other dirs
+    pub path: PathBuf,
+    pub kind: UnitFileKind,
+    /// Files with the same name that are hidden by path
+    pub shadowed: Vec<PathBuf>,
+}
+
+fn collect_unit_files(
+    dir: &PathBuf,
+    files: &mut HashMap<String, UnitFile>,
+    linked: &mut Vec<(String, PathBuf)>,
+) -> Result<(), ParsingError> {
+    let entries = get_file_list(dir).map_err(|e| ParsingError::new(e, dir.clone()))?;
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if path.is_dir() {
+            // *.d dirs contain drop-ins and no units
+            if !name.ends_with(".d") {
+                collect_unit_files(&path, files, linked)?;
+            }
+            continue;
+        }
+
+        let kind = match std::fs::read_link(&path) {
+            Ok(target) => {
+                if target == Path::new("/dev/null") {
+                    UnitFileKind::Masked
+                } else {
+                    match target.file_name().map(|t| t.to_string_lossy().into_owned()) {
+                        Some(target_name) if target_name != name => {
+                            linked.push((target_name.clone(), dir.join(&target)));
+                            UnitFileKind::Alias(target_name)
+                        }
+                        _ => UnitFileKind::Regular,
+                    }
+                }
+            }
+            // not a symlink
+            Err(_) => UnitFileKind::Regular,
+        };
+
+        match files.get_mut(&name) {
+            Some(existing) => existing.shadowed.push(path),
+            None => {
+                files.insert(
+                    name,
+                    UnitFile {
+                        path,
+                        kind,
+                        shadowed: Vec::new(),
+                    },
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Find all unit files in the unit dirs indexed by the unit name. Dirs that do not exist are skipped.
+pub fn find_unit_files(unit_dirs: &[PathBuf]) -> Result<HashMap<String, UnitFile>, ParsingError> {
+    let mut files = HashMap::new();
+    let mut linked = Vec::new();
+    for dir in unit_dirs {
+        if !dir.exists() {
+            warn!("Unit dir {:?} does not exist", dir);
+            continue;
+        }
+        collect_unit_files(dir, &mut files, &mut linked)?;
+    }
+
+    // aliases can point to units outside of the unit dirs, these are loaded under the name of the target
+    for (target_name, target_path) in linked {
+        files.entry(target_name).or_insert_with(|| UnitFile {
+            path: target_path,
+            kind: UnitFileKind::Regular,
+            shadowed: Vec::new(),
+        });
+    }
+    Ok(files)
+}
@@ -0,0 +1,347 @@
+//! Parse command lines like systemd does for ExecStart= and friends. See the section "Command lines" in systemd.service(5)
+//! for the syntax. Environment variables are expanded only right before the command is executed.
+
+use crate::units::*;
+
+/// The fixed search path that is used if the command is not a path but only the name of an executable
+pub const EXECUTABLE_SEARCH_PATH: &[&str] = &[
+    "/usr/local/sbin",
+    "/usr/local/bin",
+    "/usr/sbin",
+    "/usr/bin",
+    "/sbin",
+    "/bin",
+];
+
+#[derive(Debug, Eq, PartialEq)]
+enum Token {
+    Word(String),
+    /// A lone unescaped ';' separating two command lines
+    Separator,
+}
+
+/// Parse all values of one setting (like ExecStartPre=) into command lines. An empty value resets the list.
+pub fn parse_cmdlines_setting(
+    setting_name: &str,
+    values: Option<Vec<(EntryPosition, String)>>,
+) -> Result<Vec<Commandline>, ParsingErrorReason> {
+    let mut cmdlines = Vec::new();
+    for (_, value) in values.unwrap_or_default() {
+        if value.is_empty() {
+            cmdlines.clear();
+            continue;
+        }
+        let parsed = parse_cmdlines(&value).map_err(|e| {
+            ParsingErrorReason::Generic(format!(
+                "Invalid command line in {}={}: {}",
+                setting_name, value, e
+            ))
+        })?;
+        cmdlines.extend(parsed);
+    }
+    Ok(cmdlines)
+}
+
+/// Parse one value that may contain multiple command lines separated by lone semicolons
+pub fn parse_cmdlines(raw: &str) -> Result<Vec<Commandline>, String> {
+    let tokens = tokenize(raw)?;
+    let mut cmdlines = Vec::new();
+    let mut words = Vec::new();
+    for token in tokens {
+        match token {
+            Token::Word(word) => words.push(word),
+            Token::Separator => {
+                cmdlines.push(parse_single_cmdline(words)?);
+                words = Vec::new();
+            }
+        }
+    }
+    if !words.is_empty() {
+        cmdlines.push(parse_single_cmdline(words)?);
+    }
+    Ok(cmdlines)
+}
+
+fn parse_single_cmdline(mut words: Vec<String>) -> Result<Commandline, String> {
+    if words.is_empty() {
+        return Err("Empty command line".into());
+    }
+    let first = words.remove(0);
+
+    let mut prefixes = Vec::new();
+    let mut rest = first.as_str();
+    loop {
+        let prefix = if rest.starts_with("!!") {
+            CommandlinePrefix::DoubleExclamation
+        } else {
+            match rest.chars().next() {
+                Some('@') => CommandlinePrefix::AtSign,
+                Some('-') => CommandlinePrefix::Minus,
+                Some(':') => CommandlinePrefix::Colon,
+                Some('+') => CommandlinePrefix::Plus,
+                Some('!') => CommandlinePrefix::Exclamation,
+                _ => break,
+            }
+        };
+        if prefixes.contains(&prefix) {
+            return Err(format!("Prefix {:?} used more than once", prefix));
+        }
+        rest = if prefix == CommandlinePrefix::DoubleExclamation {
+            &rest[2..]
+        } else {
+            &rest[1..]
+        };
+        prefixes.push(prefix);
+    }
+
+    let privilege_prefixes = prefixes
+        .iter()
+        .filter(|prefix| {
+            matches!(
+                prefix,
+                CommandlinePrefix::Plus
+                    | CommandlinePrefix::Exclamation
+                    | CommandlinePrefix::DoubleExclamation
+            )
+        })
+        .count();
+    if privilege_prefixes > 1 {
+        return Err("Only one of the prefixes '+', '!' and '!!' may be used".into());
+    }
+    if rest.is_empty() {
+        return Err("No executable given".into());
+    }
+    if prefixes.contains(&CommandlinePrefix::AtSign) && words.is_empty() {
+        return Err("The '@' prefix needs a second word that is used as argv[0]".into());
+    }
+
+    Ok(Commandline {
+        cmd: rest.to_owned(),
+        args: words,
+        prefixes,
+    })
+}
+
+/// Split into words like a command line but without treating ';' specially. Used for Environment=
+pub fn split_words(raw: &str) -> Result<Vec<String>, String> {
+    Ok(tokenize(raw)?
+        .into_iter()
+        .map(|token| match token {
+            Token::Word(word) => word,
+            Token::Separator => ";".to_owned(),
+        })
+        .collect())
+}
+
+/// Split into words, respecting quotes and C-style escapes
+fn tokenize(raw: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = raw.chars().peekable();
+
+    loop {
+        while let Some(c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        let first = match chars.peek() {
+            Some(c) => *c,
+            None => break,
+        };
+
+        // escapes can produce arbitrary bytes, so collect bytes and check for valid UTF-8 once the word is complete
+        let mut word = Vec::new();
+        if first == '"' || first == '\'' {
+            chars.next();
+            let mut closed = false;
+            while let Some(c) = chars.next() {
+                if c == first {
+                    closed = true;
+                    break;
+                } else if c == '\\' {
+                    unescape(&mut chars, &mut word)?;
+                } else {
+                    push_char(&mut word, c);
+                }
+            }
+            if !closed {
+                return Err(format!("Missing closing quote: {}", first));
+            }
+            match chars.peek() {
+                Some(c) if !c.is_whitespace() => {
+                    return Err(format!(
+                        "Closing quote must be followed by whitespace, found: {}",
+                        c
+                    ));
+                }
+                _ => {}
+            }
+            tokens.push(Token::Word(word_to_string(word)?));
+        } else {
+            let mut escaped = false;
+            while let Some(c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                let c = *c;
+                chars.next();
+                if c == '\\' {
+                    escaped = true;
+                    unescape(&mut chars, &mut word)?;
+                } else {
+                    push_char(&mut word, c);
+                }
+            }
+            if word == b";" && !escaped {
+                tokens.push(Token::Separator);
+            } else {
+                tokens.push(Token::Word(word_to_string(word)?));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+fn push_char(word: &mut Vec<u8>, c: char) {
+    let mut buf = [0u8; 4];
+    word.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+
+fn word_to_string(word: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(word).map_err(|e| {
+        format!(
+            "Escape sequences result in invalid UTF-8: {:?}",
+            String::from_utf8_lossy(e.as_bytes())
+        )
+    })
+}
+
+/// Called after a backslash was read. Consumes the rest of the escape sequence and appends the resulting bytes to the word.
+/// NUL bytes are rejected because they can not be passed to exec.
+fn unescape<I: Iterator<Item = char>>(
+    chars: &mut std::iter::Peekable<I>,
+    word: &mut Vec<u8>,
+) -> Result<(), String> {
+    let c = match chars.next() {
+        Some(c) => c,
+        None => return Err("Trailing backslash".into()),
+    };
+    let unescaped = match c {
+        'a' => 0x07,
+        'b' => 0x08,
+        'f' => 0x0C,
+        'n' => b'\n',
+        'r' => b'\r',
+        't' => b'\t',
+        'v' => 0x0B,
+        's' => b' ',
+        'x' => {
+            let hex: String = (0..2).filter_map(|_| chars.next()).collect();
+            let value = u8::from_str_radix(&hex, 16)
+                .map_err(|_| format!("Invalid hex escape: \\x{}", hex))?;
+            if value == 0 {
+                return Err(format!("NUL byte is not allowed: \\x{}", hex));
+            }
+            value
+        }
+        '0'..='7' => {
+            let mut octal = c.to_string();
+            octal.extend((0..2).filter_map(|_| chars.next()));
+            let value = u8::from_str_radix(&octal, 8)
+                .map_err(|_| format!("Invalid octal escape: \\{}", octal))?;
+            if value == 0 {
+                return Err(format!("NUL byte is not allowed: \\{}", octal));
+            }
+            value
+        }
+        '\\' | '"' | '\'' | ';' => {
+            push_char(word, c);
+            return Ok(());
+        }
+        _ => {
+            warn!("Unknown escape sequence in command line: \\{}", c);
+            push_char(word, c);
+            return Ok(());
+        }
+    };
+    word.push(unescaped);
+    Ok(())
+}
+
+/// Expand environment variables in the arguments of a command line. A word that is exactly "$VAR" gets replaced by the
+/// value split into words, "${VAR}" gets replaced inside of a word without splitting. "$$" is a literal '$'.
+/// Unknown variables are treated as empty.
+pub fn expand_env_vars(args: &[String], env: &[(String, String)]) -> Vec<String> {
+    let lookup = |name: &str| {
+        env.iter()
+            .rev()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+            .unwrap_or("")
+    };
+
+    let mut expanded = Vec::new();
+    for arg in args {
+        if arg.len() > 1
+            && arg.starts_with('$')
+            && arg[1..]
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            let value = lookup(&arg[1..]);
+            match tokenize(value) {
+                Ok(tokens) => expanded.extend(tokens.into_iter().map(|token| match token {
+                    Token::Word(word) => word,
+                    Token::Separator => ";".to_owned(),
+                })),
+                Err(_) => expanded.extend(value.split_whitespace().map(str::to_owned)),
+            }
+            continue;
+        }
+
+        let mut word = String::new();
+        let mut rest = arg.as_str();
+        while let Some(pos) = rest.find('$') {
+            word.push_str(&rest[..pos]);
+            rest = &rest[pos..];
+            if rest.starts_with("$$") {
+                word.push('$');
+                rest = &rest[2..];
+            } else if rest.starts_with("${") {
+                match rest.find('}') {
+                    Some(end) => {
+                        word.push_str(lookup(&rest[2..end]));
+                        rest = &rest[end + 1..];
+                    }
+                    None => {
+                        word.push_str(rest);
+                        rest = "";
+                    }
+                }
+            } else {
+                word.push('$');
+                rest = &rest[1..];
+            }
+        }
+        word.push_str(rest);
+        expanded.push(word);
+    }
+    expanded
+}
+
+/// Commands without a '/' are searched in a fixed search path. If nothing is found the command is returned as is.
+pub fn resolve_executable(cmd: &str) -> std::path::PathBuf {
+    if cmd.contains('/') {
+        return std::path::PathBuf::from(cmd);
+    }
+    for dir in EXECUTABLE_SEARCH_PATH {
+        let candidate = std::path::PathBuf::from(dir).join(cmd);
+        if candidate.is_file() {
+            return candidate;
+        }
+    }
+    std::path::PathBuf::from(cmd)
+}
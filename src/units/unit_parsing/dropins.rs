@@ -0,0 +1,225 @@
+//! Drop-in files (foo.service.d/*.conf) that change settings of a unit without editing the unit file, see systemd.unit(5)
+
+use crate::units::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Settings where each assignment adds to the list. All other settings are replaced by later assignments.
+const LIST_SETTINGS: &[&str] = &[
+    "WANTS",
+    "REQUIRES",
+    "BEFORE",
+    "AFTER",
+    "CONFLICTS",
+    "BINDSTO",
+    "PARTOF",
+    "REQUISITE",
+    "ONFAILURE",
+    "ONSUCCESS",
+    "WANTEDBY",
+    "REQUIREDBY",
+    "ALIAS",
+    "EXECSTART",
+    "EXECSTARTPRE",
+    "EXECSTARTPOST",
+    "EXECSTOP",
+    "EXECSTOPPOST",
+    "EXECRELOAD",
+    "ENVIRONMENT",
+    "ENVIRONMENTFILE",
+    "PASSENVIRONMENT",
+    "UNSETENVIRONMENT",
+    "SUPPLEMENTARYGROUPS",
+    "SOCKETS",
+    "SERVICE",
+    "LISTENSTREAM",
+    "LISTENDATAGRAM",
+    "LISTENSEQUENTIALPACKET",
+    "LISTENFIFO",
+    "ONACTIVESEC",
+    "ONBOOTSEC",
+    "ONSTARTUPSEC",
+    "ONUNITACTIVESEC",
+    "ONUNITINACTIVESEC",
+    "ONCALENDAR",
+    "PATHEXISTS",
+    "PATHEXISTSGLOB",
+    "PATHCHANGED",
+    "PATHMODIFIED",
+    "DIRECTORYNOTEMPTY",
+];
+
+/// Names of the directories that can hold drop-ins for this unit, the most specific first.
+/// For foo-bar@baz.service these are foo-bar@baz.service.d, foo-bar@.service.d, foo-bar-.service.d,
+/// foo-.service.d, -.service.d and service.d
+fn dropin_dir_names(unit_name: &str) -> Vec<String> {
+    let mut names = vec![format!("{}.d", unit_name)];
+    if let Some(template) = template_name(unit_name) {
+        names.push(format!("{}.d", template));
+    }
+    if let Some(dot) = unit_name.rfind('.') {
+        let (prefix, suffix) = unit_name.split_at(dot);
+        let mut dashes: Vec<usize> = prefix.match_indices('-').map(|(idx, _)| idx).collect();
+        dashes.reverse();
+        for dash in dashes {
+            names.push(format!("{}{}.d", &prefix[..=dash], suffix));
+        }
+        let type_dir = format!("-{}.d", suffix);
+        if !names.contains(&type_dir) {
+            names.push(type_dir);
+        }
+        names.push(format!("{}.d", &suffix[1..]));
+    }
+    names
+}
+
+/// Find all drop-ins for this unit in the unit dirs, sorted by their file name. A drop-in hides drop-ins with
+/// the same file name in less specific directories and in later unit dirs.
+pub fn find_dropins(unit_dirs: &[PathBuf], unit_name: &str) -> Vec<PathBuf> {
+    let mut found: HashMap<std::ffi::OsString, PathBuf> = HashMap::new();
+    for dir in unit_dirs {
+        for dir_name in dropin_dir_names(unit_name) {
+            let entries = match std::fs::read_dir(dir.join(dir_name)) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let path = entry.path();
+                if path.is_file() && path.extension().map(|ext| ext == "conf").unwrap_or(false) {
+                    found.entry(entry.file_name()).or_insert(path);
+                }
+            }
+        }
+    }
+    let mut dropins: Vec<(std::ffi::OsString, PathBuf)> = found.into_iter().collect();
+    dropins.sort();
+    dropins.into_iter().map(|(_, path)| path).collect()
+}
+
+/// The position of each entry keeps the order of the entries across files and allows to find the file and line of a
+/// setting for error messages
+fn set_file_index(parsed_file: &mut ParsedFile, file_idx: u32) {
+    for section in parsed_file.values_mut() {
+        for values in section.values_mut() {
+            for ((entry_file, _), _) in values.iter_mut() {
+                *entry_file = file_idx;
+            }
+        }
+    }
+}
+
+/// Merge a drop-in into the unit. List settings are extended, an empty assignment removes all earlier values.
+/// Other settings are replaced, an empty assignment resets them to their default.
+fn merge_dropin(unit: &mut ParsedFile, dropin: ParsedFile) {
+    for (section_name, section) in dropin {
+        let unit_section = unit.entry(section_name).or_default();
+        for (setting, mut values) in section {
+            if is_list_setting(&setting) {
+                let merged = unit_section.entry(setting.clone()).or_default();
+                if let Some(reset) = values.iter().rposition(|(_, value)| value.is_empty()) {
+                    merged.clear();
+                    values.drain(..=reset);
+                }
+                merged.extend(values);
+                if merged.is_empty() {
+                    unit_section.remove(&setting);
+                }
+            } else {
+                match values.pop() {
+                    Some((_, value)) if value.is_empty() => {
+                        unit_section.remove(&setting);
+                    }
+                    Some(last) => {
+                        unit_section.insert(setting, vec![last]);
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
+/// Condition*= and Assert*= accumulate like the other list settings
+fn is_list_setting(setting: &str) -> bool {
+    LIST_SETTINGS.contains(&setting)
+        || setting.starts_with("CONDITION")
+        || setting.starts_with("ASSERT")
+}
+
+fn read_and_parse(path: &Path) -> Result<ParsedFile, ParsingError> {
+    let content = std::fs::read_to_string(path).map_err(|e| {
+        ParsingError::new(ParsingErrorReason::from(Box::new(e)), path.to_path_buf())
+    })?;
+    parse_file(&content).map_err(|e| ParsingError::new(e, path.to_path_buf()))
+}
+
+/// Read the unit file and merge all drop-ins for the unit into it. Returns the merged settings and all files that
+/// contributed to them, starting with the unit file.
+pub fn read_unit_file(
+    file_path: &Path,
+    unit_name: &str,
+    unit_dirs: &[PathBuf],
+) -> Result<(ParsedFile, Vec<PathBuf>), ParsingError> {
+    let mut parsed_file = read_and_parse(file_path)?;
+    let mut sources = vec![file_path.to_path_buf()];
+
+    let mut search_dirs = unit_dirs.to_vec();
+    if let Some(parent) = file_path.parent() {
+        if !search_dirs.iter().any(|dir| dir == parent) {
+            search_dirs.push(parent.to_path_buf());
+        }
+    }
+    for dropin_path in find_dropins(&search_dirs, unit_name) {
+        trace!("Apply drop-in {:?} to {}", dropin_path, unit_name);
+        let mut dropin = read_and_parse(&dropin_path)?;
+        set_file_index(&mut dropin, sources.len() as u32);
+        merge_dropin(&mut parsed_file, dropin);
+        sources.push(dropin_path);
+    }
+    Ok((parsed_file, sources))
+}
+
+/// Find the file and line of the setting that caused the error
+pub fn locate_error(
+    reason: ParsingErrorReason,
+    parsed_file: &ParsedFile,
+    sources: &[PathBuf],
+) -> ParsingError {
+    let (setting, value) = match &reason {
+        ParsingErrorReason::UnknownSetting(setting, value) => {
+            (setting.to_uppercase(), Some(value.clone()))
+        }
+        ParsingErrorReason::UnusedSetting(setting) => (setting.to_uppercase(), None),
+        ParsingErrorReason::SettingTooManyValues(setting, _) => (setting.to_uppercase(), None),
+        _ => return ParsingError::new(reason, sources[0].clone()),
+    };
+
+    let entries: Vec<&(EntryPosition, String)> = parsed_file
+        .values()
+        .filter_map(|section| section.get(&setting))
+        .flatten()
+        .collect();
+    let entry = entries
+        .iter()
+        // some parsers report the value uppercased
+        .filter(|(_, entry_value)| {
+            value
+                .as_ref()
+                .map(|value| value.eq_ignore_ascii_case(entry_value))
+                .unwrap_or(false)
+        })
+        .map(|(entry, _)| *entry)
+        .max()
+        .or_else(|| entries.iter().map(|(entry, _)| *entry).max());
+
+    match entry {
+        Some((file_idx, line)) => {
+            let path = sources
+                .get(file_idx as usize)
+                .unwrap_or(&sources[0])
+                .clone();
+            ParsingError::new(reason, path).with_line(line)
+        }
+        None => ParsingError::new(reason, sources[0].clone()),
+    }
+}
@@ -1,17 +1,28 @@
+mod commandline;
+mod dropins;
+mod path_unit;
 mod service_unit;
 mod socket_unit;
 mod target_unit;
+mod templates;
+mod timer_unit;
 mod unit_parser;
 
+pub use commandline::*;
+pub use dropins::*;
+pub use path_unit::*;
 pub use service_unit::*;
 pub use socket_unit::*;
 pub use target_unit::*;
+pub use templates::*;
+pub use timer_unit::*;
 pub use unit_parser::*;
 
 #[derive(Debug)]
 pub struct ParsingError {
     inner: ParsingErrorReason,
     path: std::path::PathBuf,
+    line: Option<u32>,
 }
 
 impl ParsingError {
@@ -19,8 +30,15 @@ impl ParsingError {
         ParsingError {
             inner: reason,
             path,
+            line: None,
         }
     }
+
+    /// The line in the file where the offending setting was found
+    pub fn with_line(mut self, line: u32) -> ParsingError {
+        self.line = Some(line);
+        self
+    }
 }
 
 #[derive(Debug)]
@@ -40,71 +58,67 @@ pub enum ParsingErrorReason {
 
 impl std::fmt::Display for ParsingError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let location = match self.line {
+            Some(line) => format!("{:?} (line {})", self.path, line),
+            None => format!("{:?}", self.path),
+        };
         match &self.inner {
             ParsingErrorReason::UnknownSetting(name, value) => {
                 write!(
                     f,
-                    "In file {:?}: setting {} was set to unrecognized value: {}",
-                    self.path, name, value
+                    "In file {}: setting {} was set to unrecognized value: {}",
+                    location, name, value
                 )?;
             }
             ParsingErrorReason::UnusedSetting(name) => {
-                write!(
-                    f,
-                    "In file {:?}: unused setting {} occured",
-                    self.path, name
-                )?;
+                write!(f, "In file {}: unused setting {} occured", location, name)?;
             }
             ParsingErrorReason::MissingSetting(name) => {
-                write!(
-                    f,
-                    "In file {:?}: required setting {} missing",
-                    self.path, name
-                )?;
+                write!(f, "In file {}: required setting {} missing", location, name)?;
             }
             ParsingErrorReason::SectionNotFound(name) => {
                 write!(
                     f,
-                    "In file {:?}: Section {} wasn't found but is required",
-                    self.path, name
+                    "In file {}: Section {} wasn't found but is required",
+                    location, name
                 )?;
             }
             ParsingErrorReason::UnknownSection(name) => {
-                write!(f, "In file {:?}: Section {} is unknown", self.path, name)?;
+                write!(f, "In file {}: Section {} is unknown", location, name)?;
             }
             ParsingErrorReason::SectionTooOften(name) => {
                 write!(
                     f,
-                    "In file {:?}: section {} occured multiple times",
-                    self.path, name
+                    "In file {}: section {} occured multiple times",
+                    location, name
                 )?;
             }
             ParsingErrorReason::UnknownSocketAddr(addr) => {
                 write!(
                     f,
-                    "In file {:?}: Can not open sockets of addr: {}",
-                    self.path, addr
+                    "In file {}: Can not open sockets of addr: {}",
+                    location, addr
                 )?;
             }
             ParsingErrorReason::UnsupportedSetting(addr) => {
                 write!(
                     f,
-                    "In file {:?}: Setting not supported by this build (maybe need to enable feature flag?): {}",
-                    self.path, addr
+                    "In file {}: Setting not supported by this build (maybe need to enable feature flag?): {}",
+                    location, addr
                 )?;
             }
             ParsingErrorReason::SettingTooManyValues(name, values) => {
                 write!(
                     f,
-                    "In file {:?}: setting {} occured with too many values: {:?}",
-                    self.path, name, values
+                    "In file {}: setting {} occured with too many values: {:?}",
+                    location, name, values
                 )?;
             }
             ParsingErrorReason::FileError(e) => {
-                write!(f, "While parsing file {:?}: {}", self.path, e)?;
+                write!(f, "While parsing file {}: {}", location, e)?;
             }
             ParsingErrorReason::Generic(e) => {
-                write!(f, "While parsing file {:?}: {}", self.path, e)?;
+                write!(f, "While parsing file {}: {}", location, e)?;
             }
         }
 
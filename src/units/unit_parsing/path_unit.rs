@@ -0,0 +1,134 @@
+use crate::path_activation::PathWatch;
+use crate::units::*;
+use std::path::PathBuf;
+
+pub fn parse_path(
+    parsed_file: ParsedFile,
+    path: &PathBuf,
+    chosen_id: UnitId,
+) -> Result<Unit, ParsingErrorReason> {
+    let mut path_config = None;
+    let mut install_config = None;
+    let mut unit_config = None;
+
+    for (name, section) in parsed_file {
+        match name.as_str() {
+            "[Path]" => {
+                path_config = Some(parse_path_section(section)?);
+            }
+            "[Unit]" => {
+                unit_config = Some(parse_unit_section(section, path)?);
+            }
+            "[Install]" => {
+                install_config = Some(parse_install_section(section)?);
+            }
+
+            _ => return Err(ParsingErrorReason::UnknownSection(name.to_owned())),
+        }
+    }
+
+    let mut path_config = match path_config {
+        Some(conf) => conf,
+        None => return Err(ParsingErrorReason::SectionNotFound("Path".to_owned())),
+    };
+
+    let conf = match unit_config {
+        Some(conf) => conf,
+        None => return Err(ParsingErrorReason::SectionNotFound("Unit".to_owned())),
+    };
+
+    if path_config.unit.is_empty() {
+        let name = conf.name();
+        let name_without_suffix = name.trim_end_matches(".path");
+        path_config.unit = format!("{}.service", name_without_suffix);
+    }
+
+    Ok(Unit {
+        conf,
+        id: chosen_id,
+        install: Install {
+            install_config,
+            wants: Vec::new(),
+            wanted_by: Vec::new(),
+            requires: Vec::new(),
+            required_by: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            conflicts: Vec::new(),
+            binds_to: Vec::new(),
+            bound_by: Vec::new(),
+            part_of: Vec::new(),
+            consists_of: Vec::new(),
+            requisite: Vec::new(),
+            requisite_of: Vec::new(),
+        },
+        triggered_handlers: Vec::new(),
+        specialized: UnitSpecialized::Path(PathWatch::new(path_config)),
+    })
+}
+
+fn parse_path_section(mut section: ParsedSection) -> Result<PathConfig, ParsingErrorReason> {
+    let exists = section.remove("PATHEXISTS");
+    let exists_glob = section.remove("PATHEXISTSGLOB");
+    let changed = section.remove("PATHCHANGED");
+    let modified = section.remove("PATHMODIFIED");
+    let not_empty = section.remove("DIRECTORYNOTEMPTY");
+    let unit = section.remove("UNIT");
+
+    if !section.is_empty() {
+        return Err(ParsingErrorReason::UnusedSetting(
+            section.keys().next().unwrap().to_owned(),
+        ));
+    }
+
+    // keep the order of the unit file, an empty assignment removes all conditions before it
+    let mut settings = Vec::new();
+    for (values, make_condition) in [
+        (
+            exists,
+            (|p: String| PathCondition::Exists(p.into())) as fn(String) -> PathCondition,
+        ),
+        (exists_glob, PathCondition::ExistsGlob),
+        (changed, |p| PathCondition::Changed(p.into())),
+        (modified, |p| PathCondition::Modified(p.into())),
+        (not_empty, |p| PathCondition::DirectoryNotEmpty(p.into())),
+    ] {
+        for (line, value) in values.unwrap_or_default() {
+            settings.push((line, value, make_condition));
+        }
+    }
+    settings.sort_by_key(|(line, _, _)| *line);
+
+    let mut conditions = Vec::new();
+    for (_, value, make_condition) in settings {
+        let value = value.trim().to_owned();
+        if value.is_empty() {
+            conditions.clear();
+        } else {
+            conditions.push(make_condition(value));
+        }
+    }
+
+    let unit = match unit {
+        Some(vec) => {
+            if vec.len() == 1 {
+                vec[0].1.clone()
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "Unit".to_owned(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+        // filled in with the name of the path unit later
+        None => String::new(),
+    };
+
+    if conditions.is_empty() {
+        return Err(ParsingErrorReason::MissingSetting(
+            "PathExists, PathExistsGlob, PathChanged, PathModified or DirectoryNotEmpty".to_owned(),
+        ));
+    }
+
+    Ok(PathConfig { conditions, unit })
+}
@@ -1,4 +1,4 @@
-use crate::services::{Service, ServiceRuntimeInfo};
+use crate::services::{FdStoreRequest, Service, ServiceRuntimeInfo};
 use crate::units::*;
 use std::path::PathBuf;
 
@@ -126,6 +126,23 @@ pub fn parse_service(
             requires: Vec::new(),
             before: Vec::new(),
             after: Vec::new(),
+            conflicts: Vec::new(),
+            binds_to: Vec::new(),
+            part_of: Vec::new(),
+            requisite: Vec::new(),
+            on_failure: Vec::new(),
+            on_success: Vec::new(),
+            conditions: Vec::new(),
+            asserts: Vec::new(),
+
+            start_limit_interval: None,
+            start_limit_burst: None,
+
+            dropins: Vec::new(),
+
+            shadowed: Vec::new(),
+
+            aliases: Vec::new(),
         }),
         install: Install {
             wants: Vec::new(),
@@ -134,14 +151,29 @@ pub fn parse_service(
             required_by: Vec::new(),
             before: Vec::new(),
             after: Vec::new(),
+            conflicts: Vec::new(),
+            binds_to: Vec::new(),
+            bound_by: Vec::new(),
+            part_of: Vec::new(),
+            consists_of: Vec::new(),
+            requisite: Vec::new(),
+            requisite_of: Vec::new(),
             install_config,
         },
+        triggered_handlers: Vec::new(),
         specialized: UnitSpecialized::Service(Service {
             supp_gids,
             uid,
             gid,
             pid: None,
             signaled_ready: false,
+            watchdog_timeout: None,
+            watchdog_last_ping: None,
+            watchdog_triggered: false,
+            timeout_triggered: false,
+            notified_errno: None,
+            fd_store_request: FdStoreRequest::default(),
+            environment: Vec::new(),
 
             service_config,
             socket_names: Vec::new(),
@@ -153,13 +185,13 @@ pub fn parse_service(
             runtime_info: ServiceRuntimeInfo {
                 restarted: 0,
                 up_since: None,
+                restart_times: Vec::new(),
             },
 
             notifications: None,
             notifications_path: None,
             stdout_dup: None,
             stderr_dup: None,
-            notifications_buffer: String::new(),
             stdout_buffer: Vec::new(),
             stderr_buffer: Vec::new(),
 
@@ -168,49 +200,28 @@ pub fn parse_service(
     })
 }
 
-fn parse_timeout(descr: &str) -> Timeout {
-    if descr.to_uppercase() == "INFINITY" {
-        Timeout::Infinity
-    } else {
-        match descr.parse::<u64>() {
-            Ok(secs) => Timeout::Duration(std::time::Duration::from_secs(secs)),
-            Err(_) => {
-                let mut sum = 0;
-                let split = descr.split(' ').collect::<Vec<_>>();
-                for t in &split {
-                    if t.ends_with("min") {
-                        let mins = t[0..t.len() - 3].parse::<u64>().unwrap();
-                        sum += mins * 60;
-                    } else if t.ends_with("hrs") {
-                        let hrs = t[0..t.len() - 3].parse::<u64>().unwrap();
-                        sum += hrs * 60 * 60;
-                    } else if t.ends_with("s") {
-                        let secs = t[0..t.len() - 1].parse::<u64>().unwrap();
-                        sum += secs;
-                    }
-                }
-                Timeout::Duration(std::time::Duration::from_secs(sum))
-            }
-        }
-    }
-}
-
 fn parse_service_section(mut section: ParsedSection) -> Result<ServiceConfig, ParsingErrorReason> {
     let exec = section.remove("EXECSTART");
     let stop = section.remove("EXECSTOP");
+    let reload = section.remove("EXECRELOAD");
     let stoppost = section.remove("EXECSTOPPOST");
     let startpre = section.remove("EXECSTARTPRE");
     let startpost = section.remove("EXECSTARTPOST");
     let starttimeout = section.remove("TIMEOUTSTARTSEC");
     let stoptimeout = section.remove("TIMEOUTSTOPSEC");
+    let reloadtimeout = section.remove("TIMEOUTRELOADSEC");
     let generaltimeout = section.remove("TIMEOUTSEC");
 
     let restart = section.remove("RESTART");
+    let restart_sec = section.remove("RESTARTSEC");
+    let watchdog_sec = section.remove("WATCHDOGSEC");
+    let fd_store_max = section.remove("FILEDESCRIPTORSTOREMAX");
     let sockets = section.remove("SOCKETS");
     let notify_access = section.remove("NOTIFYACCESS");
     let srcv_type = section.remove("TYPE");
-    let accept = section.remove("ACCEPT");
     let dbus_name = section.remove("BUSNAME");
+    let pid_file = section.remove("PIDFILE");
+    let remain_after_exit = section.remove("REMAINAFTEREXIT");
 
     let exec_config = super::parse_exec_section(&mut section)?;
 
@@ -223,7 +234,7 @@ fn parse_service_section(mut section: ParsedSection) -> Result<ServiceConfig, Pa
     let starttimeout = match starttimeout {
         Some(vec) => {
             if vec.len() == 1 {
-                Some(parse_timeout(&vec[0].1))
+                Some(parse_timeout("TimeoutStartSec", &vec[0].1)?)
             } else {
                 return Err(ParsingErrorReason::SettingTooManyValues(
                     "TimeoutStartSec".to_owned(),
@@ -236,7 +247,7 @@ fn parse_service_section(mut section: ParsedSection) -> Result<ServiceConfig, Pa
     let stoptimeout = match stoptimeout {
         Some(vec) => {
             if vec.len() == 1 {
-                Some(parse_timeout(&vec[0].1))
+                Some(parse_timeout("TimeoutStopSec", &vec[0].1)?)
             } else {
                 return Err(ParsingErrorReason::SettingTooManyValues(
                     "TimeoutStopSec".to_owned(),
@@ -246,34 +257,35 @@ fn parse_service_section(mut section: ParsedSection) -> Result<ServiceConfig, Pa
         }
         None => None,
     };
-    let generaltimeout = match generaltimeout {
+    let reloadtimeout = match reloadtimeout {
         Some(vec) => {
             if vec.len() == 1 {
-                Some(parse_timeout(&vec[0].1))
+                Some(parse_timeout("TimeoutReloadSec", &vec[0].1)?)
             } else {
                 return Err(ParsingErrorReason::SettingTooManyValues(
-                    "TimeoutSec".to_owned(),
+                    "TimeoutReloadSec".to_owned(),
                     super::map_tupels_to_second(vec),
                 ));
             }
         }
         None => None,
     };
-
-    let exec = match exec {
-        Some(mut vec) => {
+    let generaltimeout = match generaltimeout {
+        Some(vec) => {
             if vec.len() == 1 {
-                vec.remove(0).1
+                Some(parse_timeout("TimeoutSec", &vec[0].1)?)
             } else {
                 return Err(ParsingErrorReason::SettingTooManyValues(
-                    "ExecStart".to_owned(),
+                    "TimeoutSec".to_owned(),
                     super::map_tupels_to_second(vec),
                 ));
             }
         }
-        None => "".to_string(),
+        None => None,
     };
 
+    let exec = parse_cmdlines_setting("ExecStart", exec)?;
+
     let srcv_type = match srcv_type {
         Some(vec) => {
             if vec.len() == 1 {
@@ -281,6 +293,9 @@ fn parse_service_section(mut section: ParsedSection) -> Result<ServiceConfig, Pa
                     "simple" => ServiceType::Simple,
                     "notify" => ServiceType::Notify,
                     "oneshot" => ServiceType::OneShot,
+                    "forking" => ServiceType::Forking,
+                    "exec" => ServiceType::Exec,
+                    "idle" => ServiceType::Idle,
                     "dbus" => {
                         if cfg!(feature = "dbus_support") {
                             ServiceType::Dbus
@@ -334,22 +349,11 @@ fn parse_service_section(mut section: ParsedSection) -> Result<ServiceConfig, Pa
         None => NotifyKind::Main,
     };
 
-    let stop = match stop {
-        Some(vec) => map_tupels_to_second(vec),
-        None => Vec::new(),
-    };
-    let stoppost = match stoppost {
-        Some(vec) => map_tupels_to_second(vec),
-        None => Vec::new(),
-    };
-    let startpre = match startpre {
-        Some(vec) => map_tupels_to_second(vec),
-        None => Vec::new(),
-    };
-    let startpost = match startpost {
-        Some(vec) => map_tupels_to_second(vec),
-        None => Vec::new(),
-    };
+    let stop = parse_cmdlines_setting("ExecStop", stop)?;
+    let reload = parse_cmdlines_setting("ExecReload", reload)?;
+    let stoppost = parse_cmdlines_setting("ExecStopPost", stoppost)?;
+    let startpre = parse_cmdlines_setting("ExecStartPre", startpre)?;
+    let startpost = parse_cmdlines_setting("ExecStartPost", startpost)?;
 
     let restart = match restart {
         Some(vec) => {
@@ -357,6 +361,11 @@ fn parse_service_section(mut section: ParsedSection) -> Result<ServiceConfig, Pa
                 match vec[0].1.to_uppercase().as_str() {
                     "ALWAYS" => ServiceRestart::Always,
                     "NO" => ServiceRestart::No,
+                    "ON-SUCCESS" => ServiceRestart::OnSuccess,
+                    "ON-FAILURE" => ServiceRestart::OnFailure,
+                    "ON-ABNORMAL" => ServiceRestart::OnAbnormal,
+                    "ON-ABORT" => ServiceRestart::OnAbort,
+                    "ON-WATCHDOG" => ServiceRestart::OnWatchdog,
 
                     name => {
                         return Err(ParsingErrorReason::UnknownSetting(
@@ -374,18 +383,52 @@ fn parse_service_section(mut section: ParsedSection) -> Result<ServiceConfig, Pa
         }
         None => ServiceRestart::No,
     };
-    let accept = match accept {
+    let restart_sec = match restart_sec {
         Some(vec) => {
             if vec.len() == 1 {
-                string_to_bool(&vec[0].1)
+                Some(parse_timeout("RestartSec", &vec[0].1)?)
             } else {
                 return Err(ParsingErrorReason::SettingTooManyValues(
-                    "Accept".to_owned(),
+                    "RestartSec".to_owned(),
                     super::map_tupels_to_second(vec),
                 ));
             }
         }
-        None => false,
+        None => None,
+    };
+    let watchdog_sec = match watchdog_sec {
+        Some(vec) => {
+            if vec.len() == 1 {
+                Some(parse_timeout("WatchdogSec", &vec[0].1)?)
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "WatchdogSec".to_owned(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+        None => None,
+    };
+    let fd_store_max = match fd_store_max {
+        Some(vec) => {
+            if vec.len() == 1 {
+                match vec[0].1.parse::<u64>() {
+                    Ok(max) => max,
+                    Err(_) => {
+                        return Err(ParsingErrorReason::UnknownSetting(
+                            "FileDescriptorStoreMax".to_owned(),
+                            vec[0].1.clone(),
+                        ))
+                    }
+                }
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "FileDescriptorStoreMax".to_owned(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+        None => 0,
     };
     let dbus_name = match dbus_name {
         Some(vec) => {
@@ -401,6 +444,43 @@ fn parse_service_section(mut section: ParsedSection) -> Result<ServiceConfig, Pa
         None => None,
     };
 
+    let pid_file = match pid_file {
+        Some(vec) => {
+            if vec.len() == 1 {
+                // like systemd relative paths are relative to /run
+                Some(std::path::Path::new("/run").join(&vec[0].1))
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "PIDFile".to_owned(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+        None => None,
+    };
+
+    let remain_after_exit = match remain_after_exit {
+        Some(vec) => {
+            if vec.len() == 1 {
+                string_to_bool(&vec[0].1)
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "RemainAfterExit".to_owned(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+        None => false,
+    };
+
+    // only oneshot services may run multiple commands one after the other
+    if exec.len() > 1 && srcv_type != ServiceType::OneShot {
+        return Err(ParsingErrorReason::SettingTooManyValues(
+            "ExecStart".to_owned(),
+            exec.iter().map(|cmd| cmd.to_string()).collect(),
+        ));
+    }
+
     if let ServiceType::Dbus = srcv_type {
         if dbus_name.is_none() {
             return Err(ParsingErrorReason::MissingSetting("BusName".to_owned()));
@@ -412,16 +492,22 @@ fn parse_service_section(mut section: ParsedSection) -> Result<ServiceConfig, Pa
         srcv_type,
         notifyaccess,
         restart,
-        accept,
+        restart_sec,
+        watchdog_sec,
+        fd_store_max,
         dbus_name,
+        pid_file,
+        remain_after_exit,
         exec,
         stop,
+        reload,
         stoppost,
         startpre,
         startpost,
         starttimeout,
         stoptimeout,
+        reloadtimeout,
         generaltimeout,
-        sockets: map_tupels_to_second(sockets.unwrap_or_default()),
+        sockets: split_list_values(sockets.unwrap_or_default()),
     })
 }
@@ -32,8 +32,8 @@ pub fn parse_socket(
         }
     }
 
-    let (sock_name, services, sock_configs) = match socket_configs {
-        Some(triple) => triple,
+    let (sock_name, services, sock_configs, accept) = match socket_configs {
+        Some(settings) => settings,
         None => return Err(ParsingErrorReason::SectionNotFound("Socket".to_owned())),
     };
 
@@ -55,13 +55,24 @@ pub fn parse_socket(
             required_by: Vec::new(),
             before: Vec::new(),
             after: Vec::new(),
+            conflicts: Vec::new(),
+            binds_to: Vec::new(),
+            bound_by: Vec::new(),
+            part_of: Vec::new(),
+            consists_of: Vec::new(),
+            requisite: Vec::new(),
+            requisite_of: Vec::new(),
         },
+        triggered_handlers: Vec::new(),
         specialized: UnitSpecialized::Socket(Socket {
             activated: false,
             name: sock_name,
             sockets: sock_configs,
             services,
             exec_config,
+            accept,
+            connections: Vec::new(),
+            accepted: 0,
         }),
     })
 }
@@ -84,10 +95,52 @@ fn parse_unix_addr(addr: &str) -> Result<String, ()> {
     }
 }
 
-fn parse_socket_section(
-    mut section: ParsedSection,
-) -> Result<(String, Vec<String>, Vec<SocketConfig>), ParsingErrorReason> {
+fn parse_single_value(
+    setting: &str,
+    values: Option<Vec<(EntryPosition, String)>>,
+) -> Result<Option<String>, ParsingErrorReason> {
+    match values {
+        Some(vec) => {
+            if vec.len() == 1 {
+                Ok(Some(vec[0].1.clone()))
+            } else {
+                Err(ParsingErrorReason::SettingTooManyValues(
+                    setting.to_owned(),
+                    super::map_tupels_to_second(vec),
+                ))
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+fn parse_connection_limit(
+    setting: &str,
+    values: Option<Vec<(EntryPosition, String)>>,
+) -> Result<Option<usize>, ParsingErrorReason> {
+    match parse_single_value(setting, values)? {
+        Some(value) => match value.parse::<usize>() {
+            Ok(limit) if limit > 0 => Ok(Some(limit)),
+            _ => Err(ParsingErrorReason::UnknownSetting(
+                setting.to_owned(),
+                value,
+            )),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Same default as systemd uses
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// FileDescriptorName=, Service=, the sockets to listen on and the settings for Accept=yes
+type SocketSettings = (String, Vec<String>, Vec<SocketConfig>, Option<AcceptConfig>);
+
+fn parse_socket_section(mut section: ParsedSection) -> Result<SocketSettings, ParsingErrorReason> {
     let fdname = section.remove("FILEDESCRIPTORNAME");
+    let accept = section.remove("ACCEPT");
+    let max_connections = section.remove("MAXCONNECTIONS");
+    let max_connections_per_source = section.remove("MAXCONNECTIONSPERSOURCE");
     let services = section.remove("SERVICE");
     let streams = section.remove("LISTENSTREAM");
     let datagrams = section.remove("LISTENDATAGRAM");
@@ -118,10 +171,33 @@ fn parse_socket_section(
     let fdname = fdname.unwrap_or("unknown".into());
 
     let services = services
-        .map(|vec| super::map_tupels_to_second(vec))
+        .map(|vec| super::split_list_values(vec))
         .unwrap_or_default();
 
-    let mut socket_kinds: Vec<(u32, SocketKind)> = Vec::new();
+    let accept = parse_single_value("Accept", accept)?
+        .map(|value| string_to_bool(&value))
+        .unwrap_or(false);
+    let max_connections = parse_connection_limit("MaxConnections", max_connections)?
+        .unwrap_or(DEFAULT_MAX_CONNECTIONS);
+    let max_connections_per_source =
+        parse_connection_limit("MaxConnectionsPerSource", max_connections_per_source)?
+            .unwrap_or(max_connections);
+    let accept = if accept {
+        // the connections are handled by instances of the template service named like the socket
+        if !services.is_empty() {
+            return Err(ParsingErrorReason::Generic(
+                "Service= can not be used together with Accept=yes".to_owned(),
+            ));
+        }
+        Some(AcceptConfig {
+            max_connections,
+            max_connections_per_source,
+        })
+    } else {
+        None
+    };
+
+    let mut socket_kinds: Vec<(EntryPosition, SocketKind)> = Vec::new();
     if let Some(mut streams) = streams {
         for _ in 0..streams.len() {
             let (entry_num, value) = streams.remove(0);
@@ -147,9 +223,23 @@ fn parse_socket_section(
         }
     }
 
-    // we need to preserve the original ordering
-    socket_kinds.sort_by(|l, r| u32::cmp(&l.0, &r.0));
-    let socket_kinds: Vec<SocketKind> = socket_kinds.drain(..).map(|(_, kind)| kind).collect();
+    // we need to preserve the original ordering. An empty value resets all sockets listed before it
+    socket_kinds.sort_by_key(|(position, _)| *position);
+    let mut kinds = Vec::new();
+    for (_, kind) in socket_kinds {
+        let addr = match &kind {
+            SocketKind::Stream(addr)
+            | SocketKind::Datagram(addr)
+            | SocketKind::Sequential(addr)
+            | SocketKind::Fifo(addr) => addr,
+        };
+        if addr.is_empty() {
+            kinds.clear();
+        } else {
+            kinds.push(kind);
+        }
+    }
+    let socket_kinds = kinds;
 
     let mut socket_configs = Vec::new();
 
@@ -203,8 +293,22 @@ fn parse_socket_section(
             }
         };
 
+        if accept.is_some() {
+            match &specialized {
+                SpecializedSocketConfig::TcpSocket(_)
+                | SpecializedSocketConfig::UnixSocket(UnixSocketConfig::Stream(_))
+                | SpecializedSocketConfig::UnixSocket(UnixSocketConfig::Sequential(_)) => {}
+                _ => {
+                    return Err(ParsingErrorReason::Generic(format!(
+                        "Accept=yes only works with stream and sequential packet sockets, not with: {:?}",
+                        kind
+                    )))
+                }
+            }
+        }
+
         socket_configs.push(SocketConfig { kind, specialized });
     }
 
-    Ok((fdname, services, socket_configs))
+    Ok((fdname, services, socket_configs, accept))
 }
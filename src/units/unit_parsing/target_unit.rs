@@ -39,7 +39,15 @@ pub fn parse_target(
             required_by: Vec::new(),
             before: Vec::new(),
             after: Vec::new(),
+            conflicts: Vec::new(),
+            binds_to: Vec::new(),
+            bound_by: Vec::new(),
+            part_of: Vec::new(),
+            consists_of: Vec::new(),
+            requisite: Vec::new(),
+            requisite_of: Vec::new(),
         },
+        triggered_handlers: Vec::new(),
         specialized: UnitSpecialized::Target,
     })
 }
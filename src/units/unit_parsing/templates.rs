@@ -0,0 +1,184 @@
+//! Template units like getty@.service are instantiated for each instance name that is referenced (getty@tty1.service).
+//! Specifiers like %i are expanded in all settings of all units, see systemd.unit(5)
+
+use crate::units::*;
+
+/// Split "getty@tty1.service" into ("getty", "tty1", ".service"). Templates have an empty instance.
+pub fn split_instance_name(name: &str) -> Option<(&str, &str, &str)> {
+    let at = name.find('@')?;
+    let dot = name.rfind('.')?;
+    if dot < at {
+        return None;
+    }
+    Some((&name[..at], &name[at + 1..dot], &name[dot..]))
+}
+
+pub fn is_template_name(name: &str) -> bool {
+    match split_instance_name(name) {
+        Some((_, instance, _)) => instance.is_empty(),
+        None => false,
+    }
+}
+
+/// The name of the template this instance is created from: getty@tty1.service -> getty@.service
+pub fn template_name(name: &str) -> Option<String> {
+    match split_instance_name(name) {
+        Some((prefix, instance, suffix)) if !instance.is_empty() => {
+            Some(format!("{}@{}", prefix, suffix))
+        }
+        _ => None,
+    }
+}
+
+/// Reverse the escaping done by systemd-escape: "-" is a "/" and "\xNN" is the byte NN
+pub fn unescape_instance(instance: &str) -> String {
+    let mut bytes = Vec::new();
+    let raw = instance.as_bytes();
+    let mut idx = 0;
+    while idx < raw.len() {
+        if raw[idx] == b'-' {
+            bytes.push(b'/');
+            idx += 1;
+            continue;
+        }
+        if raw[idx] == b'\\' && idx + 3 < raw.len() && raw[idx + 1] == b'x' {
+            // the two bytes after \x are not necessarily on a char boundary
+            let hex = std::str::from_utf8(&raw[idx + 2..idx + 4]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                bytes.push(byte);
+                idx += 4;
+                continue;
+            }
+        }
+        bytes.push(raw[idx]);
+        idx += 1;
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// The user a unit runs as (User= or the user running rustysd) and it's home directory
+fn unit_user(parsed_file: &ParsedFile) -> Result<(String, String), ParsingErrorReason> {
+    let configured_user = ["[Service]", "[Socket]"]
+        .iter()
+        .filter_map(|section| parsed_file.get(*section))
+        .filter_map(|section| section.get("USER"))
+        .filter_map(|values| values.last())
+        .map(|(_, user)| user.clone())
+        .next();
+
+    match configured_user {
+        Some(user) => {
+            let entry = if let Ok(uid) = user.parse::<u32>() {
+                crate::platform::pwnam::getpwuid_r(nix::unistd::Uid::from_raw(uid))
+            } else {
+                crate::platform::pwnam::getpwnam_r(&user)
+            }
+            .map_err(ParsingErrorReason::Generic)?;
+            let home = entry
+                .home
+                .map(|home| home.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            Ok((entry.name, home))
+        }
+        None => {
+            let uid = nix::unistd::getuid();
+            match crate::platform::pwnam::getpwuid_r(uid) {
+                Ok(entry) => {
+                    let home = entry
+                        .home
+                        .map(|home| home.to_string_lossy().into_owned())
+                        .unwrap_or_default();
+                    Ok((entry.name, home))
+                }
+                Err(_) => Ok((
+                    std::env::var("USER").unwrap_or_else(|_| uid.to_string()),
+                    std::env::var("HOME").unwrap_or_default(),
+                )),
+            }
+        }
+    }
+}
+
+fn runtime_dir() -> String {
+    let uid = nix::unistd::getuid();
+    if uid.is_root() {
+        "/run".to_owned()
+    } else {
+        std::env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| format!("/run/user/{}", uid))
+    }
+}
+
+fn expand_value(value: &str, specifiers: &dyn Fn(char) -> Option<String>) -> String {
+    let mut expanded = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            expanded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => expanded.push('%'),
+            Some(spec) => match specifiers(spec) {
+                Some(replacement) => expanded.push_str(&replacement),
+                // unknown specifiers are kept as they are
+                None => {
+                    expanded.push('%');
+                    expanded.push(spec);
+                }
+            },
+            None => expanded.push('%'),
+        }
+    }
+    expanded
+}
+
+/// Expand %n, %N, %p, %i, %I, %u, %h, %t and %% in all settings of the unit with this name
+pub fn expand_specifiers(
+    parsed_file: &mut ParsedFile,
+    unit_name: &str,
+) -> Result<(), ParsingErrorReason> {
+    let name_without_suffix = match unit_name.rfind('.') {
+        Some(dot) => &unit_name[..dot],
+        None => unit_name,
+    };
+    let (prefix, instance) = match split_instance_name(unit_name) {
+        Some((prefix, instance, _)) => (prefix, instance),
+        None => (name_without_suffix, ""),
+    };
+
+    let uses_user = parsed_file.values().any(|section| {
+        section
+            .values()
+            .flatten()
+            .any(|(_, value)| value.contains("%u") || value.contains("%h"))
+    });
+    let (user, home) = if uses_user {
+        unit_user(parsed_file)?
+    } else {
+        (String::new(), String::new())
+    };
+    let runtime_dir = runtime_dir();
+
+    let specifiers = |spec: char| match spec {
+        'n' => Some(unit_name.to_owned()),
+        'N' => Some(name_without_suffix.to_owned()),
+        'p' => Some(prefix.to_owned()),
+        'i' => Some(instance.to_owned()),
+        'I' => Some(unescape_instance(instance)),
+        'u' => Some(user.clone()),
+        'h' => Some(home.clone()),
+        't' => Some(runtime_dir.clone()),
+        _ => None,
+    };
+
+    for section in parsed_file.values_mut() {
+        for values in section.values_mut() {
+            for (_, value) in values.iter_mut() {
+                if value.contains('%') {
+                    *value = expand_value(value, &specifiers);
+                }
+            }
+        }
+    }
+    Ok(())
+}
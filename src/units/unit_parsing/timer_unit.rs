@@ -0,0 +1,377 @@
+use crate::timers::{CalendarComponent, CalendarRange, CalendarSpec, Timer};
+use crate::units::*;
+use std::path::PathBuf;
+
+pub fn parse_timer(
+    parsed_file: ParsedFile,
+    path: &PathBuf,
+    chosen_id: UnitId,
+) -> Result<Unit, ParsingErrorReason> {
+    let mut timer_config = None;
+    let mut install_config = None;
+    let mut unit_config = None;
+
+    for (name, section) in parsed_file {
+        match name.as_str() {
+            "[Timer]" => {
+                timer_config = Some(parse_timer_section(section)?);
+            }
+            "[Unit]" => {
+                unit_config = Some(parse_unit_section(section, path)?);
+            }
+            "[Install]" => {
+                install_config = Some(parse_install_section(section)?);
+            }
+
+            _ => return Err(ParsingErrorReason::UnknownSection(name.to_owned())),
+        }
+    }
+
+    let mut timer_config = match timer_config {
+        Some(conf) => conf,
+        None => return Err(ParsingErrorReason::SectionNotFound("Timer".to_owned())),
+    };
+
+    let conf = match unit_config {
+        Some(conf) => conf,
+        None => return Err(ParsingErrorReason::SectionNotFound("Unit".to_owned())),
+    };
+
+    if timer_config.unit.is_empty() {
+        let name = conf.name();
+        let name_without_suffix = name.trim_end_matches(".timer");
+        timer_config.unit = format!("{}.service", name_without_suffix);
+    }
+
+    Ok(Unit {
+        conf,
+        id: chosen_id,
+        install: Install {
+            install_config,
+            wants: Vec::new(),
+            wanted_by: Vec::new(),
+            requires: Vec::new(),
+            required_by: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            conflicts: Vec::new(),
+            binds_to: Vec::new(),
+            bound_by: Vec::new(),
+            part_of: Vec::new(),
+            consists_of: Vec::new(),
+            requisite: Vec::new(),
+            requisite_of: Vec::new(),
+        },
+        triggered_handlers: Vec::new(),
+        specialized: UnitSpecialized::Timer(Timer::new(timer_config)),
+    })
+}
+
+/// An empty assignment resets the list, like in systemd
+fn parse_timespan_list(
+    setting: &str,
+    values: Option<Vec<(EntryPosition, String)>>,
+) -> Result<Vec<std::time::Duration>, ParsingErrorReason> {
+    let mut spans = Vec::new();
+    for (_, value) in values.unwrap_or_default() {
+        if value.trim().is_empty() {
+            spans.clear();
+            continue;
+        }
+        spans.push(
+            parse_timespan(&value)
+                .map_err(|_| ParsingErrorReason::UnknownSetting(setting.to_owned(), value))?,
+        );
+    }
+    Ok(spans)
+}
+
+fn parse_timer_section(mut section: ParsedSection) -> Result<TimerConfig, ParsingErrorReason> {
+    let on_active = section.remove("ONACTIVESEC");
+    let on_boot = section.remove("ONBOOTSEC");
+    let on_startup = section.remove("ONSTARTUPSEC");
+    let on_unit_active = section.remove("ONUNITACTIVESEC");
+    let on_unit_inactive = section.remove("ONUNITINACTIVESEC");
+    let on_calendar = section.remove("ONCALENDAR");
+    let persistent = section.remove("PERSISTENT");
+    let unit = section.remove("UNIT");
+
+    if !section.is_empty() {
+        return Err(ParsingErrorReason::UnusedSetting(
+            section.keys().next().unwrap().to_owned(),
+        ));
+    }
+
+    let on_active = parse_timespan_list("OnActiveSec", on_active)?;
+    // rustysd starts with the system, so there is no difference between these two
+    let mut on_boot = parse_timespan_list("OnBootSec", on_boot)?;
+    on_boot.extend(parse_timespan_list("OnStartupSec", on_startup)?);
+    let on_unit_active = parse_timespan_list("OnUnitActiveSec", on_unit_active)?;
+    let on_unit_inactive = parse_timespan_list("OnUnitInactiveSec", on_unit_inactive)?;
+
+    let mut calendar = Vec::new();
+    for (_, value) in on_calendar.unwrap_or_default() {
+        if value.trim().is_empty() {
+            calendar.clear();
+            continue;
+        }
+        calendar.push(
+            parse_calendar(&value)
+                .map_err(|_| ParsingErrorReason::UnknownSetting("OnCalendar".to_owned(), value))?,
+        );
+    }
+
+    let persistent = match persistent {
+        Some(vec) => {
+            if vec.len() == 1 {
+                string_to_bool(&vec[0].1)
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "Persistent".to_owned(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+        None => false,
+    };
+
+    let unit = match unit {
+        Some(vec) => {
+            if vec.len() == 1 {
+                vec[0].1.clone()
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "Unit".to_owned(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+        // filled in with the name of the timer later
+        None => String::new(),
+    };
+
+    if on_active.is_empty()
+        && on_boot.is_empty()
+        && on_unit_active.is_empty()
+        && on_unit_inactive.is_empty()
+        && calendar.is_empty()
+    {
+        return Err(ParsingErrorReason::MissingSetting(
+            "OnActiveSec, OnBootSec, OnUnitActiveSec, OnUnitInactiveSec or OnCalendar".to_owned(),
+        ));
+    }
+
+    Ok(TimerConfig {
+        on_active,
+        on_boot,
+        on_unit_active,
+        on_unit_inactive,
+        on_calendar: calendar,
+        persistent,
+        unit,
+    })
+}
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+fn parse_weekday(name: &str) -> Result<u8, String> {
+    let lower = name.to_lowercase();
+    WEEKDAYS
+        .iter()
+        .position(|day| lower.len() >= 3 && lower.starts_with(day))
+        .map(|idx| idx as u8)
+        .ok_or_else(|| format!("Unknown weekday: {}", name))
+}
+
+/// Weekday lists like "Mon,Wed..Fri" as bitmask. Ranges may wrap around the end of the week ("Sat..Mon")
+fn parse_weekdays(descr: &str) -> Result<u8, String> {
+    let mut mask = 0u8;
+    for part in descr.split(',').filter(|part| !part.is_empty()) {
+        let (start, end) = match part.find("..") {
+            Some(idx) => (
+                parse_weekday(&part[..idx])?,
+                parse_weekday(&part[idx + 2..])?,
+            ),
+            None => {
+                let day = parse_weekday(part)?;
+                (day, day)
+            }
+        };
+        let mut day = start;
+        loop {
+            mask |= 1 << day;
+            if day == end {
+                break;
+            }
+            day = (day + 1) % 7;
+        }
+    }
+    Ok(mask)
+}
+
+fn parse_calendar_value(value: &str, min: u32, max: u32) -> Result<u32, String> {
+    let parsed = value
+        .parse::<u32>()
+        .map_err(|_| format!("Invalid value in calendar expression: {}", value))?;
+    if parsed < min || parsed > max {
+        return Err(format!(
+            "Value {} in calendar expression is not between {} and {}",
+            parsed, min, max
+        ));
+    }
+    Ok(parsed)
+}
+
+/// One component like "*", "1,15", "8..18/2" or "*/15"
+fn parse_calendar_component(descr: &str, min: u32, max: u32) -> Result<CalendarComponent, String> {
+    if descr == "*" {
+        return Ok(CalendarComponent::default());
+    }
+    let mut ranges = Vec::new();
+    for part in descr.split(',') {
+        let (range, step) = match part.find('/') {
+            Some(idx) => {
+                let step = parse_calendar_value(&part[idx + 1..], 1, max.max(1))?;
+                (&part[..idx], step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else {
+            match range.find("..") {
+                Some(idx) => (
+                    parse_calendar_value(&range[..idx], min, max)?,
+                    parse_calendar_value(&range[idx + 2..], min, max)?,
+                ),
+                None => {
+                    let start = parse_calendar_value(range, min, max)?;
+                    // "10/5" means every fifth starting at 10
+                    let end = if step > 1 { max } else { start };
+                    (start, end)
+                }
+            }
+        };
+        if start > end {
+            return Err(format!("Invalid range in calendar expression: {}", range));
+        }
+        ranges.push(CalendarRange { start, end, step });
+    }
+    Ok(CalendarComponent(ranges))
+}
+
+/// Parse a calendar expression of the form "[Weekdays] [[Year-]Month-Day] [Hour:Minute[:Second]] [UTC]"
+/// or one of the shorthands like "daily" or "weekly"
+pub fn parse_calendar(descr: &str) -> Result<CalendarSpec, String> {
+    let expression = descr.trim().to_owned();
+    let mut parts: Vec<&str> = expression.split_whitespace().collect();
+
+    let mut utc = false;
+    if let Some(last) = parts.last() {
+        if last.eq_ignore_ascii_case("UTC") {
+            utc = true;
+            parts.pop();
+        }
+    }
+
+    if parts.len() == 1 {
+        let shorthand = match parts[0].to_lowercase().as_str() {
+            "minutely" => Some(vec!["*-*-*", "*:*:00"]),
+            "hourly" => Some(vec!["*-*-*", "*:00:00"]),
+            "daily" => Some(vec!["*-*-*", "00:00:00"]),
+            "monthly" => Some(vec!["*-*-01", "00:00:00"]),
+            "weekly" => Some(vec!["Mon", "*-*-*", "00:00:00"]),
+            "yearly" | "annually" => Some(vec!["*-01-01", "00:00:00"]),
+            "quarterly" => Some(vec!["*-01,04,07,10-01", "00:00:00"]),
+            "semiannually" => Some(vec!["*-01,07-01", "00:00:00"]),
+            _ => None,
+        };
+        if let Some(shorthand) = shorthand {
+            parts = shorthand;
+        }
+    }
+    if parts.is_empty() {
+        return Err("Empty calendar expression".to_owned());
+    }
+
+    let mut weekdays = None;
+    if let Some(first) = parts.first() {
+        if first.starts_with(|c: char| c.is_ascii_alphabetic()) {
+            weekdays = Some(parse_weekdays(first)?);
+            parts.remove(0);
+        }
+    }
+
+    let mut date = None;
+    let mut time = None;
+    for part in parts {
+        if part.contains(':') && time.is_none() {
+            time = Some(part);
+        } else if part.contains('-') && date.is_none() && time.is_none() {
+            date = Some(part);
+        } else {
+            return Err(format!("Invalid calendar expression: {}", expression));
+        }
+    }
+
+    let (years, months, days) = match date {
+        Some(date) => {
+            let fields: Vec<&str> = date.split('-').collect();
+            match fields.len() {
+                3 => (
+                    parse_calendar_component(fields[0], 1970, 2199)?,
+                    parse_calendar_component(fields[1], 1, 12)?,
+                    parse_calendar_component(fields[2], 1, 31)?,
+                ),
+                2 => (
+                    CalendarComponent::default(),
+                    parse_calendar_component(fields[0], 1, 12)?,
+                    parse_calendar_component(fields[1], 1, 31)?,
+                ),
+                _ => return Err(format!("Invalid date in calendar expression: {}", date)),
+            }
+        }
+        None => (
+            CalendarComponent::default(),
+            CalendarComponent::default(),
+            CalendarComponent::default(),
+        ),
+    };
+
+    let (hours, minutes, seconds) = match time {
+        Some(time) => {
+            let fields: Vec<&str> = time.split(':').collect();
+            match fields.len() {
+                3 => (
+                    parse_calendar_component(fields[0], 0, 23)?,
+                    parse_calendar_component(fields[1], 0, 59)?,
+                    parse_calendar_component(fields[2], 0, 59)?,
+                ),
+                2 => (
+                    parse_calendar_component(fields[0], 0, 23)?,
+                    parse_calendar_component(fields[1], 0, 59)?,
+                    parse_calendar_component("00", 0, 59)?,
+                ),
+                _ => return Err(format!("Invalid time in calendar expression: {}", time)),
+            }
+        }
+        // without a time the expression means midnight
+        None => (
+            parse_calendar_component("00", 0, 23)?,
+            parse_calendar_component("00", 0, 59)?,
+            parse_calendar_component("00", 0, 59)?,
+        ),
+    };
+
+    Ok(CalendarSpec {
+        expression,
+        weekdays,
+        years,
+        months,
+        days,
+        hours,
+        minutes,
+        seconds,
+        utc,
+    })
+}
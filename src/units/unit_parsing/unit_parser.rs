@@ -5,27 +5,38 @@ use crate::units::*;
 use std::collections::HashMap;
 use std::path::PathBuf;
 
-pub type ParsedSection = HashMap<String, Vec<(u32, String)>>;
+/// The index of the file an entry comes from (0 is the unit file, the drop-ins follow in the order they were applied)
+/// and the line of the entry in that file
+pub type EntryPosition = (u32, u32);
+pub type ParsedSection = HashMap<String, Vec<(EntryPosition, String)>>;
 pub type ParsedFile = HashMap<String, ParsedSection>;
 
+/// Parse the content of a unit file. The entries of the sections are numbered with the line they were found on
 pub fn parse_file(content: &str) -> Result<ParsedFile, ParsingErrorReason> {
     let mut sections = HashMap::new();
-    let lines: Vec<&str> = content.split('\n').collect();
-    let lines: Vec<_> = lines.iter().map(|s| s.trim()).collect();
+    let lines = merge_continued_lines(content);
+    let lines: Vec<_> = lines
+        .iter()
+        .map(|(line_number, line)| (*line_number, line.trim()))
+        .collect();
 
     let mut lines_left = &lines[..];
 
     // remove lines before the first section
-    while !lines_left.is_empty() && !lines_left[0].starts_with('[') {
+    while !lines_left.is_empty() && !lines_left[0].1.starts_with('[') {
         lines_left = &lines_left[1..];
     }
-    let mut current_section_name: String = lines_left[0].into();
+    if lines_left.is_empty() {
+        // a file without any sections, e.g. a drop-in that only contains comments
+        return Ok(sections);
+    }
+    let mut current_section_name: String = lines_left[0].1.into();
     let mut current_section_lines = Vec::new();
 
     lines_left = &lines_left[1..];
 
     while !lines_left.is_empty() {
-        let line = lines_left[0];
+        let (line_number, line) = lines_left[0];
 
         if line.starts_with('[') {
             if sections.contains_key(&current_section_name) {
@@ -41,7 +52,7 @@ pub fn parse_file(content: &str) -> Result<ParsedFile, ParsingErrorReason> {
             current_section_name = line.into();
             current_section_lines.clear();
         } else {
-            current_section_lines.push(line);
+            current_section_lines.push((line_number, line));
         }
         lines_left = &lines_left[1..];
     }
@@ -58,10 +69,54 @@ pub fn parse_file(content: &str) -> Result<ParsedFile, ParsingErrorReason> {
     Ok(sections)
 }
 
+/// A line ending in a backslash is continued on the next line. The merged lines keep the (1-based) number
+/// of the line they started on
+fn merge_continued_lines(content: &str) -> Vec<(u32, String)> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    let mut start_line = 1;
+    for (idx, line) in content.split('\n').enumerate() {
+        if current.is_empty() {
+            start_line = idx as u32 + 1;
+        }
+        let trimmed = line.trim_end();
+        if trimmed.ends_with('\\') && !line.trim_start().starts_with('#') {
+            current.push_str(&trimmed[..trimmed.len() - 1]);
+            current.push(' ');
+        } else {
+            current.push_str(line);
+            lines.push((start_line, std::mem::take(&mut current)));
+        }
+    }
+    if !current.is_empty() {
+        lines.push((start_line, current));
+    }
+    lines
+}
+
 pub fn map_tupels_to_second<X, Y: Clone>(v: Vec<(X, Y)>) -> Vec<Y> {
     v.iter().map(|(_, scnd)| scnd.clone()).collect()
 }
 
+/// Settings that take a list of unit names accept them separated by whitespace like systemd does, or by commas.
+/// An empty value resets the list.
+pub fn split_list_values(v: Vec<(EntryPosition, String)>) -> Vec<String> {
+    let mut names = Vec::new();
+    for (_, value) in v {
+        if value.trim().is_empty() {
+            names.clear();
+            continue;
+        }
+        names.extend(
+            value
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|name| !name.is_empty())
+                .map(|name| name.to_owned()),
+        );
+    }
+    names
+}
+
 pub fn string_to_bool(s: &str) -> bool {
     if s.len() == 0 {
         return false;
@@ -74,6 +129,125 @@ pub fn string_to_bool(s: &str) -> bool {
     *s_upper == *"YES" || *s_upper == *"TRUE" || is_num_and_one
 }
 
+/// Parse a timeout setting which is either "infinity" or a time span like parse_timespan accepts
+pub fn parse_timeout(setting_name: &str, descr: &str) -> Result<Timeout, ParsingErrorReason> {
+    if descr.trim().eq_ignore_ascii_case("infinity") {
+        Ok(Timeout::Infinity)
+    } else {
+        parse_timespan(descr).map(Timeout::Duration).map_err(|e| {
+            ParsingErrorReason::Generic(format!("Invalid value for {}: {}", setting_name, e))
+        })
+    }
+}
+
+/// Parse a time span like "5min 30s", "1h30m" or "90" (plain numbers are seconds), see systemd.time(7)
+pub fn parse_timespan(descr: &str) -> Result<std::time::Duration, String> {
+    let descr = descr.trim();
+    if let Ok(secs) = descr.parse::<u64>() {
+        return Ok(std::time::Duration::from_secs(secs));
+    }
+    let mut sum = std::time::Duration::from_secs(0);
+    let mut chars = descr.chars().peekable();
+    let mut found_any = false;
+    loop {
+        while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut number = String::new();
+        while let Some(c) = chars.peek() {
+            if c.is_ascii_digit() || *c == '.' {
+                number.push(*c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+            chars.next();
+        }
+        let mut unit = String::new();
+        while let Some(c) = chars.peek() {
+            if c.is_alphabetic() {
+                unit.push(*c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        let number = number
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid time span: {}", descr))?;
+        let micros_per_unit: u64 = match unit.as_str() {
+            "us" | "usec" => 1,
+            "ms" | "msec" => 1_000,
+            "" | "s" | "sec" | "second" | "seconds" => 1_000_000,
+            "m" | "min" | "minute" | "minutes" => 60 * 1_000_000,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60 * 1_000_000,
+            "d" | "day" | "days" => 24 * 60 * 60 * 1_000_000,
+            "w" | "week" | "weeks" => 7 * 24 * 60 * 60 * 1_000_000,
+            "M" | "month" | "months" => 2_629_800 * 1_000_000,
+            "y" | "year" | "years" => 31_557_600 * 1_000_000,
+            _ => return Err(format!("Unknown time unit {} in: {}", unit, descr)),
+        };
+        sum += std::time::Duration::from_micros((number * micros_per_unit as f64) as u64);
+        found_any = true;
+    }
+    if !found_any {
+        return Err(format!("Invalid time span: {}", descr));
+    }
+    Ok(sum)
+}
+
+/// Parse all Condition*= or Assert*= settings, depending on the prefix. An empty value resets the conditions of that kind
+fn parse_conditions(
+    section: &mut ParsedSection,
+    prefix: &str,
+) -> Result<Vec<Condition>, ParsingErrorReason> {
+    let mut conditions = Vec::new();
+    for kind in &ConditionKind::all() {
+        let setting = format!("{}{}", prefix, kind.name());
+        let values = match section.remove(&setting.to_uppercase()) {
+            Some(values) => values,
+            None => continue,
+        };
+        for (_, value) in values {
+            if value.is_empty() {
+                conditions.retain(|condition: &Condition| condition.kind != *kind);
+                continue;
+            }
+            let mut check = value.trim();
+            let trigger = check.starts_with('|');
+            if trigger {
+                check = check[1..].trim_start();
+            }
+            let negate = check.starts_with('!');
+            if negate {
+                check = check[1..].trim_start();
+            }
+            let needs_path = matches!(
+                kind,
+                ConditionKind::PathExists
+                    | ConditionKind::PathIsDirectory
+                    | ConditionKind::FileNotEmpty
+                    | ConditionKind::DirectoryNotEmpty
+            );
+            if check.is_empty() || (needs_path && !check.starts_with('/')) {
+                return Err(ParsingErrorReason::UnknownSetting(setting, value));
+            }
+            conditions.push(Condition {
+                kind: *kind,
+                value: check.to_owned(),
+                negate,
+                trigger,
+            });
+        }
+    }
+    Ok(conditions)
+}
+
 pub fn parse_unit_section(
     mut section: ParsedSection,
     path: &PathBuf,
@@ -82,7 +256,17 @@ pub fn parse_unit_section(
     let requires = section.remove("REQUIRES");
     let after = section.remove("AFTER");
     let before = section.remove("BEFORE");
+    let conflicts = section.remove("CONFLICTS");
+    let binds_to = section.remove("BINDSTO");
+    let part_of = section.remove("PARTOF");
+    let requisite = section.remove("REQUISITE");
+    let on_failure = section.remove("ONFAILURE");
+    let on_success = section.remove("ONSUCCESS");
     let description = section.remove("DESCRIPTION");
+    let start_limit_interval = section.remove("STARTLIMITINTERVALSEC");
+    let start_limit_burst = section.remove("STARTLIMITBURST");
+    let conditions = parse_conditions(&mut section, "Condition")?;
+    let asserts = parse_conditions(&mut section, "Assert")?;
 
     if !section.is_empty() {
         return Err(ParsingErrorReason::UnusedSetting(
@@ -90,13 +274,61 @@ pub fn parse_unit_section(
         ));
     }
 
+    let start_limit_interval = match start_limit_interval {
+        Some(vec) => {
+            if vec.len() == 1 {
+                Some(parse_timeout("StartLimitIntervalSec", &vec[0].1)?)
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "StartLimitIntervalSec".to_owned(),
+                    map_tupels_to_second(vec),
+                ));
+            }
+        }
+        None => None,
+    };
+    let start_limit_burst = match start_limit_burst {
+        Some(vec) => {
+            if vec.len() == 1 {
+                match vec[0].1.parse::<u64>() {
+                    Ok(burst) => Some(burst),
+                    Err(_) => {
+                        return Err(ParsingErrorReason::UnknownSetting(
+                            "StartLimitBurst".to_owned(),
+                            vec[0].1.clone(),
+                        ))
+                    }
+                }
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "StartLimitBurst".to_owned(),
+                    map_tupels_to_second(vec),
+                ));
+            }
+        }
+        None => None,
+    };
+
     Ok(UnitConfig {
         filepath: path.clone(),
         description: description.map(|x| (x[0]).1.clone()).unwrap_or_default(),
-        wants: map_tupels_to_second(wants.unwrap_or_default()),
-        requires: map_tupels_to_second(requires.unwrap_or_default()),
-        after: map_tupels_to_second(after.unwrap_or_default()),
-        before: map_tupels_to_second(before.unwrap_or_default()),
+        wants: split_list_values(wants.unwrap_or_default()),
+        requires: split_list_values(requires.unwrap_or_default()),
+        after: split_list_values(after.unwrap_or_default()),
+        before: split_list_values(before.unwrap_or_default()),
+        conflicts: split_list_values(conflicts.unwrap_or_default()),
+        binds_to: split_list_values(binds_to.unwrap_or_default()),
+        part_of: split_list_values(part_of.unwrap_or_default()),
+        requisite: split_list_values(requisite.unwrap_or_default()),
+        on_failure: split_list_values(on_failure.unwrap_or_default()),
+        on_success: split_list_values(on_success.unwrap_or_default()),
+        conditions,
+        asserts,
+        start_limit_interval,
+        start_limit_burst,
+        dropins: Vec::new(),
+        shadowed: Vec::new(),
+        aliases: Vec::new(),
     })
 }
 
@@ -104,6 +336,18 @@ pub fn parse_exec_section(section: &mut ParsedSection) -> Result<ExecConfig, Par
     let user = section.remove("USER");
     let group = section.remove("GROUP");
     let supplementary_groups = section.remove("SUPPLEMENTARYGROUPS");
+    let environment = section.remove("ENVIRONMENT");
+    let environment_files = section.remove("ENVIRONMENTFILE");
+    let pass_environment = section.remove("PASSENVIRONMENT");
+    let unset_environment = section.remove("UNSETENVIRONMENT");
+    let working_directory = section.remove("WORKINGDIRECTORY");
+    let root_directory = section.remove("ROOTDIRECTORY");
+    let umask = section.remove("UMASK");
+    let stdin = section.remove("STANDARDINPUT");
+    let stdout = section.remove("STANDARDOUTPUT");
+    let stderr = section.remove("STANDARDERROR");
+    let syslog_identifier = section.remove("SYSLOGIDENTIFIER");
+    let syslog_facility = section.remove("SYSLOGFACILITY");
 
     let user = match user {
         None => None,
@@ -145,18 +389,335 @@ pub fn parse_exec_section(section: &mut ParsedSection) -> Result<ExecConfig, Par
         }),
     };
 
+    let mut env_assignments = Vec::new();
+    for (_, value) in environment.unwrap_or_default() {
+        if value.is_empty() {
+            env_assignments.clear();
+            continue;
+        }
+        let words = split_words(&value).map_err(|e| {
+            ParsingErrorReason::Generic(format!("Invalid Environment={}: {}", value, e))
+        })?;
+        for word in words {
+            match split_env_assignment(&word) {
+                Some(assignment) => env_assignments.push(assignment),
+                None => warn!("Ignoring invalid environment assignment: {}", word),
+            }
+        }
+    }
+
+    let mut env_files = Vec::new();
+    for (_, value) in environment_files.unwrap_or_default() {
+        if value.is_empty() {
+            env_files.clear();
+        } else if let Some(path) = value.strip_prefix('-') {
+            env_files.push((PathBuf::from(path), true));
+        } else {
+            env_files.push((PathBuf::from(value), false));
+        }
+    }
+
+    let working_directory = match working_directory {
+        None => None,
+        Some(vec) => {
+            if vec.len() == 1 {
+                let value = &vec[0].1;
+                let (value, optional) = match value.strip_prefix('-') {
+                    Some(value) => (value, true),
+                    None => (value.as_str(), false),
+                };
+                if value == "~" {
+                    Some((home_directory(user.as_deref())?, optional))
+                } else if value.starts_with('/') {
+                    Some((PathBuf::from(value), optional))
+                } else {
+                    return Err(ParsingErrorReason::Generic(format!(
+                        "WorkingDirectory= must be an absolute path or '~', got: {}",
+                        value
+                    )));
+                }
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "WorkingDirectory".into(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+    };
+
+    let root_directory = match root_directory {
+        None => None,
+        Some(vec) => {
+            if vec.len() == 1 {
+                if vec[0].1.starts_with('/') {
+                    Some(PathBuf::from(&vec[0].1))
+                } else {
+                    return Err(ParsingErrorReason::Generic(format!(
+                        "RootDirectory= must be an absolute path, got: {}",
+                        vec[0].1
+                    )));
+                }
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "RootDirectory".into(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+    };
+
+    let umask = match umask {
+        None => None,
+        Some(vec) => {
+            if vec.len() == 1 {
+                match u32::from_str_radix(&vec[0].1, 8) {
+                    Ok(bits) if bits <= 0o777 => {
+                        Some(nix::sys::stat::Mode::from_bits_truncate(bits as _))
+                    }
+                    _ => {
+                        return Err(ParsingErrorReason::Generic(format!(
+                            "UMask= must be an octal number up to 0777, got: {}",
+                            vec[0].1
+                        )));
+                    }
+                }
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "UMask".into(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+    };
+
+    let stdin = match stdin {
+        None => StandardInput::Null,
+        Some(vec) => {
+            if vec.len() == 1 {
+                parse_standard_input(&vec[0].1)?
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "StandardInput".into(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+    };
+    // like systemd the output goes to the same place as the input if that is interactive
+    let default_stdout = match stdin {
+        StandardInput::Socket | StandardInput::Tty => StandardOutput::Inherit,
+        StandardInput::Null | StandardInput::File(_) => StandardOutput::Journal,
+    };
+    let stdout = match stdout {
+        None => default_stdout,
+        Some(vec) => {
+            if vec.len() == 1 {
+                parse_standard_output("StandardOutput", &vec[0].1)?
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "StandardOutput".into(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+    };
+    let stderr = match stderr {
+        None => StandardOutput::Inherit,
+        Some(vec) => {
+            if vec.len() == 1 {
+                parse_standard_output("StandardError", &vec[0].1)?
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "StandardError".into(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+    };
+
+    let syslog_identifier = match syslog_identifier {
+        None => None,
+        Some(vec) => {
+            if vec.len() == 1 {
+                Some(vec[0].1.clone())
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "SyslogIdentifier".into(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+    };
+    let syslog_facility = match syslog_facility {
+        None => SYSLOG_FACILITY_DAEMON,
+        Some(vec) => {
+            if vec.len() == 1 {
+                match parse_syslog_facility(&vec[0].1) {
+                    Some(facility) => facility,
+                    None => {
+                        return Err(ParsingErrorReason::UnknownSetting(
+                            "SyslogFacility".into(),
+                            vec[0].1.clone(),
+                        ))
+                    }
+                }
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "SyslogFacility".into(),
+                    super::map_tupels_to_second(vec),
+                ));
+            }
+        }
+    };
+
     Ok(ExecConfig {
         user,
         group,
         supplementary_groups,
+        environment: env_assignments,
+        environment_files: env_files,
+        pass_environment: split_env_names(pass_environment),
+        unset_environment: split_env_names(unset_environment),
+        working_directory,
+        root_directory,
+        umask,
+        stdin,
+        stdout,
+        stderr,
+        syslog_identifier,
+        syslog_facility,
     })
 }
 
+const SYSLOG_FACILITY_DAEMON: u8 = 3;
+
+/// The facility names from syslog(3) mapped to their codes
+fn parse_syslog_facility(name: &str) -> Option<u8> {
+    let code = match name {
+        "kern" => 0,
+        "user" => 1,
+        "mail" => 2,
+        "daemon" => SYSLOG_FACILITY_DAEMON,
+        "auth" => 4,
+        "syslog" => 5,
+        "lpr" => 6,
+        "news" => 7,
+        "uucp" => 8,
+        "cron" => 9,
+        "authpriv" => 10,
+        "ftp" => 11,
+        _ => {
+            let local = name.strip_prefix("local")?.parse::<u8>().ok()?;
+            if local > 7 {
+                return None;
+            }
+            16 + local
+        }
+    };
+    Some(code)
+}
+
+fn parse_standard_input(value: &str) -> Result<StandardInput, ParsingErrorReason> {
+    if let Some(path) = value.strip_prefix("file:") {
+        return Ok(StandardInput::File(absolute_path("StandardInput", path)?));
+    }
+    match value {
+        "null" => Ok(StandardInput::Null),
+        "socket" => Ok(StandardInput::Socket),
+        "tty" => Ok(StandardInput::Tty),
+        _ => Err(ParsingErrorReason::UnknownSetting(
+            "StandardInput".into(),
+            value.to_owned(),
+        )),
+    }
+}
+
+fn parse_standard_output(
+    setting_name: &str,
+    value: &str,
+) -> Result<StandardOutput, ParsingErrorReason> {
+    if let Some(path) = value.strip_prefix("file:") {
+        return Ok(StandardOutput::File(absolute_path(setting_name, path)?));
+    }
+    if let Some(path) = value.strip_prefix("append:") {
+        return Ok(StandardOutput::Append(absolute_path(setting_name, path)?));
+    }
+    if let Some(path) = value.strip_prefix("truncate:") {
+        return Ok(StandardOutput::Truncate(absolute_path(setting_name, path)?));
+    }
+    if let Some(name) = value.strip_prefix("fd:") {
+        if name.is_empty() {
+            return Err(ParsingErrorReason::UnknownSetting(
+                setting_name.into(),
+                value.to_owned(),
+            ));
+        }
+        return Ok(StandardOutput::Fd(name.to_owned()));
+    }
+    match value {
+        "journal" => Ok(StandardOutput::Journal),
+        "inherit" => Ok(StandardOutput::Inherit),
+        "null" => Ok(StandardOutput::Null),
+        "socket" => Ok(StandardOutput::Socket),
+        _ => Err(ParsingErrorReason::UnknownSetting(
+            setting_name.into(),
+            value.to_owned(),
+        )),
+    }
+}
+
+fn absolute_path(setting_name: &str, path: &str) -> Result<PathBuf, ParsingErrorReason> {
+    if path.starts_with('/') {
+        Ok(PathBuf::from(path))
+    } else {
+        Err(ParsingErrorReason::Generic(format!(
+            "{}= needs an absolute path, got: {}",
+            setting_name, path
+        )))
+    }
+}
+
+/// The home directory of the user, root if no user is set
+fn home_directory(user: Option<&str>) -> Result<PathBuf, ParsingErrorReason> {
+    let user = user.unwrap_or("root");
+    let pwentry = crate::platform::pwnam::getpwnam_r(user).map_err(|e| {
+        ParsingErrorReason::Generic(format!("Couldnt get home directory for {}: {}", user, e))
+    })?;
+    pwentry
+        .home
+        .ok_or_else(|| ParsingErrorReason::Generic(format!("User {} has no home directory", user)))
+}
+
+/// Split "KEY=value" at the first '='. The key must be a valid variable name
+pub fn split_env_assignment(assignment: &str) -> Option<(String, String)> {
+    let pos = assignment.find('=')?;
+    let (key, value) = (&assignment[..pos], &assignment[pos + 1..]);
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return None;
+    }
+    Some((key.to_owned(), value.to_owned()))
+}
+
+/// PassEnvironment= and UnsetEnvironment= take whitespace separated lists, an empty value resets the list
+fn split_env_names(values: Option<Vec<(EntryPosition, String)>>) -> Vec<String> {
+    let mut names = Vec::new();
+    for (_, value) in values.unwrap_or_default() {
+        if value.is_empty() {
+            names.clear();
+        } else {
+            names.extend(value.split_whitespace().map(|name| name.to_owned()));
+        }
+    }
+    names
+}
+
 pub fn parse_install_section(
     mut section: ParsedSection,
 ) -> Result<InstallConfig, ParsingErrorReason> {
     let wantedby = section.remove("WANTEDBY");
     let requiredby = section.remove("REQUIREDBY");
+    let default_instance = section.remove("DEFAULTINSTANCE");
+    let alias = section.remove("ALIAS");
 
     if !section.is_empty() {
         return Err(ParsingErrorReason::UnusedSetting(
@@ -164,9 +725,25 @@ pub fn parse_install_section(
         ));
     }
 
+    let default_instance = match default_instance {
+        Some(vec) => {
+            if vec.len() == 1 {
+                Some(vec[0].1.clone())
+            } else {
+                return Err(ParsingErrorReason::SettingTooManyValues(
+                    "DefaultInstance".to_owned(),
+                    map_tupels_to_second(vec),
+                ));
+            }
+        }
+        None => None,
+    };
+
     Ok(InstallConfig {
-        wanted_by: map_tupels_to_second(wantedby.unwrap_or_default()),
-        required_by: map_tupels_to_second(requiredby.unwrap_or_default()),
+        wanted_by: split_list_values(wantedby.unwrap_or_default()),
+        required_by: split_list_values(requiredby.unwrap_or_default()),
+        default_instance,
+        alias: split_list_values(alias.unwrap_or_default()),
     })
 }
 
@@ -210,11 +787,10 @@ pub fn get_file_list(path: &PathBuf) -> Result<Vec<std::fs::DirEntry>, ParsingEr
     Ok(files)
 }
 
-pub fn parse_section(lines: &[&str]) -> ParsedSection {
+pub fn parse_section(lines: &[(u32, &str)]) -> ParsedSection {
     let mut entries: ParsedSection = HashMap::new();
 
-    let mut entry_number = 0;
-    for line in lines {
+    for (line_number, line) in lines {
         //ignore comments
         if line.starts_with('#') {
             continue;
@@ -231,13 +807,9 @@ pub fn parse_section(lines: &[&str]) -> ParsedSection {
         let value = value.trim_start_matches('=');
         let value = value.trim();
         let name = name.trim().to_uppercase();
-        let values: Vec<String> = value.split(',').map(|x| x.into()).collect();
 
         let vec = entries.entry(name).or_insert_with(Vec::new);
-        for value in values {
-            vec.push((entry_number, value));
-            entry_number += 1;
-        }
+        vec.push(((0, *line_number), value.to_owned()));
     }
 
     entries
@@ -14,6 +14,8 @@ pub enum UnitIdKind {
     Target,
     Socket,
     Service,
+    Timer,
+    Path,
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Hash)]
@@ -61,6 +63,9 @@ pub struct RuntimeInfo {
     pub fd_store: ArcMutFDStore,
     pub config: crate::config::Config,
     pub last_id: Arc<Mutex<u64>>,
+    pub job_table: ArcJobTable,
+    pub journal: crate::journal::ArcMutJournal,
+    pub log_sink: crate::log_sink::ArcLogSink,
 }
 
 // This will be passed through to all the different threads as a central state struct
@@ -97,9 +102,36 @@ pub enum UnitStatus {
     Starting,
     Started,
     StartedWaitingForSocket,
+    /// The service sent RELOADING=1 and did not yet send READY=1 again
+    Reloading,
+    /// The service sent STOPPING=1 and is shutting down on it's own
+    StoppingGracefully,
     Stopping,
     Stopped,
     StoppedFinal(String),
+    /// A oneshot service without RemainAfterExit= that ran successfully. Units ordered after it can start
+    Exited,
+    /// A Condition*= was not met so the unit was not started. Units ordered after it can start
+    Skipped(String),
+}
+
+impl UnitStatus {
+    /// The unit is running or on it's way up or down
+    pub fn is_active(&self) -> bool {
+        match self {
+            UnitStatus::Starting
+            | UnitStatus::Started
+            | UnitStatus::StartedWaitingForSocket
+            | UnitStatus::Reloading
+            | UnitStatus::StoppingGracefully
+            | UnitStatus::Stopping => true,
+            UnitStatus::NeverStarted
+            | UnitStatus::Stopped
+            | UnitStatus::StoppedFinal(_)
+            | UnitStatus::Exited
+            | UnitStatus::Skipped(_) => false,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -107,6 +139,8 @@ pub enum UnitSpecialized {
     Socket(Socket),
     Service(Service),
     Target,
+    Timer(crate::timers::Timer),
+    Path(crate::path_activation::PathWatch),
 }
 
 #[derive(Debug, Default)]
@@ -124,15 +158,59 @@ pub struct Install {
     pub before: Vec<UnitId>,
     pub after: Vec<UnitId>,
 
+    pub conflicts: Vec<UnitId>,
+    pub binds_to: Vec<UnitId>,
+    pub bound_by: Vec<UnitId>,
+    pub part_of: Vec<UnitId>,
+    pub consists_of: Vec<UnitId>,
+    pub requisite: Vec<UnitId>,
+    pub requisite_of: Vec<UnitId>,
+
     pub install_config: Option<InstallConfig>,
 }
 
+impl Install {
+    /// Remove all relations to a unit that is removed from the unit table
+    pub fn remove_references(&mut self, id: UnitId) {
+        for ids in &mut [
+            &mut self.wants,
+            &mut self.wanted_by,
+            &mut self.requires,
+            &mut self.required_by,
+            &mut self.before,
+            &mut self.after,
+            &mut self.conflicts,
+            &mut self.binds_to,
+            &mut self.bound_by,
+            &mut self.part_of,
+            &mut self.consists_of,
+            &mut self.requisite,
+            &mut self.requisite_of,
+        ] {
+            ids.retain(|other| *other != id);
+        }
+    }
+
+    /// Units that are stopped when this unit is stopped
+    pub fn stopped_with(&self) -> Vec<UnitId> {
+        let mut ids = self.required_by.clone();
+        ids.extend(self.bound_by.iter().copied());
+        ids.extend(self.consists_of.iter().copied());
+        ids.extend(self.requisite_of.iter().copied());
+        ids.sort();
+        ids.dedup();
+        ids
+    }
+}
+
 pub struct Unit {
     pub id: UnitId,
     pub conf: UnitConfig,
     pub specialized: UnitSpecialized,
 
     pub install: Install,
+    /// The OnFailure= or OnSuccess= units that were started the last time this unit failed or succeeded
+    pub triggered_handlers: Vec<String>,
 }
 
 impl Unit {
@@ -158,6 +236,17 @@ impl Unit {
         }
     }
 
+    /// The unit that gets activated by this timer or path unit
+    pub fn triggered_unit(&self) -> Option<UnitId> {
+        match &self.specialized {
+            UnitSpecialized::Timer(timer) => timer.unit_id,
+            UnitSpecialized::Path(path) => path.unit_id,
+            UnitSpecialized::Socket(_) | UnitSpecialized::Service(_) | UnitSpecialized::Target => {
+                None
+            }
+        }
+    }
+
     pub fn dedup_dependencies(&mut self) {
         self.install.wants.sort();
         self.install.wanted_by.sort();
@@ -165,6 +254,13 @@ impl Unit {
         self.install.before.sort();
         self.install.after.sort();
         self.install.requires.sort();
+        self.install.conflicts.sort();
+        self.install.binds_to.sort();
+        self.install.bound_by.sort();
+        self.install.part_of.sort();
+        self.install.consists_of.sort();
+        self.install.requisite.sort();
+        self.install.requisite_of.sort();
         // dedup after sorting
         self.install.wants.dedup();
         self.install.requires.dedup();
@@ -172,33 +268,52 @@ impl Unit {
         self.install.required_by.dedup();
         self.install.before.dedup();
         self.install.after.dedup();
+        self.install.conflicts.dedup();
+        self.install.binds_to.dedup();
+        self.install.bound_by.dedup();
+        self.install.part_of.dedup();
+        self.install.consists_of.dedup();
+        self.install.requisite.dedup();
+        self.install.requisite_of.dedup();
     }
 
     pub fn activate(
         &mut self,
-        pid_table: ArcMutPidTable,
-        fd_store: ArcMutFDStore,
+        run_info: &RuntimeInfo,
         notification_socket_path: std::path::PathBuf,
         eventfds: &[EventFd],
         allow_ignore: bool,
     ) -> Result<UnitStatus, UnitOperationError> {
         match &mut self.specialized {
             UnitSpecialized::Target => trace!("Reached target {}", self.conf.name()),
+            UnitSpecialized::Timer(timer) => {
+                timer.activated_at = Some(std::time::Instant::now());
+                // calendar timers start counting from the first check, which considers Persistent=
+                timer.calendar_base = None;
+            }
+            UnitSpecialized::Path(_) => {
+                trace!("Start watching paths of {}", self.conf.name())
+            }
             UnitSpecialized::Socket(sock) => {
-                sock.open_all(self.conf.name(), self.id, &mut *fd_store.write().unwrap())
-                    .map_err(|e| UnitOperationError {
-                        unit_name: self.conf.name(),
-                        unit_id: self.id,
-                        reason: UnitOperationErrorReason::SocketOpenError(format!("{}", e)),
-                    })?;
+                sock.open_all(
+                    self.conf.name(),
+                    self.id,
+                    &mut *run_info.fd_store.write().unwrap(),
+                )
+                .map_err(|e| UnitOperationError {
+                    unit_name: self.conf.name(),
+                    unit_id: self.id,
+                    reason: UnitOperationErrorReason::SocketOpenError(format!("{}", e)),
+                })?;
             }
             UnitSpecialized::Service(srvc) => {
+                let exits_when_done = srvc.service_config.srcv_type == ServiceType::OneShot
+                    && !srvc.service_config.remain_after_exit;
                 match srvc
                     .start(
                         self.id,
                         &self.conf.name(),
-                        fd_store,
-                        pid_table,
+                        run_info,
                         notification_socket_path,
                         eventfds,
                         allow_ignore,
@@ -208,7 +323,12 @@ impl Unit {
                         unit_id: self.id,
                         reason: UnitOperationErrorReason::ServiceStartError(e),
                     })? {
-                    crate::services::StartResult::Started => return Ok(UnitStatus::Started),
+                    crate::services::StartResult::Started => {
+                        if exits_when_done {
+                            return Ok(UnitStatus::Exited);
+                        }
+                        return Ok(UnitStatus::Started);
+                    }
                     crate::services::StartResult::WaitingForSocket => {
                         return Ok(UnitStatus::StartedWaitingForSocket)
                     }
@@ -217,6 +337,33 @@ impl Unit {
         }
         Ok(UnitStatus::Started)
     }
+    pub fn reload(&mut self, pid_table: ArcMutPidTable) -> Result<(), UnitOperationError> {
+        trace!("Reload unit: {}", self.conf.name());
+        match &mut self.specialized {
+            UnitSpecialized::Service(srvc) => {
+                srvc.reload(self.id, &self.conf.name(), pid_table)
+                    .map_err(|e| UnitOperationError {
+                        unit_name: self.conf.name(),
+                        unit_id: self.id,
+                        reason: UnitOperationErrorReason::ServiceReloadError(e),
+                    })?;
+            }
+            UnitSpecialized::Socket(_)
+            | UnitSpecialized::Target
+            | UnitSpecialized::Timer(_)
+            | UnitSpecialized::Path(_) => {
+                return Err(UnitOperationError {
+                    unit_name: self.conf.name(),
+                    unit_id: self.id,
+                    reason: UnitOperationErrorReason::GenericReloadError(
+                        "Only services can be reloaded".into(),
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
     pub fn deactivate(
         &mut self,
         pid_table: ArcMutPidTable,
@@ -225,6 +372,10 @@ impl Unit {
         trace!("Deactivate unit: {}", self.conf.name());
         match &mut self.specialized {
             UnitSpecialized::Target => { /* nothing to do */ }
+            UnitSpecialized::Timer(timer) => {
+                timer.activated_at = None;
+            }
+            UnitSpecialized::Path(_) => { /* the watcher only looks at started path units */ }
             UnitSpecialized::Socket(sock) => {
                 sock.close_all(self.conf.name(), &mut *fd_store.write().unwrap())
                     .map_err(|e| UnitOperationError {
@@ -246,8 +397,33 @@ impl Unit {
     }
 }
 
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ConditionKind {
+    PathExists,
+    PathIsDirectory,
+    FileNotEmpty,
+    DirectoryNotEmpty,
+    Environment,
+    Host,
+    KernelCommandLine,
+    Virtualization,
+    User,
+}
+
+/// One line of a Condition*= or Assert*= setting
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Condition {
+    pub kind: ConditionKind,
+    pub value: String,
+    /// The '!' prefix inverts the check
+    pub negate: bool,
+    /// With the '|' prefix it is enough if one of the triggering conditions is met
+    pub trigger: bool,
+}
+
 #[derive(Debug)]
 pub struct UnitConfig {
+    /// For instances of templates this is the path of the template with the file name of the instance
     pub filepath: PathBuf,
 
     pub description: String,
@@ -256,6 +432,32 @@ pub struct UnitConfig {
     pub requires: Vec<String>,
     pub before: Vec<String>,
     pub after: Vec<String>,
+    /// Starting this unit stops these units and the other way around
+    pub conflicts: Vec<String>,
+    /// Like requires but this unit is also stopped when one of these stops
+    pub binds_to: Vec<String>,
+    /// Stopping or restarting one of these units stops or restarts this unit
+    pub part_of: Vec<String>,
+    /// These units must already be active, starting this unit fails otherwise
+    pub requisite: Vec<String>,
+    /// Started when this unit fails
+    pub on_failure: Vec<String>,
+    /// Started when this unit exits successfully
+    pub on_success: Vec<String>,
+    /// If one of these is not met the unit is skipped
+    pub conditions: Vec<Condition>,
+    /// If one of these is not met the unit fails
+    pub asserts: Vec<Condition>,
+
+    pub start_limit_interval: Option<Timeout>,
+    pub start_limit_burst: Option<u64>,
+
+    /// The drop-ins that were merged into the unit file, in the order they were applied
+    pub dropins: Vec<PathBuf>,
+    /// Files with the same name in unit dirs with a lower precedence
+    pub shadowed: Vec<PathBuf>,
+    /// Other names this unit can be referenced by
+    pub aliases: Vec<String>,
 }
 
 impl UnitConfig {
@@ -302,6 +504,9 @@ unsafe impl Send for SocketConfig {}
 pub struct InstallConfig {
     pub wanted_by: Vec<String>,
     pub required_by: Vec<String>,
+    /// The instance of a template that is used when the template itself is enabled
+    pub default_instance: Option<String>,
+    pub alias: Vec<String>,
 }
 
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
@@ -310,6 +515,11 @@ pub enum ServiceType {
     Notify,
     Dbus,
     OneShot,
+    Forking,
+    /// Like simple but the start only succeeds once the executable has actually been exec'd
+    Exec,
+    /// Like simple but the start is delayed until all other jobs have been dispatched
+    Idle,
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -324,6 +534,11 @@ pub enum NotifyKind {
 pub enum ServiceRestart {
     Always,
     No,
+    OnSuccess,
+    OnFailure,
+    OnAbnormal,
+    OnAbort,
+    OnWatchdog,
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
@@ -337,26 +552,197 @@ pub struct ExecConfig {
     pub user: Option<String>,
     pub group: Option<String>,
     pub supplementary_groups: Vec<String>,
+    /// Assignments from Environment=, later ones override earlier ones
+    pub environment: Vec<(String, String)>,
+    /// Files from EnvironmentFile=. The bool is set if the file is optional ('-' prefix)
+    pub environment_files: Vec<(PathBuf, bool)>,
+    /// Variables from the environment of rustysd that are passed on
+    pub pass_environment: Vec<String>,
+    /// Either "VAR" or "VAR=value" to only unset the exact assignment
+    pub unset_environment: Vec<String>,
+    /// A '~' is already replaced with the home of the user. The bool is set if the directory is optional ('-' prefix)
+    pub working_directory: Option<(PathBuf, bool)>,
+    pub root_directory: Option<PathBuf>,
+    pub umask: Option<nix::sys::stat::Mode>,
+    pub stdin: StandardInput,
+    pub stdout: StandardOutput,
+    pub stderr: StandardOutput,
+    /// Used as the identifier when forwarding output to syslog/journald. Defaults to the name of the executable
+    pub syslog_identifier: Option<String>,
+    /// Facility code as in RFC 5424, defaults to daemon
+    pub syslog_facility: u8,
+}
+
+/// What StandardInput= is connected to
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum StandardInput {
+    Null,
+    /// The socket of a socket activated service, only allowed if there is exactly one
+    Socket,
+    File(PathBuf),
+    /// /dev/console
+    Tty,
+}
+
+/// What StandardOutput= or StandardError= are connected to
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum StandardOutput {
+    /// Relayed into the log of rustysd, this is the default for stdout
+    Journal,
+    /// stdout gets the same fd as stdin, stderr the same as stdout. This is the default for stderr
+    Inherit,
+    Null,
+    /// Opened for writing without truncating it
+    File(PathBuf),
+    Append(PathBuf),
+    Truncate(PathBuf),
+    /// The socket of a socket activated service, only allowed if there is exactly one
+    Socket,
+    /// The socket of this service with this FileDescriptorName=
+    Fd(String),
+}
+
+/// Special prefixes of the executable in a command line. See systemd.service(5) for the details
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum CommandlinePrefix {
+    /// The second word is passed as argv[0]
+    AtSign,
+    /// A failing exit is treated as success
+    Minus,
+    /// No environment variable expansion
+    Colon,
+    /// Run with full privileges
+    Plus,
+    /// Do not change the user or groups
+    Exclamation,
+    /// Same as Exclamation since ambient capabilities are not supported
+    DoubleExclamation,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Commandline {
+    pub cmd: String,
+    /// If the AtSign prefix is set the first of these is argv[0]
+    pub args: Vec<String>,
+    pub prefixes: Vec<CommandlinePrefix>,
+}
+
+impl Commandline {
+    pub fn has_prefix(&self, prefix: CommandlinePrefix) -> bool {
+        self.prefixes.contains(&prefix)
+    }
+
+    /// The argv[0] given with the AtSign prefix
+    pub fn argv0(&self) -> Option<&str> {
+        if self.has_prefix(CommandlinePrefix::AtSign) {
+            self.args.first().map(|arg| arg.as_str())
+        } else {
+            None
+        }
+    }
+
+    /// The arguments after argv[0] with the environment variables expanded (unless the Colon prefix is set)
+    pub fn expanded_args(&self, env: &[(String, String)]) -> Vec<String> {
+        let args = if self.has_prefix(CommandlinePrefix::AtSign) {
+            &self.args[1..]
+        } else {
+            &self.args[..]
+        };
+        if self.has_prefix(CommandlinePrefix::Colon) {
+            args.to_vec()
+        } else {
+            crate::units::expand_env_vars(args, env)
+        }
+    }
+
+    /// Whether this should be run without dropping privileges to User= / Group=
+    pub fn keeps_privileges(&self) -> bool {
+        self.has_prefix(CommandlinePrefix::Plus)
+            || self.has_prefix(CommandlinePrefix::Exclamation)
+            || self.has_prefix(CommandlinePrefix::DoubleExclamation)
+    }
+}
+
+impl fmt::Display for Commandline {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for prefix in &self.prefixes {
+            let prefix = match prefix {
+                CommandlinePrefix::AtSign => "@",
+                CommandlinePrefix::Minus => "-",
+                CommandlinePrefix::Colon => ":",
+                CommandlinePrefix::Plus => "+",
+                CommandlinePrefix::Exclamation => "!",
+                CommandlinePrefix::DoubleExclamation => "!!",
+            };
+            f.write_str(prefix)?;
+        }
+        f.write_str(&self.cmd)?;
+        for arg in &self.args {
+            write!(f, " {}", arg)?;
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Eq, PartialEq, Debug)]
 pub struct ServiceConfig {
     pub restart: ServiceRestart,
-    pub accept: bool,
+    pub restart_sec: Option<Timeout>,
+    pub watchdog_sec: Option<Timeout>,
+    /// How many fds the service may store with FDSTORE=1. 0 disables the fd store for this service
+    pub fd_store_max: u64,
     pub notifyaccess: NotifyKind,
-    pub exec: String,
-    pub stop: Vec<String>,
-    pub stoppost: Vec<String>,
-    pub startpre: Vec<String>,
-    pub startpost: Vec<String>,
+    /// Only Type=oneshot allows more than one command, they are run one after the other
+    pub exec: Vec<Commandline>,
+    pub stop: Vec<Commandline>,
+    pub reload: Vec<Commandline>,
+    pub stoppost: Vec<Commandline>,
+    pub startpre: Vec<Commandline>,
+    pub startpost: Vec<Commandline>,
     pub srcv_type: ServiceType,
     pub starttimeout: Option<Timeout>,
     pub stoptimeout: Option<Timeout>,
+    /// TimeoutReloadSec=, falls back to the start timeout if it is not set
+    pub reloadtimeout: Option<Timeout>,
     pub generaltimeout: Option<Timeout>,
 
     pub exec_config: ExecConfig,
 
     pub dbus_name: Option<String>,
+    /// Where a service of type forking writes the pid of the actual daemon. Without it the main pid is guessed and the
+    /// start fails if that is not possible
+    pub pid_file: Option<std::path::PathBuf>,
+    /// A oneshot service stays active after all commands exited successfully
+    pub remain_after_exit: bool,
 
     pub sockets: Vec<String>,
 }
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TimerConfig {
+    pub on_active: Vec<std::time::Duration>,
+    pub on_boot: Vec<std::time::Duration>,
+    pub on_unit_active: Vec<std::time::Duration>,
+    pub on_unit_inactive: Vec<std::time::Duration>,
+    pub on_calendar: Vec<crate::timers::CalendarSpec>,
+    /// Catch up on calendar events that were missed while the timer was not active
+    pub persistent: bool,
+    /// The unit that is activated when the timer elapses
+    pub unit: String,
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum PathCondition {
+    Exists(PathBuf),
+    ExistsGlob(String),
+    Changed(PathBuf),
+    Modified(PathBuf),
+    DirectoryNotEmpty(PathBuf),
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PathConfig {
+    pub conditions: Vec<PathCondition>,
+    /// The unit that is activated when one of the conditions is met
+    pub unit: String,
+}
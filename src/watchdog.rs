@@ -0,0 +1,59 @@
+//! Enforce the WatchdogSec= setting of services. Services that do not send WATCHDOG=1 in time get killed
+//! and are restarted according to their Restart= setting by the exit handler.
+
+use crate::units::*;
+
+// check at least this often, so newly started services are picked up in time
+const MAX_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+pub fn start_watchdog_thread(run_info: ArcRuntimeInfo) {
+    std::thread::spawn(move || loop {
+        let next_check = check_all_watchdogs(&run_info);
+        std::thread::sleep(next_check);
+    });
+}
+
+/// Trigger the watchdog of all services that did not ping in time. Returns how long it can wait until the next deadline.
+pub fn check_all_watchdogs(run_info: &ArcRuntimeInfo) -> std::time::Duration {
+    let mut next_check = MAX_CHECK_INTERVAL;
+
+    let unit_table_locked = run_info.unit_table.read().unwrap();
+    for unit in unit_table_locked.values() {
+        let mut unit_locked = unit.lock().unwrap();
+        let name = unit_locked.conf.name();
+        let id = unit_locked.id;
+        if let UnitSpecialized::Service(srvc) = &mut unit_locked.specialized {
+            let (timeout, last_ping) = match (srvc.watchdog_timeout, srvc.watchdog_last_ping) {
+                (Some(timeout), Some(last_ping)) => (timeout, last_ping),
+                _ => continue,
+            };
+            if srvc.watchdog_triggered {
+                continue;
+            }
+
+            // starting/stopping services are handled by their respective timeouts
+            let running = {
+                let status_table_locked = run_info.status_table.read().unwrap();
+                match status_table_locked.get(&id) {
+                    Some(status) => *status.lock().unwrap() == UnitStatus::Started,
+                    None => false,
+                }
+            };
+            if !running {
+                continue;
+            }
+
+            let elapsed = last_ping.elapsed();
+            if elapsed >= timeout {
+                warn!(
+                    "Watchdog of service {} timed out. Last ping was {:?} ago",
+                    name, elapsed
+                );
+                srvc.trigger_watchdog(&name);
+            } else {
+                next_check = next_check.min(timeout - elapsed);
+            }
+        }
+    }
+    next_check
+}